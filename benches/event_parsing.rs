@@ -0,0 +1,72 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// The fields of the indexer's `PangeaOrderEvent`, which the binary crate
+/// doesn't export.
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct PangeaOrderEvent {
+    chain: u64,
+    block_number: i64,
+    block_hash: String,
+    transaction_hash: String,
+    transaction_index: u64,
+    log_index: u64,
+    market_id: String,
+    order_id: String,
+    event_type: Option<String>,
+    asset: Option<String>,
+    amount: Option<u128>,
+    asset_type: Option<String>,
+    order_type: Option<String>,
+    price: Option<u128>,
+    user: Option<String>,
+    order_matcher: Option<String>,
+    owner: Option<String>,
+    limit_type: Option<String>,
+    #[serde(default)]
+    block_timestamp: Option<i64>,
+}
+
+/// The committed payload corpus, one encoded event per entry, as the
+/// stream delivers them.
+fn payloads() -> Vec<Vec<u8>> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/payloads.json");
+    let corpus: Vec<Value> = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+    corpus
+        .iter()
+        .map(|payload| serde_json::to_vec(payload).unwrap())
+        .collect()
+}
+
+fn event_parsing(c: &mut Criterion) {
+    let payloads = payloads();
+    let mut group = c.benchmark_group("event_parsing");
+    group.bench_function("serde_json", |b| {
+        b.iter(|| {
+            for payload in &payloads {
+                let event: PangeaOrderEvent = serde_json::from_slice(payload).unwrap();
+                black_box(event);
+            }
+        })
+    });
+    group.bench_function("simd_json", |b| {
+        b.iter_batched(
+            || payloads.clone(),
+            |mut payloads| {
+                for payload in &mut payloads {
+                    let event: PangeaOrderEvent = simd_json::serde::from_slice(payload).unwrap();
+                    black_box(event);
+                }
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, event_parsing);
+criterion_main!(benches);
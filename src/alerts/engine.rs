@@ -0,0 +1,90 @@
+use chrono::Utc;
+use log::{info, warn};
+use std::sync::Arc;
+use tokio::time::{self, Duration};
+
+use crate::alerts::notifier::Notifier;
+use crate::alerts::rules::AlertRule;
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::status::IndexerStatus;
+use crate::storage::backend::Storage;
+
+const DEFAULT_CHECK_INTERVAL_SECS: u64 = 30;
+
+/// Periodically evaluates the configured rules and notifies once when a
+/// rule starts firing and once when it resolves.
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    notifiers: Vec<Notifier>,
+    firing: Vec<bool>,
+    http: reqwest::Client,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>, notifiers: Vec<Notifier>) -> Self {
+        AlertEngine {
+            firing: vec![false; rules.len()],
+            rules,
+            notifiers,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn check(&mut self, order_book: &dyn Storage, status: &IndexerStatus) {
+        let now = Utc::now().timestamp_millis() as u64;
+        for (index, rule) in self.rules.iter().enumerate() {
+            let violation = rule.evaluate(order_book, status, now);
+            let message = match (&violation, self.firing[index]) {
+                (Some(violation), false) => {
+                    format!("[{}] ALERT: {}", status.market_id(), violation)
+                }
+                (None, true) => format!("[{}] RESOLVED: {:?}", status.market_id(), rule),
+                _ => continue,
+            };
+            self.firing[index] = violation.is_some();
+
+            warn!("{}", message);
+            for notifier in &self.notifiers {
+                notifier.send(&self.http, &message).await;
+            }
+        }
+    }
+}
+
+pub fn initialize_alerting(
+    tasks: &mut Vec<tokio::task::JoinHandle<()>>,
+    order_book: Arc<dyn Storage>,
+    status: Arc<IndexerStatus>,
+) -> Result<(), Error> {
+    let interval = match ev_opt("ALERT_CHECK_INTERVAL_SECS") {
+        Some(value) => match value.parse()? {
+            0 => {
+                return Err(Error::InvalidEnvValue(
+                    "ALERT_CHECK_INTERVAL_SECS".to_owned(),
+                    value,
+                ))
+            }
+            secs => Duration::from_secs(secs),
+        },
+        None => Duration::from_secs(DEFAULT_CHECK_INTERVAL_SECS),
+    };
+    let rules = AlertRule::from_env()?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let notifiers = Notifier::from_env();
+    if notifiers.is_empty() {
+        info!("Alert rules configured without a notifier, alerts will only be logged");
+    }
+
+    let mut engine = AlertEngine::new(rules, notifiers);
+    tasks.push(tokio::spawn(async move {
+        let mut ticker = time::interval(interval);
+        loop {
+            ticker.tick().await;
+            engine.check(&order_book, &status).await;
+        }
+    }));
+    Ok(())
+}
@@ -0,0 +1,3 @@
+pub mod engine;
+pub mod notifier;
+pub mod rules;
@@ -0,0 +1,61 @@
+use log::warn;
+use serde_json::json;
+use std::time::Duration;
+
+use crate::config::env::ev_opt;
+
+/// How long a delivery may take before it is given up, so an unresponsive
+/// endpoint doesn't hold up the checks that raise alerts.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub enum Notifier {
+    /// POSTs `{"text": message}` to the URL.
+    Webhook {
+        url: String,
+    },
+    Telegram {
+        bot_token: String,
+        chat_id: String,
+    },
+}
+
+impl Notifier {
+    /// Notifiers configured through `ALERT_WEBHOOK_URL` and
+    /// `TELEGRAM_BOT_TOKEN` + `TELEGRAM_CHAT_ID`.
+    pub fn from_env() -> Vec<Self> {
+        let mut notifiers = vec![];
+        if let Some(url) = ev_opt("ALERT_WEBHOOK_URL") {
+            notifiers.push(Notifier::Webhook { url });
+        }
+        if let (Some(bot_token), Some(chat_id)) =
+            (ev_opt("TELEGRAM_BOT_TOKEN"), ev_opt("TELEGRAM_CHAT_ID"))
+        {
+            notifiers.push(Notifier::Telegram { bot_token, chat_id });
+        }
+        notifiers
+    }
+
+    pub async fn send(&self, http: &reqwest::Client, message: &str) {
+        let request = match self {
+            Notifier::Webhook { url } => http.post(url).json(&json!({ "text": message })),
+            Notifier::Telegram { bot_token, chat_id } => http
+                .post(format!(
+                    "https://api.telegram.org/bot{}/sendMessage",
+                    bot_token
+                ))
+                .json(&json!({ "chat_id": chat_id, "text": message })),
+        };
+
+        let result = async {
+            request
+                .timeout(SEND_TIMEOUT)
+                .send()
+                .await?
+                .error_for_status()
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("Failed to deliver alert: {}", e);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::status::IndexerStatus;
+use crate::storage::backend::Storage;
+
+const MINUTE_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertRule {
+    /// Indexer trails the chain head by more than this many blocks.
+    MaxLagBlocks(i64),
+    /// No trade indexed for this many minutes.
+    NoTradesMinutes(u64),
+    /// Best bid/ask spread wider than this, in basis points of the mid.
+    MaxSpreadBps(u128),
+}
+
+impl AlertRule {
+    /// Rules enabled through `ALERT_MAX_LAG_BLOCKS`,
+    /// `ALERT_NO_TRADES_MINUTES` and `ALERT_MAX_SPREAD_BPS`.
+    pub fn from_env() -> Result<Vec<Self>, Error> {
+        let mut rules = vec![];
+        if let Some(value) = ev_opt("ALERT_MAX_LAG_BLOCKS") {
+            rules.push(AlertRule::MaxLagBlocks(value.parse()?));
+        }
+        if let Some(value) = ev_opt("ALERT_NO_TRADES_MINUTES") {
+            rules.push(AlertRule::NoTradesMinutes(value.parse()?));
+        }
+        if let Some(value) = ev_opt("ALERT_MAX_SPREAD_BPS") {
+            rules.push(AlertRule::MaxSpreadBps(value.parse()?));
+        }
+        Ok(rules)
+    }
+
+    /// Describes the violation, or `None` while the condition holds or
+    /// cannot be evaluated yet.
+    pub fn evaluate(
+        &self,
+        order_book: &dyn Storage,
+        status: &IndexerStatus,
+        now: u64,
+    ) -> Option<String> {
+        match *self {
+            AlertRule::MaxLagBlocks(max) => {
+                let behind = status.blocks_behind()?;
+                (behind > max).then(|| format!("indexer is {} blocks behind (max {})", behind, max))
+            }
+            AlertRule::NoTradesMinutes(minutes) => {
+                // A market without trades has been idle since the book
+                // was created.
+                let last = order_book
+                    .last_trade_timestamp()
+                    .unwrap_or_else(|| order_book.epoch());
+                let idle = now.saturating_sub(last);
+                (idle > minutes * MINUTE_MS).then(|| {
+                    format!(
+                        "no trades for {} minutes (max {})",
+                        idle / MINUTE_MS,
+                        minutes
+                    )
+                })
+            }
+            AlertRule::MaxSpreadBps(max) => {
+                let (bid, ask) = (order_book.best_bid()?, order_book.best_ask()?);
+                let mid = (bid + ask) / 2;
+                if ask <= bid || mid == 0 {
+                    return None;
+                }
+                let spread_bps = (ask - bid).saturating_mul(10_000) / mid;
+                (spread_bps > max).then(|| format!("spread is {} bps (max {})", spread_bps, max))
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::config::check::check_config;
+use crate::error::Error;
+use crate::indexer::fixtures::generate_fixtures;
+use crate::indexer::payload_compat::check_payloads;
+use crate::indexer::replay::verify_replay;
+use crate::storage::market_registry::contract_ids;
+
+/// Spark order book indexer and API. Runs the server when no subcommand is
+/// given.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Discards stored checkpoints and replays every market from its start
+    /// block.
+    #[arg(long)]
+    pub from_scratch: bool,
+    /// Rebuilds every market's book from its event log (`EVENT_LOG_DIR`)
+    /// instead of a checkpoint or a Pangea backfill, then streams from the
+    /// last logged block.
+    #[arg(long, conflicts_with = "from_scratch")]
+    pub from_log: bool,
+    /// Serves synthetic markets of random makers and takers instead of
+    /// indexing Pangea, for frontend development without credentials.
+    /// Markets are `CONTRACT_ID` or a single made-up one.
+    #[arg(long, conflicts_with_all = ["from_scratch", "from_log"])]
+    pub dev: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Replays a block range twice from Pangea and fails if the two resulting
+    /// book hashes differ.
+    VerifyReplay {
+        #[arg(long)]
+        from_block: i64,
+        #[arg(long)]
+        to_block: i64,
+        /// Market to replay; defaults to the first one in `CONTRACT_ID`.
+        #[arg(long)]
+        market_id: Option<String>,
+        /// Read the events from the market's event log (`EVENT_LOG_DIR`)
+        /// instead of Pangea.
+        #[arg(long)]
+        from_log: bool,
+    },
+    /// Samples real events from Pangea into anonymized handler test
+    /// fixtures.
+    Fixtures {
+        #[arg(long)]
+        from_block: i64,
+        #[arg(long)]
+        to_block: i64,
+        /// Events to keep per event type.
+        #[arg(long, default_value_t = 5)]
+        per_type: usize,
+        #[arg(long, default_value = "fixtures/events.json")]
+        out: PathBuf,
+        /// Market to sample; defaults to the first one in `CONTRACT_ID`.
+        #[arg(long)]
+        market_id: Option<String>,
+        /// Keep the payloads as Pangea sent them, for `check-payloads`.
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Reads a corpus of raw Pangea payloads through the event model and
+    /// fails, listing unknown, missing and changed fields, if any no longer
+    /// match it.
+    CheckPayloads {
+        #[arg(long, default_value = "fixtures/payloads.json")]
+        corpus: PathBuf,
+    },
+    /// Inspects the configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Validates every setting and prints the effective values with secrets
+    /// masked. Exits non-zero if anything is missing or malformed.
+    Check {
+        /// Also connect to Pangea and the Fuel node.
+        #[arg(long)]
+        connect: bool,
+    },
+}
+
+pub async fn run(command: Command) -> Result<(), Error> {
+    match command {
+        Command::VerifyReplay {
+            from_block,
+            to_block,
+            market_id,
+            from_log,
+        } => {
+            let market_id = match market_id {
+                Some(market_id) => market_id,
+                None => default_market()?,
+            };
+            verify_replay(&market_id, from_block, to_block, from_log).await
+        }
+        Command::Fixtures {
+            from_block,
+            to_block,
+            per_type,
+            out,
+            market_id,
+            raw,
+        } => {
+            let market_id = match market_id {
+                Some(market_id) => market_id,
+                None => default_market()?,
+            };
+            generate_fixtures(&market_id, from_block, to_block, per_type, &out, raw).await
+        }
+        Command::CheckPayloads { corpus } => check_payloads(&corpus),
+        Command::Config {
+            command: ConfigCommand::Check { connect },
+        } => check_config(connect).await,
+    }
+}
+
+/// The first market in `CONTRACT_ID`, for commands run without `--market-id`.
+fn default_market() -> Result<String, Error> {
+    Ok(contract_ids()?.remove(0))
+}
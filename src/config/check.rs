@@ -0,0 +1,96 @@
+use ethers_core::types::H256;
+use std::str::FromStr;
+
+use crate::config::env::ev_opt;
+use crate::config::schema::CONFIG_VARS;
+use crate::error::Error;
+use crate::indexer::fuel_node::FuelNodeClient;
+use crate::indexer::pangea::create_pangea_client;
+use crate::indexer::pangea_credentials::PangeaCredentials;
+use crate::oracle::price_signer::PriceSigner;
+
+const MASK: &str = "********";
+
+/// Prints the effective configuration with secrets masked and fails if any
+/// value is missing or malformed. With `connect`, also opens a Pangea
+/// connection and queries the Fuel node when one is configured.
+pub async fn check_config(connect: bool) -> Result<(), Error> {
+    let mut problems = vec![];
+    for var in CONFIG_VARS {
+        let shown = match var.effective_value() {
+            Some(_) if var.secret => MASK.to_owned(),
+            Some(value) if ev_opt(var.name).is_none() => format!("{} (default)", value),
+            Some(value) => value,
+            None => "(unset)".to_owned(),
+        };
+        println!("{} = {}", var.name, shown);
+        if let Some(problem) = var.problem() {
+            problems.push(format!("{}: {}", var.name, problem));
+        }
+    }
+
+    if ev_opt("CONTRACT_ID").is_none() && ev_opt("MARKET_DISCOVERY").as_deref() != Some("true") {
+        problems.push("CONTRACT_ID: required unless MARKET_DISCOVERY=true".to_owned());
+    }
+    if ev_opt("MARKET_DISCOVERY").as_deref() == Some("true") {
+        for name in ["SPARK_REGISTRY_ID", "FUEL_NODE_URL"] {
+            if ev_opt(name).is_none() {
+                problems.push(format!("{}: required with MARKET_DISCOVERY=true", name));
+            }
+        }
+    }
+    if ev_opt("PANGEA_CREDENTIALS_FILE").is_none() {
+        for name in ["PANGEA_USERNAME", "PANGEA_PASSWORD"] {
+            if ev_opt(name).is_none() {
+                problems.push(format!(
+                    "{}: required unless PANGEA_CREDENTIALS_FILE is set",
+                    name
+                ));
+            }
+        }
+    } else if let Err(e) = PangeaCredentials::load() {
+        problems.push(format!("PANGEA_CREDENTIALS_FILE: {}", e));
+    }
+    for name in [
+        "CONTRACT_ID",
+        "MARKET_ALLOWLIST",
+        "MARKET_DENYLIST",
+        "MARKET_DISCOVERY_ASSETS",
+        "SPARK_REGISTRY_ID",
+    ] {
+        for market_id in ev_opt(name).iter().flat_map(|value| value.split(',')) {
+            let market_id = market_id.trim();
+            if market_id.is_empty() {
+                continue;
+            }
+            if let Err(e) = H256::from_str(market_id) {
+                problems.push(format!("{}: '{}': {}", name, market_id, e));
+            }
+        }
+    }
+    if let Err(e) = PriceSigner::from_env() {
+        problems.push(format!("PRICE_SIGNER_KEY: {}", e));
+    }
+
+    if connect && problems.is_empty() {
+        match create_pangea_client().await {
+            Ok(_) => println!("Pangea: connected"),
+            Err(e) => problems.push(format!("Pangea: {}", e)),
+        }
+        if let Some(node) = FuelNodeClient::from_env() {
+            match node.latest_block().await {
+                Ok(head) => println!("Fuel node: head at block {}", head.height),
+                Err(e) => problems.push(format!("Fuel node: {}", e)),
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        println!("Configuration OK");
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    Err(Error::InvalidConfig(problems.len()))
+}
@@ -5,3 +5,7 @@ use crate::error::Error;
 pub fn ev(key: &str) -> Result<String, Error> {
     env::var(key).map_err(|e| Error::EnvVarError(key.to_owned(), e.to_string()))
 }
+
+pub fn ev_opt(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.is_empty())
+}
@@ -1 +1,4 @@
+pub mod check;
 pub mod env;
+pub mod redaction;
+pub mod schema;
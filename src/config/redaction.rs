@@ -0,0 +1,65 @@
+use fuel_crypto::Hasher;
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+
+const TRUNCATED_LEN: usize = 6;
+const HASHED_LEN: usize = 8;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RedactionMode {
+    None,
+    Truncate,
+    Hash,
+}
+
+static MODE: OnceLock<RedactionMode> = OnceLock::new();
+
+/// Reads `LOG_REDACTION` (`none`, `truncate` or `hash`). Must be called once
+/// at startup; until then values are logged in full. Any other value is an
+/// error rather than a silent fallback to logging everything.
+pub fn init_from_env() -> Result<(), Error> {
+    let mode = match ev_opt("LOG_REDACTION").as_deref() {
+        None | Some("none") => RedactionMode::None,
+        Some("truncate") => RedactionMode::Truncate,
+        Some("hash") => RedactionMode::Hash,
+        Some(other) => {
+            return Err(Error::InvalidEnvValue(
+                "LOG_REDACTION".to_owned(),
+                other.to_owned(),
+            ))
+        }
+    };
+    let _ = MODE.set(mode);
+    Ok(())
+}
+
+fn mode() -> RedactionMode {
+    MODE.get().copied().unwrap_or(RedactionMode::None)
+}
+
+/// Wraps a user address or order id for logging according to the privacy
+/// config. Storage and API responses always keep the full value.
+pub fn redact(value: &str) -> Redacted<'_> {
+    Redacted(value)
+}
+
+pub struct Redacted<'a>(&'a str);
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match mode() {
+            RedactionMode::None => f.write_str(self.0),
+            RedactionMode::Truncate => {
+                let prefix: String = self.0.chars().take(TRUNCATED_LEN).collect();
+                write!(f, "{}…", prefix)
+            }
+            RedactionMode::Hash => {
+                let digest = Hasher::hash(self.0.as_bytes());
+                write!(f, "#{}", &hex::encode(digest)[..HASHED_LEN * 2])
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use url::Url;
+
+use crate::config::env::ev_opt;
+use crate::web::client_ip::IpRange;
+
+/// What a configuration value must parse as.
+#[derive(Debug, Clone, Copy)]
+pub enum ValueKind {
+    Text,
+    Integer,
+    Decimal,
+    Bool,
+    Url,
+    /// Comma-separated addresses and CIDR blocks.
+    IpRanges,
+    /// Comma-separated values.
+    List,
+    OneOf(&'static [&'static str]),
+}
+
+impl ValueKind {
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        match self {
+            ValueKind::Text | ValueKind::List => Ok(()),
+            ValueKind::Integer => value
+                .parse::<i128>()
+                .map(|_| ())
+                .map_err(|_| format!("'{}' is not an integer", value)),
+            ValueKind::Decimal => value
+                .parse::<f64>()
+                .map(|_| ())
+                .map_err(|_| format!("'{}' is not a number", value)),
+            ValueKind::Bool => match value {
+                "true" | "false" => Ok(()),
+                _ => Err(format!("'{}' is not true or false", value)),
+            },
+            ValueKind::Url => Url::parse(value).map(|_| ()).map_err(|e| e.to_string()),
+            ValueKind::IpRanges => value
+                .split(',')
+                .map(str::trim)
+                .filter(|entry| !entry.is_empty())
+                .try_for_each(|entry| entry.parse::<IpRange>().map(|_| ())),
+            ValueKind::OneOf(choices) => {
+                if choices.contains(&value) {
+                    Ok(())
+                } else {
+                    Err(format!("'{}' is not one of {}", value, choices.join(", ")))
+                }
+            }
+        }
+    }
+}
+
+/// One environment variable the service reads.
+#[derive(Debug, Clone, Copy)]
+pub struct ConfigVar {
+    pub name: &'static str,
+    pub kind: ValueKind,
+    pub required: bool,
+    /// Masked when the effective config is printed.
+    pub secret: bool,
+    /// Value used when the variable is unset, for display.
+    pub default: Option<&'static str>,
+}
+
+const fn required(name: &'static str, kind: ValueKind) -> ConfigVar {
+    ConfigVar {
+        name,
+        kind,
+        required: true,
+        secret: false,
+        default: None,
+    }
+}
+
+const fn optional(name: &'static str, kind: ValueKind, default: Option<&'static str>) -> ConfigVar {
+    ConfigVar {
+        name,
+        kind,
+        required: false,
+        secret: false,
+        default,
+    }
+}
+
+const fn secret(var: ConfigVar) -> ConfigVar {
+    ConfigVar {
+        secret: true,
+        ..var
+    }
+}
+
+/// Every environment variable read at startup. Keep in sync when adding a
+/// new `ev`/`ev_opt` call.
+pub const CONFIG_VARS: &[ConfigVar] = &[
+    // Required unless MARKET_DISCOVERY is on; see check_config.
+    optional("CONTRACT_ID", ValueKind::List, None),
+    required("CONTRACT_START_BLOCK", ValueKind::Integer),
+    required("SERVER_PORT", ValueKind::Integer),
+    required("PANGEA_URL", ValueKind::List),
+    // Required unless PANGEA_CREDENTIALS_FILE is set; see check_config.
+    optional("PANGEA_USERNAME", ValueKind::Text, None),
+    secret(optional("PANGEA_PASSWORD", ValueKind::Text, None)),
+    optional("PANGEA_CREDENTIALS_FILE", ValueKind::Text, None),
+    optional(
+        "PANGEA_CREDENTIALS_POLL_SECS",
+        ValueKind::Integer,
+        Some("30"),
+    ),
+    optional("MARKET_DISCOVERY", ValueKind::Bool, Some("false")),
+    optional("MARKET_DISCOVERY_ASSETS", ValueKind::List, None),
+    // Required with MARKET_DISCOVERY; see check_config.
+    optional("SPARK_REGISTRY_ID", ValueKind::Text, None),
+    optional("MARKET_ALLOWLIST", ValueKind::List, None),
+    optional("MARKET_DENYLIST", ValueKind::List, None),
+    optional("MARKET_MIGRATIONS", ValueKind::List, None),
+    optional("MARKET_START_BLOCKS", ValueKind::List, None),
+    optional("QUOTE_RATES", ValueKind::List, None),
+    optional("REFERENCE_CURRENCY", ValueKind::Text, Some("USD")),
+    optional("FUEL_NODE_URL", ValueKind::Url, None),
+    optional(
+        "CHAIN_HEAD_POLL_INTERVAL_SECS",
+        ValueKind::Integer,
+        Some("5"),
+    ),
+    optional("CLOCK_SKEW_TOLERANCE_MS", ValueKind::Integer, Some("5000")),
+    optional(
+        "LOG_REDACTION",
+        ValueKind::OneOf(&["none", "truncate", "hash"]),
+        Some("none"),
+    ),
+    optional("MAINTENANCE_MODE", ValueKind::Bool, Some("false")),
+    optional("PUBLIC_MARKETS", ValueKind::List, None),
+    optional("HIDDEN_MARKETS", ValueKind::List, None),
+    optional("SIZE_HISTOGRAM_BOUNDS", ValueKind::List, None),
+    optional(
+        "ANOMALY_PRICE_DEVIATION_PCT",
+        ValueKind::Decimal,
+        Some("20"),
+    ),
+    optional("ANOMALY_SIZE_SIGMA", ValueKind::Decimal, Some("4")),
+    optional("ANOMALY_WINDOW", ValueKind::Integer, Some("100")),
+    optional("ANOMALY_TAG_IN_STORAGE", ValueKind::Bool, Some("true")),
+    secret(optional("PRICE_SIGNER_KEY", ValueKind::Text, None)),
+    optional("PRICE_SIGNER_LEVELS", ValueKind::Integer, Some("5")),
+    optional("AUDIT_LOG_PATH", ValueKind::Text, Some("audit.log")),
+    optional("USAGE_LOG_PATH", ValueKind::Text, Some("usage.log")),
+    optional(
+        "SUBSCRIPTION_USAGE_PATH",
+        ValueKind::Text,
+        Some("subscription_usage.log"),
+    ),
+    optional("COLD_STORAGE_DIR", ValueKind::Text, None),
+    optional("COLD_STORAGE_AFTER_DAYS", ValueKind::Integer, Some("30")),
+    optional("FEE_RATE_BPS", ValueKind::Integer, None),
+    optional("FEE_MAKER_BPS", ValueKind::Integer, None),
+    optional("FEE_TAKER_BPS", ValueKind::Integer, None),
+    optional("FEE_REVENUE_DIR", ValueKind::Text, Some("fee_revenue")),
+    optional("DAILY_REPORT_DIR", ValueKind::Text, None),
+    optional("DAILY_REPORT_TOP_TRADERS", ValueKind::Integer, Some("10")),
+    optional("DAILY_REPORT_WEBHOOK_URL", ValueKind::Url, None),
+    optional(
+        "DAILY_REPORT_WEBHOOK_ATTEMPTS",
+        ValueKind::Integer,
+        Some("12"),
+    ),
+    optional("REORG_DEPTH", ValueKind::Integer, Some("64")),
+    optional("REORDER_WINDOW_MS", ValueKind::Integer, Some("200")),
+    optional("RECONNECT_BASE_MS", ValueKind::Integer, Some("500")),
+    optional("RECONNECT_MAX_MS", ValueKind::Integer, Some("30000")),
+    optional("RECONNECT_MAX_ATTEMPTS", ValueKind::Integer, None),
+    optional("PANGEA_BACKFILL_CHUNKS", ValueKind::Integer, Some("1")),
+    optional(
+        "PANGEA_HISTORICAL_FORMAT",
+        ValueKind::OneOf(&["json", "arrow"]),
+        Some("json"),
+    ),
+    optional("PANGEA_FAILOVER_AFTER", ValueKind::Integer, Some("3")),
+    optional("PANGEA_HTTP_FALLBACK", ValueKind::Bool, Some("true")),
+    optional("PANGEA_POLL_INTERVAL_MS", ValueKind::Integer, Some("2000")),
+    optional("PANGEA_STALE_STREAM_SECS", ValueKind::Integer, Some("300")),
+    optional("PANGEA_PROGRESS_PROBE_SECS", ValueKind::Integer, Some("30")),
+    optional("PANGEA_MAX_CONNECTIONS", ValueKind::Integer, Some("4")),
+    optional("CONFIRMATION_DEPTH", ValueKind::Integer, Some("0")),
+    optional("RETENTION_REFRESH_SECS", ValueKind::Integer, Some("3600")),
+    optional(
+        "ADDRESS_LABELS_PATH",
+        ValueKind::Text,
+        Some("address_labels.json"),
+    ),
+    optional("CHECKPOINT_DIR", ValueKind::Text, None),
+    optional("CHECKPOINT_INTERVAL_SECS", ValueKind::Integer, Some("60")),
+    optional("CHECKPOINT_FOLLOW", ValueKind::Bool, Some("false")),
+    secret(optional("DATABASE_URL", ValueKind::Url, None)),
+    optional("DATABASE_MAX_CONNECTIONS", ValueKind::Integer, Some("5")),
+    optional("EVENT_LOG_DIR", ValueKind::Text, None),
+    optional(
+        "EVENT_LOG_SEGMENT_BLOCKS",
+        ValueKind::Integer,
+        Some("100000"),
+    ),
+    optional("EVENT_LOG_COMPACT_AFTER_BLOCKS", ValueKind::Integer, None),
+    optional(
+        "EVENT_LOG_COMPACT_INTERVAL_SECS",
+        ValueKind::Integer,
+        Some("3600"),
+    ),
+    optional("STARTUP_CHECK_SEGMENTS", ValueKind::Integer, Some("3")),
+    optional("STARTUP_CHECK_BLOCKS", ValueKind::Integer, Some("10")),
+    optional("STARTUP_CHECK_MAX_DIVERGENT", ValueKind::Integer, Some("0")),
+    secret(optional("ADMIN_API_KEY", ValueKind::Text, None)),
+    optional("ADMIN_IP_ALLOWLIST", ValueKind::IpRanges, None),
+    secret(optional("API_KEY_SCOPES", ValueKind::List, None)),
+    optional("ORDER_FEED_MASK_ADDRESSES", ValueKind::Bool, Some("true")),
+    secret(optional("ORDER_FEED_MASK_KEY", ValueKind::Text, None)),
+    optional("ORDER_FEED_BUFFER", ValueKind::Integer, Some("1024")),
+    optional("OIDC_ISSUER_URL", ValueKind::Url, None),
+    optional("OIDC_ADMIN_IDENTITIES", ValueKind::List, None),
+    optional("OIDC_AUDIENCE", ValueKind::Text, None),
+    optional("TRUSTED_PROXIES", ValueKind::IpRanges, None),
+    optional("IP_DENYLIST", ValueKind::IpRanges, None),
+    optional(
+        "WARMUP_MODE",
+        ValueKind::OneOf(&["off", "flag", "refuse"]),
+        Some("off"),
+    ),
+    optional("WARMUP_MAX_BLOCKS_BEHIND", ValueKind::Integer, Some("10")),
+    optional("DEPTH_DEFAULT_LEVELS", ValueKind::Integer, Some("50")),
+    optional("DEPTH_REDUCED_LEVELS", ValueKind::Integer, Some("10")),
+    optional("DEPTH_HIGH_LOAD_RPS", ValueKind::Integer, Some("200")),
+    optional("TRACE_SAMPLE_RATE", ValueKind::Decimal, Some("0")),
+    optional("SLOW_QUERY_MS", ValueKind::Integer, None),
+    optional("SLOW_QUERY_BUFFER", ValueKind::Integer, Some("100")),
+    optional("LOAD_SHED_ANALYTICS_RPS", ValueKind::Integer, None),
+    optional("LOAD_SHED_STANDARD_RPS", ValueKind::Integer, None),
+    optional("GRAPHQL_WS_PORT", ValueKind::Integer, None),
+    optional("WS_AUTH_GRACE_SECS", ValueKind::Integer, Some("30")),
+    optional("ALERT_MAX_LAG_BLOCKS", ValueKind::Integer, None),
+    optional("ALERT_NO_TRADES_MINUTES", ValueKind::Integer, None),
+    optional("ALERT_MAX_SPREAD_BPS", ValueKind::Integer, None),
+    optional("ALERT_CHECK_INTERVAL_SECS", ValueKind::Integer, Some("30")),
+    secret(optional("ALERT_WEBHOOK_URL", ValueKind::Url, None)),
+    secret(optional("TELEGRAM_BOT_TOKEN", ValueKind::Text, None)),
+    optional("TELEGRAM_CHAT_ID", ValueKind::Text, None),
+    optional("DEV_EVENTS_PER_SECOND", ValueKind::Integer, Some("5")),
+    optional("DEV_MID_PRICE", ValueKind::Integer, Some("3000000000000")),
+    optional("DEV_TICK_SIZE", ValueKind::Integer, Some("10000000")),
+    optional("DEV_TRADERS", ValueKind::Integer, Some("10")),
+    optional("DEV_SEED", ValueKind::Integer, None),
+];
+
+impl ConfigVar {
+    /// The value from the environment, falling back to the default.
+    pub fn effective_value(&self) -> Option<String> {
+        ev_opt(self.name).or_else(|| self.default.map(str::to_owned))
+    }
+
+    /// Problem with the current value, if any.
+    pub fn problem(&self) -> Option<String> {
+        match ev_opt(self.name) {
+            None if self.required => Some("required but not set".to_owned()),
+            None => None,
+            Some(value) => self.kind.validate(&value).err(),
+        }
+    }
+}
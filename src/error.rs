@@ -27,6 +27,64 @@ pub enum Error {
 
     #[error("Parsing error: {0}")]
     ParsingError(#[from] ParsingError),
+
+    #[error("Invalid price signer key: {0}")]
+    PriceSignerKeyError(String),
+
+    #[error("File error '{0}': {1}")]
+    FileError(String, std::io::Error),
+
+    #[error("Pangea stream error: {0}")]
+    StreamError(String),
+
+    #[error("Replay is not deterministic: first run {0}, second run {1}")]
+    ReplayMismatch(String, String),
+
+    #[error("{0} of {1} stored Pangea payloads no longer match the event model")]
+    IncompatiblePayloads(usize, usize),
+
+    #[error(
+        "Startup consistency check failed: {0} stored trades diverge from Pangea (allowed {1})"
+    )]
+    ConsistencyCheckFailed(usize, usize),
+
+    #[error("Invalid configuration: {0} problems found")]
+    InvalidConfig(usize),
+
+    #[error("Invalid value '{1}' for environment variable '{0}'")]
+    InvalidEnvValue(String, String),
+
+    #[error("PANGEA_URL lists no endpoints")]
+    NoPangeaEndpoints,
+
+    #[error("Market {0} is the default market and cannot be removed")]
+    DefaultMarketRemoval(String),
+
+    #[error("Gave up reconnecting to Pangea for {0} after {1} attempts")]
+    ReconnectAttemptsExhausted(String, u32),
+
+    #[error("Event log snapshot '{0}' exists but cannot be read by this build")]
+    UnreadableSnapshot(String),
+
+    #[error("Background task failed: {0}")]
+    TaskError(#[from] tokio::task::JoinError),
+
+    #[cfg(feature = "simd-json")]
+    #[error("simd-json error {0}")]
+    SimdJsonError(#[from] simd_json::Error),
+
+    #[cfg(feature = "postgres")]
+    #[error("Postgres error {0}")]
+    PostgresError(#[from] sqlx::Error),
+
+    #[error("Arrow error {0}")]
+    ArrowError(#[from] arrow::error::ArrowError),
+
+    #[error("Fuel node error {0}")]
+    FuelsError(#[from] fuels::types::errors::Error),
+
+    #[error("Peer request error: {0}")]
+    PeerRequestError(#[from] reqwest::Error),
 }
 
 #[derive(Error, Debug)]
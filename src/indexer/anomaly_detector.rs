@@ -0,0 +1,190 @@
+use chrono::Utc;
+use log::warn;
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+
+use crate::alerts::notifier::Notifier;
+use crate::config::env::ev_opt;
+use crate::config::redaction::redact;
+use crate::error::{Error, ParsingError};
+use crate::indexer::order_event_handler::PangeaOrderEvent;
+use crate::storage::backend::Storage;
+
+const DEFAULT_PRICE_DEVIATION_PCT: f64 = 20.0;
+const DEFAULT_SIZE_SIGMA: f64 = 4.0;
+const DEFAULT_WINDOW: usize = 100;
+const MIN_SAMPLES: usize = 10;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AnomalyKind {
+    PriceDeviation,
+    SizeOutlier,
+}
+
+#[derive(Debug, Clone)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub order_id: String,
+    pub event_type: String,
+    pub block_number: i64,
+    pub transaction_hash: String,
+    pub value: u128,
+    pub expected: u128,
+    pub timestamp: u64,
+}
+
+pub struct AnomalyConfig {
+    pub price_deviation_pct: f64,
+    pub size_sigma: f64,
+    pub window: usize,
+    pub tag_in_storage: bool,
+}
+
+impl AnomalyConfig {
+    pub fn from_env() -> Result<Self, Error> {
+        let price_deviation_pct = match ev_opt("ANOMALY_PRICE_DEVIATION_PCT") {
+            Some(value) => parse_f64(&value)?,
+            None => DEFAULT_PRICE_DEVIATION_PCT,
+        };
+        let size_sigma = match ev_opt("ANOMALY_SIZE_SIGMA") {
+            Some(value) => parse_f64(&value)?,
+            None => DEFAULT_SIZE_SIGMA,
+        };
+        let window = match ev_opt("ANOMALY_WINDOW") {
+            Some(value) => value.parse()?,
+            None => DEFAULT_WINDOW,
+        };
+        let tag_in_storage = ev_opt("ANOMALY_TAG_IN_STORAGE").is_none_or(|v| v != "false");
+
+        Ok(AnomalyConfig {
+            price_deviation_pct,
+            size_sigma,
+            window,
+            tag_in_storage,
+        })
+    }
+}
+
+#[derive(Default)]
+struct RecentSamples {
+    trade_prices: VecDeque<u128>,
+    sizes: VecDeque<u128>,
+}
+
+/// Flags events whose trade price strays too far from recent trades or whose
+/// size is a statistical outlier. Flagged events are still applied; the
+/// detector only reports them, in the log and to the alert notifiers.
+pub struct AnomalyDetector {
+    config: AnomalyConfig,
+    samples: RwLock<RecentSamples>,
+    notifiers: Arc<Vec<Notifier>>,
+    http: reqwest::Client,
+}
+
+impl AnomalyDetector {
+    pub fn new(config: AnomalyConfig, notifiers: Vec<Notifier>) -> Self {
+        AnomalyDetector {
+            config,
+            samples: RwLock::new(RecentSamples::default()),
+            notifiers: Arc::new(notifiers),
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Checks `event` against recent ones. With `notify`, anomalies are
+    /// also sent to the notifiers, which historical events are not.
+    pub fn inspect(&self, order_book: &dyn Storage, event: &PangeaOrderEvent, notify: bool) {
+        let event_type = event.event_type.as_deref().unwrap_or_default();
+        let mut found = vec![];
+        let mut samples = self.samples.write().unwrap();
+
+        if let (Some(amount), "Open" | "Trade") = (event.amount, event_type) {
+            if let Some((mean, std_dev)) = mean_and_std_dev(&samples.sizes) {
+                if std_dev > 0.0 && (amount as f64 - mean) / std_dev > self.config.size_sigma {
+                    found.push((AnomalyKind::SizeOutlier, amount, mean as u128));
+                }
+            }
+            push_bounded(&mut samples.sizes, amount, self.config.window);
+        }
+
+        if let (Some(price), "Trade") = (event.price, event_type) {
+            if let Some((mean, _)) = mean_and_std_dev(&samples.trade_prices) {
+                let deviation_pct = (price as f64 - mean).abs() / mean * 100.0;
+                if mean > 0.0 && deviation_pct > self.config.price_deviation_pct {
+                    found.push((AnomalyKind::PriceDeviation, price, mean as u128));
+                }
+            }
+            push_bounded(&mut samples.trade_prices, price, self.config.window);
+        }
+        drop(samples);
+
+        for (kind, value, expected) in found {
+            let message = format!(
+                "Anomalous {} event {:?} for order {} in block {}: value {}, expected ~{}",
+                event_type,
+                kind,
+                redact(&event.order_id),
+                event.block_number,
+                value,
+                expected
+            );
+            warn!("{}", message);
+            if notify {
+                self.notify(format!("[{}] ANOMALY: {}", event.market_id, message));
+            }
+            if self.config.tag_in_storage {
+                order_book.flag_anomaly(Anomaly {
+                    kind,
+                    order_id: event.order_id.clone(),
+                    event_type: event_type.to_owned(),
+                    block_number: event.block_number,
+                    transaction_hash: event.transaction_hash.clone(),
+                    value,
+                    expected,
+                    timestamp: Utc::now().timestamp_millis() as u64,
+                });
+            }
+        }
+    }
+
+    /// Sends `message` off the indexing path, which must not wait on it.
+    fn notify(&self, message: String) {
+        if self.notifiers.is_empty() {
+            return;
+        }
+        let notifiers = Arc::clone(&self.notifiers);
+        let http = self.http.clone();
+        tokio::spawn(async move {
+            for notifier in notifiers.iter() {
+                notifier.send(&http, &message).await;
+            }
+        });
+    }
+}
+
+fn push_bounded(samples: &mut VecDeque<u128>, value: u128, window: usize) {
+    samples.push_back(value);
+    while samples.len() > window {
+        samples.pop_front();
+    }
+}
+
+fn mean_and_std_dev(samples: &VecDeque<u128>) -> Option<(f64, f64)> {
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+    let count = samples.len() as f64;
+    let mean = samples.iter().map(|&v| v as f64).sum::<f64>() / count;
+    let variance = samples
+        .iter()
+        .map(|&v| (v as f64 - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    Some((mean, variance.sqrt()))
+}
+
+fn parse_f64(value: &str) -> Result<f64, Error> {
+    value
+        .parse()
+        .map_err(|_| ParsingError::StringParsingError(value.to_owned()).into())
+}
@@ -0,0 +1,308 @@
+use arrow::array::{Array, ArrayRef, AsArray};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Int64Type, UInt64Type};
+use arrow::error::ArrowError;
+use arrow::ipc::reader::StreamReader;
+use arrow::record_batch::RecordBatch;
+use std::io::Cursor;
+
+use crate::error::Error;
+use crate::indexer::order_event_handler::PangeaOrderEvent;
+
+/// Decodes one chunk of a `Format::Arrow` response, a complete Arrow IPC
+/// stream, into order events in stream order.
+///
+/// Events are read straight from the columns, which carry the same names
+/// as the fields of the JSON stream. Columns are cast to the type of their
+/// field, so amounts may come as decimals, integers or strings; a missing
+/// optional column reads as null.
+pub fn decode_order_events(chunk: &[u8]) -> Result<Vec<PangeaOrderEvent>, Error> {
+    let reader = StreamReader::try_new(Cursor::new(chunk), None)?;
+    let mut events = vec![];
+    for batch in reader {
+        events.extend(read_batch(&batch?)?);
+    }
+    Ok(events)
+}
+
+fn read_batch(batch: &RecordBatch) -> Result<Vec<PangeaOrderEvent>, Error> {
+    let text = |name| Column::cast(batch, name, &DataType::Utf8);
+    let (chain, block_number, block_hash, transaction_hash) = (
+        Column::cast(batch, "chain", &DataType::UInt64)?,
+        Column::cast(batch, "block_number", &DataType::Int64)?,
+        text("block_hash")?,
+        text("transaction_hash")?,
+    );
+    let (transaction_index, log_index, market_id, order_id) = (
+        Column::cast(batch, "transaction_index", &DataType::UInt64)?,
+        Column::cast(batch, "log_index", &DataType::UInt64)?,
+        text("market_id")?,
+        text("order_id")?,
+    );
+    let (event_type, asset, amount, asset_type, order_type) = (
+        text("event_type")?,
+        text("asset")?,
+        text("amount")?,
+        text("asset_type")?,
+        text("order_type")?,
+    );
+    let (price, user, order_matcher, owner, limit_type) = (
+        text("price")?,
+        text("user")?,
+        text("order_matcher")?,
+        text("owner")?,
+        text("limit_type")?,
+    );
+    let block_timestamp = Column::cast(batch, "block_timestamp", &DataType::Int64)?;
+
+    (0..batch.num_rows())
+        .map(|row| {
+            Ok(PangeaOrderEvent {
+                chain: chain.required(row, Column::uint)?,
+                block_number: block_number.required(row, Column::int)?,
+                block_hash: block_hash.required(row, Column::text)?,
+                transaction_hash: transaction_hash.required(row, Column::text)?,
+                transaction_index: transaction_index.required(row, Column::uint)?,
+                log_index: log_index.required(row, Column::uint)?,
+                market_id: market_id.required(row, Column::text)?,
+                order_id: order_id.required(row, Column::text)?,
+                event_type: event_type.text(row),
+                asset: asset.text(row),
+                amount: amount.amount(row)?,
+                asset_type: asset_type.text(row),
+                order_type: order_type.text(row),
+                price: price.amount(row)?,
+                user: user.text(row),
+                order_matcher: order_matcher.text(row),
+                owner: owner.text(row),
+                limit_type: limit_type.text(row),
+                block_timestamp: block_timestamp.int(row),
+            })
+        })
+        .collect()
+}
+
+/// One column of a batch, cast to the type its field is read as.
+struct Column {
+    name: &'static str,
+    values: Option<ArrayRef>,
+}
+
+impl Column {
+    fn cast(batch: &RecordBatch, name: &'static str, data_type: &DataType) -> Result<Self, Error> {
+        let values = match batch.column_by_name(name) {
+            Some(column) => Some(cast(column, data_type)?),
+            None => None,
+        };
+        Ok(Column { name, values })
+    }
+
+    fn valid(&self, row: usize) -> Option<&ArrayRef> {
+        self.values.as_ref().filter(|values| values.is_valid(row))
+    }
+
+    fn text(&self, row: usize) -> Option<String> {
+        self.valid(row)
+            .map(|values| values.as_string::<i32>().value(row).to_owned())
+    }
+
+    fn uint(&self, row: usize) -> Option<u64> {
+        self.valid(row)
+            .map(|values| values.as_primitive::<UInt64Type>().value(row))
+    }
+
+    fn int(&self, row: usize) -> Option<i64> {
+        self.valid(row)
+            .map(|values| values.as_primitive::<Int64Type>().value(row))
+    }
+
+    /// A raw amount, which may not fit in 64 bits.
+    fn amount(&self, row: usize) -> Result<Option<u128>, Error> {
+        self.text(row)
+            .map(|amount| {
+                amount.parse().map_err(|_| {
+                    ArrowError::ParseError(format!("Invalid {} '{}'", self.name, amount)).into()
+                })
+            })
+            .transpose()
+    }
+
+    fn required<T>(&self, row: usize, read: fn(&Self, usize) -> Option<T>) -> Result<T, Error> {
+        read(self, row).ok_or_else(|| {
+            ArrowError::SchemaError(format!("Row {} has no {}", row, self.name)).into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::array::{Decimal128Array, Int64Array, StringArray, UInt64Array};
+    use arrow::ipc::writer::StreamWriter;
+    use std::sync::Arc;
+
+    fn event(
+        log_index: u64,
+        event_type: &str,
+        amount: u128,
+        block_timestamp: Option<i64>,
+    ) -> PangeaOrderEvent {
+        PangeaOrderEvent {
+            chain: 0,
+            block_number: 9_000_002,
+            block_hash: "0xb10c".to_owned(),
+            transaction_hash: "0x7a".to_owned(),
+            transaction_index: 1,
+            log_index,
+            market_id: "0x7f".to_owned(),
+            order_id: format!("0x{}", log_index),
+            event_type: Some(event_type.to_owned()),
+            asset: Some("0xa1".to_owned()),
+            amount: Some(amount),
+            asset_type: Some("Base".to_owned()),
+            order_type: Some("Sell".to_owned()),
+            price: Some(3_128_450_000_000),
+            user: Some("0x01".to_owned()),
+            order_matcher: None,
+            owner: Some("0x01".to_owned()),
+            limit_type: Some("GTC".to_owned()),
+            block_timestamp,
+        }
+    }
+
+    fn text(values: Vec<Option<&str>>) -> ArrayRef {
+        Arc::new(StringArray::from(values))
+    }
+
+    fn uint(values: Vec<u64>) -> ArrayRef {
+        Arc::new(UInt64Array::from(values))
+    }
+
+    fn int(values: Vec<Option<i64>>) -> ArrayRef {
+        Arc::new(Int64Array::from(values))
+    }
+
+    fn decimal(values: Vec<Option<u128>>) -> ArrayRef {
+        let values: Vec<Option<i128>> = values
+            .into_iter()
+            .map(|value| value.map(|value| value as i128))
+            .collect();
+        Arc::new(
+            Decimal128Array::from(values)
+                .with_precision_and_scale(38, 0)
+                .unwrap(),
+        )
+    }
+
+    /// Writes `events` as Pangea does, amounts as 128-bit decimals.
+    fn encode(events: &[PangeaOrderEvent]) -> Vec<u8> {
+        let column =
+            |read: fn(&PangeaOrderEvent) -> Option<&str>| text(events.iter().map(read).collect());
+        let batch = RecordBatch::try_from_iter_with_nullable([
+            (
+                "chain",
+                uint(events.iter().map(|e| e.chain).collect()),
+                false,
+            ),
+            (
+                "block_number",
+                int(events.iter().map(|e| Some(e.block_number)).collect()),
+                false,
+            ),
+            ("block_hash", column(|e| Some(e.block_hash.as_str())), false),
+            (
+                "transaction_hash",
+                column(|e| Some(e.transaction_hash.as_str())),
+                false,
+            ),
+            (
+                "transaction_index",
+                uint(events.iter().map(|e| e.transaction_index).collect()),
+                false,
+            ),
+            (
+                "log_index",
+                uint(events.iter().map(|e| e.log_index).collect()),
+                false,
+            ),
+            ("market_id", column(|e| Some(e.market_id.as_str())), false),
+            ("order_id", column(|e| Some(e.order_id.as_str())), false),
+            ("event_type", column(|e| e.event_type.as_deref()), true),
+            ("asset", column(|e| e.asset.as_deref()), true),
+            (
+                "amount",
+                decimal(events.iter().map(|e| e.amount).collect()),
+                true,
+            ),
+            ("asset_type", column(|e| e.asset_type.as_deref()), true),
+            ("order_type", column(|e| e.order_type.as_deref()), true),
+            (
+                "price",
+                decimal(events.iter().map(|e| e.price).collect()),
+                true,
+            ),
+            ("user", column(|e| e.user.as_deref()), true),
+            (
+                "order_matcher",
+                column(|e| e.order_matcher.as_deref()),
+                true,
+            ),
+            ("owner", column(|e| e.owner.as_deref()), true),
+            ("limit_type", column(|e| e.limit_type.as_deref()), true),
+            (
+                "block_timestamp",
+                int(events.iter().map(|e| e.block_timestamp).collect()),
+                true,
+            ),
+        ])
+        .unwrap();
+
+        let mut writer = StreamWriter::try_new(Vec::new(), &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.into_inner().unwrap()
+    }
+
+    #[test]
+    fn events_survive_a_round_trip() {
+        let events = vec![
+            event(
+                0,
+                "Open",
+                25_000_000_000_000_000_000_000,
+                Some(1_718_000_000),
+            ),
+            event(1, "Trade", 1_500_000, None),
+        ];
+        let decoded = decode_order_events(&encode(&events)).unwrap();
+        assert_eq!(
+            serde_json::to_string(&decoded).unwrap(),
+            serde_json::to_string(&events).unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_optional_columns_read_as_null() {
+        let batch = RecordBatch::try_from_iter([
+            ("chain", uint(vec![0])),
+            ("block_number", int(vec![Some(7)])),
+            ("block_hash", text(vec![Some("0xb10c")])),
+            ("transaction_hash", text(vec![Some("0x7a")])),
+            ("transaction_index", uint(vec![0])),
+            ("log_index", uint(vec![0])),
+            ("market_id", text(vec![Some("0x7f")])),
+            ("order_id", text(vec![Some("0x1")])),
+            ("amount", text(vec![Some("25000000000000000000000")])),
+        ])
+        .unwrap();
+        let events = read_batch(&batch).unwrap();
+        assert_eq!(events[0].amount, Some(25_000_000_000_000_000_000_000));
+        assert_eq!(events[0].block_timestamp, None);
+        assert_eq!(events[0].event_type, None);
+    }
+
+    #[test]
+    fn rejects_rows_without_required_fields() {
+        let batch = RecordBatch::try_from_iter([("chain", uint(vec![0]))]).unwrap();
+        assert!(read_batch(&batch).is_err());
+    }
+}
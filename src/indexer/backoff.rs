@@ -0,0 +1,71 @@
+use chrono::Utc;
+use std::time::Duration;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+
+const DEFAULT_RECONNECT_BASE_MS: u64 = 500;
+const DEFAULT_RECONNECT_MAX_MS: u64 = 30_000;
+
+/// Delays between reconnect attempts to Pangea.
+///
+/// The delay doubles from `RECONNECT_BASE_MS` (default 500) up to
+/// `RECONNECT_MAX_MS` (default 30000) with every consecutive failure, and up
+/// to half of it again is added at random so markets do not reconnect in
+/// lockstep. After `RECONNECT_MAX_ATTEMPTS` consecutive failures the indexer
+/// gives up; unset means retry forever.
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    max_attempts: Option<u32>,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn from_env() -> Result<Self, Error> {
+        let millis = |key: &str, default: u64| -> Result<Duration, Error> {
+            Ok(Duration::from_millis(match ev_opt(key) {
+                Some(ms) => ms.parse()?,
+                None => default,
+            }))
+        };
+        Ok(Backoff {
+            base: millis("RECONNECT_BASE_MS", DEFAULT_RECONNECT_BASE_MS)?,
+            max: millis("RECONNECT_MAX_MS", DEFAULT_RECONNECT_MAX_MS)?,
+            max_attempts: ev_opt("RECONNECT_MAX_ATTEMPTS")
+                .map(|attempts| attempts.parse())
+                .transpose()?,
+            attempt: 0,
+        })
+    }
+
+    /// Consecutive failed attempts so far.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// The delay before the next attempt, or `None` once the attempts are
+    /// used up.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self
+            .max_attempts
+            .is_some_and(|max_attempts| self.attempt >= max_attempts)
+        {
+            return None;
+        }
+        let delay = self
+            .base
+            .saturating_mul(1 << self.attempt.min(16))
+            .min(self.max);
+        self.attempt += 1;
+
+        let jitter_range = delay.as_millis() as u64 / 2 + 1;
+        let jitter = Utc::now().timestamp_subsec_nanos() as u64 % jitter_range;
+        Some(delay + Duration::from_millis(jitter))
+    }
+
+    /// Called once a connection delivers data again.
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
@@ -0,0 +1,89 @@
+use log::warn;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::indexer::fuel_node::FuelNodeClient;
+use crate::indexer::order_event_handler::PangeaOrderEvent;
+
+const MAX_CACHED_BLOCKS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockMetadata {
+    pub block_hash: String,
+    /// Unix seconds.
+    pub timestamp: Option<i64>,
+}
+
+/// Chain time for processed blocks, so storage relies on when a block was
+/// produced rather than on whatever the event payload happened to carry.
+///
+/// A timestamp reported by Pangea is cached as is. For blocks without one,
+/// the Fuel node at `FUEL_NODE_URL` is asked for the block header, when
+/// configured.
+pub struct BlockMetadataCache {
+    node: Option<FuelNodeClient>,
+    blocks: RwLock<BTreeMap<i64, BlockMetadata>>,
+}
+
+impl BlockMetadataCache {
+    pub fn new(node: Option<FuelNodeClient>) -> Self {
+        BlockMetadataCache {
+            node,
+            blocks: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(FuelNodeClient::from_env())
+    }
+
+    pub fn get(&self, block_number: i64) -> Option<BlockMetadata> {
+        self.blocks.read().unwrap().get(&block_number).cloned()
+    }
+
+    /// Fills in `block_timestamp` from the cache or the node when the event
+    /// came without one.
+    pub async fn enrich(&self, event: &mut PangeaOrderEvent) {
+        if let Some(cached) = self.get(event.block_number) {
+            if cached.block_hash == event.block_hash {
+                event.block_timestamp = event.block_timestamp.or(cached.timestamp);
+                return;
+            }
+            warn!(
+                "Block {} hash changed from {} to {}, refetching metadata",
+                event.block_number, cached.block_hash, event.block_hash
+            );
+        }
+
+        let timestamp = match event.block_timestamp {
+            Some(timestamp) => Some(timestamp),
+            None => self.fetch_timestamp(event.block_number).await,
+        };
+        event.block_timestamp = timestamp;
+        self.insert(
+            event.block_number,
+            BlockMetadata {
+                block_hash: event.block_hash.clone(),
+                timestamp,
+            },
+        );
+    }
+
+    fn insert(&self, block_number: i64, metadata: BlockMetadata) {
+        let mut blocks = self.blocks.write().unwrap();
+        blocks.insert(block_number, metadata);
+        while blocks.len() > MAX_CACHED_BLOCKS {
+            blocks.pop_first();
+        }
+    }
+
+    async fn fetch_timestamp(&self, block_number: i64) -> Option<i64> {
+        match self.node.as_ref()?.block(block_number).await {
+            Ok(header) => header?.timestamp,
+            Err(e) => {
+                warn!("Failed to fetch block {} from node: {}", block_number, e);
+                None
+            }
+        }
+    }
+}
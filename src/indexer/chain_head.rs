@@ -0,0 +1,61 @@
+use chrono::Utc;
+use log::{info, warn};
+use std::sync::Arc;
+use tokio::time::{self, Duration};
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::fuel_node::FuelNodeClient;
+use crate::indexer::status::ChainHead;
+use crate::storage::market_registry::MarketRegistry;
+
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 5;
+
+/// Polls the Fuel node for the latest block so lag is known even when the
+/// market produces no events, every `CHAIN_HEAD_POLL_INTERVAL_SECS`
+/// (default 5, at least 1). The head is shared by every market. Does
+/// nothing unless `FUEL_NODE_URL` is set.
+pub fn initialize_chain_head_tracker(
+    tasks: &mut Vec<tokio::task::JoinHandle<()>>,
+    markets: Arc<MarketRegistry>,
+) -> Result<(), Error> {
+    let Some(node) = FuelNodeClient::from_env() else {
+        info!("FUEL_NODE_URL not set, chain head tracking disabled");
+        return Ok(());
+    };
+    let interval = match ev_opt("CHAIN_HEAD_POLL_INTERVAL_SECS") {
+        Some(value) => match value.parse()? {
+            0 => {
+                return Err(Error::InvalidEnvValue(
+                    "CHAIN_HEAD_POLL_INTERVAL_SECS".to_owned(),
+                    value,
+                ))
+            }
+            secs => Duration::from_secs(secs),
+        },
+        None => Duration::from_secs(DEFAULT_POLL_INTERVAL_SECS),
+    };
+
+    tasks.push(tokio::spawn(track_chain_head(node, markets, interval)));
+    Ok(())
+}
+
+async fn track_chain_head(node: FuelNodeClient, markets: Arc<MarketRegistry>, interval: Duration) {
+    let mut ticker = time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match node.latest_block().await {
+            Ok(header) => {
+                let head = ChainHead {
+                    block_number: header.height,
+                    block_timestamp: header.timestamp,
+                    observed_at: Utc::now().timestamp_millis() as u64,
+                };
+                for market in markets.all() {
+                    market.status.set_chain_head(head);
+                }
+            }
+            Err(e) => warn!("Failed to poll chain head: {}", e),
+        }
+    }
+}
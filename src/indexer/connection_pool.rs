@@ -0,0 +1,198 @@
+use log::warn;
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+
+use crate::indexer::pangea::PangeaClient;
+use crate::indexer::pangea_credentials::PangeaCredentials;
+
+/// What a connection was opened with. Only connections with the same key
+/// are shared.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ConnectionKey {
+    pub url: String,
+    pub http: bool,
+    pub credentials: PangeaCredentials,
+}
+
+struct Pooled {
+    key: ConnectionKey,
+    client: Weak<PangeaClient>,
+}
+
+/// Counts for the connections currently open.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoolStats {
+    pub connections: usize,
+    /// Distinct logins among them; more than one while markets move over to
+    /// rotated credentials.
+    pub credentials: usize,
+    /// Markets and tools holding a connection.
+    pub holders: usize,
+}
+
+/// The Pangea connections open in this process, shared between markets so
+/// their backfills and delta streams multiplex over a bounded number of
+/// connections instead of one each.
+///
+/// A connection stays open while anything holds it. Connections with
+/// replaced credentials don't count toward the limit; they close once the
+/// last market on them has reconnected. A connection that failed is
+/// evicted, so nobody else is handed it.
+#[derive(Default)]
+pub struct ConnectionPool {
+    connections: Mutex<PoolState>,
+    /// Signalled whenever an opening connection is done, successfully or
+    /// not.
+    opened: tokio::sync::Notify,
+}
+
+#[derive(Default)]
+struct PoolState {
+    pooled: Vec<Pooled>,
+    /// Connections being opened. They count toward the limit, so markets
+    /// starting together don't all open one, without holding a lock while
+    /// connecting.
+    opening: Vec<ConnectionKey>,
+}
+
+impl PoolState {
+    fn open_with(&self, key: &ConnectionKey) -> usize {
+        self.pooled
+            .iter()
+            .map(|pooled| &pooled.key)
+            .chain(&self.opening)
+            .filter(|open| open.credentials == key.credentials)
+            .count()
+    }
+
+    fn least_used(&self, key: &ConnectionKey) -> Option<Arc<PangeaClient>> {
+        self.pooled
+            .iter()
+            .filter(|pooled| pooled.key == *key)
+            .filter_map(|pooled| pooled.client.upgrade())
+            .min_by_key(Arc::strong_count)
+    }
+}
+
+/// Takes a reserved slot out of the opening list however connecting ends,
+/// including when the acquiring task is cancelled.
+struct Opening<'a> {
+    pool: &'a ConnectionPool,
+    key: ConnectionKey,
+}
+
+impl Drop for Opening<'_> {
+    fn drop(&mut self) {
+        let mut state = self.pool.connections.lock().unwrap();
+        if let Some(index) = state.opening.iter().position(|key| *key == self.key) {
+            state.opening.swap_remove(index);
+        }
+        drop(state);
+        self.pool.opened.notify_waiters();
+    }
+}
+
+static POOL: OnceLock<ConnectionPool> = OnceLock::new();
+
+pub fn connection_pool() -> &'static ConnectionPool {
+    POOL.get_or_init(ConnectionPool::default)
+}
+
+impl ConnectionPool {
+    /// A connection for `key`: a new one while fewer than `max` are open
+    /// with its credentials, otherwise the least used one with the same
+    /// key. When none matches, as after failing over to another endpoint,
+    /// one is opened beyond the limit.
+    pub async fn acquire<F, Fut, E>(
+        &self,
+        key: ConnectionKey,
+        max: usize,
+        connect: F,
+    ) -> Result<Arc<PangeaClient>, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<PangeaClient, E>>,
+    {
+        let (open, opening) = loop {
+            let opened = self.opened.notified();
+            let mut state = self.connections.lock().unwrap();
+            state
+                .pooled
+                .retain(|pooled| pooled.client.strong_count() > 0);
+            let open = state.open_with(&key);
+            if open < max {
+                break (open, self.reserve(&mut state, &key));
+            }
+            if let Some(client) = state.least_used(&key) {
+                return Ok(client);
+            }
+            if !state.opening.contains(&key) {
+                break (open, self.reserve(&mut state, &key));
+            }
+            // Another market is opening the connection this one would
+            // share; wait for it rather than open one more.
+            drop(state);
+            opened.await;
+        };
+        if open >= max {
+            warn!(
+                "Opening Pangea connection {} to {}, beyond PANGEA_MAX_CONNECTIONS ({})",
+                open + 1,
+                key.url,
+                max
+            );
+        }
+
+        let client = Arc::new(connect().await?);
+        self.connections.lock().unwrap().pooled.push(Pooled {
+            key,
+            client: Arc::downgrade(&client),
+        });
+        drop(opening);
+        Ok(client)
+    }
+
+    fn reserve(&self, state: &mut PoolState, key: &ConnectionKey) -> Opening<'_> {
+        state.opening.push(key.clone());
+        Opening {
+            pool: self,
+            key: key.clone(),
+        }
+    }
+
+    /// Stops handing out `client` after it failed. Holders keep it until
+    /// they reconnect; the next `acquire` opens a fresh connection or
+    /// shares a healthy one.
+    pub fn evict(&self, client: &Arc<PangeaClient>) {
+        let mut state = self.connections.lock().unwrap();
+        let before = state.pooled.len();
+        state.pooled.retain(|pooled| {
+            pooled
+                .client
+                .upgrade()
+                .is_some_and(|pooled| !Arc::ptr_eq(&pooled, client))
+        });
+        if state.pooled.len() < before {
+            warn!("Evicted a failed Pangea connection from the pool");
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let state = self.connections.lock().unwrap();
+        let live: Vec<&Pooled> = state
+            .pooled
+            .iter()
+            .filter(|pooled| pooled.client.strong_count() > 0)
+            .collect();
+        PoolStats {
+            connections: live.len(),
+            credentials: live
+                .iter()
+                .map(|pooled| &pooled.key.credentials)
+                .collect::<HashSet<_>>()
+                .len(),
+            holders: live.iter().map(|pooled| pooled.client.strong_count()).sum(),
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use ethers_core::types::H256;
+use fuel_crypto::Hasher;
+use log::{info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::order_event_handler::EventIndex;
+use crate::indexer::pangea::{create_pangea_client, PangeaClient};
+use crate::indexer::replay::replay_range;
+use crate::storage::cold_storage::ColdTradeStore;
+use crate::storage::market_registry::MarketState;
+use crate::web::graphql::TradeOrderEvent;
+
+const DEFAULT_SAMPLE_SEGMENTS: usize = 3;
+const DEFAULT_SAMPLE_BLOCKS: usize = 10;
+const DEFAULT_MAX_DIVERGENT: usize = 0;
+
+/// Compares the most recently archived segments with the same blocks
+/// fetched from Pangea, so a corrupted or stale archive is caught before
+/// the service starts answering history queries from it.
+///
+/// Samples `STARTUP_CHECK_SEGMENTS` segments (default 3, `0` disables the
+/// check) and fails when more than `STARTUP_CHECK_MAX_DIVERGENT` trades
+/// (default 0) are missing, extra or different.
+pub async fn check_cold_storage(market_id: &str, cold_store: &ColdTradeStore) -> Result<(), Error> {
+    let sample = env_or("STARTUP_CHECK_SEGMENTS", DEFAULT_SAMPLE_SEGMENTS)?;
+    let segments = cold_store.recent_segments(sample)?;
+    if segments.is_empty() {
+        return Ok(());
+    }
+
+    let client = create_pangea_client().await?;
+    let contract_h256 = H256::from_str(market_id)?;
+
+    let mut divergent = 0;
+    for archived in segments {
+        divergent += compare_with_pangea(&client, contract_h256, archived, "archived").await?;
+    }
+    check_divergent(divergent)
+}
+
+/// Compares the trades of the state a market resumes from, its book
+/// rebuilt from the event log or else its checkpoint, with the same blocks
+/// fetched from Pangea, so the indexer doesn't carry on from a corrupted
+/// or stale book.
+///
+/// Samples the last `STARTUP_CHECK_BLOCKS` blocks with trades (default 10,
+/// `0` disables the check) and fails like [`check_cold_storage`].
+pub async fn check_restored_state(market: &MarketState) -> Result<(), Error> {
+    let sample = env_or("STARTUP_CHECK_BLOCKS", DEFAULT_SAMPLE_BLOCKS)?;
+    if sample == 0 {
+        return Ok(());
+    }
+    let (source, trades) = if market.status.last_processed_block() > 0 {
+        ("logged", market.order_book.get_trade_events())
+    } else {
+        let checkpoint = match &market.checkpoints {
+            Some(checkpoints) if !checkpoints.is_follower() => checkpoints.load().await?,
+            _ => None,
+        };
+        match checkpoint {
+            Some(checkpoint) => ("checkpointed", checkpoint.trades),
+            None => return Ok(()),
+        }
+    };
+
+    let blocks: BTreeSet<i64> = trades.iter().map(|trade| trade.block_number).collect();
+    let Some(&first_block) = blocks.iter().rev().take(sample).last() else {
+        return Ok(());
+    };
+    let recent = trades
+        .into_iter()
+        .filter(|trade| trade.block_number >= first_block)
+        .collect();
+
+    let client = create_pangea_client().await?;
+    let contract_h256 = H256::from_str(market.market_id())?;
+    check_divergent(compare_with_pangea(&client, contract_h256, recent, source).await?)
+}
+
+fn env_or(name: &str, default: usize) -> Result<usize, Error> {
+    Ok(match ev_opt(name) {
+        Some(value) => value.parse()?,
+        None => default,
+    })
+}
+
+fn check_divergent(divergent: usize) -> Result<(), Error> {
+    let max_divergent = env_or("STARTUP_CHECK_MAX_DIVERGENT", DEFAULT_MAX_DIVERGENT)?;
+    if divergent > max_divergent {
+        return Err(Error::ConsistencyCheckFailed(divergent, max_divergent));
+    }
+    Ok(())
+}
+
+/// Replays the blocks `local` spans from Pangea and counts the trades
+/// missing, extra or different on either side.
+async fn compare_with_pangea(
+    client: &PangeaClient,
+    contract_h256: H256,
+    mut local: Vec<TradeOrderEvent>,
+    source: &str,
+) -> Result<usize, Error> {
+    // Busted trades are not replayed.
+    local.retain(|trade| !trade.busted);
+    let (Some(first), Some(last)) = (
+        local.iter().map(TradeOrderEvent::index).min(),
+        local.iter().map(TradeOrderEvent::index).max(),
+    ) else {
+        return Ok(0);
+    };
+
+    let replayed: Vec<TradeOrderEvent> =
+        replay_range(client, contract_h256, first.block_number, last.block_number)
+            .await?
+            .get_trade_events()
+            .into_iter()
+            .filter(|trade| (first..=last).contains(&trade.index()))
+            .collect();
+
+    let (local_hash, replayed_hash) = (hash_trades(&local), hash_trades(&replayed));
+    if local_hash == replayed_hash {
+        info!(
+            "Blocks {}..={}: {} {} trades match Pangea, hash {}",
+            first.block_number,
+            last.block_number,
+            local.len(),
+            source,
+            local_hash
+        );
+        return Ok(0);
+    }
+
+    let mismatches = count_mismatches(&local, &replayed);
+    warn!(
+        "Blocks {}..={}: {} {} trades (hash {}) vs {} from Pangea (hash {}), {} diverge",
+        first.block_number,
+        last.block_number,
+        local.len(),
+        source,
+        local_hash,
+        replayed.len(),
+        replayed_hash,
+        mismatches
+    );
+    Ok(mismatches)
+}
+
+/// The fields both sides must agree on; timestamps are left out because a
+/// bare replay does not normalize them the way live indexing does.
+fn fingerprint(trade: &TradeOrderEvent) -> (&str, &str, &str) {
+    (&trade.id, &trade.trade_price, &trade.trade_size)
+}
+
+fn hash_trades(trades: &[TradeOrderEvent]) -> String {
+    let mut by_index: Vec<&TradeOrderEvent> = trades.iter().collect();
+    by_index.sort_by_key(|trade| trade.index());
+
+    let mut bytes = vec![];
+    for trade in by_index {
+        let index = trade.index();
+        bytes.extend_from_slice(&index.block_number.to_be_bytes());
+        bytes.extend_from_slice(&index.transaction_index.to_be_bytes());
+        bytes.extend_from_slice(&index.log_index.to_be_bytes());
+        let (id, price, size) = fingerprint(trade);
+        for field in [id, price, size] {
+            bytes.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(field.as_bytes());
+        }
+    }
+    hex::encode(Hasher::hash(&bytes))
+}
+
+fn count_mismatches(local: &[TradeOrderEvent], replayed: &[TradeOrderEvent]) -> usize {
+    let replayed: BTreeMap<EventIndex, &TradeOrderEvent> = replayed
+        .iter()
+        .map(|trade| (trade.index(), trade))
+        .collect();
+
+    let local_indexes: BTreeSet<EventIndex> = local.iter().map(TradeOrderEvent::index).collect();
+
+    let mut mismatches = replayed
+        .keys()
+        .filter(|index| !local_indexes.contains(index))
+        .count();
+    for trade in local {
+        match replayed.get(&trade.index()) {
+            Some(other) if fingerprint(other) == fingerprint(trade) => {}
+            _ => mismatches += 1,
+        }
+    }
+    mismatches
+}
@@ -0,0 +1,375 @@
+use chrono::Utc;
+use log::info;
+use pangea_client::futures::stream::{self, BoxStream};
+use pangea_client::futures::StreamExt;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::order_event_handler::PangeaOrderEvent;
+use crate::indexer::spot_order::OrderType;
+use crate::storage::backend::Storage;
+use crate::storage::market_registry::{contract_ids, MarketState};
+
+/// Id of the market served in dev mode when `CONTRACT_ID` is unset.
+pub const DEV_MARKET_ID: &str =
+    "0xdededededededededededededededededededededededededededededededede";
+const BASE_ASSET: &str = "0xbabababababababababababababababababababababababababababababababa";
+const DEFAULT_EVENTS_PER_SECOND: u32 = 5;
+const DEFAULT_MID_PRICE: u128 = 3_000_000_000_000;
+const DEFAULT_TICK_SIZE: u128 = 10_000_000;
+const DEFAULT_TRADERS: usize = 10;
+/// Order sizes are whole multiples of this, up to 100 lots.
+const LOT_SIZE: u128 = 1_000_000;
+/// How far from the mid makers quote, in ticks.
+const MAX_QUOTE_TICKS: u128 = 10;
+
+/// Settings of the synthetic markets `--dev` runs in place of Pangea.
+pub struct DevMarketConfig {
+    events_per_second: u32,
+    mid_price: u128,
+    tick_size: u128,
+    traders: usize,
+    seed: u64,
+}
+
+impl DevMarketConfig {
+    /// `DEV_EVENTS_PER_SECOND` (default 5), `DEV_MID_PRICE` (default 3000
+    /// with 9 decimals), `DEV_TICK_SIZE` (default 0.01), `DEV_TRADERS`
+    /// (default 10) and `DEV_SEED`, without which every run differs.
+    pub fn from_env() -> Result<Self, Error> {
+        Ok(DevMarketConfig {
+            events_per_second: match ev_opt("DEV_EVENTS_PER_SECOND") {
+                Some(rate) => rate.parse()?,
+                None => DEFAULT_EVENTS_PER_SECOND,
+            },
+            mid_price: match ev_opt("DEV_MID_PRICE") {
+                Some(price) => price.parse()?,
+                None => DEFAULT_MID_PRICE,
+            },
+            tick_size: match ev_opt("DEV_TICK_SIZE") {
+                Some(tick) => tick.parse::<u128>()?.max(1),
+                None => DEFAULT_TICK_SIZE,
+            },
+            traders: match ev_opt("DEV_TRADERS") {
+                Some(traders) => traders.parse::<usize>()?.max(2),
+                None => DEFAULT_TRADERS,
+            },
+            seed: match ev_opt("DEV_SEED") {
+                Some(seed) => seed.parse()?,
+                None => uuid::Uuid::new_v4().as_u128() as u64,
+            },
+        })
+    }
+}
+
+static DEV_MODE: OnceLock<DevMarketConfig> = OnceLock::new();
+
+/// Switches the process to synthetic markets. Must be called at startup,
+/// before any market starts.
+pub fn enable_dev_mode() -> Result<(), Error> {
+    let config = DevMarketConfig::from_env()?;
+    info!(
+        "Dev mode: synthetic markets at {} events per second, seed {}",
+        config.events_per_second, config.seed
+    );
+    let _ = DEV_MODE.set(config);
+    Ok(())
+}
+
+pub fn dev_mode() -> Option<&'static DevMarketConfig> {
+    DEV_MODE.get()
+}
+
+/// `CONTRACT_ID` if set, else [`DEV_MARKET_ID`].
+pub fn dev_market_ids() -> Result<Vec<String>, Error> {
+    match ev_opt("CONTRACT_ID") {
+        Some(_) => contract_ids(),
+        None => Ok(vec![DEV_MARKET_ID.to_owned()]),
+    }
+}
+
+/// A synthetic market read in place of Pangea's stream: random makers
+/// quote around a drifting mid price, takers fill the best quotes and some
+/// orders are cancelled, one block per second. The indexer reads it like
+/// any Pangea connection, so the whole API serves it as it would a live
+/// market.
+pub struct DevFeed {
+    order_book: Arc<dyn Storage>,
+    generator: Mutex<Generator>,
+}
+
+impl DevFeed {
+    pub fn new(market: &MarketState, config: &'static DevMarketConfig) -> Self {
+        DevFeed {
+            order_book: Arc::clone(&market.order_book),
+            generator: Mutex::new(Generator::new(market.market_id(), config)),
+        }
+    }
+
+    /// The market's events in the blocks after `from_block`, one JSON
+    /// document each, as Pangea's delta stream sends them. The market has no
+    /// history, so a bounded request finds nothing.
+    pub fn spark_orders(
+        &self,
+        from_block: i64,
+        deltas: bool,
+    ) -> BoxStream<'_, Result<Vec<u8>, Error>> {
+        if !deltas {
+            return stream::empty().boxed();
+        }
+        let ticker = tokio::time::interval(Duration::from_secs(1));
+        stream::unfold(ticker, move |mut ticker| async move {
+            ticker.tick().await;
+            Some((stream::iter(self.next_block(from_block)), ticker))
+        })
+        .flatten()
+        .boxed()
+    }
+
+    /// The events of the next block, generated from the book as the
+    /// previous block left it.
+    fn next_block(&self, from_block: i64) -> Vec<Result<Vec<u8>, Error>> {
+        let mut generator = self.generator.lock().unwrap();
+        generator.next_block(from_block);
+        let mut events = vec![];
+        for _ in 0..generator.config.events_per_second {
+            events.extend(generator.next_action(self.order_book.as_ref()));
+        }
+        events
+            .iter()
+            .map(|event| serde_json::to_vec(event).map_err(Error::from))
+            .collect()
+    }
+}
+
+struct Generator {
+    market_id: String,
+    config: &'static DevMarketConfig,
+    rng: XorShift,
+    mid: u128,
+    traders: Vec<String>,
+    block_number: i64,
+    block_hash: String,
+    transaction_index: u64,
+    /// Orders filled or cancelled in the current block, which the book
+    /// still shows until the block is applied.
+    touched: HashSet<String>,
+}
+
+impl Generator {
+    fn new(market_id: &str, config: &'static DevMarketConfig) -> Self {
+        // Markets started with the same seed still trade differently.
+        let market_seed = market_id
+            .bytes()
+            .fold(config.seed, |seed, byte| seed.rotate_left(5) ^ byte as u64);
+        let mut rng = XorShift::new(market_seed);
+        let traders = (0..config.traders).map(|_| rng.hex_id()).collect();
+        Generator {
+            market_id: market_id.to_owned(),
+            config,
+            rng,
+            mid: config.mid_price,
+            traders,
+            block_number: 0,
+            block_hash: String::new(),
+            transaction_index: 0,
+            touched: HashSet::new(),
+        }
+    }
+
+    /// Starts a block past both the last one generated and `from_block`.
+    fn next_block(&mut self, from_block: i64) {
+        self.block_number = self.block_number.max(from_block) + 1;
+        self.block_hash = self.rng.hex_id();
+        self.transaction_index = 0;
+        self.touched.clear();
+        let tick = self.config.tick_size;
+        self.mid = match self.rng.below(3) {
+            0 => self
+                .mid
+                .saturating_sub(tick)
+                .max(tick * (MAX_QUOTE_TICKS + 1)),
+            1 => self.mid + tick,
+            _ => self.mid,
+        };
+    }
+
+    /// The events of one transaction: usually a new quote, otherwise a fill
+    /// of the best opposite quote or a cancellation, falling back to a new
+    /// quote when the book has nothing to fill or cancel.
+    fn next_action(&mut self, order_book: &dyn Storage) -> Vec<PangeaOrderEvent> {
+        let order_type = if self.rng.below(2) == 0 {
+            OrderType::Buy
+        } else {
+            OrderType::Sell
+        };
+        let events = match self.rng.below(100) {
+            0..=54 => vec![],
+            55..=84 => self.take(order_book, order_type),
+            _ => self.cancel(order_book, order_type),
+        };
+        let events = if events.is_empty() {
+            vec![self.open(order_book, order_type)]
+        } else {
+            events
+        };
+        self.transaction_index += 1;
+        events
+    }
+
+    /// A resting order a few ticks from the mid, never crossing the book.
+    fn open(&mut self, order_book: &dyn Storage, order_type: OrderType) -> PangeaOrderEvent {
+        let tick = self.config.tick_size;
+        let offset = tick * (1 + self.rng.below(MAX_QUOTE_TICKS as u64) as u128);
+        let price = match order_type {
+            OrderType::Buy => {
+                let price = self.mid.saturating_sub(offset);
+                match order_book.best_ask() {
+                    Some(ask) => price.min(ask.saturating_sub(tick)),
+                    None => price,
+                }
+            }
+            OrderType::Sell => {
+                let price = self.mid + offset;
+                match order_book.best_bid() {
+                    Some(bid) => price.max(bid + tick),
+                    None => price,
+                }
+            }
+        };
+        let order_id = self.rng.hex_id();
+        let mut event = self.event("Open", order_id, 0);
+        event.user = Some(self.trader());
+        event.asset = Some(BASE_ASSET.to_owned());
+        event.amount = Some(self.size());
+        event.order_type = Some(format!("{:?}", order_type));
+        event.limit_type = Some("GTC".to_owned());
+        event.price = Some(price);
+        event
+    }
+
+    /// A taker on `order_type`'s side filling part or all of the first
+    /// order at the best opposite price, reported as the maker's fill and
+    /// then the taker's.
+    fn take(&mut self, order_book: &dyn Storage, order_type: OrderType) -> Vec<PangeaOrderEvent> {
+        let (best, maker_side) = match order_type {
+            OrderType::Buy => (order_book.best_ask(), OrderType::Sell),
+            OrderType::Sell => (order_book.best_bid(), OrderType::Buy),
+        };
+        let Some(maker) = best.and_then(|price| {
+            order_book
+                .get_range(price, price, maker_side)
+                .into_iter()
+                .find(|order| !self.touched.contains(&order.id))
+        }) else {
+            return vec![];
+        };
+        self.touched.insert(maker.id.clone());
+        let size = self.size().min(maker.amount);
+        let taker = self.trader_other_than(&maker.user);
+
+        let mut maker_fill = self.event("Trade", maker.id.clone(), 0);
+        maker_fill.user = Some(maker.user.clone());
+        maker_fill.order_type = Some(format!("{:?}", maker_side));
+        maker_fill.limit_type = Some("GTC".to_owned());
+        let taker_order_id = self.rng.hex_id();
+        let mut taker_fill = self.event("Trade", taker_order_id, 1);
+        taker_fill.user = Some(taker);
+        taker_fill.order_type = Some(format!("{:?}", order_type));
+        taker_fill.limit_type = Some("IOC".to_owned());
+        for fill in [&mut maker_fill, &mut taker_fill] {
+            fill.asset = Some(BASE_ASSET.to_owned());
+            fill.amount = Some(size);
+            fill.price = Some(maker.price);
+        }
+        vec![maker_fill, taker_fill]
+    }
+
+    /// Cancels a random resting order on `order_type`'s side.
+    fn cancel(&mut self, order_book: &dyn Storage, order_type: OrderType) -> Vec<PangeaOrderEvent> {
+        let orders = order_book.get_range(0, u128::MAX, order_type);
+        if orders.is_empty() {
+            return vec![];
+        }
+        let order = &orders[self.rng.below(orders.len() as u64) as usize];
+        if !self.touched.insert(order.id.clone()) {
+            return vec![];
+        }
+        let mut event = self.event("Cancel", order.id.clone(), 0);
+        event.user = Some(order.user.clone());
+        event.order_type = Some(format!("{:?}", order_type));
+        vec![event]
+    }
+
+    fn event(&mut self, event_type: &str, order_id: String, log_index: u64) -> PangeaOrderEvent {
+        PangeaOrderEvent {
+            chain: 0,
+            block_number: self.block_number,
+            block_hash: self.block_hash.clone(),
+            transaction_hash: self.rng.hex_id(),
+            transaction_index: self.transaction_index,
+            log_index,
+            market_id: self.market_id.clone(),
+            order_id,
+            event_type: Some(event_type.to_owned()),
+            asset: None,
+            amount: None,
+            asset_type: None,
+            order_type: None,
+            price: None,
+            user: None,
+            order_matcher: None,
+            owner: None,
+            limit_type: None,
+            block_timestamp: Some(Utc::now().timestamp()),
+        }
+    }
+
+    fn trader(&mut self) -> String {
+        let index = self.rng.below(self.traders.len() as u64) as usize;
+        self.traders[index].clone()
+    }
+
+    fn trader_other_than(&mut self, user: &str) -> String {
+        loop {
+            let trader = self.trader();
+            if trader != user {
+                return trader;
+            }
+        }
+    }
+
+    fn size(&mut self) -> u128 {
+        LOT_SIZE * (1 + self.rng.below(100) as u128)
+    }
+}
+
+/// Enough randomness for made-up traffic, reproducible from a seed.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // Zero is the one state xorshift never leaves.
+        XorShift(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    /// A random 32 byte id in `0x` hex, like addresses and order ids.
+    fn hex_id(&mut self) -> String {
+        let bytes: Vec<u8> = (0..4).flat_map(|_| self.next().to_be_bytes()).collect();
+        format!("0x{}", hex::encode(bytes))
+    }
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::order_event_handler::PangeaOrderEvent;
+
+const DEFAULT_REORDER_WINDOW_MS: u64 = 200;
+
+/// Collects the events of the block being received and releases them in
+/// chain order, so events that arrive interleaved (a cancel ahead of the
+/// open it cancels) are still applied the way the chain executed them.
+///
+/// A block is released when an event from another block arrives, or once
+/// the stream has been quiet for `REORDER_WINDOW_MS` (default 200).
+pub struct BlockBuffer {
+    events: Vec<PangeaOrderEvent>,
+    window: Duration,
+}
+
+impl BlockBuffer {
+    pub fn from_env() -> Result<Self, Error> {
+        let window_ms = match ev_opt("REORDER_WINDOW_MS") {
+            Some(ms) => ms.parse()?,
+            None => DEFAULT_REORDER_WINDOW_MS,
+        };
+        Ok(BlockBuffer {
+            events: vec![],
+            window: Duration::from_millis(window_ms),
+        })
+    }
+
+    /// How long to wait for more events before releasing the block.
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Buffers `event`. Returns the previously buffered block, sorted, if
+    /// `event` belongs to a different one.
+    pub fn push(&mut self, event: PangeaOrderEvent) -> Vec<PangeaOrderEvent> {
+        let released = match self.events.first() {
+            Some(first) if first.block_number != event.block_number => self.flush(),
+            _ => vec![],
+        };
+        self.events.push(event);
+        released
+    }
+
+    /// Takes every buffered event, sorted by position.
+    pub fn flush(&mut self) -> Vec<PangeaOrderEvent> {
+        let mut events = std::mem::take(&mut self.events);
+        events.sort_by_key(|event| event.index());
+        events
+    }
+}
@@ -0,0 +1,120 @@
+use ethers_core::types::H256;
+use log::info;
+use pangea_client::{futures::StreamExt, query::Bound, requests::fuel::GetSparkOrderRequest};
+use serde::Serialize;
+use serde_json::value::{to_raw_value, RawValue};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::error::Error;
+use crate::indexer::order_event_handler::PangeaOrderEvent;
+use crate::indexer::pangea::create_pangea_client;
+
+/// Writes the first `per_type` events of every event type in
+/// `[from_block, to_block]` to `out` as a JSON array, in chain order, for
+/// the handler tests to load. With `raw`, the payloads are written as
+/// Pangea sent them instead, as a corpus for `check-payloads`.
+///
+/// Trader addresses (`user`, `owner`, `order_matcher`) are replaced by
+/// sequential placeholders in order of first appearance, so the same range
+/// always produces the same file and an address keeps its placeholder
+/// across the events it appears in.
+pub async fn generate_fixtures(
+    contract_id: &str,
+    from_block: i64,
+    to_block: i64,
+    per_type: usize,
+    out: &Path,
+    raw: bool,
+) -> Result<(), Error> {
+    let client = create_pangea_client().await?;
+    let request = GetSparkOrderRequest {
+        from_block: Bound::Exact(from_block),
+        to_block: Bound::Exact(to_block),
+        market_id__in: HashSet::from([H256::from_str(contract_id)?]),
+        ..Default::default()
+    };
+    let mut stream = client.spark_orders(request, false).await?;
+
+    let mut events = vec![];
+    while let Some(data) = stream.next().await {
+        let data = data?;
+        let payload: BTreeMap<String, Box<RawValue>> = serde_json::from_slice(&data)?;
+        events.push((PangeaOrderEvent::from_json(data)?, payload));
+    }
+    events.sort_by_key(|(event, _)| event.index());
+
+    let mut taken: BTreeMap<String, usize> = BTreeMap::new();
+    let mut anonymizer = Anonymizer::default();
+    let mut fixtures = vec![];
+    for (mut event, mut payload) in events {
+        let event_type = event.event_type.clone().unwrap_or_default();
+        let count = taken.entry(event_type).or_default();
+        if *count == per_type {
+            continue;
+        }
+        *count += 1;
+        for address in [&mut event.user, &mut event.owner, &mut event.order_matcher]
+            .into_iter()
+            .flatten()
+        {
+            *address = anonymizer.placeholder(address);
+        }
+        for field in ["user", "owner", "order_matcher"] {
+            let Some(value) = payload.get_mut(field) else {
+                continue;
+            };
+            if let Ok(address) = serde_json::from_str::<String>(value.get()) {
+                *value = to_raw_value(&anonymizer.placeholder(&address))?;
+            }
+        }
+        fixtures.push(if raw {
+            Fixture::Raw(payload)
+        } else {
+            Fixture::Event(event)
+        });
+    }
+
+    if let Some(dir) = out.parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::FileError(dir.display().to_string(), e))?;
+    }
+    fs::write(out, serde_json::to_vec_pretty(&fixtures)?)
+        .map_err(|e| Error::FileError(out.display().to_string(), e))?;
+    info!(
+        "Wrote {} fixtures to {} ({})",
+        fixtures.len(),
+        out.display(),
+        taken
+            .iter()
+            .map(|(event_type, count)| format!("{} {}", count, event_type))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(())
+}
+
+/// A payload is kept as raw JSON text, so amounts past `u64::MAX` are
+/// written exactly as Pangea sent them.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Fixture {
+    Raw(BTreeMap<String, Box<RawValue>>),
+    Event(PangeaOrderEvent),
+}
+
+#[derive(Default)]
+struct Anonymizer {
+    placeholders: HashMap<String, String>,
+}
+
+impl Anonymizer {
+    fn placeholder(&mut self, address: &str) -> String {
+        let next = self.placeholders.len() + 1;
+        self.placeholders
+            .entry(address.to_owned())
+            .or_insert_with(|| format!("0x{:064x}", next))
+            .clone()
+    }
+}
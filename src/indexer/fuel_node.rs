@@ -0,0 +1,66 @@
+use fuels::prelude::Provider;
+use fuels::types::block::Block;
+use tokio::sync::OnceCell;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub height: i64,
+    /// Unix seconds.
+    pub timestamp: Option<i64>,
+}
+
+impl From<&Block> for BlockHeader {
+    fn from(block: &Block) -> Self {
+        BlockHeader {
+            height: block.header.height as i64,
+            timestamp: block.header.time.map(|time| time.timestamp()),
+        }
+    }
+}
+
+/// The Fuel node at `FUEL_NODE_URL`, reached through a fuels-rs provider
+/// that connects on first use.
+pub struct FuelNodeClient {
+    url: String,
+    provider: OnceCell<Provider>,
+}
+
+impl FuelNodeClient {
+    pub fn new(url: String) -> Self {
+        FuelNodeClient {
+            url,
+            provider: OnceCell::new(),
+        }
+    }
+
+    pub fn from_env() -> Option<Self> {
+        ev_opt("FUEL_NODE_URL").map(Self::new)
+    }
+
+    pub async fn provider(&self) -> Result<&Provider, Error> {
+        Ok(self
+            .provider
+            .get_or_try_init(|| Provider::connect(&self.url))
+            .await?)
+    }
+
+    pub async fn block(&self, height: i64) -> Result<Option<BlockHeader>, Error> {
+        let Ok(height) = u32::try_from(height) else {
+            return Ok(None);
+        };
+        let block = self
+            .provider()
+            .await?
+            .block_by_height(height.into())
+            .await?;
+        Ok(block.as_ref().map(BlockHeader::from))
+    }
+
+    pub async fn latest_block(&self) -> Result<BlockHeader, Error> {
+        let chain = self.provider().await?.chain_info().await?;
+        Ok(BlockHeader::from(&chain.latest_block))
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use tokio::time::{self, Duration};
+
+use crate::config::env::ev_opt;
+use crate::storage::market_registry::market_key;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct SwitchState {
+    pub indexing_halted: bool,
+    pub api_hidden: bool,
+}
+
+impl SwitchState {
+    fn update(&mut self, indexing_halted: Option<bool>, api_hidden: Option<bool>) {
+        if let Some(indexing_halted) = indexing_halted {
+            self.indexing_halted = indexing_halted;
+        }
+        if let Some(api_hidden) = api_hidden {
+            self.api_hidden = api_hidden;
+        }
+    }
+
+    fn or(self, other: SwitchState) -> SwitchState {
+        SwitchState {
+            indexing_halted: self.indexing_halted || other.indexing_halted,
+            api_hidden: self.api_hidden || other.api_hidden,
+        }
+    }
+}
+
+/// Which markets the public API serves. Unlisted markets keep indexing but
+/// are treated as hidden, e.g. test markets. Ids are kept as
+/// [`market_key`]s.
+#[derive(Debug, Default)]
+struct MarketListing {
+    /// When set, only these markets are listed.
+    allowlist: Option<HashSet<String>>,
+    unlisted: HashSet<String>,
+}
+
+impl MarketListing {
+    fn is_listed(&self, market_id: &str) -> bool {
+        let key = market_key(market_id);
+        !self.unlisted.contains(&key)
+            && self
+                .allowlist
+                .as_ref()
+                .is_none_or(|allowlist| allowlist.contains(&key))
+    }
+}
+
+fn market_list_from_env(key: &str) -> Option<HashSet<String>> {
+    ev_opt(key).map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|market_id| !market_id.is_empty())
+            .map(market_key)
+            .collect()
+    })
+}
+
+/// Admin toggles that pause indexing or hide API data, either for every
+/// market or for a single one. A market is affected if either its own switch
+/// or the global one is set. Market ids match ignoring case and a `0x`
+/// prefix, as in the market registry.
+///
+/// Maintenance mode is separate: indexing pauses at the next block boundary
+/// while the API keeps serving the paused state, flagged as frozen.
+///
+/// Listing is separate too: `PUBLIC_MARKETS` (an allowlist) and
+/// `HIDDEN_MARKETS` decide which markets are publicly queryable at all.
+#[derive(Default)]
+pub struct KillSwitches {
+    global: RwLock<SwitchState>,
+    markets: RwLock<HashMap<String, SwitchState>>,
+    maintenance: AtomicBool,
+    listing: RwLock<MarketListing>,
+}
+
+impl KillSwitches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_env() -> Self {
+        let kill_switches = Self::new();
+        if ev_opt("MAINTENANCE_MODE").as_deref() == Some("true") {
+            kill_switches.set_maintenance(true);
+        }
+        *kill_switches.listing.write().unwrap() = MarketListing {
+            allowlist: market_list_from_env("PUBLIC_MARKETS"),
+            unlisted: market_list_from_env("HIDDEN_MARKETS").unwrap_or_default(),
+        };
+        kill_switches
+    }
+
+    pub fn is_maintenance(&self) -> bool {
+        self.maintenance.load(Ordering::SeqCst)
+    }
+
+    pub fn set_maintenance(&self, enabled: bool) {
+        self.maintenance.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn global(&self) -> SwitchState {
+        *self.global.read().unwrap()
+    }
+
+    pub fn market(&self, market_id: &str) -> SwitchState {
+        self.markets
+            .read()
+            .unwrap()
+            .get(&market_key(market_id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Switches in force for `market_id`. An unlisted market is reported as
+    /// hidden.
+    pub fn effective(&self, market_id: &str) -> SwitchState {
+        let unlisted = SwitchState {
+            indexing_halted: false,
+            api_hidden: !self.is_listed(market_id),
+        };
+        self.global().or(self.market(market_id)).or(unlisted)
+    }
+
+    pub fn is_listed(&self, market_id: &str) -> bool {
+        self.listing.read().unwrap().is_listed(market_id)
+    }
+
+    pub fn set_listed(&self, market_id: &str, listed: bool) {
+        let key = market_key(market_id);
+        let mut listing = self.listing.write().unwrap();
+        if listed {
+            listing.unlisted.remove(&key);
+            if let Some(allowlist) = listing.allowlist.as_mut() {
+                allowlist.insert(key);
+            }
+        } else {
+            listing.unlisted.insert(key);
+        }
+    }
+
+    pub fn update_global(
+        &self,
+        indexing_halted: Option<bool>,
+        api_hidden: Option<bool>,
+    ) -> SwitchState {
+        let mut global = self.global.write().unwrap();
+        global.update(indexing_halted, api_hidden);
+        *global
+    }
+
+    pub fn update_market(
+        &self,
+        market_id: &str,
+        indexing_halted: Option<bool>,
+        api_hidden: Option<bool>,
+    ) -> SwitchState {
+        let mut markets = self.markets.write().unwrap();
+        let state = markets.entry(market_key(market_id)).or_default();
+        state.update(indexing_halted, api_hidden);
+        *state
+    }
+
+    /// Holds the caller while indexing is halted for `market_id`. Events are
+    /// left unread on the stream rather than dropped, so nothing is lost.
+    pub async fn wait_while_indexing_halted(&self, market_id: &str) {
+        while self.effective(market_id).indexing_halted {
+            time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    pub async fn wait_while_maintenance(&self) {
+        while self.is_maintenance() {
+            time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use log::{error, info};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::order_event_handler::handle_order_events;
+use crate::indexer::timestamp_normalizer::TimestampNormalizer;
+use crate::storage::backend::Storage;
+use crate::storage::event_log::EventLog;
+use crate::storage::market_registry::{MarketRegistry, MarketState};
+
+const DEFAULT_COMPACT_INTERVAL_SECS: u64 = 3600;
+
+/// Compacts `market_id`'s event log up to the newest segment older than
+/// `keep_blocks`: the previous snapshot and the logged events after it are
+/// replayed into a fresh book, which becomes the new snapshot. Orders
+/// filled or cancelled by then are gone from the book and so from the log;
+/// open orders and trades carry over. Returns the block
+/// compacted up to, or `None` when no segment was old enough.
+pub fn compact_event_log(
+    market_id: &str,
+    event_log: &EventLog,
+    keep_blocks: i64,
+) -> Result<Option<i64>, Error> {
+    let Some(until_block) = event_log.compactable_until(keep_blocks) else {
+        return Ok(None);
+    };
+
+    let market = MarketState::new(market_id.to_owned());
+    let registry = MarketRegistry::new(vec![market.clone()]);
+    let from_block = match event_log.snapshot(market_id)? {
+        Some(snapshot) => {
+            let from_block = snapshot.block_number + 1;
+            market.order_book.restore(snapshot);
+            from_block
+        }
+        None => i64::MIN,
+    };
+    let normalizer = TimestampNormalizer::from_env()?;
+    let events: Vec<_> = event_log
+        .read(from_block, until_block)?
+        .into_iter()
+        .map(|mut event| {
+            // The log also holds the events of markets that migrated here.
+            event.market_id = market_id.to_owned();
+            let time = normalizer.normalize(&event);
+            (event, time)
+        })
+        .collect();
+    let count = events.len();
+    handle_order_events(&registry, events);
+
+    let snapshot = market.order_book.snapshot(until_block);
+    let orders = snapshot.orders.len();
+    let segments = event_log.replace_with_snapshot(&snapshot)?;
+    info!(
+        "Compacted the event log of {} up to block {}: {} events in {} segments, {} orders still open",
+        market_id, until_block, count, segments, orders
+    );
+    Ok(Some(until_block))
+}
+
+/// With `EVENT_LOG_COMPACT_AFTER_BLOCKS` set, compacts the market's event
+/// log every `EVENT_LOG_COMPACT_INTERVAL_SECS` (default 3600), keeping the
+/// segments within that many blocks of the newest logged event as they are.
+/// Compaction reads and writes files and replays into a book, so it runs on
+/// the blocking pool.
+pub fn initialize_log_compaction(
+    tasks: &mut Vec<tokio::task::JoinHandle<()>>,
+    market: &MarketState,
+) -> Result<(), Error> {
+    let (Some(event_log), Some(keep_blocks)) = (
+        market.event_log.clone(),
+        ev_opt("EVENT_LOG_COMPACT_AFTER_BLOCKS"),
+    ) else {
+        return Ok(());
+    };
+    let keep_blocks = keep_blocks.parse::<i64>()?.max(1);
+    let interval = match ev_opt("EVENT_LOG_COMPACT_INTERVAL_SECS") {
+        Some(secs) => Duration::from_secs(secs.parse()?),
+        None => Duration::from_secs(DEFAULT_COMPACT_INTERVAL_SECS),
+    };
+    let market_id = market.market_id().to_owned();
+
+    tasks.push(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let compaction = {
+                let (market_id, event_log) = (market_id.clone(), Arc::clone(&event_log));
+                tokio::task::spawn_blocking(move || {
+                    compact_event_log(&market_id, &event_log, keep_blocks)
+                })
+            };
+            let compacted = match compaction.await {
+                Ok(compacted) => compacted,
+                Err(e) => Err(e.into()),
+            };
+            if let Err(e) = compacted {
+                error!("Failed to compact the event log of {}: {}", market_id, e);
+            }
+        }
+    }));
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use ethers_core::types::H256;
+use fuels::types::AssetId;
+use log::info;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use crate::config::env::{ev, ev_opt};
+use crate::error::Error;
+use crate::indexer::fuel_node::FuelNodeClient;
+use crate::indexer::spark_contracts::SparkContracts;
+use crate::storage::market_registry::contract_ids;
+
+/// `MARKET_ALLOWLIST` and `MARKET_DENYLIST`, applied to discovered markets.
+struct DiscoveryFilter {
+    /// When set, only these markets are indexed.
+    allowlist: Option<HashSet<H256>>,
+    denylist: HashSet<H256>,
+}
+
+impl DiscoveryFilter {
+    fn from_env() -> Result<Self, Error> {
+        Ok(DiscoveryFilter {
+            allowlist: id_set_from_env("MARKET_ALLOWLIST")?,
+            denylist: id_set_from_env("MARKET_DENYLIST")?.unwrap_or_default(),
+        })
+    }
+
+    fn accepts(&self, market: &H256) -> bool {
+        !self.denylist.contains(market)
+            && self
+                .allowlist
+                .as_ref()
+                .is_none_or(|allowlist| allowlist.contains(market))
+    }
+}
+
+fn id_set_from_env(key: &str) -> Result<Option<HashSet<H256>>, Error> {
+    ev_opt(key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(|id| H256::from_str(id).map_err(Error::from))
+                .collect()
+        })
+        .transpose()
+}
+
+/// Market ids to index. Without `MARKET_DISCOVERY=true` these are exactly
+/// `CONTRACT_ID`. With it, the markets Spark's market registry at
+/// `SPARK_REGISTRY_ID` holds for the assets of the `CONTRACT_ID` markets and
+/// those in `MARKET_DISCOVERY_ASSETS` are added after the `CONTRACT_ID`
+/// ones, minus anything rejected by `MARKET_ALLOWLIST`/`MARKET_DENYLIST`.
+/// The registry is read through the Fuel node at `FUEL_NODE_URL`.
+/// `CONTRACT_ID` markets are always kept, and the first of them stays the
+/// default market.
+///
+/// Discovery runs once at startup; markets registered later are picked up
+/// on the next restart.
+pub async fn resolve_market_ids() -> Result<Vec<String>, Error> {
+    if ev_opt("MARKET_DISCOVERY").as_deref() != Some("true") {
+        return contract_ids();
+    }
+
+    let mut market_ids = match ev_opt("CONTRACT_ID") {
+        Some(_) => contract_ids()?,
+        None => vec![],
+    };
+    let mut known: HashSet<H256> = market_ids
+        .iter()
+        .map(|market_id| H256::from_str(market_id))
+        .collect::<Result<_, _>>()?;
+
+    let filter = DiscoveryFilter::from_env()?;
+    for market in discover_markets(&known).await? {
+        if !filter.accepts(&market) {
+            info!("Skipping discovered market {:?}", market);
+            continue;
+        }
+        if known.insert(market) {
+            info!("Discovered market {:?}", market);
+            market_ids.push(format!("{:?}", market));
+        }
+    }
+
+    if market_ids.is_empty() {
+        return Err(Error::EnvVarError(
+            "CONTRACT_ID".to_owned(),
+            "no contract ids configured and none discovered".to_owned(),
+        ));
+    }
+    Ok(market_ids)
+}
+
+/// The registered market of every pair of assets traded by the `known`
+/// markets or listed in `MARKET_DISCOVERY_ASSETS`, either way round.
+async fn discover_markets(known: &HashSet<H256>) -> Result<Vec<H256>, Error> {
+    let Some(node) = FuelNodeClient::from_env() else {
+        return Err(Error::EnvVarError(
+            "FUEL_NODE_URL".to_owned(),
+            "required with MARKET_DISCOVERY=true".to_owned(),
+        ));
+    };
+    let registry = H256::from_str(&ev("SPARK_REGISTRY_ID")?)?;
+    let contracts = SparkContracts::connect(&node).await?;
+
+    let mut assets: Vec<AssetId> = id_set_from_env("MARKET_DISCOVERY_ASSETS")?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|asset| AssetId::new(asset.0))
+        .collect();
+    for market in known {
+        let market = contracts.market_assets(market).await?;
+        assets.extend([market.base_asset, market.quote_asset]);
+    }
+    assets.sort();
+    assets.dedup();
+
+    let pairs: Vec<(AssetId, AssetId)> = assets
+        .iter()
+        .flat_map(|base| {
+            assets
+                .iter()
+                .filter(move |quote| *quote != base)
+                .map(move |quote| (*base, *quote))
+        })
+        .collect();
+    info!(
+        "Discovering markets for {} asset pairs in registry {:?}...",
+        pairs.len(),
+        registry
+    );
+    let markets = contracts.registered_markets(&registry, pairs).await?;
+    info!("Discovered {} registered markets", markets.len());
+    Ok(markets)
+}
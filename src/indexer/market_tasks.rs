@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+use crate::alerts::engine::initialize_alerting;
+use crate::error::Error;
+use crate::indexer::dev_market::dev_mode;
+use crate::indexer::kill_switches::KillSwitches;
+use crate::indexer::log_compaction::initialize_log_compaction;
+use crate::indexer::pangea::initialize_pangea_indexer;
+use crate::storage::checkpoint::initialize_checkpoint_follower;
+use crate::storage::cold_storage::initialize_cold_storage;
+use crate::storage::daily_reports::initialize_daily_reports;
+use crate::storage::market_registry::{MarketRegistry, MarketState};
+use crate::storage::retention::initialize_retention;
+
+/// Starts everything that runs per market: the Pangea indexer, alerting,
+/// retention rebuilds and, when configured, archiving to cold storage,
+/// daily reports and event log compaction. A market following another
+/// instance's checkpoints runs only retention besides the follower, leaving
+/// the rest, alerting included, to that instance so every alert fires once.
+/// In dev mode the indexer reads a synthetic market, which sends no alerts
+/// and writes nothing. The tasks are pushed onto `tasks` and tracked in
+/// `registry`, so removing the market stops them.
+pub async fn start_market(
+    tasks: &mut Vec<JoinHandle<()>>,
+    registry: &Arc<MarketRegistry>,
+    market: MarketState,
+    kill_switches: &Arc<KillSwitches>,
+) -> Result<(), Error> {
+    let first = tasks.len();
+    let dev = dev_mode().is_some();
+    let follower = market
+        .checkpoints
+        .as_ref()
+        .filter(|checkpoints| !dev && checkpoints.is_follower());
+    match follower {
+        Some(checkpoints) => initialize_checkpoint_follower(
+            tasks,
+            Arc::clone(&market.order_book),
+            Arc::clone(&market.status),
+            Arc::clone(checkpoints),
+        )?,
+        None => {
+            initialize_pangea_indexer(
+                tasks,
+                Arc::clone(registry),
+                market.clone(),
+                Arc::clone(kill_switches),
+            )
+            .await?
+        }
+    }
+    initialize_retention(tasks, market.clone())?;
+    if follower.is_none() && !dev {
+        initialize_alerting(
+            tasks,
+            Arc::clone(&market.order_book),
+            Arc::clone(&market.status),
+        )?;
+        start_writers(tasks, &market)?;
+    }
+    registry.track_tasks(
+        market.market_id(),
+        tasks[first..].iter().map(JoinHandle::abort_handle),
+    );
+    Ok(())
+}
+
+/// The tasks that write the market's cold storage, reports and event log.
+fn start_writers(tasks: &mut Vec<JoinHandle<()>>, market: &MarketState) -> Result<(), Error> {
+    if let Some(cold_store) = &market.cold_store {
+        initialize_cold_storage(
+            tasks,
+            Arc::clone(&market.order_book),
+            Arc::clone(cold_store),
+        )?;
+    }
+    if let Some(daily_reports) = &market.daily_reports {
+        initialize_daily_reports(tasks, market.clone(), Arc::clone(daily_reports))?;
+    }
+    initialize_log_compaction(tasks, market)
+}
@@ -1,3 +1,26 @@
+pub mod anomaly_detector;
+pub mod arrow_events;
+pub mod backoff;
+pub mod block_metadata;
+pub mod chain_head;
+pub mod connection_pool;
+pub mod consistency_check;
+pub mod dev_market;
+pub mod event_buffer;
+pub mod fixtures;
+pub mod fuel_node;
+pub mod kill_switches;
+pub mod log_compaction;
+pub mod market_discovery;
+pub mod market_tasks;
 pub mod order_event_handler;
 pub mod pangea;
+pub mod pangea_credentials;
+pub mod payload_compat;
+pub mod reorg;
+pub mod replay;
+pub mod resync;
+pub mod spark_contracts;
 pub mod spot_order;
+pub mod status;
+pub mod timestamp_normalizer;
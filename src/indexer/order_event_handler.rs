@@ -1,9 +1,22 @@
+use crate::config::redaction::redact;
+use crate::error::Error;
 use crate::indexer::spot_order::{LimitType, OrderStatus, OrderType, SpotOrder};
-use crate::storage::order_book::OrderBook;
-use chrono::Utc;
-use log::{error, info};
+use crate::indexer::timestamp_normalizer::EventTime;
+use crate::metrics::metrics;
+use crate::storage::backend::StorageBatch;
+use crate::storage::market_registry::MarketRegistry;
+use crate::storage::market_registry::MarketState;
+use crate::storage::order_book::MatchFill;
+use crate::storage::size_distribution::SizeKind;
+use crate::storage::undo::Change;
+use crate::web::order_feed::OrderChange;
+use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+
+/// How many blocks back already applied events are remembered.
+pub const DEDUPE_WINDOW_BLOCKS: i64 = 256;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PangeaOrderEvent {
@@ -25,39 +38,212 @@ pub struct PangeaOrderEvent {
     pub order_matcher: Option<String>,
     pub owner: Option<String>,
     pub limit_type: Option<String>,
+    /// Unix seconds; not every Pangea payload carries it.
+    #[serde(default)]
+    pub block_timestamp: Option<i64>,
+}
+
+/// Position of an event on chain. Orders events that share a block, and so
+/// a block timestamp, the way the chain executed them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EventIndex {
+    pub block_number: i64,
+    pub transaction_index: u64,
+    pub log_index: u64,
+}
+
+impl EventIndex {
+    /// The API cursor for this position, `<block>-<transaction>-<log>`.
+    /// Cursors sort like the events they point at and stay valid across
+    /// restarts.
+    pub fn cursor(&self) -> String {
+        format!(
+            "{}-{}-{}",
+            self.block_number, self.transaction_index, self.log_index
+        )
+    }
+
+    pub fn from_cursor(cursor: &str) -> Option<Self> {
+        let mut parts = cursor.splitn(3, '-');
+        Some(EventIndex {
+            block_number: parts.next()?.parse().ok()?,
+            transaction_index: parts.next()?.parse().ok()?,
+            log_index: parts.next()?.parse().ok()?,
+        })
+    }
+}
+
+/// Events applied to one book, keyed on block, transaction hash and log
+/// index, so an event replayed by a reconnecting stream is applied once.
+///
+/// Blocks more than `DEDUPE_WINDOW_BLOCKS` behind the newest one are
+/// forgotten; the delta stream never resumes that far back.
+#[derive(Default)]
+pub struct AppliedEvents {
+    blocks: Mutex<BTreeMap<i64, HashSet<(String, u64)>>>,
+}
+
+impl AppliedEvents {
+    /// Records `event`. Returns false if it was applied before.
+    pub fn insert(&self, event: &PangeaOrderEvent) -> bool {
+        let mut blocks = self.blocks.lock().unwrap();
+        let inserted = blocks
+            .entry(event.block_number)
+            .or_default()
+            .insert((event.transaction_hash.clone(), event.log_index));
+        if let Some(&newest) = blocks.keys().next_back() {
+            *blocks = blocks.split_off(&(newest - DEDUPE_WINDOW_BLOCKS));
+        }
+        inserted
+    }
+
+    /// Forgets the events of `block_number` and later, for when a reorg
+    /// undoes them.
+    pub fn forget_from(&self, block_number: i64) {
+        self.blocks.lock().unwrap().split_off(&block_number);
+    }
+
+    /// Forgets everything, for when the book itself is reset.
+    pub fn clear(&self) {
+        self.blocks.lock().unwrap().clear();
+    }
+}
+
+/// Applies `event` to the book of the market it belongs to. Events for
+/// markets missing from `registry` are dropped.
+pub async fn handle_order_event(
+    registry: &MarketRegistry,
+    event: PangeaOrderEvent,
+    time: EventTime,
+) {
+    handle_order_events(registry, vec![(event, time)]);
 }
 
-pub async fn handle_order_event(order_book: Arc<OrderBook>, event: PangeaOrderEvent) {
+/// Applies `events` in order. Consecutive events for the same market are
+/// applied under a single lock of its book, so readers see each run at once
+/// and a busy block takes the lock once instead of once per event. Returns
+/// the changes made, oldest first, for undoing them on a reorg.
+pub fn handle_order_events(
+    registry: &MarketRegistry,
+    events: Vec<(PangeaOrderEvent, EventTime)>,
+) -> Vec<Change> {
+    let mut changes = vec![];
+    let mut events = events.into_iter().peekable();
+    while let Some((event, time)) = events.next() {
+        let Some(market) = registry.get(&event.market_id) else {
+            warn!("Dropping event for unknown market {}", event.market_id);
+            continue;
+        };
+        let market_id = event.market_id.clone();
+        let mut batch = market.order_book.batch();
+        apply_event(&market, &mut batch, event, time);
+        while let Some((event, time)) = events.next_if(|(next, _)| next.market_id == market_id) {
+            apply_event(&market, &mut batch, event, time);
+        }
+        changes.extend(batch.take_changes());
+    }
+    changes
+}
+
+fn apply_event(
+    market: &MarketState,
+    batch: &mut dyn StorageBatch,
+    event: PangeaOrderEvent,
+    time: EventTime,
+) {
+    let order_book = &market.order_book;
+    if !order_book.mark_applied(&event) {
+        info!(
+            "Skipping already applied event {} in block {}",
+            event.index().cursor(),
+            event.block_number
+        );
+        return;
+    }
+
     if let Some(event_type) = event.event_type.as_deref() {
         match event_type {
             "Open" => {
-                if let Some(order) = create_new_order_from_event(&event) {
-                    order_book.add_order(order);
-                    info!("Added new order with id: {}", event.order_id);
+                if let Some(order) = create_new_order_from_event(&event, market.market_id(), time) {
+                    batch.record_activity(&order.user, order.timestamp);
+                    batch.record_size(SizeKind::Order, order.amount, order.timestamp);
+                    batch.record_order_flow(order.order_type, order.amount, order.timestamp);
+                    batch.add_order(order);
+                    info!("Added new order with id: {}", redact(&event.order_id));
                 }
             }
             "Trade" => {
+                if let Some(user) = event.user.as_deref() {
+                    batch.record_activity(user, time.normalized);
+                }
+                if let (Some(price), Some(size)) = (event.price, event.amount) {
+                    // Read before the fill below takes the order off the book.
+                    let resting_since = event
+                        .order_type_to_enum()
+                        .and_then(|side| batch.get_order(&event.order_id, side))
+                        .map(|order| order.priority);
+                    let recorded = batch.record_trade(
+                        market.market_id(),
+                        price,
+                        size,
+                        time,
+                        event.index(),
+                        event.trade_fill(resting_since),
+                    );
+                    // Both fills of a match report its size; count it once.
+                    if recorded == MatchFill::First {
+                        metrics().record_trade(&event.market_id, size);
+                        batch.record(Change::MatchCounted { size });
+                        batch.record_size(SizeKind::Trade, size, time.normalized);
+                        if let Some(fee_revenue) = &market.fee_revenue {
+                            if fee_revenue.record_match(price, size, time.normalized) {
+                                batch.record(Change::FeeRevenue {
+                                    price,
+                                    size,
+                                    timestamp: time.normalized,
+                                });
+                            }
+                        }
+                    }
+                    // Only the second fill of a match tells who took it, and
+                    // the match is counted once.
+                    if let MatchFill::Second {
+                        aggressor: Some(initiator),
+                    } = recorded
+                    {
+                        batch.record_trade_flow(initiator, size, time.normalized);
+                    }
+                }
                 if let Some(match_size) = event.amount {
                     let o_type = event.order_type_to_enum();
                     let l_type = event.limit_type_to_enum();
-                    process_trade(&order_book, &event.order_id, match_size, o_type, l_type);
+                    process_trade(batch, &event.order_id, match_size, o_type, l_type);
                 }
             }
             "Cancel" => {
-                order_book.remove_order(&event.order_id, event.order_type_to_enum());
+                batch.remove_order(
+                    &event.order_id,
+                    event.order_type_to_enum(),
+                    OrderChange::Cancel,
+                );
                 info!(
                     "Removed order with id: {} due to Cancel event",
-                    event.order_id
+                    redact(&event.order_id)
                 );
             }
             _ => {
                 error!("Unknown event type: {}", event_type);
             }
         }
+        batch.observe_quote(time.normalized);
     }
 }
 
-fn create_new_order_from_event(event: &PangeaOrderEvent) -> Option<SpotOrder> {
+fn create_new_order_from_event(
+    event: &PangeaOrderEvent,
+    market_id: &str,
+    time: EventTime,
+) -> Option<SpotOrder> {
     if let (Some(price), Some(amount), Some(order_type), Some(user)) = (
         event.price,
         event.amount,
@@ -72,13 +258,17 @@ fn create_new_order_from_event(event: &PangeaOrderEvent) -> Option<SpotOrder> {
 
         Some(SpotOrder {
             id: event.order_id.clone(),
+            market_id: market_id.to_owned(),
             user: user.clone(),
             asset: event.asset.clone().unwrap_or_default(),
             amount,
             price,
-            timestamp: Utc::now().timestamp_millis() as u64,
+            timestamp: time.normalized,
+            raw_timestamp: time.raw,
             order_type: order_type_enum,
             status: Some(OrderStatus::New),
+            priority: 0,
+            block_number: event.block_number,
         })
     } else {
         None
@@ -86,7 +276,7 @@ fn create_new_order_from_event(event: &PangeaOrderEvent) -> Option<SpotOrder> {
 }
 
 pub fn process_trade(
-    order_book: &OrderBook,
+    batch: &mut dyn StorageBatch,
     order_id: &str,
     trade_amount: u128,
     order_type: Option<OrderType>,
@@ -95,40 +285,94 @@ pub fn process_trade(
     match (order_type, limit_type) {
         (Some(order_type), Some(limit_type)) => match limit_type {
             LimitType::GTC => {
-                if let Some(mut order) = order_book.get_order(order_id, order_type) {
+                if let Some(mut order) = batch.get_order(order_id, order_type) {
                     if order.amount > trade_amount {
                         order.amount -= trade_amount;
                         order.status = Some(OrderStatus::PartiallyMatched);
-                        order_book.update_order(order.clone());
+                        batch.update_order(order.clone());
 
                         info!(
                             "Updated order with id: {} - partially matched, remaining amount: {}",
-                            order_id, order.amount
+                            redact(order_id),
+                            order.amount
                         );
                     } else {
                         order.status = Some(OrderStatus::Matched);
-                        order_book.remove_order(order_id, Some(order_type));
-                        info!("Removed order with id: {} - fully matched", order_id);
+                        batch.remove_order(order_id, Some(order_type), OrderChange::Fill);
+                        info!(
+                            "Removed order with id: {} - fully matched",
+                            redact(order_id)
+                        );
                     }
                 } else {
-                    error!("Order with id: {} not found for trade event", order_id);
+                    error!(
+                        "Order with id: {} not found for trade event",
+                        redact(order_id)
+                    );
                 }
             }
             _ => {
-                order_book.remove_order(order_id, Some(order_type));
-                info!("Removed order with id: {} - FOK or IOC matched", order_id);
+                batch.remove_order(order_id, Some(order_type), OrderChange::Fill);
+                info!(
+                    "Removed order with id: {} - FOK or IOC matched",
+                    redact(order_id)
+                );
             }
         },
         _ => {
             error!(
                 "Order type or limit type is None for order_id: {}. Cannot process trade event.",
-                order_id
+                redact(order_id)
             );
         }
     }
 }
 
+/// One fill of a match, as a Spark trade event reports it. Which of the two
+/// fills of a match was the maker is only known once both are recorded:
+/// the one whose order rested in the book first.
+#[derive(Debug, Clone, Default)]
+pub struct TradeFill {
+    pub order_id: String,
+    pub side: Option<OrderType>,
+    pub user: Option<String>,
+    /// Book priority of the filled order, lower for orders that rested
+    /// earlier; `None` if it never rested in the book.
+    pub resting_since: Option<u64>,
+}
+
 impl PangeaOrderEvent {
+    /// Parses one event of Pangea's JSON stream.
+    #[cfg(not(feature = "simd-json"))]
+    pub fn from_json(data: Vec<u8>) -> Result<Self, Error> {
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Parses one event of Pangea's JSON stream with SIMD, in place.
+    #[cfg(feature = "simd-json")]
+    pub fn from_json(mut data: Vec<u8>) -> Result<Self, Error> {
+        Ok(simd_json::serde::from_slice(&mut data)?)
+    }
+
+    pub fn index(&self) -> EventIndex {
+        EventIndex {
+            block_number: self.block_number,
+            transaction_index: self.transaction_index,
+            log_index: self.log_index,
+        }
+    }
+
+    /// The fill this trade event reports, for an order that has rested in
+    /// the book since `resting_since`.
+    pub fn trade_fill(&self, resting_since: Option<u64>) -> TradeFill {
+        TradeFill {
+            order_id: self.order_id.clone(),
+            side: self.order_type_to_enum(),
+            user: self.user.clone(),
+            resting_since,
+        }
+    }
+
     pub fn order_type_to_enum(&self) -> Option<OrderType> {
         self.order_type
             .as_deref()
@@ -150,3 +394,97 @@ impl PangeaOrderEvent {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::state::hash_entries;
+    use std::fs;
+    use std::path::Path;
+
+    /// The committed sample written by the `fixtures` command: an order
+    /// partly filled by a crossing one, and an order opened and cancelled.
+    fn fixtures() -> Vec<(PangeaOrderEvent, EventTime)> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/events.json");
+        let events: Vec<PangeaOrderEvent> =
+            serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+        events
+            .into_iter()
+            .map(|event| {
+                let timestamp = event.block_timestamp.map(|secs| secs as u64 * 1000);
+                let time = EventTime {
+                    raw: timestamp,
+                    normalized: timestamp.unwrap_or_default(),
+                };
+                (event, time)
+            })
+            .collect()
+    }
+
+    fn apply_fixtures() -> (MarketRegistry, MarketState) {
+        let events = fixtures();
+        let market = MarketState::new(events[0].0.market_id.clone());
+        let registry = MarketRegistry::new(vec![market.clone()]);
+        handle_order_events(&registry, events);
+        (registry, market)
+    }
+
+    fn placeholder(n: u64) -> String {
+        format!("0x{:064x}", n)
+    }
+
+    #[test]
+    fn fills_and_cancels_update_the_book() {
+        let (_, market) = apply_fixtures();
+        let book = &market.order_book;
+        assert_eq!(book.order_count(OrderType::Buy), 0);
+
+        let sells = book.get_range(0, u128::MAX, OrderType::Sell);
+        assert_eq!(sells.len(), 1);
+        assert_eq!(sells[0].id, format!("0x{}", "11".repeat(32)));
+        assert_eq!(sells[0].amount, 1_500_000);
+        assert_eq!(sells[0].status, Some(OrderStatus::PartiallyMatched));
+    }
+
+    #[test]
+    fn both_fills_of_a_match_record_one_match() {
+        let (_, market) = apply_fixtures();
+        let matches = market.matches_between(0, u64::MAX).unwrap();
+        assert_eq!(matches.len(), 1);
+
+        let matched = &matches[0];
+        assert_eq!(matched.trade_size, "500000");
+        assert_eq!(matched.seller, Some(placeholder(1)));
+        assert_eq!(matched.buyer, Some(placeholder(2)));
+        assert_eq!(
+            matched.maker_order_id,
+            Some(format!("0x{}", "11".repeat(32)))
+        );
+        assert_eq!(
+            matched.taker_order_id,
+            Some(format!("0x{}", "22".repeat(32)))
+        );
+        assert_eq!(matched.aggressor_side, Some(OrderType::Buy));
+    }
+
+    #[test]
+    fn replayed_events_change_nothing() {
+        let (registry, market) = apply_fixtures();
+        assert!(handle_order_events(&registry, fixtures()).is_empty());
+        assert_eq!(market.order_book.get_trade_events().len(), 2);
+        assert_eq!(market.order_book.order_count(OrderType::Sell), 1);
+    }
+
+    #[test]
+    fn state_hash_follows_the_book() {
+        let (_, market) = apply_fixtures();
+        let book = &market.order_book;
+        assert_eq!(book.state_hash(), hash_entries(&book.state_entries()));
+        book.remove_order(
+            &format!("0x{}", "11".repeat(32)),
+            Some(OrderType::Sell),
+            OrderChange::Cancel,
+        );
+        assert_eq!(book.state_hash(), hash_entries(&[]));
+    }
+}
@@ -1,26 +1,236 @@
+use chrono::Utc;
 use ethers_core::types::H256;
-use log::{error, info};
+use log::{error, info, warn};
 use pangea_client::Client;
 use pangea_client::{
-    futures::StreamExt, provider::FuelProvider, query::Bound, requests::fuel::GetSparkOrderRequest,
-    ClientBuilder, Format, WsProvider,
+    futures::stream::BoxStream, futures::StreamExt, provider::FuelProvider, query::Bound,
+    requests::fuel::GetSparkOrderRequest, ClientBuilder, Format, HttpProvider, WsProvider,
 };
 use std::collections::HashSet;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use crate::config::env::ev;
+use crate::alerts::notifier::Notifier;
+use crate::config::env::{ev, ev_opt};
 use crate::error::Error;
-use crate::indexer::order_event_handler::handle_order_event;
-use crate::indexer::order_event_handler::PangeaOrderEvent;
-use crate::storage::order_book::OrderBook;
+use crate::indexer::anomaly_detector::{AnomalyConfig, AnomalyDetector};
+use crate::indexer::arrow_events::decode_order_events;
+use crate::indexer::backoff::Backoff;
+use crate::indexer::block_metadata::BlockMetadataCache;
+use crate::indexer::connection_pool::{connection_pool, ConnectionKey};
+use crate::indexer::dev_market::{dev_mode, DevFeed};
+use crate::indexer::event_buffer::BlockBuffer;
+use crate::indexer::fuel_node::FuelNodeClient;
+use crate::indexer::kill_switches::KillSwitches;
+use crate::indexer::order_event_handler::handle_order_events;
+use crate::indexer::order_event_handler::{EventIndex, PangeaOrderEvent};
+use crate::indexer::pangea_credentials::{credential_rotations, PangeaCredentials};
+use crate::indexer::reorg::ReorgDetector;
+use crate::indexer::resync::ResyncRequests;
+use crate::indexer::status::{IndexerStatus, SyncPhase};
+use crate::indexer::timestamp_normalizer::{EventTime, TimestampNormalizer};
+use crate::metrics::metrics;
+use crate::storage::backend::Storage;
+use crate::storage::checkpoint::CheckpointStore;
+use crate::storage::event_log::EventLog;
+use crate::storage::market_registry::{MarketRegistry, MarketState};
+use crate::storage::undo::undo;
 
+const DEFAULT_BACKFILL_CHUNKS: usize = 1;
+const DEFAULT_FAILOVER_AFTER: u32 = 3;
+const DEFAULT_MAX_CONNECTIONS: usize = 4;
+const DEFAULT_POLL_INTERVAL_MS: u64 = 2000;
+const DEFAULT_STALE_STREAM_SECS: u64 = 300;
+const DEFAULT_PROGRESS_PROBE_SECS: u64 = 30;
+const SYNC_PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// How often HTTP polling tries to get back onto WebSocket.
+const WS_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Everything the indexer needs to apply events for one market.
+struct IndexerContext {
+    registry: Arc<MarketRegistry>,
+    order_book: Arc<dyn Storage>,
+    status: Arc<IndexerStatus>,
+    kill_switches: Arc<KillSwitches>,
+    detector: AnomalyDetector,
+    normalizer: TimestampNormalizer,
+    blocks: BlockMetadataCache,
+    checkpoints: Option<Arc<CheckpointStore>>,
+    event_log: Option<Arc<EventLog>>,
+    reorgs: ReorgDetector,
+    /// The market's contract and every contract it migrated from.
+    market_ids: HashSet<H256>,
+    /// Prepared events waiting to be applied together.
+    pending: Mutex<Vec<(PangeaOrderEvent, EventTime)>>,
+    resyncs: Arc<ResyncRequests>,
+}
+
+impl IndexerContext {
+    /// Prepares one event and queues it for `apply_pending`; a new block
+    /// applies the previous one first. Returns the block to resume streaming
+    /// after when the event revealed a reorg and the book was rolled back.
+    async fn apply_event(&self, mut order: PangeaOrderEvent) -> Option<i64> {
+        let fork_block = self.reorgs.observe(
+            order.block_number,
+            &order.block_hash,
+            self.status.last_processed_block(),
+        );
+        if let Some(fork_block) = fork_block {
+            if self.status.phase() == SyncPhase::Live {
+                if let Some(resume_block) = self.roll_back(fork_block, "Reorg") {
+                    return Some(resume_block);
+                }
+            }
+        }
+        if let Some(event_log) = &self.event_log {
+            if let Err(e) = event_log.append(&order) {
+                error!("Failed to log event {}: {}", order.index().cursor(), e);
+            }
+        }
+        if let Some(market_id) = self.registry.migrated_to(&order.market_id) {
+            order.market_id = market_id;
+        }
+
+        if order.block_number != self.status.last_processed_block() {
+            self.finish_block();
+
+            // Only pause between blocks so the served snapshot is never a
+            // half-applied block.
+            if self.kill_switches.is_maintenance() {
+                info!(
+                    "Maintenance mode: indexer paused after block {}",
+                    self.status.last_processed_block()
+                );
+                self.kill_switches.wait_while_maintenance().await;
+                info!("Maintenance mode lifted, resuming indexing");
+            }
+        }
+        self.kill_switches
+            .wait_while_indexing_halted(self.status.market_id())
+            .await;
+
+        self.status.set_last_processed_block(order.block_number);
+        metrics().record_event(&order.market_id);
+        match self.status.phase() {
+            SyncPhase::Live => self
+                .status
+                .record_event(Utc::now().timestamp_millis() as u64),
+            SyncPhase::Backfilling => self.status.record_backfill_event(),
+            SyncPhase::Starting => {}
+        }
+        let live = self.status.phase() == SyncPhase::Live;
+        self.detector.inspect(&self.order_book, &order, live);
+        self.blocks.enrich(&mut order).await;
+        let time = self.normalizer.normalize(&order);
+        self.pending.lock().unwrap().push((order, time));
+        None
+    }
+
+    /// Applies the queued events under one lock of the book, once they are
+    /// in the event log.
+    fn apply_pending(&self) {
+        let events = std::mem::take(&mut *self.pending.lock().unwrap());
+        if let Some(event_log) = &self.event_log {
+            if let Err(e) = event_log.flush() {
+                error!("Failed to write the event log: {}", e);
+            }
+        }
+        if !events.is_empty() {
+            let changes = handle_order_events(&self.registry, events);
+            self.reorgs
+                .record(self.status.last_processed_block(), changes);
+        }
+    }
+
+    /// Whether `order` is a repeat of an event already applied, as when a
+    /// reconnect re-reads the last block. Events from a block seen under a
+    /// different hash are not repeats: they reveal a reorg.
+    fn already_applied(&self, order: &PangeaOrderEvent, last_applied: EventIndex) -> bool {
+        order.index() <= last_applied && self.reorgs.is_known(order.block_number, &order.block_hash)
+    }
+
+    /// Undoes the kept changes of `fork_block` and the blocks after it and
+    /// returns the block before, from which the canonical branch is
+    /// streamed again. A fork older than the kept changes is applied over
+    /// the current state, as before reorg handling existed. `reason` is for
+    /// the log.
+    fn roll_back(&self, fork_block: i64, reason: &str) -> Option<i64> {
+        let Some((block_number, changes)) = self.reorgs.rollback(fork_block) else {
+            error!(
+                "{} at block {} for {} is deeper than the kept undo history; not rolling back",
+                reason,
+                fork_block,
+                self.status.market_id()
+            );
+            return None;
+        };
+        warn!(
+            "{} at block {} for {}: rolling back to block {}",
+            reason,
+            fork_block,
+            self.status.market_id(),
+            block_number
+        );
+        self.pending.lock().unwrap().clear();
+        if let Some(event_log) = &self.event_log {
+            event_log.rewind(block_number);
+        }
+        if let Some(market) = self.registry.get(self.status.market_id()) {
+            let busted = undo(&market, changes);
+            if busted > 0 {
+                warn!(
+                    "Busted {} trades after block {} for {}",
+                    busted,
+                    block_number,
+                    self.status.market_id()
+                );
+            }
+        }
+        self.order_book.forget_applied_from(fork_block);
+        self.status.set_last_processed_block(block_number);
+        self.status.rewind_stream_block(block_number);
+        self.status
+            .set_state_hash(block_number, self.order_book.state_hash());
+        Some(block_number)
+    }
+
+    /// Undoes the last `blocks` blocks, like a reorg there would, so the
+    /// stream fetches them and every block after up to the chain head again
+    /// and applies them in chain order. Returns the block to resume after,
+    /// or `None` if that is further back than the kept undo history.
+    fn resync(&self, blocks: i64) -> Option<i64> {
+        let from = self.status.last_processed_block() - blocks + 1;
+        self.roll_back(from, "Resync")
+    }
+
+    /// Applies what is left of the current block and records its state
+    /// hash.
+    fn finish_block(&self) {
+        self.apply_pending();
+        let block_number = self.status.last_processed_block();
+        if block_number > 0 {
+            self.status
+                .set_state_hash(block_number, self.order_book.state_hash());
+            if let Some(checkpoints) = &self.checkpoints {
+                checkpoints.maybe_save(self.order_book.as_ref(), block_number);
+            }
+            if self.status.phase() == SyncPhase::Live {
+                self.reorgs.block_finished(block_number);
+            }
+        }
+    }
+}
+
+/// Starts indexing `market`, one of the markets in `registry`.
 pub async fn initialize_pangea_indexer(
     tasks: &mut Vec<tokio::task::JoinHandle<()>>,
-    order_book: Arc<OrderBook>,
+    registry: Arc<MarketRegistry>,
+    market: MarketState,
+    kill_switches: Arc<KillSwitches>,
 ) -> Result<(), Error> {
     let ws_task_pangea = tokio::spawn(async move {
-        if let Err(e) = start_pangea_indexer(order_book).await {
+        if let Err(e) = start_pangea_indexer(registry, market, kill_switches).await {
             eprintln!("Pangea error: {}", e);
         }
     });
@@ -29,117 +239,959 @@ pub async fn initialize_pangea_indexer(
     Ok(())
 }
 
-async fn start_pangea_indexer(order_book: Arc<OrderBook>) -> Result<(), Error> {
-    let client = create_pangea_client().await?;
+async fn start_pangea_indexer(
+    registry: Arc<MarketRegistry>,
+    market: MarketState,
+    kill_switches: Arc<KillSwitches>,
+) -> Result<(), Error> {
+    let dev = dev_mode();
+    let (mut endpoints, mut client, market) = match dev {
+        Some(config) => {
+            let client = Arc::new(PangeaClient::Dev(DevFeed::new(&market, config)));
+            // Synthetic events are neither logged nor checkpointed.
+            let market = MarketState {
+                checkpoints: None,
+                event_log: None,
+                ..market
+            };
+            (PangeaEndpoints::dev(Arc::clone(&client)), client, market)
+        }
+        None => {
+            let mut endpoints = PangeaEndpoints::from_env()?;
+            let client = endpoints.connect().await?;
+            (endpoints, client, market)
+        }
+    };
 
-    let contract_start_block: i64 = ev("CONTRACT_START_BLOCK")?.parse()?;
-    let contract_h256 = H256::from_str(&ev("CONTRACT_ID")?)?;
+    let mut contract_start_block: i64 = match (market.start_block, dev) {
+        (Some(start_block), _) => start_block,
+        (None, Some(_)) => 0,
+        (None, None) => ev("CONTRACT_START_BLOCK")?.parse()?,
+    };
+    // A book rebuilt from the event log before start only needs the blocks
+    // from its last one on. That block is read again since the log may have
+    // been cut off inside it; its events already applied are skipped as
+    // repeats.
+    let rebuilt_block = market.status.last_processed_block();
+    if rebuilt_block > 0 {
+        info!(
+            "Resuming {} from its event log at block {}",
+            market.market_id(),
+            rebuilt_block
+        );
+        contract_start_block = rebuilt_block;
+    } else if let Some(checkpoint) = match &market.checkpoints {
+        Some(checkpoints) => checkpoints.load().await?,
+        None => None,
+    } {
+        info!(
+            "Resuming {} from checkpoint at block {}",
+            market.market_id(),
+            checkpoint.block_number
+        );
+        market
+            .status
+            .set_last_processed_block(checkpoint.block_number);
+        contract_start_block = checkpoint.block_number + 1;
+        market.order_book.restore(checkpoint);
+    }
+    let mut market_ids = HashSet::from([H256::from_str(market.market_id())?]);
+    for old_id in registry.predecessors(market.market_id()) {
+        info!(
+            "Indexing {} together with {}, which migrated to it",
+            market.market_id(),
+            old_id
+        );
+        market_ids.insert(H256::from_str(&old_id)?);
+    }
+    // Synthetic markets raise no alerts.
+    let notifiers = match dev {
+        Some(_) => vec![],
+        None => Notifier::from_env(),
+    };
+    let ctx = IndexerContext {
+        registry,
+        market_ids,
+        pending: Mutex::new(vec![]),
+        order_book: market.order_book,
+        status: market.status,
+        kill_switches,
+        detector: AnomalyDetector::new(AnomalyConfig::from_env()?, notifiers),
+        normalizer: TimestampNormalizer::from_env()?,
+        blocks: BlockMetadataCache::from_env(),
+        checkpoints: market.checkpoints,
+        event_log: market.event_log,
+        reorgs: ReorgDetector::from_env()?,
+        resyncs: market.resyncs,
+    };
+    if let Some(timestamp) = ctx.order_book.last_trade_timestamp() {
+        ctx.normalizer.resume_from(timestamp);
+    }
 
-    let mut last_processed_block =
-        fetch_historical_data(&client, &order_book, contract_start_block, contract_h256).await?;
+    ctx.status.set_phase(SyncPhase::Backfilling);
+    ctx.status
+        .start_backfill(contract_start_block, Utc::now().timestamp_millis() as u64);
+    let progress_log = AbortOnDrop(tokio::spawn(log_sync_progress(Arc::clone(&ctx.status))));
+    let mut backoff = Backoff::from_env()?;
+    let mut from_block = contract_start_block;
+    let mut last_processed_block = 0;
+    loop {
+        match fetch_historical_data(&client, &ctx, from_block).await {
+            Ok(block_number) => {
+                last_processed_block = last_processed_block.max(block_number);
+                break;
+            }
+            Err(e @ Error::PangeaClientError(_)) => {
+                error!(
+                    "Failed to load historical orders for {} from block {}: {e}",
+                    ctx.status.market_id(),
+                    from_block
+                );
+                connection_pool().evict(&client);
+                wait_to_reconnect(&ctx, &mut backoff).await?;
+                let reconnected = if endpoints.should_fail_over(backoff.attempt()) {
+                    endpoints.fail_over().await
+                } else {
+                    endpoints.connect().await
+                };
+                match reconnected {
+                    Ok(next) => client = next,
+                    Err(e) => error!("Failed to reconnect to Pangea: {e}"),
+                }
+                // The last applied block is read again, as after a rebuild
+                // from the event log; its events already applied are
+                // skipped as repeats.
+                if ctx.status.last_processed_block() > 0 {
+                    from_block = ctx.status.last_processed_block();
+                    last_processed_block = from_block;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    drop(progress_log);
+    ctx.finish_block();
+    ctx.status
+        .finish_backfill(Utc::now().timestamp_millis() as u64);
+    if let Some(progress) = ctx
+        .status
+        .sync_progress(Utc::now().timestamp_millis() as u64)
+    {
+        info!(
+            "Historical sync of {} done: {} events in {}s",
+            ctx.status.market_id(),
+            progress.events,
+            progress.elapsed_ms / 1000
+        );
+    }
 
     if last_processed_block == 0 {
         last_processed_block = contract_start_block;
     }
 
     info!("Switching to listening for new orders (deltas)");
+    ctx.status.set_phase(SyncPhase::Live);
+    ctx.reorgs.start(ctx.status.last_processed_block());
 
-    listen_for_new_deltas(&client, &order_book, last_processed_block, contract_h256).await
+    listen_for_new_deltas(client, endpoints, &ctx, last_processed_block).await
 }
 
-async fn create_pangea_client() -> Result<Client<WsProvider>, Error> {
-    let username = ev("PANGEA_USERNAME")?;
-    let password = ev("PANGEA_PASSWORD")?;
-    let url = ev("PANGEA_URL")?;
+/// Aborts a task when dropped, so a helper task such as the progress log
+/// also stops when the indexer task awaiting alongside it is aborted.
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
 
-    let client = ClientBuilder::default()
-        .endpoint(&url)
-        .credential(username, password)
-        .build::<WsProvider>()
-        .await?;
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Logs historical sync progress every `SYNC_PROGRESS_LOG_INTERVAL` until
+/// aborted.
+async fn log_sync_progress(status: Arc<IndexerStatus>) {
+    let mut ticker = tokio::time::interval(SYNC_PROGRESS_LOG_INTERVAL);
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let Some(progress) = status.sync_progress(Utc::now().timestamp_millis() as u64) else {
+            continue;
+        };
+        let total = progress
+            .total_blocks()
+            .map_or("?".to_owned(), |total| total.to_string());
+        let eta = progress
+            .eta_secs()
+            .map_or("unknown".to_owned(), |eta| format!("{}s", eta));
+        info!(
+            "Historical sync of {}: {}/{} blocks, {:.0} events/s, ETA {}",
+            status.market_id(),
+            progress.blocks_processed(),
+            total,
+            progress.events_per_second(),
+            eta
+        );
+    }
+}
 
-    info!("Pangea ws client created and connected.");
-    Ok(client)
+/// A connection to Pangea. WebSocket is preferred; HTTP serves the same
+/// bounded requests, and new blocks are polled for instead of subscribed to.
+/// In dev mode a synthetic market stands in for Pangea.
+pub(crate) enum PangeaClient {
+    Ws(Client<WsProvider>),
+    Http(Client<HttpProvider>),
+    Dev(DevFeed),
 }
 
+impl PangeaClient {
+    /// Whether new blocks have to be polled for.
+    pub(crate) fn is_polling(&self) -> bool {
+        matches!(self, PangeaClient::Http(_))
+    }
+
+    /// Spark order events matching `request`, one JSON document each.
+    pub(crate) async fn spark_orders(
+        &self,
+        request: GetSparkOrderRequest,
+        deltas: bool,
+    ) -> Result<BoxStream<'_, Result<Vec<u8>, Error>>, Error> {
+        self.spark_orders_by_format(request, Format::JsonStream, deltas)
+            .await
+    }
+
+    /// Spark order events matching `request`, as chunks in `format`.
+    pub(crate) async fn spark_orders_by_format(
+        &self,
+        request: GetSparkOrderRequest,
+        format: Format,
+        deltas: bool,
+    ) -> Result<BoxStream<'_, Result<Vec<u8>, Error>>, Error> {
+        Ok(match self {
+            PangeaClient::Ws(client) => client
+                .get_fuel_spark_orders_by_format(request, format, deltas)
+                .await?
+                .map(|data| data.map_err(Error::from))
+                .boxed(),
+            PangeaClient::Http(client) => client
+                .get_fuel_spark_orders_by_format(request, format, deltas)
+                .await?
+                .map(|data| data.map_err(Error::from))
+                .boxed(),
+            PangeaClient::Dev(feed) => {
+                let from_block = match request.from_block {
+                    Bound::Exact(block_number) => block_number,
+                    _ => 0,
+                };
+                feed.spark_orders(from_block, deltas)
+            }
+        })
+    }
+}
+
+/// The Pangea endpoints in `PANGEA_URL`, a comma-separated list in order of
+/// preference. After `PANGEA_FAILOVER_AFTER` (default 3) consecutive failed
+/// reconnects the backfill or delta stream moves on to the next endpoint.
+///
+/// When no endpoint accepts a WebSocket connection, the same endpoints are
+/// used over HTTP unless `PANGEA_HTTP_FALLBACK=false`, polling for new
+/// blocks every `PANGEA_POLL_INTERVAL_MS` (default 2000) and retrying
+/// WebSocket every minute.
+///
+/// A delta stream that sends nothing for `PANGEA_STALE_STREAM_SECS` (default
+/// 300, 0 to wait forever) is treated as dead and reopened after the first
+/// backoff delay, without counting toward the reconnect attempts.
+///
+/// A subscription only sends blocks with events for the market, so every
+/// `PANGEA_PROGRESS_PROBE_SECS` (default 30, 0 to never probe) the blocks
+/// up to the chain head seen one probe earlier are fetched with a bounded
+/// request. Once it completes the stream counts as caught up to that head.
+///
+/// Connections are shared between markets through the connection pool, at
+/// most `PANGEA_MAX_CONNECTIONS` (default 4) per login.
+struct PangeaEndpoints {
+    urls: Vec<String>,
+    active: usize,
+    failover_after: u32,
+    max_connections: usize,
+    http_fallback: bool,
+    poll_interval: Duration,
+    stale_after: Option<Duration>,
+    probe_interval: Option<Duration>,
+    last_ws_attempt: Option<Instant>,
+    /// The synthetic market every connect returns in dev mode.
+    dev: Option<Arc<PangeaClient>>,
+}
+
+impl PangeaEndpoints {
+    fn from_env() -> Result<Self, Error> {
+        let urls: Vec<String> = ev("PANGEA_URL")?
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_owned)
+            .collect();
+        if urls.is_empty() {
+            return Err(Error::NoPangeaEndpoints);
+        }
+        let failover_after = match ev_opt("PANGEA_FAILOVER_AFTER") {
+            Some(attempts) => attempts.parse::<u32>()?.max(1),
+            None => DEFAULT_FAILOVER_AFTER,
+        };
+        let max_connections = match ev_opt("PANGEA_MAX_CONNECTIONS") {
+            Some(connections) => connections.parse::<usize>()?.max(1),
+            None => DEFAULT_MAX_CONNECTIONS,
+        };
+        let poll_interval = match ev_opt("PANGEA_POLL_INTERVAL_MS") {
+            Some(ms) => Duration::from_millis(ms.parse()?),
+            None => Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+        };
+        let stale_after = match ev_opt("PANGEA_STALE_STREAM_SECS") {
+            Some(secs) => secs.parse()?,
+            None => DEFAULT_STALE_STREAM_SECS,
+        };
+        let probe_interval = match ev_opt("PANGEA_PROGRESS_PROBE_SECS") {
+            Some(secs) => secs.parse()?,
+            None => DEFAULT_PROGRESS_PROBE_SECS,
+        };
+        Ok(PangeaEndpoints {
+            urls,
+            active: 0,
+            failover_after,
+            max_connections,
+            http_fallback: ev_opt("PANGEA_HTTP_FALLBACK").as_deref() != Some("false"),
+            poll_interval,
+            stale_after: (stale_after > 0).then(|| Duration::from_secs(stale_after)),
+            probe_interval: (probe_interval > 0).then(|| Duration::from_secs(probe_interval)),
+            last_ws_attempt: None,
+            dev: None,
+        })
+    }
+
+    /// No endpoints, only `client`, which never fails, goes quiet or skips
+    /// blocks.
+    fn dev(client: Arc<PangeaClient>) -> Self {
+        PangeaEndpoints {
+            urls: vec![],
+            active: 0,
+            failover_after: DEFAULT_FAILOVER_AFTER,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            http_fallback: false,
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            stale_after: None,
+            probe_interval: None,
+            last_ws_attempt: None,
+            dev: Some(client),
+        }
+    }
+
+    /// Connects over WebSocket, falling back to HTTP when that fails for
+    /// every endpoint.
+    async fn connect(&mut self) -> Result<Arc<PangeaClient>, Error> {
+        if let Some(client) = &self.dev {
+            return Ok(Arc::clone(client));
+        }
+        self.last_ws_attempt = Some(Instant::now());
+        let ws_error = match self.connect_via(false).await {
+            Ok(client) => return Ok(client),
+            Err(e) => e,
+        };
+        if !self.http_fallback {
+            return Err(ws_error);
+        }
+        warn!(
+            "No Pangea endpoint accepts WebSocket connections ({}), falling back to HTTP polling",
+            ws_error
+        );
+        self.connect_via(true).await
+    }
+
+    /// Connects to the active endpoint, moving down the list while
+    /// connecting fails, until every endpoint has been tried once. Reuses a
+    /// pooled connection to the endpoint once the pool is full.
+    async fn connect_via(&mut self, http: bool) -> Result<Arc<PangeaClient>, Error> {
+        let credentials = PangeaCredentials::current()?;
+
+        let mut attempts = 1;
+        loop {
+            let url = &self.urls[self.active];
+            let key = ConnectionKey {
+                url: url.clone(),
+                http,
+                credentials: credentials.clone(),
+            };
+            let connected = connection_pool()
+                .acquire(key, self.max_connections, || async {
+                    let builder = ClientBuilder::default()
+                        .endpoint(url)
+                        .credential(credentials.username.clone(), credentials.password.clone());
+                    if http {
+                        builder
+                            .build::<HttpProvider>()
+                            .await
+                            .map(PangeaClient::Http)
+                    } else {
+                        builder.build::<WsProvider>().await.map(PangeaClient::Ws)
+                    }
+                })
+                .await;
+            match connected {
+                Ok(client) => {
+                    let transport = if http { "http" } else { "ws" };
+                    info!("Pangea {} client connected to {}.", transport, url);
+                    return Ok(client);
+                }
+                Err(e) if attempts < self.urls.len() => {
+                    warn!("Failed to connect to Pangea at {}: {}", url, e);
+                    self.active = (self.active + 1) % self.urls.len();
+                    attempts += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// A WebSocket client to replace HTTP polling, if the retry interval
+    /// has passed and an endpoint accepts one again.
+    async fn upgrade(&mut self) -> Option<Arc<PangeaClient>> {
+        if self
+            .last_ws_attempt
+            .is_some_and(|attempt| attempt.elapsed() < WS_RETRY_INTERVAL)
+        {
+            return None;
+        }
+        self.last_ws_attempt = Some(Instant::now());
+        match self.connect_via(false).await {
+            Ok(client) => {
+                info!("Pangea WebSocket is reachable again, leaving HTTP polling");
+                Some(client)
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Whether `failures` consecutive failed reconnects call for another
+    /// endpoint.
+    fn should_fail_over(&self, failures: u32) -> bool {
+        self.urls.len() > 1 && failures > 0 && failures % self.failover_after == 0
+    }
+
+    /// Connects to the next endpoint in the list.
+    async fn fail_over(&mut self) -> Result<Arc<PangeaClient>, Error> {
+        self.active = (self.active + 1) % self.urls.len();
+        warn!("Failing over to Pangea endpoint {}", self.urls[self.active]);
+        self.connect().await
+    }
+}
+
+/// A client for the first reachable endpoint in `PANGEA_URL`.
+pub(crate) async fn create_pangea_client() -> Result<Arc<PangeaClient>, Error> {
+    PangeaEndpoints::from_env()?.connect().await
+}
+
+/// Loads every event from `contract_start_block` up to the latest block.
+/// With `PANGEA_HISTORICAL_FORMAT=arrow` the events arrive as Arrow record
+/// batches and are decoded a batch at a time instead of line by line.
+///
+/// With `PANGEA_BACKFILL_CHUNKS` above 1 and a Fuel node to learn the chain
+/// head from, the range is split into that many block ranges, fetched
+/// concurrently and applied in block order as each one completes. A single
+/// chunk streams events straight into the book instead of holding them.
+///
+/// Once every range is complete, the stream counts as caught up to the
+/// chain head known before the requests went out. A request Pangea refuses
+/// fails the whole load; the caller reconnects and loads again from the
+/// last applied block.
 async fn fetch_historical_data(
-    client: &Client<WsProvider>,
-    order_book: &Arc<OrderBook>,
+    client: &PangeaClient,
+    ctx: &IndexerContext,
     contract_start_block: i64,
-    contract_h256: H256,
 ) -> Result<i64, Error> {
-    let request_all = GetSparkOrderRequest {
-        from_block: Bound::Exact(contract_start_block),
-        to_block: Bound::Latest,
-        market_id__in: HashSet::from([contract_h256]),
-        ..Default::default()
+    let arrow = ev_opt("PANGEA_HISTORICAL_FORMAT").as_deref() == Some("arrow");
+    let chunks = match ev_opt("PANGEA_BACKFILL_CHUNKS") {
+        Some(chunks) => chunks.parse::<usize>()?.max(1),
+        None => DEFAULT_BACKFILL_CHUNKS,
     };
+    let head = ctx.status.chain_head();
+    let ranges = backfill_ranges(contract_start_block, chunks).await;
 
-    let stream_all = client
-        .get_fuel_spark_orders_by_format(request_all, Format::JsonStream, false)
-        .await
-        .expect("Failed to get fuel spark orders");
-
-    pangea_client::futures::pin_mut!(stream_all);
-
-    info!("Starting to load all historical orders...");
     let mut last_processed_block = 0;
+    let mut complete = true;
+    let mut buffer = BlockBuffer::from_env()?;
+    if ranges.len() == 1 {
+        info!("Starting to load all historical orders...");
+        let mut stream_all = client
+            .spark_orders_by_format(
+                historical_request(ctx, ranges[0]),
+                historical_format(arrow),
+                false,
+            )
+            .await?;
 
-    while let Some(data) = stream_all.next().await {
-        match data {
-            Ok(data) => {
-                let data = String::from_utf8(data)?;
-                let order: PangeaOrderEvent = serde_json::from_str(&data)?;
+        while let Some(data) = stream_all.next().await {
+            match data {
+                Ok(data) => {
+                    for order in decode_historical(data, arrow)? {
+                        last_processed_block = order.block_number;
+                        for order in buffer.push(order) {
+                            ctx.apply_event(order).await;
+                        }
+                        ctx.apply_pending();
+                    }
+                }
+                Err(e) => {
+                    error!("Error in the stream of historical orders: {e}");
+                    complete = false;
+                    break;
+                }
+            }
+        }
+    } else {
+        info!(
+            "Starting to load all historical orders in {} chunks...",
+            ranges.len()
+        );
+        let mut fetches = pangea_client::futures::stream::iter(ranges)
+            .map(|range| fetch_backfill_chunk(client, ctx, range, arrow))
+            .buffered(chunks);
+
+        while let Some((orders, chunk_complete)) = fetches.next().await.transpose()? {
+            for order in orders {
                 last_processed_block = order.block_number;
-                handle_order_event(order_book.clone(), order).await;
+                for order in buffer.push(order) {
+                    ctx.apply_event(order).await;
+                }
+                ctx.apply_pending();
             }
-            Err(e) => {
-                error!("Error in the stream of historical orders: {e}");
+            if !chunk_complete {
+                // Later chunks would leave a gap; the delta stream picks up
+                // from the last applied block instead.
+                complete = false;
                 break;
             }
         }
     }
+    for order in buffer.flush() {
+        ctx.apply_event(order).await;
+    }
+    ctx.apply_pending();
+    if let Some(head) = head.filter(|_| complete) {
+        ctx.status.confirm_stream_block(head.block_number);
+    }
 
     Ok(last_processed_block)
 }
 
+/// Block ranges for `chunks` concurrent backfill requests, the last one
+/// open-ended. A single range when the chain head is unknown.
+async fn backfill_ranges(from_block: i64, chunks: usize) -> Vec<(i64, Option<i64>)> {
+    if chunks == 1 {
+        return vec![(from_block, None)];
+    }
+    let head = match FuelNodeClient::from_env() {
+        Some(node) => node.latest_block().await.ok(),
+        None => None,
+    };
+    let Some(head) = head.filter(|head| head.height > from_block) else {
+        warn!("Chain head unknown, backfilling in a single request");
+        return vec![(from_block, None)];
+    };
+    let size = (head.height - from_block + 1 + chunks as i64 - 1) / chunks as i64;
+    let mut ranges: Vec<(i64, Option<i64>)> = (0..chunks as i64)
+        .map(|chunk| from_block + chunk * size)
+        .filter(|&start| start <= head.height)
+        .map(|start| (start, Some(start + size - 1)))
+        .collect();
+    if let Some(last) = ranges.last_mut() {
+        last.1 = None;
+    }
+    ranges
+}
+
+/// Every event in one backfill range. The events are held until the range
+/// is complete; `false` means the stream broke off and they stop early.
+async fn fetch_backfill_chunk(
+    client: &PangeaClient,
+    ctx: &IndexerContext,
+    range: (i64, Option<i64>),
+    arrow: bool,
+) -> Result<(Vec<PangeaOrderEvent>, bool), Error> {
+    let mut stream = client
+        .spark_orders_by_format(
+            historical_request(ctx, range),
+            historical_format(arrow),
+            false,
+        )
+        .await?;
+    let mut orders = vec![];
+    while let Some(data) = stream.next().await {
+        match data {
+            Ok(data) => orders.extend(decode_historical(data, arrow)?),
+            Err(e) => {
+                error!(
+                    "Error in the stream of historical orders from block {}: {e}",
+                    range.0
+                );
+                return Ok((orders, false));
+            }
+        }
+    }
+    Ok((orders, true))
+}
+
+fn historical_request(
+    ctx: &IndexerContext,
+    (from, to): (i64, Option<i64>),
+) -> GetSparkOrderRequest {
+    GetSparkOrderRequest {
+        from_block: Bound::Exact(from),
+        to_block: to.map_or(Bound::Latest, Bound::Exact),
+        market_id__in: ctx.market_ids.clone(),
+        ..Default::default()
+    }
+}
+
+fn historical_format(arrow: bool) -> Format {
+    if arrow {
+        Format::Arrow
+    } else {
+        Format::JsonStream
+    }
+}
+
+fn decode_historical(data: Vec<u8>, arrow: bool) -> Result<Vec<PangeaOrderEvent>, Error> {
+    if arrow {
+        decode_order_events(&data)
+    } else {
+        Ok(vec![PangeaOrderEvent::from_json(data)?])
+    }
+}
+
+/// Follows new blocks. Tracks the position of the last applied event so a
+/// reconnect can re-read the block it broke off in without applying
+/// anything twice. Spark events are sparse, so a jump in block number
+/// between two of them is normal; events the subscription skipped are
+/// found by the progress probe instead, which fetches the blocks past the
+/// stream's confirmed progress with a bounded request. Events are applied a block
+/// at a time, in chain order; a block still buffered when the stream breaks
+/// is dropped and re-read after reconnecting. Repeated failures switch to
+/// the next Pangea endpoint, resuming from the same position, and so does
+/// a credential rotation, which rebuilds the client. A connection that
+/// failed is evicted from the pool and replaced. Over HTTP the same
+/// requests run up to the latest block on every poll.
 async fn listen_for_new_deltas(
-    client: &Client<WsProvider>,
-    order_book: &Arc<OrderBook>,
-    mut last_processed_block: i64,
-    contract_h256: H256,
+    mut client: Arc<PangeaClient>,
+    mut endpoints: PangeaEndpoints,
+    ctx: &IndexerContext,
+    last_processed_block: i64,
 ) -> Result<(), Error> {
+    let mut last_applied = block_end(last_processed_block);
+    let mut buffer = BlockBuffer::from_env()?;
+    let mut backoff = Backoff::from_env()?;
+    let mut rotations = credential_rotations();
+    let mut rotated = false;
+    let mut broken = false;
+    let mut probe = endpoints.probe_interval.map(tokio::time::interval);
+    let mut probe_head = None;
     loop {
+        let failed = std::mem::take(&mut broken);
+        if failed {
+            connection_pool().evict(&client);
+        }
+        if std::mem::take(&mut rotated) || rotations.has_changed().unwrap_or(false) {
+            rotations.borrow_and_update();
+            match endpoints.connect().await {
+                Ok(next) => client = next,
+                Err(e) => error!("Failed to reconnect to Pangea with new credentials: {e}"),
+            }
+        } else if endpoints.should_fail_over(backoff.attempt()) {
+            match endpoints.fail_over().await {
+                Ok(next) => client = next,
+                Err(e) => error!("Failed to fail over to another Pangea endpoint: {e}"),
+            }
+        } else if failed {
+            match endpoints.connect().await {
+                Ok(next) => client = next,
+                Err(e) => error!("Failed to reconnect to Pangea: {e}"),
+            }
+        } else if client.is_polling() {
+            if let Some(ws) = endpoints.upgrade().await {
+                client = ws;
+            }
+        }
+        let polling = client.is_polling();
+        let head = ctx.status.chain_head();
         let request_deltas = GetSparkOrderRequest {
-            from_block: Bound::Exact(last_processed_block + 1),
-            to_block: Bound::Subscribe,
-            market_id__in: HashSet::from([contract_h256]),
+            from_block: Bound::Exact(last_applied.block_number),
+            to_block: if polling {
+                Bound::Latest
+            } else {
+                Bound::Subscribe
+            },
+            market_id__in: ctx.market_ids.clone(),
             ..Default::default()
         };
 
-        let stream_deltas = client
-            .get_fuel_spark_orders_by_format(request_deltas, Format::JsonStream, true)
-            .await
-            .expect("Failed to get fuel spark deltas");
-
-        pangea_client::futures::pin_mut!(stream_deltas);
+        let mut stream_deltas = match client.spark_orders(request_deltas, !polling).await {
+            Ok(stream_deltas) => stream_deltas,
+            Err(e) => {
+                error!("Failed to subscribe to new orders (deltas): {e}");
+                broken = true;
+                wait_to_reconnect(ctx, &mut backoff).await?;
+                continue;
+            }
+        };
 
-        while let Some(data) = stream_deltas.next().await {
-            match data {
-                Ok(data) => {
-                    let data = String::from_utf8(data)?;
-                    let order: PangeaOrderEvent = serde_json::from_str(&data)?;
-                    last_processed_block = order.block_number;
-                    handle_order_event(order_book.clone(), order).await;
+        let mut poll_completed = false;
+        let mut last_message = Instant::now();
+        loop {
+            let window = (!buffer.is_empty()).then(|| buffer.window());
+            let read = async {
+                match window {
+                    Some(window) => tokio::time::timeout(window, stream_deltas.next()).await,
+                    None => Ok(stream_deltas.next().await),
                 }
-                Err(e) => {
+            };
+            let stale = async {
+                match endpoints.stale_after {
+                    Some(after) => {
+                        tokio::time::sleep(after.saturating_sub(last_message.elapsed())).await
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            let probe_tick = async {
+                match probe.as_mut().filter(|_| !polling) {
+                    Some(probe) => {
+                        probe.tick().await;
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            let next = tokio::select! {
+                read = read => read,
+                Ok(()) = rotations.changed() => {
+                    rotated = true;
+                    break;
+                }
+                () = stale => {
+                    warn!(
+                        "No new orders (deltas) for {} in {:?}, reconnecting from block {}",
+                        ctx.status.market_id(),
+                        last_message.elapsed(),
+                        last_applied.block_number
+                    );
+                    // The stream was up, just quiet; a quiet market must not
+                    // use up reconnect attempts or fail over.
+                    backoff.reset();
+                    break;
+                }
+                () = probe_tick => {
+                    // A probe only covers blocks the subscription had a
+                    // probe interval to deliver, and none half-buffered.
+                    let to = std::mem::replace(
+                        &mut probe_head,
+                        ctx.status.chain_head().map(|head| head.block_number),
+                    );
+                    if let Some(to) = to.filter(|_| buffer.is_empty()) {
+                        match probe_progress(&client, ctx, to, &mut last_applied).await {
+                            Ok(Some(rollback_block)) => {
+                                last_applied = block_end(rollback_block);
+                                break;
+                            }
+                            Ok(None) => {}
+                            Err(e) => warn!(
+                                "Failed to probe the blocks up to {} for {}: {e}",
+                                to,
+                                ctx.status.market_id()
+                            ),
+                        }
+                    }
+                    continue;
+                }
+                blocks = ctx.resyncs.next() => {
+                    match ctx.resync(blocks) {
+                        Some(rollback_block) => {
+                            last_applied = block_end(rollback_block);
+                            break;
+                        }
+                        None => continue,
+                    }
+                }
+            };
+            let next = match next {
+                Ok(next) => next,
+                Err(_) => {
+                    // The stream went quiet, so the buffered block is complete.
+                    let events = buffer.flush();
+                    if let Some(rollback_block) =
+                        apply_in_order(ctx, events, &mut last_applied).await
+                    {
+                        last_applied = block_end(rollback_block);
+                        break;
+                    }
+                    continue;
+                }
+            };
+            match next {
+                Some(Ok(data)) => {
+                    backoff.reset();
+                    last_message = Instant::now();
+                    let order = PangeaOrderEvent::from_json(data)?;
+                    let events = buffer.push(order);
+                    if let Some(rollback_block) =
+                        apply_in_order(ctx, events, &mut last_applied).await
+                    {
+                        last_applied = block_end(rollback_block);
+                        break;
+                    }
+                }
+                Some(Err(e)) => {
                     error!("Error in the stream of new orders (deltas): {e}");
+                    broken = true;
                     break;
                 }
+                None => {
+                    poll_completed = polling;
+                    break;
+                }
+            }
+        }
+        if poll_completed {
+            // A poll ends at the latest block, so the buffered block is
+            // complete, and the stream is caught up to the head known when
+            // it was sent.
+            backoff.reset();
+            let events = buffer.flush();
+            if let Some(rollback_block) = apply_in_order(ctx, events, &mut last_applied).await {
+                last_applied = block_end(rollback_block);
+            } else if let Some(head) = head {
+                ctx.status.confirm_stream_block(head.block_number);
             }
+            tokio::time::sleep(endpoints.poll_interval).await;
+            continue;
+        }
+        buffer.flush();
+        if rotated {
+            continue;
+        }
+
+        wait_to_reconnect(ctx, &mut backoff).await?;
+    }
+}
+
+/// Sleeps for the next backoff delay before reconnecting. Fails once the
+/// configured number of consecutive attempts is used up.
+async fn wait_to_reconnect(ctx: &IndexerContext, backoff: &mut Backoff) -> Result<(), Error> {
+    let market_id = ctx.status.market_id();
+    let Some(delay) = backoff.next_delay() else {
+        return Err(Error::ReconnectAttemptsExhausted(
+            market_id.to_owned(),
+            backoff.attempt(),
+        ));
+    };
+    metrics().record_reconnect(market_id);
+    warn!(
+        "Reconnecting to Pangea for {} in {:?}, attempt {}",
+        market_id,
+        delay,
+        backoff.attempt()
+    );
+    tokio::time::sleep(delay).await;
+    Ok(())
+}
+
+/// Applies events released by the block buffer, skipping repeats. Returns
+/// the rollback block if an event revealed a reorg.
+async fn apply_in_order(
+    ctx: &IndexerContext,
+    events: Vec<PangeaOrderEvent>,
+    last_applied: &mut EventIndex,
+) -> Option<i64> {
+    for order in events {
+        if ctx.already_applied(&order, *last_applied) {
+            continue;
+        }
+        *last_applied = order.index();
+        if let Some(rollback_block) = ctx.apply_event(order).await {
+            return Some(rollback_block);
+        }
+    }
+    ctx.apply_pending();
+    None
+}
+
+/// Fetches the blocks from the stream's progress up to `to` and applies any
+/// events the subscription has not delivered, then counts the stream as
+/// caught up to `to`. Returns the rollback block if one of them revealed a
+/// reorg.
+async fn probe_progress(
+    client: &PangeaClient,
+    ctx: &IndexerContext,
+    to: i64,
+    last_applied: &mut EventIndex,
+) -> Result<Option<i64>, Error> {
+    let from = ctx.status.stream_block() + 1;
+    if to < from {
+        return Ok(None);
+    }
+    if let Some(rollback_block) = backfill_gap(client, ctx, (from, to), last_applied).await? {
+        return Ok(Some(rollback_block));
+    }
+    ctx.apply_pending();
+    ctx.status.confirm_stream_block(to);
+    Ok(None)
+}
+
+/// Fetches the blocks in `gap` (inclusive) and applies any events the delta
+/// stream skipped, in chain order. Returns the rollback block if one of
+/// them revealed a reorg.
+async fn backfill_gap(
+    client: &PangeaClient,
+    ctx: &IndexerContext,
+    (from, to): (i64, i64),
+    last_applied: &mut EventIndex,
+) -> Result<Option<i64>, Error> {
+    let request = GetSparkOrderRequest {
+        from_block: Bound::Exact(from),
+        to_block: Bound::Exact(to),
+        market_id__in: ctx.market_ids.clone(),
+        ..Default::default()
+    };
+    let mut stream = client.spark_orders(request, false).await?;
+
+    let mut events = vec![];
+    while let Some(data) = stream.next().await {
+        events.push(PangeaOrderEvent::from_json(data?)?);
+    }
+    events.sort_by_key(|order| order.index());
+
+    let mut recovered = 0;
+    for order in events {
+        if ctx.already_applied(&order, *last_applied) {
+            continue;
+        }
+        recovered += 1;
+        *last_applied = order.index();
+        if let Some(rollback_block) = ctx.apply_event(order).await {
+            return Ok(Some(rollback_block));
         }
+    }
+    if recovered > 0 {
+        warn!(
+            "Recovered {} events for {} that the delta stream skipped in blocks {}..={}",
+            recovered,
+            ctx.status.market_id(),
+            from,
+            to
+        );
+    }
+    Ok(None)
+}
 
-        info!("Reconnecting to listen for new deltas...");
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+/// A position after every event of `block_number`.
+fn block_end(block_number: i64) -> EventIndex {
+    EventIndex {
+        block_number,
+        transaction_index: u64::MAX,
+        log_index: u64::MAX,
     }
 }
@@ -0,0 +1,138 @@
+use log::{error, info};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{OnceLock, RwLock};
+use std::time::SystemTime;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::time::{self, Duration};
+
+use crate::config::env::{ev, ev_opt};
+use crate::error::Error;
+
+const DEFAULT_CREDENTIALS_POLL_SECS: u64 = 30;
+
+/// The login for Pangea. Looked up on every connect, so a rotated password
+/// is picked up by the next client built.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub struct PangeaCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl PangeaCredentials {
+    /// From the JSON object (`username`, `password`) in
+    /// `PANGEA_CREDENTIALS_FILE` when set, otherwise from
+    /// `PANGEA_USERNAME`/`PANGEA_PASSWORD`.
+    pub fn load() -> Result<Self, Error> {
+        match ev_opt("PANGEA_CREDENTIALS_FILE") {
+            Some(path) => {
+                let bytes = fs::read(&path).map_err(|e| Error::FileError(path, e))?;
+                Ok(serde_json::from_slice(&bytes)?)
+            }
+            None => Ok(PangeaCredentials {
+                username: ev("PANGEA_USERNAME")?,
+                password: ev("PANGEA_PASSWORD")?,
+            }),
+        }
+    }
+
+    /// The credentials to connect with: the ones last reloaded, otherwise
+    /// as `load` reads them.
+    pub fn current() -> Result<Self, Error> {
+        match CURRENT.read().unwrap().clone() {
+            Some(credentials) => Ok(credentials),
+            None => Self::load(),
+        }
+    }
+
+    /// Like `load`, but with `PANGEA_USERNAME`/`PANGEA_PASSWORD` as `.env`
+    /// has them now rather than as the process started with, which
+    /// `dotenv` alone never overrides.
+    fn load_with_dotenv() -> Result<Self, Error> {
+        if ev_opt("PANGEA_CREDENTIALS_FILE").is_some() {
+            return Self::load();
+        }
+        let vars: HashMap<String, String> = dotenv::dotenv_iter()
+            .map(|vars| vars.flatten().collect())
+            .unwrap_or_default();
+        let var = |name: &str| match vars.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => ev(name),
+        };
+        Ok(PangeaCredentials {
+            username: var("PANGEA_USERNAME")?,
+            password: var("PANGEA_PASSWORD")?,
+        })
+    }
+}
+
+/// The credentials as last reloaded, kept here rather than written back to
+/// the process environment, which other threads read concurrently.
+static CURRENT: RwLock<Option<PangeaCredentials>> = RwLock::new(None);
+
+static ROTATIONS: OnceLock<watch::Sender<u64>> = OnceLock::new();
+
+fn rotations() -> &'static watch::Sender<u64> {
+    ROTATIONS.get_or_init(|| watch::channel(0).0)
+}
+
+/// Changes whenever the credentials do; Pangea clients built before that
+/// should be replaced.
+pub fn credential_rotations() -> watch::Receiver<u64> {
+    rotations().subscribe()
+}
+
+/// Watches for new Pangea credentials: on SIGHUP, which also re-reads
+/// `PANGEA_USERNAME`/`PANGEA_PASSWORD` from `.env`, and, with
+/// `PANGEA_CREDENTIALS_FILE` set, whenever the file changes, checked every
+/// `PANGEA_CREDENTIALS_POLL_SECS` (default 30).
+pub fn initialize_credential_reload(
+    tasks: &mut Vec<tokio::task::JoinHandle<()>>,
+) -> Result<(), Error> {
+    let interval = match ev_opt("PANGEA_CREDENTIALS_POLL_SECS") {
+        Some(value) => Duration::from_secs(value.parse()?),
+        None => Duration::from_secs(DEFAULT_CREDENTIALS_POLL_SECS),
+    };
+    let current = PangeaCredentials::load()?;
+    let mut hangups = signal(SignalKind::hangup())?;
+
+    tasks.push(tokio::spawn(async move {
+        let mut current = current;
+        let mut modified = secrets_file_modified();
+        let mut ticker = time::interval(interval);
+        loop {
+            let reloaded = tokio::select! {
+                _ = ticker.tick() => {
+                    let now = secrets_file_modified();
+                    if now == modified {
+                        continue;
+                    }
+                    modified = now;
+                    PangeaCredentials::load()
+                }
+                _ = hangups.recv() => {
+                    info!("SIGHUP received, reloading Pangea credentials");
+                    PangeaCredentials::load_with_dotenv()
+                }
+            };
+            match reloaded {
+                Ok(credentials) if credentials != current => {
+                    info!("Pangea credentials changed, reconnecting");
+                    *CURRENT.write().unwrap() = Some(credentials.clone());
+                    current = credentials;
+                    rotations().send_modify(|generation| *generation += 1);
+                }
+                Ok(_) => {}
+                Err(e) => error!("Failed to reload Pangea credentials: {}", e),
+            }
+        }
+    }));
+    Ok(())
+}
+
+fn secrets_file_modified() -> Option<SystemTime> {
+    let path = ev_opt("PANGEA_CREDENTIALS_FILE")?;
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
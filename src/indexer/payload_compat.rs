@@ -0,0 +1,183 @@
+use log::{error, info};
+use serde_json::value::RawValue;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::Error;
+use crate::indexer::order_event_handler::PangeaOrderEvent;
+
+/// A payload's fields as written. Values are kept as raw JSON text so
+/// amounts past `u64::MAX` are compared digit for digit rather than
+/// rounded through `f64`.
+type Fields = BTreeMap<String, Box<RawValue>>;
+
+/// How one stored payload differs from what `PangeaOrderEvent` reads.
+#[derive(Debug, Default)]
+struct PayloadDiff {
+    /// Fields in the payload the model ignores.
+    unknown: Vec<String>,
+    /// Required model fields the payload lacks, read as their default.
+    /// Optional fields may be left out.
+    missing: Vec<String>,
+    /// Fields whose value does not survive a round trip, with both values.
+    changed: Vec<(String, String, String)>,
+}
+
+impl PayloadDiff {
+    fn is_empty(&self) -> bool {
+        self.unknown.is_empty() && self.missing.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Reads every raw Pangea payload in `corpus` (a JSON array, as written by
+/// `fixtures --raw`) through the current event model and fails if any is
+/// rejected or carries fields the model does not know, lacks fields it
+/// requires, or reads back differently. The committed corpus,
+/// `fixtures/payloads.json`, is checked by `cargo test`; refresh it after
+/// upgrading `pangea-client` to catch wire format changes before they
+/// reach the indexer.
+pub fn check_payloads(corpus: &Path) -> Result<(), Error> {
+    let bytes = fs::read(corpus).map_err(|e| Error::FileError(corpus.display().to_string(), e))?;
+    let payloads: Vec<Box<RawValue>> = serde_json::from_slice(&bytes)?;
+
+    let mut incompatible = 0;
+    for (position, payload) in payloads.iter().enumerate() {
+        let Ok(fields) = serde_json::from_str::<Fields>(payload.get()) else {
+            error!("payload {}: not a JSON object", position);
+            incompatible += 1;
+            continue;
+        };
+        let label = describe(position, &fields);
+        let event = match PangeaOrderEvent::from_json(payload.get().as_bytes().to_vec()) {
+            Ok(event) => event,
+            Err(e) => {
+                error!("{}: rejected: {}", label, e);
+                incompatible += 1;
+                continue;
+            }
+        };
+        let read: Fields = serde_json::from_str(&serde_json::to_string(&event)?)?;
+
+        let diff = diff_fields(&fields, &read);
+        if diff.is_empty() {
+            continue;
+        }
+        incompatible += 1;
+        for field in &diff.unknown {
+            error!("{}: unknown field `{}`", label, field);
+        }
+        for field in &diff.missing {
+            error!("{}: missing field `{}`", label, field);
+        }
+        for (field, sent, read) in &diff.changed {
+            error!(
+                "{}: `{}` sent as {} but read as {}",
+                label, field, sent, read
+            );
+        }
+    }
+
+    if incompatible > 0 {
+        return Err(Error::IncompatiblePayloads(incompatible, payloads.len()));
+    }
+    info!(
+        "All {} payloads in {} match the event model",
+        payloads.len(),
+        corpus.display()
+    );
+    Ok(())
+}
+
+fn diff_fields(sent: &Fields, read: &Fields) -> PayloadDiff {
+    let mut diff = PayloadDiff::default();
+    for (field, value) in sent {
+        match read.get(field) {
+            None => diff.unknown.push(field.clone()),
+            Some(read_value) if !same_value(value, read_value) => diff.changed.push((
+                field.clone(),
+                value.get().to_owned(),
+                read_value.get().to_owned(),
+            )),
+            Some(_) => {}
+        }
+    }
+    // Absent optional fields read as null.
+    diff.missing = read
+        .iter()
+        .filter(|(field, value)| !sent.contains_key(*field) && value.get() != "null")
+        .map(|(field, _)| field.clone())
+        .collect();
+    diff
+}
+
+/// Equal, counting a number and its decimal string as the same: Pangea
+/// sends large amounts either way. Strings compare by content, everything
+/// else by its text.
+fn same_value(sent: &RawValue, read: &RawValue) -> bool {
+    let text = |value: &RawValue| {
+        serde_json::from_str::<String>(value.get()).unwrap_or_else(|_| value.get().to_owned())
+    };
+    text(sent) == text(read)
+}
+
+fn describe(position: usize, payload: &Fields) -> String {
+    let field = |name: &str| payload.get(name).map_or("?", |value| value.get());
+    format!(
+        "payload {} (block {}, log {})",
+        position,
+        field("block_number"),
+        field("log_index")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(json: &str) -> Fields {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn committed_payloads_match_the_event_model() {
+        let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/payloads.json");
+        check_payloads(&corpus).unwrap();
+    }
+
+    #[test]
+    fn optional_fields_may_be_left_out() {
+        let diff = diff_fields(
+            &fields(r#"{ "order_id": "0x1" }"#),
+            &fields(r#"{ "order_id": "0x1", "block_timestamp": null }"#),
+        );
+        assert!(diff.is_empty(), "{:?}", diff);
+    }
+
+    #[test]
+    fn large_amounts_read_back_unchanged() {
+        let sent = fields(r#"{ "amount": 25000000000000000000001 }"#);
+        let read =
+            serde_json::to_string(&BTreeMap::from([("amount", 25000000000000000000001u128)]))
+                .unwrap();
+        assert!(diff_fields(&sent, &fields(&read)).is_empty());
+
+        let read = fields(r#"{ "amount": "25000000000000000000001" }"#);
+        assert!(diff_fields(&sent, &read).is_empty());
+
+        // Amounts this close would be equal as f64.
+        let read = fields(r#"{ "amount": 25000000000000000000000 }"#);
+        assert_eq!(diff_fields(&sent, &read).changed.len(), 1);
+    }
+
+    #[test]
+    fn reports_unknown_missing_and_changed_fields() {
+        let diff = diff_fields(
+            &fields(r#"{ "price": 10, "side": "Buy" }"#),
+            &fields(r#"{ "price": 11, "chain": 0 }"#),
+        );
+        assert_eq!(diff.unknown, ["side"]);
+        assert_eq!(diff.missing, ["chain"]);
+        assert_eq!(diff.changed.len(), 1);
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::undo::Change;
+
+const DEFAULT_REORG_DEPTH: i64 = 64;
+
+#[derive(Default)]
+struct Deltas {
+    /// The changes each block made, oldest first.
+    blocks: BTreeMap<i64, Vec<Change>>,
+    /// The oldest block the market can be rolled back to; `None` until the
+    /// indexer is live.
+    horizon: Option<i64>,
+}
+
+/// Spots Pangea re-emitting a different chain segment and keeps enough of
+/// the past to roll back over it.
+///
+/// The hash of every block with events for the market is remembered for
+/// twice `REORG_DEPTH` blocks (default 64). An event from a block before the
+/// one being applied, or from a remembered block under a different hash,
+/// means the chain forked there. The changes of the last `REORG_DEPTH`
+/// blocks, to the book and its statistics, are kept to undo a fork up to
+/// that deep.
+pub struct ReorgDetector {
+    depth: i64,
+    hashes: Mutex<BTreeMap<i64, String>>,
+    deltas: Mutex<Deltas>,
+}
+
+impl ReorgDetector {
+    pub fn from_env() -> Result<Self, Error> {
+        let depth = match ev_opt("REORG_DEPTH") {
+            Some(depth) => depth.parse()?,
+            None => DEFAULT_REORG_DEPTH,
+        };
+        Ok(ReorgDetector {
+            depth,
+            hashes: Mutex::new(BTreeMap::new()),
+            deltas: Mutex::new(Deltas::default()),
+        })
+    }
+
+    /// Records the hash of an event's block. Returns the block the chain
+    /// forked at if the event does not extend the branch applied so far.
+    pub fn observe(
+        &self,
+        block_number: i64,
+        block_hash: &str,
+        last_processed_block: i64,
+    ) -> Option<i64> {
+        let mut hashes = self.hashes.lock().unwrap();
+        match hashes.get(&block_number) {
+            Some(known) if known != block_hash => return Some(block_number),
+            _ if block_number < last_processed_block => return Some(block_number),
+            Some(_) => return None,
+            None => {}
+        }
+        hashes.insert(block_number, block_hash.to_owned());
+        let keep_from = block_number - 2 * self.depth;
+        *hashes = hashes.split_off(&keep_from);
+        None
+    }
+
+    /// Whether `block_number` was seen under `block_hash`.
+    pub fn is_known(&self, block_number: i64, block_hash: &str) -> bool {
+        self.hashes
+            .lock()
+            .unwrap()
+            .get(&block_number)
+            .is_some_and(|known| known == block_hash)
+    }
+
+    /// Starts keeping changes, with the book as of the end of
+    /// `block_number` as the oldest rollback point.
+    pub fn start(&self, block_number: i64) {
+        let mut deltas = self.deltas.lock().unwrap();
+        deltas.blocks.clear();
+        deltas.horizon = Some(block_number);
+    }
+
+    /// Keeps changes applied in `block_number`, once started.
+    pub fn record(&self, block_number: i64, changes: Vec<Change>) {
+        let mut deltas = self.deltas.lock().unwrap();
+        if deltas.horizon.is_some() && !changes.is_empty() {
+            deltas
+                .blocks
+                .entry(block_number)
+                .or_default()
+                .extend(changes);
+        }
+    }
+
+    /// Drops the changes of blocks more than `REORG_DEPTH` before
+    /// `block_number`, which moves the oldest rollback point up.
+    pub fn block_finished(&self, block_number: i64) {
+        let mut deltas = self.deltas.lock().unwrap();
+        let Some(horizon) = deltas.horizon else {
+            return;
+        };
+        let keep_from = block_number - self.depth + 1;
+        let kept = deltas.blocks.split_off(&keep_from);
+        deltas.blocks = kept;
+        deltas.horizon = Some(horizon.max(keep_from - 1));
+    }
+
+    /// The block a fork at `fork_block` rolls back to, if the changes since
+    /// are kept.
+    fn rollback_point(&self, fork_block: i64) -> Option<i64> {
+        let block_number = fork_block - 1;
+        let horizon = self.deltas.lock().unwrap().horizon?;
+        (block_number >= horizon).then_some(block_number)
+    }
+
+    /// The block before `fork_block` and the changes made since, oldest
+    /// first, or `None` if the fork is older than the oldest rollback point.
+    /// Hashes and changes from `fork_block` on are dropped.
+    pub fn rollback(&self, fork_block: i64) -> Option<(i64, Vec<Change>)> {
+        let block_number = self.rollback_point(fork_block)?;
+        let mut deltas = self.deltas.lock().unwrap();
+        self.hashes.lock().unwrap().split_off(&fork_block);
+        let undone = deltas.blocks.split_off(&fork_block);
+        Some((block_number, undone.into_values().flatten().collect()))
+    }
+}
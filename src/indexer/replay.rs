@@ -0,0 +1,167 @@
+use ethers_core::types::H256;
+use log::info;
+use pangea_client::{futures::StreamExt, query::Bound, requests::fuel::GetSparkOrderRequest};
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::env::ev;
+use crate::error::Error;
+use crate::indexer::order_event_handler::{
+    handle_order_event, handle_order_events, PangeaOrderEvent,
+};
+use crate::indexer::pangea::{create_pangea_client, PangeaClient};
+use crate::indexer::timestamp_normalizer::TimestampNormalizer;
+use crate::storage::backend::Storage;
+use crate::storage::event_log::EventLog;
+use crate::storage::market_registry::{MarketRegistry, MarketState};
+
+/// Applies every event in `[from_block, to_block]` to a fresh book.
+pub async fn replay_range(
+    client: &PangeaClient,
+    contract_h256: H256,
+    from_block: i64,
+    to_block: i64,
+) -> Result<Arc<dyn Storage>, Error> {
+    let request = GetSparkOrderRequest {
+        from_block: Bound::Exact(from_block),
+        to_block: Bound::Exact(to_block),
+        market_id__in: HashSet::from([contract_h256]),
+        ..Default::default()
+    };
+
+    let mut stream = client.spark_orders(request, false).await?;
+
+    let market = MarketState::new(format!("{:?}", contract_h256));
+    let registry = MarketRegistry::new(vec![market.clone()]);
+    let normalizer = TimestampNormalizer::new(0);
+    while let Some(data) = stream.next().await {
+        let data = data?;
+        let event = PangeaOrderEvent::from_json(data)?;
+        let time = normalizer.normalize(&event);
+        handle_order_event(&registry, event, time).await;
+    }
+
+    Ok(market.order_book)
+}
+
+/// Applies the logged events in `[from_block, to_block]` to a fresh book.
+pub async fn replay_logged_range(
+    event_log: &EventLog,
+    contract_h256: H256,
+    from_block: i64,
+    to_block: i64,
+) -> Result<Arc<dyn Storage>, Error> {
+    let market = MarketState::new(format!("{:?}", contract_h256));
+    let registry = MarketRegistry::new(vec![market.clone()]);
+    let normalizer = TimestampNormalizer::new(0);
+    for event in event_log.read(from_block, to_block)? {
+        let time = normalizer.normalize(&event);
+        handle_order_event(&registry, event, time).await;
+    }
+
+    Ok(market.order_book)
+}
+
+/// Rebuilds `market`'s book from its log, for restarts that shouldn't wait
+/// for a full backfill from Pangea: from the compaction snapshot, if any,
+/// and every event logged after it. The indexer then streams from the last
+/// logged block, skipping the events of it already applied. Returns that block, or `None` when the log is
+/// empty.
+pub fn rebuild_from_log(
+    registry: &MarketRegistry,
+    market: &MarketState,
+) -> Result<Option<i64>, Error> {
+    let event_log = market
+        .event_log
+        .as_ref()
+        .ok_or_else(|| Error::EnvVarError("EVENT_LOG_DIR".to_owned(), "not set".to_owned()))?;
+    let start_block: i64 = match market.start_block {
+        Some(start_block) => start_block,
+        None => ev("CONTRACT_START_BLOCK")?.parse()?,
+    };
+
+    market.order_book.clear();
+    let mut from_block = start_block;
+    let mut last_block = None;
+    if let Some(snapshot) = event_log.snapshot(market.market_id())? {
+        from_block = snapshot.block_number + 1;
+        last_block = Some(snapshot.block_number);
+        market.order_book.restore(snapshot);
+    }
+
+    let normalizer = TimestampNormalizer::from_env()?;
+    let events: Vec<_> = event_log
+        .read(from_block, i64::MAX)?
+        .into_iter()
+        .map(|mut event| {
+            // Logged as received, before migrations renamed them.
+            if let Some(market_id) = registry.migrated_to(&event.market_id) {
+                event.market_id = market_id;
+            }
+            let time = normalizer.normalize(&event);
+            (event, time)
+        })
+        .collect();
+    let Some(last_block) = events
+        .last()
+        .map(|(event, _)| event.block_number)
+        .or(last_block)
+    else {
+        info!("Event log of {} is empty", market.market_id());
+        return Ok(None);
+    };
+    let count = events.len();
+
+    handle_order_events(registry, events);
+    market.status.set_last_processed_block(last_block);
+    market
+        .status
+        .set_state_hash(last_block, market.order_book.state_hash());
+    info!(
+        "Rebuilt {} from {} logged events up to block {}",
+        market.market_id(),
+        count,
+        last_block
+    );
+    Ok(Some(last_block))
+}
+
+/// Replays the range twice and fails if the resulting books differ, which
+/// means the handler depends on something other than the events themselves.
+/// With `from_log` the events are read from the market's event log instead
+/// of Pangea.
+pub async fn verify_replay(
+    contract_id: &str,
+    from_block: i64,
+    to_block: i64,
+    from_log: bool,
+) -> Result<(), Error> {
+    let contract_h256 = H256::from_str(contract_id)?;
+
+    let (first, second) = if from_log {
+        let event_log = EventLog::from_env(contract_id)?
+            .ok_or_else(|| Error::EnvVarError("EVENT_LOG_DIR".to_owned(), "not set".to_owned()))?;
+        (
+            replay_logged_range(&event_log, contract_h256, from_block, to_block).await?,
+            replay_logged_range(&event_log, contract_h256, from_block, to_block).await?,
+        )
+    } else {
+        let client = create_pangea_client().await?;
+        (
+            replay_range(&client, contract_h256, from_block, to_block).await?,
+            replay_range(&client, contract_h256, from_block, to_block).await?,
+        )
+    };
+    let (first, second) = (first.state_hash(), second.state_hash());
+
+    if first != second {
+        return Err(Error::ReplayMismatch(first, second));
+    }
+
+    info!(
+        "Replay of blocks {}..={} is deterministic, state hash {}",
+        from_block, to_block, first
+    );
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// Partial resyncs asked for by an operator, picked up by the market's
+/// delta stream between reads. Requests made before one is picked up merge
+/// into the deepest of them.
+#[derive(Default)]
+pub struct ResyncRequests {
+    blocks: Mutex<Option<i64>>,
+    notify: Notify,
+}
+
+impl ResyncRequests {
+    /// Asks for the last `blocks` applied blocks to be fetched again.
+    pub fn request(&self, blocks: i64) {
+        let mut pending = self.blocks.lock().unwrap();
+        *pending = Some(pending.map_or(blocks, |pending| pending.max(blocks)));
+        drop(pending);
+        self.notify.notify_one();
+    }
+
+    /// Waits for a request and returns its depth in blocks. Dropping the
+    /// future before it completes leaves the request in place.
+    pub async fn next(&self) -> i64 {
+        loop {
+            if let Some(blocks) = self.blocks.lock().unwrap().take() {
+                return blocks;
+            }
+            self.notify.notified().await;
+        }
+    }
+}
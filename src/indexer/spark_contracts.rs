@@ -0,0 +1,108 @@
+use ethers_core::types::H256;
+use fuels::accounts::wallet::WalletUnlocked;
+use fuels::types::{AssetId, ContractId};
+use spark_market_sdk::SparkMarketContract;
+use spark_registry_sdk::SparkRegistryContract;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::RwLock;
+use tokio::sync::OnceCell;
+
+use crate::error::Error;
+use crate::indexer::fuel_node::FuelNodeClient;
+
+/// The assets a Spark market trades, as its contract is configured.
+pub struct MarketAssets {
+    pub base_asset: AssetId,
+    pub base_decimals: u32,
+    pub quote_asset: AssetId,
+    pub quote_decimals: u32,
+}
+
+/// Read-only calls to Spark's market and registry contracts through the
+/// Fuel node. They are made from a throwaway wallet without funds and
+/// never submit a transaction.
+pub struct SparkContracts {
+    wallet: WalletUnlocked,
+}
+
+impl SparkContracts {
+    pub async fn connect(node: &FuelNodeClient) -> Result<Self, Error> {
+        let provider = node.provider().await?.clone();
+        Ok(SparkContracts {
+            wallet: WalletUnlocked::new_random(Some(provider)),
+        })
+    }
+
+    pub async fn market_assets(&self, market: &H256) -> Result<MarketAssets, Error> {
+        let contract =
+            SparkMarketContract::new(ContractId::new(market.0), self.wallet.clone()).await;
+        let (base_asset, base_decimals, quote_asset, quote_decimals, ..) =
+            contract.config().await?.value;
+        Ok(MarketAssets {
+            base_asset,
+            base_decimals,
+            quote_asset,
+            quote_decimals,
+        })
+    }
+
+    /// The markets the registry at `registry` holds for `pairs` of base and
+    /// quote assets. Pairs without a market are left out.
+    pub async fn registered_markets(
+        &self,
+        registry: &H256,
+        pairs: Vec<(AssetId, AssetId)>,
+    ) -> Result<Vec<H256>, Error> {
+        let contract =
+            SparkRegistryContract::new(ContractId::new(registry.0), self.wallet.clone()).await;
+        Ok(contract
+            .markets(pairs)
+            .await?
+            .value
+            .into_iter()
+            .filter_map(|(_, _, market)| market)
+            .map(|market| H256(*market))
+            .collect())
+    }
+}
+
+/// The base and quote decimals of markets, read from each market's contract
+/// on first use and kept from then on.
+pub struct MarketDecimals {
+    node: Option<FuelNodeClient>,
+    contracts: OnceCell<SparkContracts>,
+    decimals: RwLock<HashMap<H256, (u32, u32)>>,
+}
+
+impl MarketDecimals {
+    pub fn from_env() -> Self {
+        MarketDecimals {
+            node: FuelNodeClient::from_env(),
+            contracts: OnceCell::new(),
+            decimals: RwLock::default(),
+        }
+    }
+
+    /// Fails without `FUEL_NODE_URL` or when the contract cannot be read.
+    pub async fn get(&self, market_id: &str) -> Result<(u32, u32), Error> {
+        let market = H256::from_str(market_id)?;
+        if let Some(decimals) = self.decimals.read().unwrap().get(&market) {
+            return Ok(*decimals);
+        }
+        let Some(node) = &self.node else {
+            return Err(Error::EnvVarError(
+                "FUEL_NODE_URL".to_owned(),
+                "required to read market decimals".to_owned(),
+            ));
+        };
+        let contracts = self
+            .contracts
+            .get_or_try_init(|| SparkContracts::connect(node))
+            .await?;
+        let assets = contracts.market_assets(&market).await?;
+        let decimals = (assets.base_decimals, assets.quote_decimals);
+        self.decimals.write().unwrap().insert(market, decimals);
+        Ok(decimals)
+    }
+}
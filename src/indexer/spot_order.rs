@@ -1,13 +1,26 @@
+use async_graphql::Enum;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::web::timestamps::{serialize_ms, serialize_opt_ms};
+
 // NTD Adapt spark-sdk OrderType to that type
-#[derive(Debug, PartialEq, Eq, Clone, Copy, JsonSchema, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, JsonSchema, Serialize, Deserialize, Enum)]
+#[graphql(rename_items = "PascalCase")]
 pub enum OrderType {
     Buy,
     Sell,
 }
 
+impl OrderType {
+    pub fn opposite(self) -> Self {
+        match self {
+            OrderType::Buy => OrderType::Sell,
+            OrderType::Sell => OrderType::Buy,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, JsonSchema, Serialize, Deserialize)]
 pub enum LimitType {
     FOK,
@@ -27,13 +40,25 @@ pub enum OrderStatus {
 #[derive(Debug, Clone, JsonSchema, Serialize, Deserialize, Eq)]
 pub struct SpotOrder {
     pub id: String,
+    #[serde(default)]
+    pub market_id: String,
     pub user: String,
     pub asset: String,
     pub amount: u128,
     pub price: u128,
+    #[serde(serialize_with = "serialize_ms")]
     pub timestamp: u64,
+    #[serde(serialize_with = "serialize_opt_ms")]
+    pub raw_timestamp: Option<u64>,
     pub order_type: OrderType,
     pub status: Option<OrderStatus>,
+    /// Arrival sequence assigned by the book; within a price level, a lower
+    /// value is ahead in the matching queue.
+    #[serde(default)]
+    pub priority: u64,
+    /// Block the order was opened in.
+    #[serde(default)]
+    pub block_number: i64,
 }
 
 impl PartialEq for SpotOrder {
@@ -46,7 +71,8 @@ impl Ord for SpotOrder {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.price
             .cmp(&other.price)
-            .then_with(|| self.timestamp.cmp(&other.timestamp)) 
+            .then_with(|| self.timestamp.cmp(&other.timestamp))
+            .then_with(|| self.priority.cmp(&other.priority))
     }
 }
 
@@ -0,0 +1,295 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use crate::config::env::ev_opt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SyncPhase {
+    Starting,
+    Backfilling,
+    Live,
+}
+
+/// Blocks that must follow an order's block before the order counts as
+/// confirmed, from `CONFIRMATION_DEPTH` (default 0, everything confirmed).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfirmationDepth(pub i64);
+
+impl ConfirmationDepth {
+    pub fn from_env() -> Self {
+        ConfirmationDepth(
+            ev_opt("CONFIRMATION_DEPTH")
+                .and_then(|depth| depth.parse().ok())
+                .unwrap_or(0),
+        )
+    }
+}
+
+/// Seconds of events the activity rate is averaged over.
+const ACTIVITY_WINDOW_SECS: u64 = 60;
+/// Events per second from which a market counts as medium or high activity.
+const MEDIUM_ACTIVITY_EPS: f64 = 1.0;
+const HIGH_ACTIVITY_EPS: f64 = 10.0;
+
+/// How busy a market is, for clients that adapt their render rate.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ActivityLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl ActivityLevel {
+    pub fn from_rate(events_per_second: f64) -> Self {
+        if events_per_second >= HIGH_ACTIVITY_EPS {
+            ActivityLevel::High
+        } else if events_per_second >= MEDIUM_ACTIVITY_EPS {
+            ActivityLevel::Medium
+        } else {
+            ActivityLevel::Low
+        }
+    }
+}
+
+/// Progress of the indexer for one market, shared with the API.
+pub struct IndexerStatus {
+    market_id: String,
+    last_processed_block: AtomicI64,
+    /// Latest block the stream is known to have delivered every event of,
+    /// whether or not any was for the market.
+    stream_block: AtomicI64,
+    phase: RwLock<SyncPhase>,
+    state_hash: RwLock<Option<BlockStateHash>>,
+    chain_head: RwLock<Option<ChainHead>>,
+    /// Live events per local second, oldest first, for the last minute.
+    event_seconds: Mutex<VecDeque<(u64, u32)>>,
+    backfill: RwLock<Option<Backfill>>,
+    backfill_events: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Backfill {
+    start_block: i64,
+    started_at: u64,
+    finished_at: Option<u64>,
+}
+
+/// Where the historical sync stands. The total, and so the ETA, is only
+/// known once a chain head has been observed.
+#[derive(Debug, Clone, Copy)]
+pub struct SyncProgress {
+    pub start_block: i64,
+    pub current_block: i64,
+    pub target_block: Option<i64>,
+    pub events: u64,
+    pub elapsed_ms: u64,
+    pub finished: bool,
+}
+
+impl SyncProgress {
+    pub fn blocks_processed(&self) -> i64 {
+        (self.current_block - self.start_block + 1).max(0)
+    }
+
+    pub fn total_blocks(&self) -> Option<i64> {
+        self.target_block
+            .map(|target| (target - self.start_block + 1).max(0))
+    }
+
+    pub fn events_per_second(&self) -> f64 {
+        if self.elapsed_ms == 0 {
+            return 0.0;
+        }
+        self.events as f64 * 1000.0 / self.elapsed_ms as f64
+    }
+
+    /// Seconds left at the block rate so far.
+    pub fn eta_secs(&self) -> Option<u64> {
+        if self.finished {
+            return Some(0);
+        }
+        let processed = self.blocks_processed();
+        if processed == 0 {
+            return None;
+        }
+        let remaining = (self.total_blocks()? - processed).max(0);
+        Some((remaining as u128 * self.elapsed_ms as u128 / processed as u128 / 1000) as u64)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStateHash {
+    pub block_number: i64,
+    pub hash: String,
+}
+
+/// Latest block known to the node, observed independently of Spark events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainHead {
+    pub block_number: i64,
+    /// Block time in Unix seconds, when the node reported one.
+    pub block_timestamp: Option<i64>,
+    /// Local time in milliseconds when the head was last polled.
+    pub observed_at: u64,
+}
+
+impl IndexerStatus {
+    pub fn new(market_id: String) -> Self {
+        IndexerStatus {
+            market_id,
+            last_processed_block: AtomicI64::new(0),
+            stream_block: AtomicI64::new(0),
+            phase: RwLock::new(SyncPhase::Starting),
+            state_hash: RwLock::new(None),
+            chain_head: RwLock::new(None),
+            event_seconds: Mutex::new(VecDeque::new()),
+            backfill: RwLock::new(None),
+            backfill_events: AtomicU64::new(0),
+        }
+    }
+
+    pub fn market_id(&self) -> &str {
+        &self.market_id
+    }
+
+    pub fn last_processed_block(&self) -> i64 {
+        self.last_processed_block.load(Ordering::SeqCst)
+    }
+
+    pub fn set_last_processed_block(&self, block: i64) {
+        self.last_processed_block.store(block, Ordering::SeqCst);
+    }
+
+    /// Records that the stream delivered every event up to `block_number`.
+    pub fn confirm_stream_block(&self, block_number: i64) {
+        self.stream_block.fetch_max(block_number, Ordering::SeqCst);
+    }
+
+    /// Takes back confirmations after `block_number`, for a rollback.
+    pub fn rewind_stream_block(&self, block_number: i64) {
+        self.stream_block.fetch_min(block_number, Ordering::SeqCst);
+    }
+
+    /// The latest block the indexer is caught up to: the last one applied,
+    /// or a later one up to which the stream confirmed there was nothing.
+    pub fn stream_block(&self) -> i64 {
+        self.stream_block
+            .load(Ordering::SeqCst)
+            .max(self.last_processed_block())
+    }
+
+    pub fn phase(&self) -> SyncPhase {
+        *self.phase.read().unwrap()
+    }
+
+    pub fn set_phase(&self, phase: SyncPhase) {
+        *self.phase.write().unwrap() = phase;
+    }
+
+    /// Canonical book hash as of the end of the latest completed block.
+    pub fn state_hash(&self) -> Option<BlockStateHash> {
+        self.state_hash.read().unwrap().clone()
+    }
+
+    pub fn set_state_hash(&self, block_number: i64, hash: String) {
+        *self.state_hash.write().unwrap() = Some(BlockStateHash { block_number, hash });
+    }
+
+    pub fn chain_head(&self) -> Option<ChainHead> {
+        *self.chain_head.read().unwrap()
+    }
+
+    pub fn set_chain_head(&self, head: ChainHead) {
+        *self.chain_head.write().unwrap() = Some(head);
+    }
+
+    /// Whether `depth` blocks follow `block_number`, counting up to the chain
+    /// head when one is known and to the last processed block otherwise.
+    pub fn is_confirmed(&self, block_number: i64, depth: ConfirmationDepth) -> bool {
+        let tip = self
+            .chain_head()
+            .map_or(0, |head| head.block_number)
+            .max(self.last_processed_block());
+        block_number + depth.0 <= tip
+    }
+
+    /// Counts a live event applied at local time `now` (ms).
+    pub fn record_event(&self, now: u64) {
+        let second = now / 1000;
+        let mut seconds = self.event_seconds.lock().unwrap();
+        match seconds.back_mut() {
+            Some((last, count)) if *last == second => *count += 1,
+            _ => seconds.push_back((second, 1)),
+        }
+        while seconds
+            .front()
+            .is_some_and(|(first, _)| first + ACTIVITY_WINDOW_SECS <= second)
+        {
+            seconds.pop_front();
+        }
+    }
+
+    /// Live events per second over the minute before `now` (ms).
+    pub fn events_per_second(&self, now: u64) -> f64 {
+        let second = now / 1000;
+        let events: u32 = self
+            .event_seconds
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(at, _)| at + ACTIVITY_WINDOW_SECS > second)
+            .map(|(_, count)| count)
+            .sum();
+        events as f64 / ACTIVITY_WINDOW_SECS as f64
+    }
+
+    pub fn activity_level(&self, now: u64) -> ActivityLevel {
+        ActivityLevel::from_rate(self.events_per_second(now))
+    }
+
+    /// Marks the start of the historical sync from `start_block` at local
+    /// time `now` (ms).
+    pub fn start_backfill(&self, start_block: i64, now: u64) {
+        self.backfill_events.store(0, Ordering::SeqCst);
+        *self.backfill.write().unwrap() = Some(Backfill {
+            start_block,
+            started_at: now,
+            finished_at: None,
+        });
+    }
+
+    pub fn record_backfill_event(&self) {
+        self.backfill_events.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn finish_backfill(&self, now: u64) {
+        if let Some(backfill) = self.backfill.write().unwrap().as_mut() {
+            backfill.finished_at = Some(now);
+        }
+    }
+
+    /// Progress of the historical sync as of `now` (ms), once it started.
+    pub fn sync_progress(&self, now: u64) -> Option<SyncProgress> {
+        let backfill = (*self.backfill.read().unwrap())?;
+        Some(SyncProgress {
+            start_block: backfill.start_block,
+            current_block: self.last_processed_block(),
+            target_block: self.chain_head().map(|head| head.block_number),
+            events: self.backfill_events.load(Ordering::SeqCst),
+            elapsed_ms: backfill
+                .finished_at
+                .unwrap_or(now)
+                .saturating_sub(backfill.started_at),
+            finished: backfill.finished_at.is_some(),
+        })
+    }
+
+    /// How many blocks the indexer trails the chain tip by. Counted from
+    /// the stream's progress, so a quiet market that is caught up is not
+    /// behind.
+    pub fn blocks_behind(&self) -> Option<i64> {
+        let head = self.chain_head()?;
+        Some((head.block_number - self.stream_block()).max(0))
+    }
+}
@@ -0,0 +1,85 @@
+use chrono::Utc;
+use log::warn;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::order_event_handler::PangeaOrderEvent;
+
+const DEFAULT_CLOCK_SKEW_TOLERANCE_MS: u64 = 5_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventTime {
+    /// Block timestamp reported with the event, in milliseconds.
+    pub raw: Option<u64>,
+    /// Timestamp used by storage, candles and stats.
+    pub normalized: u64,
+}
+
+/// Maps event timestamps onto a single monotonic timeline.
+///
+/// Times come from block timestamps; one that lies further in the future
+/// than the tolerance is clamped to now, and the result never goes
+/// backwards, so trades can't land in an already closed bar. An event whose
+/// block has no timestamp, even from the node, takes the time of the event
+/// before it rather than the local clock, so replays give the same times
+/// and historical trades are not stamped with when they were indexed.
+pub struct TimestampNormalizer {
+    tolerance_ms: u64,
+    last_normalized: AtomicU64,
+}
+
+impl TimestampNormalizer {
+    pub fn new(tolerance_ms: u64) -> Self {
+        TimestampNormalizer {
+            tolerance_ms,
+            last_normalized: AtomicU64::new(0),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, Error> {
+        let tolerance_ms = match ev_opt("CLOCK_SKEW_TOLERANCE_MS") {
+            Some(value) => value.parse()?,
+            None => DEFAULT_CLOCK_SKEW_TOLERANCE_MS,
+        };
+        Ok(Self::new(tolerance_ms))
+    }
+
+    /// Continues the timeline from `timestamp`, the latest time already
+    /// stored, for a book restored from a checkpoint.
+    pub fn resume_from(&self, timestamp: u64) {
+        self.last_normalized.fetch_max(timestamp, Ordering::SeqCst);
+    }
+
+    pub fn normalize(&self, event: &PangeaOrderEvent) -> EventTime {
+        let raw = event
+            .block_timestamp
+            .map(|seconds| seconds.max(0) as u64 * 1000);
+        let Some(raw_ms) = raw else {
+            return EventTime {
+                raw,
+                normalized: self.last_normalized.load(Ordering::SeqCst),
+            };
+        };
+
+        let now = Utc::now().timestamp_millis() as u64;
+        let candidate = if raw_ms > now + self.tolerance_ms {
+            warn!(
+                "Block {} timestamp {} is {}ms ahead of local clock, clamping",
+                event.block_number,
+                raw_ms,
+                raw_ms - now
+            );
+            now
+        } else {
+            raw_ms
+        };
+
+        let previous = self.last_normalized.fetch_max(candidate, Ordering::SeqCst);
+
+        EventTime {
+            raw,
+            normalized: candidate.max(previous),
+        }
+    }
+}
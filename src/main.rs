@@ -1,16 +1,37 @@
+use clap::Parser;
+use cli::Cli;
 use config::env::ev;
 use error::Error;
 use futures_util::future::FutureExt;
 use futures_util::future::{join_all, select};
-use indexer::pangea::initialize_pangea_indexer;
+use indexer::chain_head::initialize_chain_head_tracker;
+use indexer::consistency_check::{check_cold_storage, check_restored_state};
+use indexer::dev_market::{dev_market_ids, enable_dev_mode};
+use indexer::kill_switches::KillSwitches;
+use indexer::market_discovery::resolve_market_ids;
+use indexer::market_tasks::start_market;
+use indexer::pangea_credentials::initialize_credential_reload;
+use indexer::replay::rebuild_from_log;
+use indexer::spark_contracts::MarketDecimals;
+use oracle::price_signer::PriceSigner;
+use oracle::reference_rates::ReferenceRates;
 use std::sync::Arc;
-use storage::order_book::OrderBook;
+use storage::address_labels::AddressLabels;
+use storage::audit_log::AuditLog;
+use storage::market_registry::MarketRegistry;
+use storage::subscription_usage::SubscriptionUsage;
+use storage::usage::UsageTracker;
 use tokio::signal;
-use web::server::rocket;
+use web::server::{rocket, ServerState};
+use web::warmup::WarmupGate;
 
+pub mod alerts;
+pub mod cli;
 pub mod config;
 pub mod error;
 pub mod indexer;
+pub mod metrics;
+pub mod oracle;
 pub mod storage;
 pub mod web;
 
@@ -18,13 +39,76 @@ pub mod web;
 async fn main() -> Result<(), Error> {
     dotenv::dotenv().ok();
     env_logger::init();
+    config::redaction::init_from_env()?;
 
-    let order_book = Arc::new(OrderBook::new());
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        return cli::run(command).await;
+    }
+
+    let market_ids = if cli.dev {
+        enable_dev_mode()?;
+        dev_market_ids()?
+    } else {
+        resolve_market_ids().await?
+    };
+    let markets = Arc::new(MarketRegistry::open(market_ids)?);
+    if cli.from_scratch {
+        for market in markets.all() {
+            if let Some(checkpoints) = &market.checkpoints {
+                checkpoints.clear().await?;
+            }
+        }
+    }
+    if cli.from_log {
+        for market in markets.all() {
+            let registry = Arc::clone(&markets);
+            tokio::task::spawn_blocking(move || rebuild_from_log(&registry, &market)).await??;
+        }
+    }
+    let price_signer = PriceSigner::from_env()?.map(Arc::new);
+    let kill_switches = Arc::new(KillSwitches::from_env());
+    let audit_log = Arc::new(AuditLog::from_env()?);
+    let labels = Arc::new(AddressLabels::from_env()?);
+    let reference_rates = Arc::new(ReferenceRates::from_env()?);
+    let usage = Arc::new(UsageTracker::from_env()?);
+    let subscription_usage = Arc::new(SubscriptionUsage::from_env()?);
+    let market_decimals = Arc::new(MarketDecimals::from_env());
+    let warmup = WarmupGate::from_env()?;
+    if !cli.dev {
+        for market in markets.all() {
+            check_restored_state(&market).await?;
+            if let Some(cold_store) = &market.cold_store {
+                check_cold_storage(market.market_id(), cold_store).await?;
+            }
+        }
+    }
     let mut tasks = vec![];
 
-    initialize_pangea_indexer(&mut tasks, Arc::clone(&order_book)).await?;
+    for market in markets.all() {
+        start_market(&mut tasks, &markets, market, &kill_switches).await?;
+    }
+    // Dev mode runs without Pangea credentials or a chain to follow.
+    if !cli.dev {
+        initialize_chain_head_tracker(&mut tasks, Arc::clone(&markets))?;
+        initialize_credential_reload(&mut tasks)?;
+    }
     let port = ev("SERVER_PORT")?.parse()?;
-    let rocket_task = tokio::spawn(run_rocket_server(port, Arc::clone(&order_book)));
+    let rocket_task = tokio::spawn(run_rocket_server(
+        port,
+        ServerState {
+            markets,
+            price_signer,
+            kill_switches,
+            audit_log,
+            usage,
+            subscription_usage,
+            market_decimals,
+            labels,
+            reference_rates,
+            warmup,
+        },
+    ));
     tasks.push(rocket_task);
 
     let ctrl_c_task = tokio::spawn(async {
@@ -43,7 +127,7 @@ async fn main() -> Result<(), Error> {
     Ok(())
 }
 
-async fn run_rocket_server(port: u16, order_book: Arc<OrderBook>) {
-    let rocket = rocket(port, order_book);
+async fn run_rocket_server(port: u16, state: ServerState) {
+    let rocket = rocket(port, state);
     let _ = rocket.launch().await;
 }
@@ -0,0 +1,199 @@
+use prometheus_client::encoding::text::encode;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+use std::sync::atomic::AtomicU64;
+use std::sync::OnceLock;
+
+use crate::indexer::connection_pool::connection_pool;
+use crate::indexer::spot_order::OrderType;
+use crate::indexer::status::IndexerStatus;
+use crate::storage::backend::Storage;
+use crate::storage::market_registry::MarketRegistry;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct MarketLabels {
+    market_id: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct BookLabels {
+    market_id: String,
+    side: String,
+}
+
+/// Prometheus metrics, every series labelled by market id so a single
+/// stalled market stands out even when totals look healthy.
+///
+/// Counters are bumped by the indexer as events arrive; gauges are read
+/// from the indexer status and the book at scrape time.
+pub struct Metrics {
+    registry: Registry,
+    events: Family<MarketLabels, Counter>,
+    trades: Family<MarketLabels, Counter>,
+    trade_volume: Family<MarketLabels, Counter<f64, AtomicU64>>,
+    busted_trades: Family<MarketLabels, Counter>,
+    busted_volume: Family<MarketLabels, Counter<f64, AtomicU64>>,
+    reconnects: Family<MarketLabels, Counter>,
+    last_processed_block: Family<MarketLabels, Gauge>,
+    chain_head: Family<MarketLabels, Gauge>,
+    blocks_behind: Family<MarketLabels, Gauge>,
+    book_orders: Family<BookLabels, Gauge>,
+    pangea_connections: Gauge,
+    pangea_credentials: Gauge,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let metrics = Metrics {
+            registry: Registry::with_prefix("spark"),
+            events: Family::default(),
+            trades: Family::default(),
+            trade_volume: Family::default(),
+            busted_trades: Family::default(),
+            busted_volume: Family::default(),
+            reconnects: Family::default(),
+            last_processed_block: Family::default(),
+            chain_head: Family::default(),
+            blocks_behind: Family::default(),
+            book_orders: Family::default(),
+            pangea_connections: Gauge::default(),
+            pangea_credentials: Gauge::default(),
+        };
+        metrics.register()
+    }
+
+    fn register(mut self) -> Self {
+        let registry = &mut self.registry;
+        registry.register("events", "Order events applied", self.events.clone());
+        registry.register("trades", "Matches indexed", self.trades.clone());
+        registry.register(
+            "trade_volume",
+            "Traded size in base asset units",
+            self.trade_volume.clone(),
+        );
+        registry.register(
+            "busted_trades",
+            "Matches voided by a reorg, counted in trades before",
+            self.busted_trades.clone(),
+        );
+        registry.register(
+            "busted_volume",
+            "Size voided by a reorg, counted in trade_volume before",
+            self.busted_volume.clone(),
+        );
+        registry.register(
+            "reconnects",
+            "Reconnect attempts to the Pangea delta stream",
+            self.reconnects.clone(),
+        );
+        registry.register(
+            "last_processed_block",
+            "Latest block applied to the book",
+            self.last_processed_block.clone(),
+        );
+        registry.register(
+            "chain_head",
+            "Latest block reported by the Fuel node",
+            self.chain_head.clone(),
+        );
+        registry.register(
+            "blocks_behind",
+            "Blocks between the chain head and the latest applied block",
+            self.blocks_behind.clone(),
+        );
+        registry.register(
+            "book_orders",
+            "Active orders in the book",
+            self.book_orders.clone(),
+        );
+        registry.register(
+            "pangea_connections",
+            "Open Pangea connections, shared between markets",
+            self.pangea_connections.clone(),
+        );
+        registry.register(
+            "pangea_credentials",
+            "Distinct Pangea logins among the open connections",
+            self.pangea_credentials.clone(),
+        );
+        self
+    }
+
+    pub fn record_event(&self, market_id: &str) {
+        self.events.get_or_create(&market(market_id)).inc();
+    }
+
+    pub fn record_trade(&self, market_id: &str, size: u128) {
+        let labels = market(market_id);
+        self.trades.get_or_create(&labels).inc();
+        self.trade_volume.get_or_create(&labels).inc_by(size as f64);
+    }
+
+    /// Counters only go up, so a voided match is counted here rather than
+    /// taken out of `trades`.
+    pub fn record_bust(&self, market_id: &str, size: u128) {
+        let labels = market(market_id);
+        self.busted_trades.get_or_create(&labels).inc();
+        self.busted_volume
+            .get_or_create(&labels)
+            .inc_by(size as f64);
+    }
+
+    pub fn record_reconnect(&self, market_id: &str) {
+        self.reconnects.get_or_create(&market(market_id)).inc();
+    }
+
+    /// Renders the text exposition format, refreshing gauges first.
+    pub fn encode(&self, markets: &MarketRegistry) -> String {
+        for market in markets.all() {
+            self.refresh_market(&market.status, &market.order_book);
+        }
+        let pool = connection_pool().stats();
+        self.pangea_connections.set(pool.connections as i64);
+        self.pangea_credentials.set(pool.credentials as i64);
+
+        let mut output = String::new();
+        // Writing into a String cannot fail.
+        encode(&mut output, &self.registry).unwrap();
+        output
+    }
+
+    fn refresh_market(&self, status: &IndexerStatus, order_book: &dyn Storage) {
+        let labels = market(status.market_id());
+        self.last_processed_block
+            .get_or_create(&labels)
+            .set(status.last_processed_block());
+        if let Some(head) = status.chain_head() {
+            self.chain_head
+                .get_or_create(&labels)
+                .set(head.block_number);
+        }
+        if let Some(behind) = status.blocks_behind() {
+            self.blocks_behind.get_or_create(&labels).set(behind);
+        }
+        for order_type in [OrderType::Buy, OrderType::Sell] {
+            let labels = BookLabels {
+                market_id: status.market_id().to_owned(),
+                side: format!("{:?}", order_type).to_lowercase(),
+            };
+            self.book_orders
+                .get_or_create(&labels)
+                .set(order_book.order_count(order_type) as i64);
+        }
+    }
+}
+
+fn market(market_id: &str) -> MarketLabels {
+    MarketLabels {
+        market_id: market_id.to_owned(),
+    }
+}
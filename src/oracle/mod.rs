@@ -0,0 +1,2 @@
+pub mod price_signer;
+pub mod reference_rates;
@@ -0,0 +1,71 @@
+use chrono::Utc;
+use fuel_crypto::{Message, PublicKey, SecretKey, Signature};
+use std::str::FromStr;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::backend::Storage;
+use crate::storage::fair_price::compute_fair_price;
+
+const DEFAULT_SIGNED_LEVELS: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct SignedPrice {
+    pub price: u128,
+    pub timestamp: u64,
+    pub levels: usize,
+    /// Hex of the signed bytes: price as 16-byte big-endian followed by
+    /// timestamp (ms) as 8-byte big-endian. Signed as sha256 of these bytes.
+    pub payload: String,
+    pub signature: String,
+    pub public_key: String,
+}
+
+/// Signs the fair price so downstream consumers can verify the adapter as a
+/// price source. Enabled by setting `PRICE_SIGNER_KEY`.
+pub struct PriceSigner {
+    secret_key: SecretKey,
+    public_key: PublicKey,
+    levels: usize,
+}
+
+impl PriceSigner {
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        let secret_key = match ev_opt("PRICE_SIGNER_KEY") {
+            Some(key) => {
+                SecretKey::from_str(&key).map_err(|e| Error::PriceSignerKeyError(e.to_string()))?
+            }
+            None => return Ok(None),
+        };
+        let levels = match ev_opt("PRICE_SIGNER_LEVELS") {
+            Some(levels) => levels.parse()?,
+            None => DEFAULT_SIGNED_LEVELS,
+        };
+
+        Ok(Some(PriceSigner {
+            public_key: PublicKey::from(&secret_key),
+            secret_key,
+            levels,
+        }))
+    }
+
+    pub fn sign_fair_price(&self, order_book: &dyn Storage) -> Option<SignedPrice> {
+        let fair_price = compute_fair_price(order_book, self.levels)?;
+        let timestamp = Utc::now().timestamp_millis() as u64;
+
+        let mut payload = Vec::with_capacity(24);
+        payload.extend_from_slice(&fair_price.price.to_be_bytes());
+        payload.extend_from_slice(&timestamp.to_be_bytes());
+
+        let signature = Signature::sign(&self.secret_key, &Message::new(&payload));
+
+        Some(SignedPrice {
+            price: fair_price.price,
+            timestamp,
+            levels: self.levels,
+            payload: hex::encode(&payload),
+            signature: signature.to_string(),
+            public_key: self.public_key.to_string(),
+        })
+    }
+}
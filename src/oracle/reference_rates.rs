@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+
+const DEFAULT_REFERENCE_CURRENCY: &str = "USD";
+/// Rates are kept as integers scaled by this, so normalizing stays exact
+/// for the `u128` amounts the book uses.
+const RATE_SCALE: u128 = 1_000_000_000;
+/// Decimals of normalized amounts, whatever the decimals of the market.
+pub const REFERENCE_DECIMALS: u32 = 6;
+
+/// Converts each market's quote currency into one reference currency, so
+/// stats can be summed across markets quoted in different stablecoins.
+///
+/// Rates come from `QUOTE_RATES`, comma-separated `market_id:rate` pairs
+/// giving one unit of the market's quote asset in `REFERENCE_CURRENCY`
+/// (default `USD`). Markets without a rate can't be normalized.
+pub struct ReferenceRates {
+    currency: String,
+    rates: HashMap<String, u128>,
+}
+
+impl ReferenceRates {
+    pub fn from_env() -> Result<Self, Error> {
+        let mut rates = HashMap::new();
+        for pair in ev_opt("QUOTE_RATES")
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let invalid = || {
+                Error::EnvVarError(
+                    "QUOTE_RATES".to_owned(),
+                    format!("'{}' is not market_id:rate", pair),
+                )
+            };
+            let (market_id, rate) = pair.split_once(':').ok_or_else(invalid)?;
+            let rate: f64 = rate.trim().parse().map_err(|_| invalid())?;
+            if !rate.is_finite() || rate < 0.0 {
+                return Err(invalid());
+            }
+            rates.insert(
+                normalize_id(market_id),
+                (rate * RATE_SCALE as f64).round() as u128,
+            );
+        }
+        Ok(ReferenceRates {
+            currency: ev_opt("REFERENCE_CURRENCY")
+                .unwrap_or_else(|| DEFAULT_REFERENCE_CURRENCY.to_owned()),
+            rates,
+        })
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// The market's rate, if one is configured.
+    pub fn rate(&self, market_id: &str) -> Option<f64> {
+        self.scaled_rate(market_id)
+            .map(|rate| rate as f64 / RATE_SCALE as f64)
+    }
+
+    /// `amount`, in the market's quote asset with `decimals` decimals, in
+    /// the reference currency with [`REFERENCE_DECIMALS`] decimals; `None`
+    /// for markets without a rate.
+    pub fn normalize(&self, market_id: &str, amount: u128, decimals: u32) -> Option<u128> {
+        let rate = self.scaled_rate(market_id)?;
+        let amount = if decimals >= REFERENCE_DECIMALS {
+            amount / 10u128.saturating_pow(decimals - REFERENCE_DECIMALS)
+        } else {
+            amount.saturating_mul(10u128.pow(REFERENCE_DECIMALS - decimals))
+        };
+        Some(
+            (amount / RATE_SCALE)
+                .saturating_mul(rate)
+                .saturating_add(amount % RATE_SCALE * rate / RATE_SCALE),
+        )
+    }
+
+    fn scaled_rate(&self, market_id: &str) -> Option<u128> {
+        self.rates.get(&normalize_id(market_id)).copied()
+    }
+}
+
+fn normalize_id(market_id: &str) -> String {
+    let market_id = market_id.trim();
+    market_id
+        .strip_prefix("0x")
+        .unwrap_or(market_id)
+        .to_ascii_lowercase()
+}
@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+
+const DEFAULT_ADDRESS_LABELS_PATH: &str = "address_labels.json";
+
+/// A display name and tags such as `market-maker`, `team` or `exchange`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressLabel {
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl AddressLabel {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+/// Labels for trader addresses, kept in one JSON object on disk keyed by
+/// address and rewritten on every change. Read from
+/// `ADDRESS_LABELS_PATH` (default `address_labels.json`); a missing file
+/// means no labels.
+pub struct AddressLabels {
+    path: PathBuf,
+    labels: RwLock<BTreeMap<String, AddressLabel>>,
+}
+
+impl AddressLabels {
+    pub fn open(path: PathBuf) -> Result<Self, Error> {
+        let labels = match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(Error::FileError(path.display().to_string(), e)),
+        };
+        Ok(AddressLabels {
+            path,
+            labels: RwLock::new(labels),
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Error> {
+        let path =
+            ev_opt("ADDRESS_LABELS_PATH").unwrap_or_else(|| DEFAULT_ADDRESS_LABELS_PATH.to_owned());
+        Self::open(PathBuf::from(path))
+    }
+
+    pub fn get(&self, address: &str) -> Option<AddressLabel> {
+        self.labels.read().unwrap().get(address).cloned()
+    }
+
+    /// Every label, or only those carrying `tag`, by address.
+    pub fn all(&self, tag: Option<&str>) -> Vec<(String, AddressLabel)> {
+        self.labels
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, label)| tag.is_none_or(|tag| label.has_tag(tag)))
+            .map(|(address, label)| (address.clone(), label.clone()))
+            .collect()
+    }
+
+    pub fn set(&self, address: String, label: AddressLabel) -> Result<(), Error> {
+        let mut labels = self.labels.write().unwrap();
+        labels.insert(address, label);
+        self.save(&labels)
+    }
+
+    /// Returns whether the address had a label.
+    pub fn remove(&self, address: &str) -> Result<bool, Error> {
+        let mut labels = self.labels.write().unwrap();
+        if labels.remove(address).is_none() {
+            return Ok(false);
+        }
+        self.save(&labels).map(|()| true)
+    }
+
+    fn save(&self, labels: &BTreeMap<String, AddressLabel>) -> Result<(), Error> {
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(labels)?)
+            .and_then(|()| fs::rename(&tmp, &self.path))
+            .map_err(|e| Error::FileError(self.path.display().to_string(), e))
+    }
+}
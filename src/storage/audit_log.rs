@@ -0,0 +1,74 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::json_lines::read_json_lines;
+
+const DEFAULT_AUDIT_LOG_PATH: &str = "audit.log";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: u64,
+    pub actor: String,
+    pub operation: String,
+    pub parameters: serde_json::Value,
+    pub request_id: Option<String>,
+    #[serde(default)]
+    pub client_ip: Option<String>,
+}
+
+/// Append-only record of admin operations, kept as JSON lines on disk and
+/// mirrored in memory for querying.
+pub struct AuditLog {
+    path: PathBuf,
+    entries: RwLock<Vec<AuditEntry>>,
+}
+
+impl AuditLog {
+    pub fn open(path: PathBuf) -> Result<Self, Error> {
+        let entries = read_json_lines(&path)?;
+
+        Ok(AuditLog {
+            path,
+            entries: RwLock::new(entries),
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Error> {
+        let path = ev_opt("AUDIT_LOG_PATH").unwrap_or_else(|| DEFAULT_AUDIT_LOG_PATH.to_owned());
+        Self::open(PathBuf::from(path))
+    }
+
+    /// Appends `entry` to the log. A failed write is logged but does not
+    /// fail the operation being audited.
+    pub fn record(&self, entry: AuditEntry) {
+        if let Err(e) = self.append_to_file(&entry) {
+            error!(
+                "Failed to write audit entry for {} to {}: {}",
+                entry.operation,
+                self.path.display(),
+                e
+            );
+        }
+        self.entries.write().unwrap().push(entry);
+    }
+
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.read().unwrap().clone()
+    }
+
+    fn append_to_file(&self, entry: &AuditEntry) -> Result<(), Error> {
+        let line = serde_json::to_string(entry)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", line))
+            .map_err(|e| Error::FileError(self.path.display().to_string(), e))
+    }
+}
@@ -0,0 +1,471 @@
+use tokio::sync::broadcast;
+
+use crate::indexer::anomaly_detector::Anomaly;
+use crate::indexer::order_event_handler::{EventIndex, PangeaOrderEvent, TradeFill};
+use crate::indexer::spot_order::{OrderType, SpotOrder};
+use crate::indexer::timestamp_normalizer::EventTime;
+use crate::storage::candles::{VolatilityPoint, VolumeProfileEntry};
+use crate::storage::checkpoint::Checkpoint;
+use crate::storage::fair_price::PriceLevel;
+use crate::storage::order_book::{BookBatch, MatchFill, OrderBook, QueuePosition};
+use crate::storage::order_flow::FlowBucket;
+use crate::storage::quote_stats::{QuoteChangeBucket, QuoteMove};
+use crate::storage::retention::RetentionCohort;
+use crate::storage::size_distribution::{SizeBucket, SizeKind};
+use crate::storage::state::StateEntry;
+use crate::storage::trader_stats::{Activity, StatsPeriod, TraderStatsBucket};
+use crate::storage::undo::Change;
+use crate::web::graphql::TradeOrderEvent;
+use crate::web::order_feed::{OrderChange, OrderUpdate};
+
+/// Where a market's orders, trades and statistics live. The indexer and
+/// GraphQL only reach a market's book through this trait, as
+/// `Arc<dyn Storage>`. [`OrderBook`], in memory, is the only backend so
+/// far; a persistent one (Postgres, RocksDB, Redis) implements this instead
+/// of changing the code that reads and writes the book through it.
+pub trait Storage: Send + Sync {
+    /// Locks the book for a batch of changes that readers see at once.
+    fn batch(&self) -> Box<dyn StorageBatch + '_>;
+
+    /// Adds a resting order at the back of its price level.
+    fn insert_order(&self, order: SpotOrder);
+
+    /// Removes an order, cancelled or filled as `change` says; without
+    /// `order_type` both sides are searched.
+    fn remove_order(&self, id: &str, order_type: Option<OrderType>, change: OrderChange);
+
+    /// Orders of one side priced in `[price_min, price_max]`, by ascending
+    /// price and in queue order within a level.
+    fn get_range(&self, price_min: u128, price_max: u128, order_type: OrderType) -> Vec<SpotOrder>;
+
+    fn order_count(&self, order_type: OrderType) -> usize;
+
+    fn best_bid(&self) -> Option<u128>;
+
+    fn best_ask(&self) -> Option<u128>;
+
+    /// Aggregated size of the best `levels` price levels, best price first.
+    fn top_levels(&self, order_type: OrderType, levels: usize) -> Vec<PriceLevel>;
+
+    fn queue_position(&self, id: &str) -> Option<QueuePosition>;
+
+    /// Every active order reduced to its state-defining fields, sorted.
+    fn state_entries(&self) -> Vec<StateEntry>;
+
+    /// Deterministic hash of the active book.
+    fn state_hash(&self) -> String;
+
+    /// Changes whenever orders or trades change.
+    fn version(&self) -> u64;
+
+    /// When the book was created, in ms; versions only compare within one
+    /// epoch.
+    fn epoch(&self) -> u64;
+
+    /// Subscribes to the order feed, along with the resting orders and the
+    /// sequence of the last update they include.
+    fn subscribe_orders(&self) -> (broadcast::Receiver<OrderUpdate>, Vec<SpotOrder>, u64);
+
+    /// Adds a trade to the tape in chain order, pairing it with the other
+    /// fill of its match.
+    fn record_trade(
+        &self,
+        market_id: &str,
+        price: u128,
+        size: u128,
+        time: EventTime,
+        index: EventIndex,
+        fill: TradeFill,
+    ) -> MatchFill;
+
+    /// Marks the trade recorded at `index` busted; returns its timestamp.
+    fn bust_trade(&self, index: EventIndex) -> Option<u64>;
+
+    /// Rebuilds candles in `[from, to]` from the tape.
+    fn rebuild_candles(&self, from: u64, to: u64) -> usize;
+
+    fn last_trade_timestamp(&self) -> Option<u64>;
+
+    /// Removes and returns the trades older than `cutoff` (ms).
+    fn take_trades_before(&self, cutoff: u64) -> Vec<TradeOrderEvent>;
+
+    /// Puts trades taken with `take_trades_before` back in front.
+    fn restore_trades(&self, restored: Vec<TradeOrderEvent>);
+
+    /// The latest trade not busted.
+    fn last_trade(&self) -> Option<TradeOrderEvent>;
+
+    /// The latest `limit` trades, newest first.
+    fn recent_trades(&self, limit: usize) -> Vec<TradeOrderEvent>;
+
+    fn get_trade_events(&self) -> Vec<TradeOrderEvent>;
+
+    /// Unique and new traders per `period`, oldest bucket first.
+    fn trader_buckets(&self, period: StatsPeriod) -> Vec<TraderStatsBucket>;
+
+    /// When `user` was first active, in ms.
+    fn first_seen(&self, user: &str) -> Option<u64>;
+
+    fn undo_activity(&self, activity: &Activity);
+
+    /// Average volume per weekday and hour over the last `lookback_days`.
+    fn volume_profile(&self, lookback_days: u64, now: u64) -> Vec<VolumeProfileEntry>;
+
+    /// Rolling realized volatility at every `interval_ms` step in
+    /// `[from, to]`, over a `window_ms` of log returns.
+    fn realized_volatility(
+        &self,
+        window_ms: u64,
+        interval_ms: u64,
+        from: u64,
+        to: u64,
+    ) -> Vec<VolatilityPoint>;
+
+    /// Trade and new-order flow in `interval_ms` buckets overlapping
+    /// `[from, to]`.
+    fn order_flow_series(&self, from: u64, to: u64, interval_ms: u64) -> Vec<FlowBucket>;
+
+    fn undo_order_flow(&self, order_type: OrderType, size: u128, timestamp: u64);
+
+    fn undo_trade_flow(&self, initiator: OrderType, size: u128, timestamp: u64);
+
+    /// Best bid and ask changes per minute in `[from, to]`.
+    fn quote_change_series(&self, from: u64, to: u64) -> Vec<QuoteChangeBucket>;
+
+    fn undo_quote(&self, quote_move: &QuoteMove);
+
+    /// Histogram of `kind` sizes over the hours overlapping `[from, to]`.
+    fn size_histogram(&self, kind: SizeKind, from: u64, to: u64) -> Vec<SizeBucket>;
+
+    fn undo_size(&self, kind: SizeKind, size: u128, timestamp: u64);
+
+    /// Retention cohorts from the last rebuild, oldest first.
+    fn retention_cohorts(&self) -> Vec<RetentionCohort>;
+
+    fn set_retention_cohorts(&self, cohorts: Vec<RetentionCohort>);
+
+    /// Records `event` as applied. Returns false if it was applied before.
+    fn mark_applied(&self, event: &PangeaOrderEvent) -> bool;
+
+    /// Forgets the applied events of `block_number` and later, for when a
+    /// reorg undoes them.
+    fn forget_applied_from(&self, block_number: i64);
+
+    fn flag_anomaly(&self, anomaly: Anomaly);
+
+    fn get_anomalies(&self) -> Vec<Anomaly>;
+
+    /// Drops every order and trade and reopens trading.
+    fn clear(&self);
+
+    /// Everything needed to restore the book as of the end of
+    /// `block_number`, which must be the last block applied.
+    fn snapshot(&self, block_number: i64) -> Checkpoint;
+
+    /// Replaces the book with `checkpoint` at once, so readers never see a
+    /// half-restored book.
+    fn restore(&self, checkpoint: Checkpoint);
+}
+
+/// A batch of changes to a book, see [`Storage::batch`]. Every change is
+/// kept until [`Self::take_changes`], so a reorg can undo it; the `discard`
+/// and `restore` operations are the undoing and keep nothing.
+pub trait StorageBatch {
+    fn add_order(&mut self, order: SpotOrder);
+
+    fn get_order(&self, id: &str, order_type: OrderType) -> Option<SpotOrder>;
+
+    fn update_order(&mut self, order: SpotOrder);
+
+    fn remove_order(&mut self, id: &str, order_type: Option<OrderType>, change: OrderChange);
+
+    fn discard_order(&mut self, id: &str, order_type: OrderType);
+
+    fn restore_order(&mut self, order: SpotOrder);
+
+    fn record_trade(
+        &mut self,
+        market_id: &str,
+        price: u128,
+        size: u128,
+        time: EventTime,
+        index: EventIndex,
+        fill: TradeFill,
+    ) -> MatchFill;
+
+    fn record_activity(&mut self, user: &str, timestamp: u64);
+
+    fn record_size(&mut self, kind: SizeKind, size: u128, timestamp: u64);
+
+    fn record_order_flow(&mut self, order_type: OrderType, size: u128, timestamp: u64);
+
+    fn record_trade_flow(&mut self, initiator: OrderType, size: u128, timestamp: u64);
+
+    fn observe_quote(&mut self, timestamp: u64);
+
+    /// Keeps a change made outside the book, to market-wide statistics.
+    fn record(&mut self, change: Change);
+
+    fn take_changes(&mut self) -> Vec<Change>;
+}
+
+impl Storage for OrderBook {
+    fn batch(&self) -> Box<dyn StorageBatch + '_> {
+        Box::new(OrderBook::batch(self))
+    }
+
+    fn insert_order(&self, order: SpotOrder) {
+        self.add_order(order);
+    }
+
+    fn remove_order(&self, id: &str, order_type: Option<OrderType>, change: OrderChange) {
+        OrderBook::remove_order(self, id, order_type, change);
+    }
+
+    fn get_range(&self, price_min: u128, price_max: u128, order_type: OrderType) -> Vec<SpotOrder> {
+        self.get_orders_in_range(price_min, price_max, order_type)
+    }
+
+    fn order_count(&self, order_type: OrderType) -> usize {
+        OrderBook::order_count(self, order_type)
+    }
+
+    fn best_bid(&self) -> Option<u128> {
+        OrderBook::best_bid(self)
+    }
+
+    fn best_ask(&self) -> Option<u128> {
+        OrderBook::best_ask(self)
+    }
+
+    fn top_levels(&self, order_type: OrderType, levels: usize) -> Vec<PriceLevel> {
+        OrderBook::top_levels(self, order_type, levels)
+    }
+
+    fn queue_position(&self, id: &str) -> Option<QueuePosition> {
+        OrderBook::queue_position(self, id)
+    }
+
+    fn state_entries(&self) -> Vec<StateEntry> {
+        OrderBook::state_entries(self)
+    }
+
+    fn state_hash(&self) -> String {
+        OrderBook::state_hash(self)
+    }
+
+    fn version(&self) -> u64 {
+        OrderBook::version(self)
+    }
+
+    fn epoch(&self) -> u64 {
+        OrderBook::epoch(self)
+    }
+
+    fn subscribe_orders(&self) -> (broadcast::Receiver<OrderUpdate>, Vec<SpotOrder>, u64) {
+        OrderBook::subscribe_orders(self)
+    }
+
+    fn record_trade(
+        &self,
+        market_id: &str,
+        price: u128,
+        size: u128,
+        time: EventTime,
+        index: EventIndex,
+        fill: TradeFill,
+    ) -> MatchFill {
+        OrderBook::record_trade(self, market_id, price, size, time, index, fill)
+    }
+
+    fn bust_trade(&self, index: EventIndex) -> Option<u64> {
+        OrderBook::bust_trade(self, index)
+    }
+
+    fn rebuild_candles(&self, from: u64, to: u64) -> usize {
+        OrderBook::rebuild_candles(self, from, to)
+    }
+
+    fn last_trade_timestamp(&self) -> Option<u64> {
+        OrderBook::last_trade_timestamp(self)
+    }
+
+    fn take_trades_before(&self, cutoff: u64) -> Vec<TradeOrderEvent> {
+        OrderBook::take_trades_before(self, cutoff)
+    }
+
+    fn restore_trades(&self, restored: Vec<TradeOrderEvent>) {
+        OrderBook::restore_trades(self, restored);
+    }
+
+    fn last_trade(&self) -> Option<TradeOrderEvent> {
+        OrderBook::last_trade(self)
+    }
+
+    fn recent_trades(&self, limit: usize) -> Vec<TradeOrderEvent> {
+        OrderBook::recent_trades(self, limit)
+    }
+
+    fn get_trade_events(&self) -> Vec<TradeOrderEvent> {
+        OrderBook::get_trade_events(self)
+    }
+
+    fn trader_buckets(&self, period: StatsPeriod) -> Vec<TraderStatsBucket> {
+        self.trader_stats().buckets(period)
+    }
+
+    fn first_seen(&self, user: &str) -> Option<u64> {
+        self.trader_stats().first_seen(user)
+    }
+
+    fn undo_activity(&self, activity: &Activity) {
+        self.trader_stats().undo(activity);
+    }
+
+    fn volume_profile(&self, lookback_days: u64, now: u64) -> Vec<VolumeProfileEntry> {
+        self.candles().volume_profile(lookback_days, now)
+    }
+
+    fn realized_volatility(
+        &self,
+        window_ms: u64,
+        interval_ms: u64,
+        from: u64,
+        to: u64,
+    ) -> Vec<VolatilityPoint> {
+        self.candles()
+            .realized_volatility(window_ms, interval_ms, from, to)
+    }
+
+    fn order_flow_series(&self, from: u64, to: u64, interval_ms: u64) -> Vec<FlowBucket> {
+        self.order_flow().series(from, to, interval_ms)
+    }
+
+    fn undo_order_flow(&self, order_type: OrderType, size: u128, timestamp: u64) {
+        self.order_flow().undo_order(order_type, size, timestamp);
+    }
+
+    fn undo_trade_flow(&self, initiator: OrderType, size: u128, timestamp: u64) {
+        self.order_flow().undo_trade(initiator, size, timestamp);
+    }
+
+    fn quote_change_series(&self, from: u64, to: u64) -> Vec<QuoteChangeBucket> {
+        self.quote_changes().series(from, to)
+    }
+
+    fn undo_quote(&self, quote_move: &QuoteMove) {
+        self.quote_changes().undo(quote_move);
+    }
+
+    fn size_histogram(&self, kind: SizeKind, from: u64, to: u64) -> Vec<SizeBucket> {
+        self.size_distribution().histogram(kind, from, to)
+    }
+
+    fn undo_size(&self, kind: SizeKind, size: u128, timestamp: u64) {
+        self.size_distribution().undo(kind, size, timestamp);
+    }
+
+    fn retention_cohorts(&self) -> Vec<RetentionCohort> {
+        self.retention().cohorts()
+    }
+
+    fn set_retention_cohorts(&self, cohorts: Vec<RetentionCohort>) {
+        self.retention().replace(cohorts);
+    }
+
+    fn mark_applied(&self, event: &PangeaOrderEvent) -> bool {
+        self.applied_events().insert(event)
+    }
+
+    fn forget_applied_from(&self, block_number: i64) {
+        self.applied_events().forget_from(block_number);
+    }
+
+    fn flag_anomaly(&self, anomaly: Anomaly) {
+        OrderBook::flag_anomaly(self, anomaly);
+    }
+
+    fn get_anomalies(&self) -> Vec<Anomaly> {
+        OrderBook::get_anomalies(self)
+    }
+
+    fn clear(&self) {
+        OrderBook::clear(self);
+    }
+
+    fn snapshot(&self, block_number: i64) -> Checkpoint {
+        Checkpoint::capture(self, block_number)
+    }
+
+    fn restore(&self, checkpoint: Checkpoint) {
+        let fresh = OrderBook::new();
+        checkpoint.restore(&fresh);
+        self.replace_with(fresh);
+    }
+}
+
+impl StorageBatch for BookBatch<'_> {
+    fn add_order(&mut self, order: SpotOrder) {
+        BookBatch::add_order(self, order);
+    }
+
+    fn get_order(&self, id: &str, order_type: OrderType) -> Option<SpotOrder> {
+        BookBatch::get_order(self, id, order_type)
+    }
+
+    fn update_order(&mut self, order: SpotOrder) {
+        BookBatch::update_order(self, order);
+    }
+
+    fn remove_order(&mut self, id: &str, order_type: Option<OrderType>, change: OrderChange) {
+        BookBatch::remove_order(self, id, order_type, change);
+    }
+
+    fn discard_order(&mut self, id: &str, order_type: OrderType) {
+        BookBatch::discard_order(self, id, order_type);
+    }
+
+    fn restore_order(&mut self, order: SpotOrder) {
+        BookBatch::restore_order(self, order);
+    }
+
+    fn record_trade(
+        &mut self,
+        market_id: &str,
+        price: u128,
+        size: u128,
+        time: EventTime,
+        index: EventIndex,
+        fill: TradeFill,
+    ) -> MatchFill {
+        BookBatch::record_trade(self, market_id, price, size, time, index, fill)
+    }
+
+    fn record_activity(&mut self, user: &str, timestamp: u64) {
+        BookBatch::record_activity(self, user, timestamp);
+    }
+
+    fn record_size(&mut self, kind: SizeKind, size: u128, timestamp: u64) {
+        BookBatch::record_size(self, kind, size, timestamp);
+    }
+
+    fn record_order_flow(&mut self, order_type: OrderType, size: u128, timestamp: u64) {
+        BookBatch::record_order_flow(self, order_type, size, timestamp);
+    }
+
+    fn record_trade_flow(&mut self, initiator: OrderType, size: u128, timestamp: u64) {
+        BookBatch::record_trade_flow(self, initiator, size, timestamp);
+    }
+
+    fn observe_quote(&mut self, timestamp: u64) {
+        BookBatch::observe_quote(self, timestamp);
+    }
+
+    fn record(&mut self, change: Change) {
+        BookBatch::record(self, change);
+    }
+
+    fn take_changes(&mut self) -> Vec<Change> {
+        BookBatch::take_changes(self)
+    }
+}
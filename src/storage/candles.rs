@@ -0,0 +1,234 @@
+use chrono::{DateTime, Datelike, Timelike};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::RwLock;
+
+pub const CANDLE_INTERVAL_MS: u64 = 60_000;
+const DAY_MS: u64 = 86_400_000;
+/// Most steps a volatility range, or its window, may span.
+pub const MAX_VOLATILITY_STEPS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candle {
+    pub start: u64,
+    pub open: u128,
+    pub high: u128,
+    pub low: u128,
+    pub close: u128,
+    pub volume: u128,
+    pub trades: u64,
+}
+
+impl Candle {
+    fn new(start: u64, price: u128, size: u128) -> Self {
+        Candle {
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: size,
+            trades: 1,
+        }
+    }
+
+    fn apply_trade(&mut self, price: u128, size: u128) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += size;
+        self.trades += 1;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VolumeProfileEntry {
+    /// 0 = Monday .. 6 = Sunday, UTC.
+    pub day_of_week: u32,
+    /// 0..23, UTC.
+    pub hour: u32,
+    pub average_volume: u128,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VolatilityPoint {
+    pub timestamp: u64,
+    /// Square root of the summed squared log returns in the window; not
+    /// annualized.
+    pub volatility: f64,
+    /// Returns the value was computed from.
+    pub samples: u32,
+}
+
+/// One-minute OHLCV bars built from indexed trades.
+#[derive(Default)]
+pub struct CandleStore {
+    candles: RwLock<BTreeMap<u64, Candle>>,
+}
+
+impl CandleStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_trade(&self, price: u128, size: u128, timestamp: u64) {
+        let start = timestamp - timestamp % CANDLE_INTERVAL_MS;
+        let mut candles = self.candles.write().unwrap();
+        candles
+            .entry(start)
+            .and_modify(|candle| candle.apply_trade(price, size))
+            .or_insert_with(|| Candle::new(start, price, size));
+    }
+
+    pub fn get_candles(&self, from: u64, to: u64) -> Vec<Candle> {
+        if from > to {
+            return vec![];
+        }
+        self.candles
+            .read()
+            .unwrap()
+            .range(from..=to)
+            .map(|(_, candle)| *candle)
+            .collect()
+    }
+
+    /// Recomputes every bar between `from` and `to` from the given
+    /// `(price, size, timestamp)` trades, replacing whatever was stored for
+    /// that range. Returns the number of bars written.
+    pub fn rebuild_range(
+        &self,
+        from: u64,
+        to: u64,
+        trades: impl IntoIterator<Item = (u128, u128, u64)>,
+    ) -> usize {
+        let from = from - from % CANDLE_INTERVAL_MS;
+        let to = (to - to % CANDLE_INTERVAL_MS).saturating_add(CANDLE_INTERVAL_MS - 1);
+
+        let rebuilt = CandleStore::new();
+        for (price, size, timestamp) in trades {
+            if (from..=to).contains(&timestamp) {
+                rebuilt.record_trade(price, size, timestamp);
+            }
+        }
+        let rebuilt = rebuilt.candles.into_inner().unwrap();
+
+        let mut candles = self.candles.write().unwrap();
+        candles.retain(|&start, _| start < from || start > to);
+        let count = rebuilt.len();
+        candles.extend(rebuilt);
+        count
+    }
+
+    /// Replaces every candle with those of `other`.
+    pub fn replace_with(&self, other: CandleStore) {
+        *self.candles.write().unwrap() = other.candles.into_inner().unwrap();
+    }
+
+    /// Average volume per (day of week, hour of day) over the `lookback_days`
+    /// days ending at `now`. Each cell is averaged over the number of times
+    /// its weekday occurs in the window, so quiet hours report zero.
+    pub fn volume_profile(&self, lookback_days: u64, now: u64) -> Vec<VolumeProfileEntry> {
+        let from = now.saturating_sub(lookback_days * DAY_MS);
+        let mut totals = [[0u128; 24]; 7];
+        for candle in self.get_candles(from, now) {
+            if let Some((day, hour)) = weekday_and_hour(candle.start) {
+                totals[day as usize][hour as usize] += candle.volume;
+            }
+        }
+
+        let mut day_occurrences = [0u128; 7];
+        let mut day = from - from % DAY_MS;
+        while day <= now {
+            if let Some((weekday, _)) = weekday_and_hour(day) {
+                day_occurrences[weekday as usize] += 1;
+            }
+            day += DAY_MS;
+        }
+
+        let mut profile = Vec::with_capacity(7 * 24);
+        for (day_of_week, hours) in totals.iter().enumerate() {
+            for (hour, volume) in hours.iter().enumerate() {
+                let occurrences = day_occurrences[day_of_week].max(1);
+                profile.push(VolumeProfileEntry {
+                    day_of_week: day_of_week as u32,
+                    hour: hour as u32,
+                    average_volume: volume / occurrences,
+                });
+            }
+        }
+        profile
+    }
+
+    /// Rolling realized volatility at every `interval_ms` step in
+    /// `[from, to]`. Closes are sampled every `interval_ms`, carrying the
+    /// last close forward through quiet bars, and each step sums the squared
+    /// log returns of the samples in the `window_ms` before it. Steps with
+    /// fewer than two returns are left out. Bars and samples are walked
+    /// once, the window's sum kept as samples enter and leave it.
+    pub fn realized_volatility(
+        &self,
+        window_ms: u64,
+        interval_ms: u64,
+        from: u64,
+        to: u64,
+    ) -> Vec<VolatilityPoint> {
+        let interval_ms = interval_ms.max(CANDLE_INTERVAL_MS);
+        if from > to || window_ms < interval_ms {
+            return vec![];
+        }
+        let first_sample = from.saturating_sub(window_ms);
+        let first_sample = first_sample - first_sample % interval_ms;
+
+        let candles = self.candles.read().unwrap();
+        let mut close = candles
+            .range(..first_sample)
+            .next_back()
+            .map(|(_, candle)| candle.close);
+        let mut bars = candles.range(first_sample..).peekable();
+        let mut previous_close = None;
+        // (sample time, squared log return since the previous sample)
+        let mut window: VecDeque<(u64, f64)> = VecDeque::new();
+        let mut sum = 0.0;
+
+        let mut points = vec![];
+        let mut sample = first_sample;
+        while sample <= to {
+            while let Some((_, candle)) = bars.next_if(|(start, _)| **start < sample) {
+                close = Some(candle.close);
+            }
+            if let (Some(previous), Some(close)) = (previous_close, close) {
+                if previous > 0 && close > 0 {
+                    let squared = (close as f64 / previous as f64).ln().powi(2);
+                    window.push_back((sample, squared));
+                    sum += squared;
+                }
+            }
+            previous_close = close;
+            while window
+                .front()
+                .is_some_and(|(time, _)| time.saturating_add(window_ms) <= sample)
+            {
+                if let Some((_, squared)) = window.pop_front() {
+                    sum -= squared;
+                }
+            }
+
+            if sample >= from && window.len() >= 2 {
+                points.push(VolatilityPoint {
+                    timestamp: sample,
+                    volatility: sum.max(0.0).sqrt(),
+                    samples: window.len() as u32,
+                });
+            }
+            let Some(next) = sample.checked_add(interval_ms) else {
+                break;
+            };
+            sample = next;
+        }
+        points
+    }
+}
+
+fn weekday_and_hour(timestamp: u64) -> Option<(u32, u32)> {
+    DateTime::from_timestamp_millis(timestamp as i64)
+        .map(|time| (time.weekday().num_days_from_monday(), time.hour()))
+}
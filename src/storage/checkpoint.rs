@@ -0,0 +1,345 @@
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::spot_order::{OrderType, SpotOrder};
+use crate::indexer::status::{IndexerStatus, SyncPhase};
+use crate::storage::backend::Storage;
+use crate::storage::order_book::OrderBook;
+#[cfg(feature = "postgres")]
+use crate::storage::postgres::{database, PostgresCheckpoints};
+use crate::web::graphql::TradeOrderEvent;
+
+const DEFAULT_CHECKPOINT_INTERVAL_SECS: u64 = 60;
+
+/// Layout of the checkpoints this build writes. Bump it whenever the layout
+/// changes in a way serde defaults can't absorb, and append the step from
+/// the previous version to `MIGRATIONS`.
+pub const CHECKPOINT_VERSION: u32 = 2;
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` checkpoint to version `n + 2`
+/// in place, given the market it belongs to.
+const MIGRATIONS: [fn(&mut Value, &str); CHECKPOINT_VERSION as usize - 1] = [unversioned_to_v2];
+
+/// The book as of the end of `block_number`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    pub block_number: i64,
+    /// Active orders in queue order.
+    pub orders: Vec<SpotOrder>,
+    /// Trades still in memory, i.e. not yet moved to cold storage.
+    pub trades: Vec<TradeOrderEvent>,
+}
+
+impl Checkpoint {
+    pub fn capture(order_book: &OrderBook, block_number: i64) -> Self {
+        let mut orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Buy);
+        orders.extend(order_book.get_orders_in_range(0, u128::MAX, OrderType::Sell));
+        orders.sort_by_key(|order| order.priority);
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            block_number,
+            orders,
+            trades: order_book.get_trade_events(),
+        }
+    }
+
+    /// Replaces the book's orders and trades with the checkpoint's.
+    pub fn restore(self, order_book: &OrderBook) {
+        order_book.clear();
+        for order in self.orders {
+            order_book.add_order(order);
+        }
+        order_book.restore_trades(self.trades);
+        order_book.rebuild_candles(0, u64::MAX);
+    }
+}
+
+/// Stores one market's latest checkpoint so a restart resumes from the last
+/// saved block instead of replaying from `CONTRACT_START_BLOCK`.
+///
+/// Enabled by `CHECKPOINT_DIR`, where each market is saved as
+/// `<market_id>.json`, or, in builds with the `postgres` feature, by
+/// `DATABASE_URL`, which takes precedence. Checkpoints are saved at most
+/// every `CHECKPOINT_INTERVAL_SECS` (default 60), always at a block
+/// boundary.
+///
+/// With `CHECKPOINT_FOLLOW=true` the process doesn't index at all; it
+/// serves the checkpoints another instance saves to the same directory or
+/// database, see [`initialize_checkpoint_follower`].
+pub struct CheckpointStore {
+    market_id: String,
+    target: Target,
+    interval: Duration,
+    follow: bool,
+    last_saved: Mutex<Option<Instant>>,
+    /// Set while a checkpoint is being written to a file.
+    writing: Arc<AtomicBool>,
+}
+
+enum Target {
+    File(PathBuf),
+    #[cfg(feature = "postgres")]
+    Postgres(PostgresCheckpoints),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::File(path) => write!(f, "{}", path.display()),
+            #[cfg(feature = "postgres")]
+            Target::Postgres(_) => write!(f, "Postgres"),
+        }
+    }
+}
+
+impl CheckpointStore {
+    pub fn from_env(market_id: &str) -> Result<Option<Self>, Error> {
+        let Some(target) = Self::target_from_env(market_id)? else {
+            return Ok(None);
+        };
+        let interval = match ev_opt("CHECKPOINT_INTERVAL_SECS") {
+            Some(secs) => Duration::from_secs(secs.parse()?),
+            None => Duration::from_secs(DEFAULT_CHECKPOINT_INTERVAL_SECS),
+        };
+        Ok(Some(CheckpointStore {
+            market_id: market_id.to_owned(),
+            target,
+            interval,
+            follow: ev_opt("CHECKPOINT_FOLLOW").as_deref() == Some("true"),
+            last_saved: Mutex::new(None),
+            writing: Arc::new(AtomicBool::new(false)),
+        }))
+    }
+
+    fn target_from_env(market_id: &str) -> Result<Option<Target>, Error> {
+        #[cfg(feature = "postgres")]
+        if let Some(pool) = database()? {
+            return Ok(Some(Target::Postgres(PostgresCheckpoints::open(
+                market_id, pool,
+            ))));
+        }
+        let Some(dir) = ev_opt("CHECKPOINT_DIR") else {
+            return Ok(None);
+        };
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir).map_err(|e| Error::FileError(dir.display().to_string(), e))?;
+        Ok(Some(Target::File(dir.join(format!("{}.json", market_id)))))
+    }
+
+    /// Whether this process serves checkpoints saved by another instead of
+    /// indexing.
+    pub fn is_follower(&self) -> bool {
+        self.follow
+    }
+
+    /// Reads the stored checkpoint, migrating it first if an older build
+    /// wrote it. A checkpoint from a newer build is ignored, so the market
+    /// replays from its start block rather than misreading it.
+    pub async fn load(&self) -> Result<Option<Checkpoint>, Error> {
+        match &self.target {
+            Target::File(path) => read_checkpoint(path, &self.market_id),
+            #[cfg(feature = "postgres")]
+            Target::Postgres(postgres) => match postgres.load().await? {
+                Some(checkpoint) => {
+                    upgrade_checkpoint(checkpoint.as_bytes(), "in Postgres", &self.market_id)
+                }
+                None => Ok(None),
+            },
+        }
+    }
+
+    /// The stored checkpoint if it is past `block_number`.
+    pub async fn load_after(&self, block_number: i64) -> Result<Option<Checkpoint>, Error> {
+        #[cfg(feature = "postgres")]
+        if let Target::Postgres(postgres) = &self.target {
+            if postgres
+                .saved_block()
+                .await?
+                .is_none_or(|saved| saved <= block_number)
+            {
+                return Ok(None);
+            }
+        }
+        Ok(self
+            .load()
+            .await?
+            .filter(|checkpoint| checkpoint.block_number > block_number))
+    }
+
+    /// Deletes the stored checkpoint, so the next start replays everything.
+    pub async fn clear(&self) -> Result<(), Error> {
+        match &self.target {
+            Target::File(path) => match fs::remove_file(path) {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(Error::FileError(path.display().to_string(), e)),
+            },
+            #[cfg(feature = "postgres")]
+            Target::Postgres(postgres) => postgres.clear().await,
+        }
+    }
+
+    /// Saves a checkpoint at `block_number` if the interval has passed. Must
+    /// be called between blocks, from within the runtime. Only taking the
+    /// snapshot happens on the caller's thread; it is serialized and written
+    /// on a blocking one, and a save is skipped while the previous one is
+    /// still being written.
+    pub fn maybe_save(&self, order_book: &dyn Storage, block_number: i64) {
+        let mut last_saved = self.last_saved.lock().unwrap();
+        if last_saved.is_some_and(|saved| saved.elapsed() < self.interval) {
+            return;
+        }
+        if self.writing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        *last_saved = Some(Instant::now());
+
+        let checkpoint = order_book.snapshot(block_number);
+        match &self.target {
+            Target::File(path) => {
+                let (path, writing) = (path.clone(), Arc::clone(&self.writing));
+                tokio::task::spawn_blocking(move || {
+                    match write_checkpoint(&path, &checkpoint) {
+                        Ok(()) => info!("Saved checkpoint at block {}", block_number),
+                        Err(e) => error!("Failed to save checkpoint to {}: {}", path.display(), e),
+                    }
+                    writing.store(false, Ordering::SeqCst);
+                });
+            }
+            #[cfg(feature = "postgres")]
+            Target::Postgres(postgres) => {
+                // Queued to the writer task, which only keeps the newest.
+                postgres.save(checkpoint);
+                self.writing.store(false, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+/// Keeps a follower's book at the newest checkpoint saved by the indexing
+/// instance, checking every `CHECKPOINT_INTERVAL_SECS`. The market counts
+/// as live from the first checkpoint restored.
+pub fn initialize_checkpoint_follower(
+    tasks: &mut Vec<tokio::task::JoinHandle<()>>,
+    order_book: Arc<dyn Storage>,
+    status: Arc<IndexerStatus>,
+    checkpoints: Arc<CheckpointStore>,
+) -> Result<(), Error> {
+    tasks.push(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(checkpoints.interval);
+        loop {
+            ticker.tick().await;
+            match checkpoints.load_after(status.last_processed_block()).await {
+                Ok(Some(checkpoint)) => {
+                    let block_number = checkpoint.block_number;
+                    order_book.restore(checkpoint);
+                    status.set_last_processed_block(block_number);
+                    status.set_state_hash(block_number, order_book.state_hash());
+                    status.set_phase(SyncPhase::Live);
+                    info!(
+                        "Following {} at block {} from {}",
+                        checkpoints.market_id, block_number, checkpoints.target
+                    );
+                }
+                Ok(None) => {}
+                Err(e) => error!(
+                    "Failed to read the checkpoint of {} from {}: {}",
+                    checkpoints.market_id, checkpoints.target, e
+                ),
+            }
+        }
+    }));
+    Ok(())
+}
+
+/// Reads the checkpoint of `market_id` at `path`, migrating it first if an
+/// older build wrote it. Missing files and checkpoints from a newer build
+/// read as `None`.
+pub fn read_checkpoint(path: &Path, market_id: &str) -> Result<Option<Checkpoint>, Error> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::FileError(path.display().to_string(), e)),
+    };
+    upgrade_checkpoint(&bytes, &path.display().to_string(), market_id)
+}
+
+/// Migrates a checkpoint read from `source` to [`CHECKPOINT_VERSION`].
+///
+/// A current checkpoint is decoded straight from `bytes`. Older ones are
+/// migrated as a `Value`, which holds amounts only up to `u64::MAX`.
+fn upgrade_checkpoint(
+    bytes: &[u8],
+    source: &str,
+    market_id: &str,
+) -> Result<Option<Checkpoint>, Error> {
+    #[derive(Deserialize)]
+    struct Versioned {
+        version: Option<u32>,
+    }
+
+    // Checkpoints written before versioning have no `version`.
+    let version = serde_json::from_slice::<Versioned>(bytes)?
+        .version
+        .unwrap_or(1)
+        .max(1);
+    if version > CHECKPOINT_VERSION {
+        warn!(
+            "Ignoring version {} checkpoint {}, this build reads up to version {}",
+            version, source, CHECKPOINT_VERSION
+        );
+        return Ok(None);
+    }
+    if version == CHECKPOINT_VERSION {
+        return Ok(Some(serde_json::from_slice(bytes)?));
+    }
+    let mut checkpoint: Value = serde_json::from_slice(bytes)?;
+    for migrate in &MIGRATIONS[version as usize - 1..] {
+        migrate(&mut checkpoint, market_id);
+    }
+    checkpoint["version"] = CHECKPOINT_VERSION.into();
+    info!(
+        "Migrated checkpoint {} from version {} to {}",
+        source, version, CHECKPOINT_VERSION
+    );
+    Ok(Some(serde_json::from_value(checkpoint)?))
+}
+
+/// Writes to a temporary file first so a crash never leaves a torn
+/// checkpoint behind.
+pub fn write_checkpoint(path: &Path, checkpoint: &Checkpoint) -> Result<(), Error> {
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec(checkpoint)?)
+        .and_then(|()| fs::rename(&tmp, path))
+        .map_err(|e| Error::FileError(path.display().to_string(), e))
+}
+
+/// Orders and trades saved before they carried their market belong to the
+/// checkpoint's.
+fn unversioned_to_v2(checkpoint: &mut Value, market_id: &str) {
+    for key in ["orders", "trades"] {
+        let Some(items) = checkpoint.get_mut(key).and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for item in items.iter_mut().filter_map(Value::as_object_mut) {
+            if item
+                .get("market_id")
+                .and_then(Value::as_str)
+                .is_none_or(str::is_empty)
+            {
+                item.insert("market_id".to_owned(), market_id.into());
+            }
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use log::{error, info};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio::time::{self, Duration};
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::backend::Storage;
+use crate::web::graphql::TradeOrderEvent;
+
+const DAY_MS: u64 = 86_400_000;
+const DEFAULT_ARCHIVE_AFTER_DAYS: u64 = 30;
+const ARCHIVE_INTERVAL: Duration = Duration::from_secs(3600);
+const COMPRESSION_LEVEL: i32 = 3;
+const SEGMENT_PREFIX: &str = "trades-";
+const SEGMENT_SUFFIX: &str = ".jsonl.zst";
+
+/// A compressed file of trades covering `[first, last]` (ms).
+#[derive(Debug, Clone)]
+struct Segment {
+    first: u64,
+    last: u64,
+    path: PathBuf,
+}
+
+/// Trades older than the hot window, moved out of memory into zstd
+/// compressed JSON-lines segments under `COLD_STORAGE_DIR/<market id>`.
+///
+/// The index is rebuilt from segment file names on startup. Reading a
+/// segment decompresses it in full, so deep-history queries are slower
+/// than those served from the hot tape.
+pub struct ColdTradeStore {
+    dir: PathBuf,
+    segments: RwLock<BTreeMap<u64, Segment>>,
+}
+
+impl ColdTradeStore {
+    pub fn open(dir: PathBuf) -> Result<Self, Error> {
+        let file_error = |e| Error::FileError(dir.display().to_string(), e);
+        fs::create_dir_all(&dir).map_err(file_error)?;
+
+        let mut segments = BTreeMap::new();
+        for entry in fs::read_dir(&dir).map_err(file_error)? {
+            let path = entry.map_err(file_error)?.path();
+            if let Some((first, last)) = parse_segment_name(&path) {
+                segments.insert(first, Segment { first, last, path });
+            }
+        }
+        info!(
+            "Cold trade storage at {} with {} segments",
+            dir.display(),
+            segments.len()
+        );
+
+        Ok(ColdTradeStore {
+            dir,
+            segments: RwLock::new(segments),
+        })
+    }
+
+    pub fn from_env(market_id: &str) -> Result<Option<Self>, Error> {
+        ev_opt("COLD_STORAGE_DIR")
+            .map(|dir| Self::open(PathBuf::from(dir).join(market_id)))
+            .transpose()
+    }
+
+    /// Writes `trades` (in tape order) as one new segment.
+    pub fn archive(&self, trades: &[TradeOrderEvent]) -> Result<(), Error> {
+        let (Some(first), Some(last)) = (trades.first(), trades.last()) else {
+            return Ok(());
+        };
+        let (first, last) = (first.timestamp, last.timestamp);
+
+        let mut lines = String::new();
+        for trade in trades {
+            lines.push_str(&serde_json::to_string(trade)?);
+            lines.push('\n');
+        }
+        let path = self.dir.join(format!(
+            "{}{}-{}{}",
+            SEGMENT_PREFIX, first, last, SEGMENT_SUFFIX
+        ));
+        let file_error = |e| Error::FileError(path.display().to_string(), e);
+        let compressed =
+            zstd::encode_all(lines.as_bytes(), COMPRESSION_LEVEL).map_err(file_error)?;
+        fs::write(&path, compressed).map_err(file_error)?;
+
+        self.segments
+            .write()
+            .unwrap()
+            .insert(first, Segment { first, last, path });
+        Ok(())
+    }
+
+    /// Latest archived trade time, if anything was archived.
+    pub fn newest(&self) -> Option<u64> {
+        self.segments
+            .read()
+            .unwrap()
+            .values()
+            .map(|segment| segment.last)
+            .max()
+    }
+
+    /// Trades of the `count` most recently archived segments, newest first.
+    pub fn recent_segments(&self, count: usize) -> Result<Vec<Vec<TradeOrderEvent>>, Error> {
+        let paths: Vec<PathBuf> = self
+            .segments
+            .read()
+            .unwrap()
+            .values()
+            .rev()
+            .take(count)
+            .map(|segment| segment.path.clone())
+            .collect();
+        paths.iter().map(|path| read_segment(path)).collect()
+    }
+
+    /// Passes every archived trade to `visit`, in tape order, reading one
+    /// segment at a time.
+    pub fn scan(&self, mut visit: impl FnMut(TradeOrderEvent)) -> Result<(), Error> {
+        let paths: Vec<PathBuf> = self
+            .segments
+            .read()
+            .unwrap()
+            .values()
+            .map(|segment| segment.path.clone())
+            .collect();
+        for path in paths {
+            read_segment(&path)?.into_iter().for_each(&mut visit);
+        }
+        Ok(())
+    }
+
+    /// Archived trades with timestamps in `[from, to]`, in tape order.
+    pub fn load_range(&self, from: u64, to: u64) -> Result<Vec<TradeOrderEvent>, Error> {
+        let segments: Vec<Segment> = self
+            .segments
+            .read()
+            .unwrap()
+            .values()
+            .filter(|segment| segment.first <= to && segment.last >= from)
+            .cloned()
+            .collect();
+
+        let mut trades = vec![];
+        for segment in segments {
+            let segment_trades = read_segment(&segment.path)?;
+            trades.extend(
+                segment_trades
+                    .into_iter()
+                    .filter(|trade| (from..=to).contains(&trade.timestamp)),
+            );
+        }
+        Ok(trades)
+    }
+}
+
+/// Archived trades followed by those of the hot tape, as one tape in chain
+/// order. The hot tape is a contiguous run up to the newest trade, so
+/// archived trades from its first one on are still in memory too and are
+/// taken from there, busts and all.
+pub fn merge_tape(
+    mut trades: Vec<TradeOrderEvent>,
+    hot: impl IntoIterator<Item = TradeOrderEvent>,
+) -> Vec<TradeOrderEvent> {
+    let mut hot = hot.into_iter().peekable();
+    if let Some(hot_start) = hot.peek().map(TradeOrderEvent::index) {
+        trades.retain(|trade| trade.index() < hot_start);
+    }
+    trades.extend(hot);
+    trades
+}
+
+/// Trades of a segment file; a segment removed from disk reads as empty.
+fn read_segment(path: &Path) -> Result<Vec<TradeOrderEvent>, Error> {
+    let file_error = |e| Error::FileError(path.display().to_string(), e);
+    let compressed = match fs::read(path) {
+        Ok(compressed) => compressed,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(file_error(e)),
+    };
+    let decompressed = zstd::decode_all(compressed.as_slice()).map_err(file_error)?;
+    String::from_utf8(decompressed)?
+        .lines()
+        .map(|line| serde_json::from_str(line).map_err(Error::from))
+        .collect()
+}
+
+fn parse_segment_name(path: &Path) -> Option<(u64, u64)> {
+    let name = path.file_name()?.to_str()?;
+    let range = name
+        .strip_prefix(SEGMENT_PREFIX)?
+        .strip_suffix(SEGMENT_SUFFIX)?;
+    let (first, last) = range.split_once('-')?;
+    Some((first.parse().ok()?, last.parse().ok()?))
+}
+
+/// Moves trades older than `COLD_STORAGE_AFTER_DAYS` (default 30) from the
+/// hot tape to cold storage once an hour.
+pub fn initialize_cold_storage(
+    tasks: &mut Vec<tokio::task::JoinHandle<()>>,
+    order_book: Arc<dyn Storage>,
+    cold_store: Arc<ColdTradeStore>,
+) -> Result<(), Error> {
+    let archive_after_ms = match ev_opt("COLD_STORAGE_AFTER_DAYS") {
+        Some(value) => value.parse::<u64>()? * DAY_MS,
+        None => DEFAULT_ARCHIVE_AFTER_DAYS * DAY_MS,
+    };
+
+    tasks.push(tokio::spawn(async move {
+        let mut ticker = time::interval(ARCHIVE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let now = chrono::Utc::now().timestamp_millis() as u64;
+            let cutoff = now.saturating_sub(archive_after_ms);
+            let mut trades = order_book.take_trades_before(cutoff);
+            // Backfill after a restart brings archived trades back into
+            // memory; drop those instead of archiving them twice.
+            if let Some(newest) = cold_store.newest() {
+                trades.retain(|trade| trade.timestamp > newest);
+            }
+            if trades.is_empty() {
+                continue;
+            }
+            match cold_store.archive(&trades) {
+                Ok(()) => info!("Archived {} trades to cold storage", trades.len()),
+                Err(e) => {
+                    error!("Failed to archive trades, keeping them in memory: {}", e);
+                    order_book.restore_trades(trades);
+                }
+            }
+        }
+    }));
+    Ok(())
+}
@@ -0,0 +1,288 @@
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tokio::time::{self, Duration};
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::status::SyncPhase;
+use crate::storage::json_lines::read_json_lines;
+use crate::storage::market_registry::MarketState;
+use crate::storage::trader_stats::StatsPeriod;
+
+const DAY_MS: u64 = 86_400_000;
+const DEFAULT_TOP_TRADERS: usize = 10;
+const DEFAULT_WEBHOOK_ATTEMPTS: u32 = 12;
+const REPORT_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraderVolume {
+    pub user: String,
+    /// Summed trade sizes in raw base-asset units.
+    pub volume: String,
+    pub trades: u64,
+}
+
+/// One market's settlement summary for a UTC day. Prices and sizes are
+/// decimal strings in raw units.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyReport {
+    pub market_id: String,
+    /// `YYYY-MM-DD`.
+    pub date: String,
+    pub day_start: u64,
+    /// `None` on a day without trades.
+    pub open: Option<String>,
+    pub high: Option<String>,
+    pub low: Option<String>,
+    pub close: Option<String>,
+    pub volume: String,
+    pub trades: u64,
+    /// `None` unless fee revenue is tracked (`FEE_MAKER_BPS`/`FEE_TAKER_BPS`).
+    pub fees: Option<String>,
+    /// By volume, largest first.
+    pub top_traders: Vec<TraderVolume>,
+}
+
+/// End-of-day reports for one market, appended to its file as JSON lines
+/// once written and reloaded on startup.
+///
+/// Enabled by `DAILY_REPORT_DIR`, which holds `<market_id>.jsonl`. Reports
+/// are built from the market's stored trades, archived and hot, and list
+/// the `DAILY_REPORT_TOP_TRADERS` (default 10) largest traders.
+pub struct DailyReports {
+    path: PathBuf,
+    top_traders: usize,
+    reports: RwLock<BTreeMap<u64, DailyReport>>,
+}
+
+impl DailyReports {
+    /// Lines that do not parse are skipped; their days are reported again.
+    pub fn open(path: PathBuf, top_traders: usize) -> Result<Self, Error> {
+        let reports = read_json_lines::<DailyReport>(&path)?
+            .into_iter()
+            .map(|report| (report.day_start, report))
+            .collect();
+
+        Ok(DailyReports {
+            path,
+            top_traders,
+            reports: RwLock::new(reports),
+        })
+    }
+
+    pub fn from_env(market_id: &str) -> Result<Option<Self>, Error> {
+        let Some(dir) = ev_opt("DAILY_REPORT_DIR") else {
+            return Ok(None);
+        };
+        let top_traders = match ev_opt("DAILY_REPORT_TOP_TRADERS") {
+            Some(count) => count.parse()?,
+            None => DEFAULT_TOP_TRADERS,
+        };
+        let dir = PathBuf::from(dir);
+        fs::create_dir_all(&dir).map_err(|e| Error::FileError(dir.display().to_string(), e))?;
+        Self::open(dir.join(format!("{}.jsonl", market_id)), top_traders).map(Some)
+    }
+
+    pub fn report(&self, day_start: u64) -> Option<DailyReport> {
+        self.reports.read().unwrap().get(&day_start).cloned()
+    }
+
+    /// Days up to the one starting at `last`, oldest first, still to be
+    /// reported: every day since the newest report, or just `last` before
+    /// the first one.
+    pub fn unreported_days(&self, last: u64) -> Vec<u64> {
+        let first = self
+            .reports
+            .read()
+            .unwrap()
+            .keys()
+            .next_back()
+            .map_or(last, |newest| newest + DAY_MS);
+        (first..=last).step_by(DAY_MS as usize).collect()
+    }
+
+    /// Builds, stores and returns the report for the day starting at
+    /// `day_start` from the market's trades and fee revenue, or returns the
+    /// stored one if it was already written. Each match counts once, and
+    /// toward the volume of both its buyer and its seller.
+    pub fn generate(&self, market: &MarketState, day_start: u64) -> Result<DailyReport, Error> {
+        if let Some(report) = self.report(day_start) {
+            return Ok(report);
+        }
+
+        let (mut open, mut high, mut low, mut close) = (None, None, None, None);
+        let (mut volume, mut trades) = (0u128, 0u64);
+        let mut trader_volume: HashMap<String, (u128, u64)> = HashMap::new();
+        for trade in market.matches_between(day_start, day_start + DAY_MS - 1)? {
+            let (Ok(price), Ok(size)) = (
+                trade.trade_price.parse::<u128>(),
+                trade.trade_size.parse::<u128>(),
+            ) else {
+                continue;
+            };
+            open.get_or_insert(price);
+            high = high.max(Some(price));
+            low = Some(low.map_or(price, |low: u128| low.min(price)));
+            close = Some(price);
+            volume = volume.saturating_add(size);
+            trades += 1;
+
+            let mut parties = vec![trade.buyer, trade.seller];
+            parties.dedup();
+            for user in parties.into_iter().flatten() {
+                let (volume, trades) = trader_volume.entry(user).or_default();
+                *volume = volume.saturating_add(size);
+                *trades += 1;
+            }
+        }
+
+        let price = |price: Option<u128>| price.map(|price| price.to_string());
+        let fees = market.fee_revenue.as_ref().map(|fee_revenue| {
+            fee_revenue
+                .series(StatsPeriod::Day, day_start, day_start)
+                .pop()
+                .map_or_else(|| "0".to_owned(), |bucket| bucket.fees)
+        });
+
+        let mut top_traders: Vec<(String, (u128, u64))> = trader_volume.into_iter().collect();
+        top_traders.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(&b.0)));
+        top_traders.truncate(self.top_traders);
+
+        let report = DailyReport {
+            market_id: market.market_id().to_owned(),
+            date: DateTime::from_timestamp_millis(day_start as i64)
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            day_start,
+            open: price(open),
+            high: price(high),
+            low: price(low),
+            close: price(close),
+            volume: volume.to_string(),
+            trades,
+            fees,
+            top_traders: top_traders
+                .into_iter()
+                .map(|(user, (volume, trades))| TraderVolume {
+                    user,
+                    volume: volume.to_string(),
+                    trades,
+                })
+                .collect(),
+        };
+
+        self.append_to_file(&report)?;
+        self.reports
+            .write()
+            .unwrap()
+            .insert(day_start, report.clone());
+        Ok(report)
+    }
+
+    fn append_to_file(&self, report: &DailyReport) -> Result<(), Error> {
+        let mut line = serde_json::to_string(report)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| Error::FileError(self.path.display().to_string(), e))
+    }
+}
+
+/// Writes the market's reports for every UTC day up to the previous one
+/// not reported yet once the indexer is live, so a backfill never produces
+/// a partial one, and POSTs each as JSON to `DAILY_REPORT_WEBHOOK_URL` when
+/// set. Failed deliveries are retried with every check, every five
+/// minutes, up to `DAILY_REPORT_WEBHOOK_ATTEMPTS` (default 12) attempts.
+pub fn initialize_daily_reports(
+    tasks: &mut Vec<tokio::task::JoinHandle<()>>,
+    market: MarketState,
+    reports: Arc<DailyReports>,
+) -> Result<(), Error> {
+    let webhook = ev_opt("DAILY_REPORT_WEBHOOK_URL");
+    let max_attempts: u32 = match ev_opt("DAILY_REPORT_WEBHOOK_ATTEMPTS") {
+        Some(attempts) => attempts.parse()?,
+        None => DEFAULT_WEBHOOK_ATTEMPTS,
+    };
+
+    tasks.push(tokio::spawn(async move {
+        let http = reqwest::Client::new();
+        let mut ticker = time::interval(REPORT_CHECK_INTERVAL);
+        // Reports still to be delivered, with the attempts made so far.
+        let mut undelivered: VecDeque<(DailyReport, u32)> = VecDeque::new();
+        loop {
+            ticker.tick().await;
+            if market.status.phase() != SyncPhase::Live {
+                continue;
+            }
+            let now = Utc::now().timestamp_millis() as u64;
+            let yesterday = now - now % DAY_MS - DAY_MS;
+
+            for day_start in reports.unreported_days(yesterday) {
+                let report = match reports.generate(&market, day_start) {
+                    Ok(report) => report,
+                    Err(e) => {
+                        error!(
+                            "Failed to write the daily report for {}: {}",
+                            market.market_id(),
+                            e
+                        );
+                        break;
+                    }
+                };
+                info!(
+                    "Wrote the {} daily report for {}",
+                    report.date,
+                    market.market_id()
+                );
+                if webhook.is_some() {
+                    undelivered.push_back((report, 0));
+                }
+            }
+
+            let Some(url) = &webhook else {
+                continue;
+            };
+            for _ in 0..undelivered.len() {
+                let Some((report, attempts)) = undelivered.pop_front() else {
+                    break;
+                };
+                let result = async {
+                    http.post(url)
+                        .json(&report)
+                        .send()
+                        .await?
+                        .error_for_status()
+                }
+                .await;
+                let Err(e) = result else {
+                    continue;
+                };
+                if attempts + 1 >= max_attempts {
+                    error!(
+                        "Giving up delivering the {} daily report for {} after {} attempts: {}",
+                        report.date,
+                        market.market_id(),
+                        attempts + 1,
+                        e
+                    );
+                } else {
+                    warn!(
+                        "Failed to deliver the {} daily report, retrying: {}",
+                        report.date, e
+                    );
+                    undelivered.push_back((report, attempts + 1));
+                }
+            }
+        }
+    }));
+    Ok(())
+}
@@ -0,0 +1,369 @@
+use log::{info, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::order_event_handler::{EventIndex, PangeaOrderEvent};
+use crate::storage::checkpoint::{read_checkpoint, write_checkpoint, Checkpoint};
+
+const DEFAULT_SEGMENT_BLOCKS: i64 = 100_000;
+const SEGMENT_PREFIX: &str = "events-";
+const SEGMENT_SUFFIX: &str = ".jsonl";
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+const SNAPSHOT_SUFFIX: &str = ".json";
+
+/// The segment being appended to.
+struct ActiveSegment {
+    first_block: i64,
+    writer: BufWriter<File>,
+}
+
+struct LogState {
+    active: Option<ActiveSegment>,
+    /// First block of every segment on disk.
+    segments: BTreeMap<i64, PathBuf>,
+    /// The last event written; events at or before it are repeats.
+    position: Option<EventIndex>,
+    /// The newest compaction snapshot and the block it was taken at.
+    snapshot: Option<(i64, PathBuf)>,
+}
+
+/// Every Pangea event a market receives, appended as JSON lines before it
+/// is applied, so replays, debugging and audits don't depend on Pangea.
+///
+/// Enabled by `EVENT_LOG_DIR`, which holds one directory per market. A new
+/// segment, `events-<first block>.jsonl`, is started every
+/// `EVENT_LOG_SEGMENT_BLOCKS` (default 100000) blocks. Events are written
+/// as received, before market migrations rename them. Blocks replaced by a
+/// reorg stay in the log; reading keeps the last version of each block.
+///
+/// Compaction replaces the oldest segments with a snapshot,
+/// `snapshot-<block>.json`, of the book they add up to, so orders that were
+/// filled or cancelled by then leave nothing but their trades behind.
+pub struct EventLog {
+    dir: PathBuf,
+    segment_blocks: i64,
+    state: Mutex<LogState>,
+}
+
+impl EventLog {
+    pub fn open(dir: PathBuf, segment_blocks: i64) -> Result<Self, Error> {
+        let file_error = |e| Error::FileError(dir.display().to_string(), e);
+        fs::create_dir_all(&dir).map_err(file_error)?;
+
+        let mut segments = BTreeMap::new();
+        let mut snapshot: Option<(i64, PathBuf)> = None;
+        for entry in fs::read_dir(&dir).map_err(file_error)? {
+            let path = entry.map_err(file_error)?.path();
+            if let Some(first_block) = parse_segment_name(&path) {
+                segments.insert(first_block, path);
+            } else if let Some(block_number) = parse_snapshot_name(&path) {
+                if snapshot
+                    .as_ref()
+                    .is_none_or(|(newest, _)| block_number > *newest)
+                {
+                    snapshot = Some((block_number, path));
+                }
+            }
+        }
+        let position = match segments.values().next_back() {
+            Some(path) => {
+                truncate_torn_line(path)?;
+                read_segment(path)?.last().map(PangeaOrderEvent::index)
+            }
+            None => None,
+        };
+        info!(
+            "Event log at {} with {} segments, compacted up to block {}",
+            dir.display(),
+            segments.len(),
+            snapshot
+                .as_ref()
+                .map_or("none".to_owned(), |(block_number, _)| block_number
+                    .to_string())
+        );
+
+        Ok(EventLog {
+            dir,
+            segment_blocks,
+            state: Mutex::new(LogState {
+                active: None,
+                segments,
+                position,
+                snapshot,
+            }),
+        })
+    }
+
+    pub fn from_env(market_id: &str) -> Result<Option<Self>, Error> {
+        let Some(dir) = ev_opt("EVENT_LOG_DIR") else {
+            return Ok(None);
+        };
+        let segment_blocks = match ev_opt("EVENT_LOG_SEGMENT_BLOCKS") {
+            Some(blocks) => blocks.parse::<i64>()?.max(1),
+            None => DEFAULT_SEGMENT_BLOCKS,
+        };
+        Self::open(PathBuf::from(dir).join(market_id), segment_blocks).map(Some)
+    }
+
+    /// Buffers `event` for the next `flush`, unless it was already written.
+    pub fn append(&self, event: &PangeaOrderEvent) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .position
+            .is_some_and(|position| event.index() <= position)
+        {
+            return Ok(());
+        }
+
+        let first_block = event.block_number - event.block_number.rem_euclid(self.segment_blocks);
+        if state
+            .active
+            .as_ref()
+            .is_none_or(|active| active.first_block != first_block)
+        {
+            if let Some(mut active) = state.active.take() {
+                self.flush_segment(&mut active)?;
+            }
+            let path = self.dir.join(segment_name(first_block));
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| Error::FileError(path.display().to_string(), e))?;
+            state.segments.entry(first_block).or_insert(path);
+            state.active = Some(ActiveSegment {
+                first_block,
+                writer: BufWriter::new(file),
+            });
+        }
+
+        let mut line = serde_json::to_string(event)?;
+        line.push('\n');
+        let active = state.active.as_mut().expect("segment opened above");
+        active
+            .writer
+            .write_all(line.as_bytes())
+            .map_err(|e| self.segment_error(active.first_block, e))?;
+        state.position = Some(event.index());
+        Ok(())
+    }
+
+    /// Writes the buffered events out. Called before they are applied.
+    pub fn flush(&self) -> Result<(), Error> {
+        match self.state.lock().unwrap().active.as_mut() {
+            Some(active) => self.flush_segment(active),
+            None => Ok(()),
+        }
+    }
+
+    /// Lets the events after `block_number` be written again, as when a
+    /// reorg rolled the book back and the canonical blocks are re-read.
+    pub fn rewind(&self, block_number: i64) {
+        let mut state = self.state.lock().unwrap();
+        let end = EventIndex {
+            block_number,
+            transaction_index: u64::MAX,
+            log_index: u64::MAX,
+        };
+        if state.position.is_some_and(|position| position > end) {
+            state.position = Some(end);
+        }
+    }
+
+    /// Logged events in `[from_block, to_block]` in chain order. A block
+    /// logged more than once, as around a reorg, is read as its last
+    /// version. Blocks up to the snapshot's have been compacted away.
+    pub fn read(&self, from_block: i64, to_block: i64) -> Result<Vec<PangeaOrderEvent>, Error> {
+        self.flush()?;
+        let paths: Vec<PathBuf> = {
+            let state = self.state.lock().unwrap();
+            let first = state
+                .segments
+                .range(..=from_block)
+                .next_back()
+                .map_or(from_block, |(&start, _)| start);
+            state
+                .segments
+                .range(first..=to_block)
+                .map(|(_, path)| path.clone())
+                .collect()
+        };
+
+        let mut events = vec![];
+        for path in paths {
+            events.extend(
+                read_segment(&path)?
+                    .into_iter()
+                    .filter(|event| (from_block..=to_block).contains(&event.block_number)),
+            );
+        }
+
+        let last_hash: HashMap<i64, String> = events
+            .iter()
+            .map(|event| (event.block_number, event.block_hash.clone()))
+            .collect();
+        let mut seen = HashSet::new();
+        events.retain(|event| {
+            last_hash.get(&event.block_number) == Some(&event.block_hash)
+                && seen.insert(event.index())
+        });
+        events.sort_by_key(PangeaOrderEvent::index);
+        Ok(events)
+    }
+
+    /// The newest compaction snapshot of `market_id`'s book, if the log was
+    /// compacted. Events after its block are still in the segments. A
+    /// snapshot that is gone, from a newer build or can't be migrated is an
+    /// error: the segments it replaced are deleted, so the log alone would
+    /// rebuild a partial book.
+    pub fn snapshot(&self, market_id: &str) -> Result<Option<Checkpoint>, Error> {
+        let path = match &self.state.lock().unwrap().snapshot {
+            Some((_, path)) => path.clone(),
+            None => return Ok(None),
+        };
+        match read_checkpoint(&path, market_id)? {
+            Some(snapshot) => Ok(Some(snapshot)),
+            None => Err(Error::UnreadableSnapshot(path.display().to_string())),
+        }
+    }
+
+    /// The last block of the newest segment that ends at least `keep_blocks`
+    /// before the last logged event and is no longer appended to. Compacting
+    /// up to it leaves recent blocks, which a reorg may still replace, as
+    /// they are.
+    pub fn compactable_until(&self, keep_blocks: i64) -> Option<i64> {
+        let state = self.state.lock().unwrap();
+        let newest_block = state.position?.block_number;
+        let active_block = state.active.as_ref().map(|active| active.first_block);
+        let firsts: Vec<i64> = state.segments.keys().copied().collect();
+        firsts
+            .windows(2)
+            .filter(|pair| active_block.is_none_or(|active| pair[0] < active))
+            .map(|pair| pair[1] - 1)
+            .filter(|&last_block| last_block + keep_blocks <= newest_block)
+            .last()
+    }
+
+    /// Stores `snapshot` and deletes the segments and the older snapshot it
+    /// replaces, i.e. everything up to its block.
+    pub fn replace_with_snapshot(&self, snapshot: &Checkpoint) -> Result<usize, Error> {
+        let block_number = snapshot.block_number;
+        let path = self.dir.join(snapshot_name(block_number));
+        write_checkpoint(&path, snapshot)?;
+
+        let (replaced, previous) = {
+            let mut state = self.state.lock().unwrap();
+            let firsts: Vec<i64> = state.segments.keys().copied().collect();
+            let replaced: Vec<PathBuf> = firsts
+                .windows(2)
+                .filter(|pair| pair[1] - 1 <= block_number)
+                .filter_map(|pair| state.segments.remove(&pair[0]))
+                .collect();
+            let previous = state
+                .snapshot
+                .replace((block_number, path))
+                .filter(|(previous_block, _)| *previous_block != block_number);
+            (replaced, previous)
+        };
+        for old in replaced
+            .iter()
+            .chain(previous.as_ref().map(|(_, path)| path))
+        {
+            fs::remove_file(old).map_err(|e| Error::FileError(old.display().to_string(), e))?;
+        }
+        Ok(replaced.len())
+    }
+
+    /// Writes the buffer out and syncs it to disk, so an event is never
+    /// applied before it would survive a crash.
+    fn flush_segment(&self, active: &mut ActiveSegment) -> Result<(), Error> {
+        active
+            .writer
+            .flush()
+            .and_then(|()| active.writer.get_ref().sync_data())
+            .map_err(|e| self.segment_error(active.first_block, e))
+    }
+
+    fn segment_error(&self, first_block: i64, e: std::io::Error) -> Error {
+        Error::FileError(
+            self.dir
+                .join(segment_name(first_block))
+                .display()
+                .to_string(),
+            e,
+        )
+    }
+}
+
+/// Cuts a partial last line, left by a crash in the middle of a write, off
+/// the segment, so appending resumes on a line of its own. The event it
+/// held was never applied.
+fn truncate_torn_line(path: &Path) -> Result<(), Error> {
+    let file_error = |e| Error::FileError(path.display().to_string(), e);
+    let contents = fs::read(path).map_err(file_error)?;
+    if contents.is_empty() || contents.ends_with(b"\n") {
+        return Ok(());
+    }
+    let keep = contents
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |newline| newline + 1);
+    warn!(
+        "Dropping a torn line of {} bytes at the end of {}",
+        contents.len() - keep,
+        path.display()
+    );
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|file| {
+            file.set_len(keep as u64)?;
+            file.sync_data()
+        })
+        .map_err(file_error)
+}
+
+fn read_segment(path: &Path) -> Result<Vec<PangeaOrderEvent>, Error> {
+    let file_error = |e| Error::FileError(path.display().to_string(), e);
+    let file = File::open(path).map_err(file_error)?;
+    let mut events = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(file_error)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        events.push(PangeaOrderEvent::from_json(line.into_bytes())?);
+    }
+    Ok(events)
+}
+
+fn segment_name(first_block: i64) -> String {
+    format!("{}{}{}", SEGMENT_PREFIX, first_block, SEGMENT_SUFFIX)
+}
+
+fn snapshot_name(block_number: i64) -> String {
+    format!("{}{}{}", SNAPSHOT_PREFIX, block_number, SNAPSHOT_SUFFIX)
+}
+
+fn parse_snapshot_name(path: &Path) -> Option<i64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix(SNAPSHOT_PREFIX)?
+        .strip_suffix(SNAPSHOT_SUFFIX)?
+        .parse()
+        .ok()
+}
+
+fn parse_segment_name(path: &Path) -> Option<i64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix(SEGMENT_PREFIX)?
+        .strip_suffix(SEGMENT_SUFFIX)?
+        .parse()
+        .ok()
+}
@@ -0,0 +1,60 @@
+use crate::indexer::spot_order::OrderType;
+use crate::storage::backend::Storage;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PriceLevel {
+    pub price: u128,
+    pub size: u128,
+}
+
+#[derive(Debug, Clone)]
+pub struct FairPrice {
+    pub price: u128,
+    pub bid_vwap: u128,
+    pub ask_vwap: u128,
+    pub bid_size: u128,
+    pub ask_size: u128,
+    pub bids: Vec<PriceLevel>,
+    pub asks: Vec<PriceLevel>,
+}
+
+/// Size-weighted mid over the top `levels` price levels of each side.
+///
+/// Each side is reduced to its volume-weighted average price, and the two
+/// averages are combined weighted by the opposite side's size, so a thin
+/// side moves the result less than a deep one. Returns `None` if either
+/// side of the book is empty.
+pub fn compute_fair_price(order_book: &dyn Storage, levels: usize) -> Option<FairPrice> {
+    let bids = order_book.top_levels(OrderType::Buy, levels);
+    let asks = order_book.top_levels(OrderType::Sell, levels);
+
+    let (bid_vwap, bid_size) = vwap(&bids)?;
+    let (ask_vwap, ask_size) = vwap(&asks)?;
+
+    let total_size = bid_size.saturating_add(ask_size);
+    let price = bid_vwap
+        .saturating_mul(ask_size)
+        .saturating_add(ask_vwap.saturating_mul(bid_size))
+        / total_size;
+
+    Some(FairPrice {
+        price,
+        bid_vwap,
+        ask_vwap,
+        bid_size,
+        ask_size,
+        bids,
+        asks,
+    })
+}
+
+fn vwap(levels: &[PriceLevel]) -> Option<(u128, u128)> {
+    let size: u128 = levels.iter().map(|level| level.size).sum();
+    if size == 0 {
+        return None;
+    }
+    let notional = levels.iter().fold(0u128, |acc, level| {
+        acc.saturating_add(level.price.saturating_mul(level.size))
+    });
+    Some((notional / size, size))
+}
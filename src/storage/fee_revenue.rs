@@ -0,0 +1,250 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::json_lines::read_json_lines;
+use crate::storage::trader_stats::StatsPeriod;
+
+const DEFAULT_FEE_REVENUE_DIR: &str = "fee_revenue";
+const DAY_MS: u64 = 86_400_000;
+
+/// Matches and fees for one UTC day. Amounts are decimal strings in raw
+/// units: `volume` sums match sizes, `notional` sums price × size and `fees`
+/// charges each match's notional the maker rate plus the taker rate.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FeeBucket {
+    pub period_start: u64,
+    pub trades: u64,
+    pub volume: String,
+    pub notional: String,
+    pub fees: String,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Totals {
+    trades: u64,
+    volume: u128,
+    notional: u128,
+    fees: u128,
+}
+
+impl Totals {
+    fn add(&mut self, other: &Totals) {
+        self.trades += other.trades;
+        self.volume = self.volume.saturating_add(other.volume);
+        self.notional = self.notional.saturating_add(other.notional);
+        self.fees = self.fees.saturating_add(other.fees);
+    }
+
+    fn subtract(&mut self, other: &Totals) {
+        self.trades = self.trades.saturating_sub(other.trades);
+        self.volume = self.volume.saturating_sub(other.volume);
+        self.notional = self.notional.saturating_sub(other.notional);
+        self.fees = self.fees.saturating_sub(other.fees);
+    }
+
+    fn to_bucket(self, period_start: u64) -> FeeBucket {
+        FeeBucket {
+            period_start,
+            trades: self.trades,
+            volume: self.volume.to_string(),
+            notional: self.notional.to_string(),
+            fees: self.fees.to_string(),
+        }
+    }
+
+    fn from_bucket(bucket: &FeeBucket) -> Result<Self, Error> {
+        Ok(Totals {
+            trades: bucket.trades,
+            volume: bucket.volume.parse()?,
+            notional: bucket.notional.parse()?,
+            fees: bucket.fees.parse()?,
+        })
+    }
+}
+
+/// Fee revenue per UTC day for one market, from the maker and taker rates
+/// of its fee schedule. The order stream carries no fee amounts, so each
+/// match is charged at the configured rates.
+///
+/// Works like the usage tracker: the current day is kept in memory and
+/// completed days are appended to the market's file as JSON lines. On
+/// startup days already in the file are loaded and not recounted when the
+/// backfill replays their trades.
+pub struct FeeRevenue {
+    path: PathBuf,
+    maker_bps: u128,
+    taker_bps: u128,
+    days: RwLock<BTreeMap<u64, Totals>>,
+    /// Days before this one are already in the file.
+    persisted_before: RwLock<u64>,
+}
+
+impl FeeRevenue {
+    pub fn open(path: PathBuf, maker_bps: u128, taker_bps: u128) -> Result<Self, Error> {
+        let buckets: Vec<FeeBucket> = read_json_lines(&path)?;
+
+        let persisted_before = buckets
+            .iter()
+            .map(|bucket| bucket.period_start + DAY_MS)
+            .max()
+            .unwrap_or(0);
+        let days = buckets
+            .iter()
+            .map(|bucket| Ok((bucket.period_start, Totals::from_bucket(bucket)?)))
+            .collect::<Result<_, Error>>()?;
+
+        Ok(FeeRevenue {
+            path,
+            maker_bps,
+            taker_bps,
+            days: RwLock::new(days),
+            persisted_before: RwLock::new(persisted_before),
+        })
+    }
+
+    /// Enabled by `FEE_MAKER_BPS` or `FEE_TAKER_BPS`; either falls back to
+    /// `FEE_RATE_BPS`, and to zero without it. Each market's series lives in
+    /// `FEE_REVENUE_DIR` (default `fee_revenue`) as `<market_id>.jsonl`.
+    pub fn from_env(market_id: &str) -> Result<Option<Self>, Error> {
+        let rate_bps = ev_opt("FEE_RATE_BPS");
+        let maker_bps = ev_opt("FEE_MAKER_BPS").or_else(|| rate_bps.clone());
+        let taker_bps = ev_opt("FEE_TAKER_BPS").or(rate_bps);
+        if maker_bps.is_none() && taker_bps.is_none() {
+            return Ok(None);
+        }
+        let maker_bps = match maker_bps {
+            Some(maker_bps) => maker_bps.parse()?,
+            None => 0,
+        };
+        let taker_bps = match taker_bps {
+            Some(taker_bps) => taker_bps.parse()?,
+            None => 0,
+        };
+        let dir = PathBuf::from(
+            ev_opt("FEE_REVENUE_DIR").unwrap_or_else(|| DEFAULT_FEE_REVENUE_DIR.to_owned()),
+        );
+        fs::create_dir_all(&dir).map_err(|e| Error::FileError(dir.display().to_string(), e))?;
+        Self::open(
+            dir.join(format!("{}.jsonl", market_id)),
+            maker_bps,
+            taker_bps,
+        )
+        .map(Some)
+    }
+
+    /// Counts one match, given once for its two fills. Returns false for a
+    /// match of a day already in the file, which is not counted again.
+    pub fn record_match(&self, price: u128, size: u128, timestamp: u64) -> bool {
+        let day_start = timestamp - timestamp % DAY_MS;
+        if day_start < *self.persisted_before.read().unwrap() {
+            return false;
+        }
+        {
+            let mut days = self.days.write().unwrap();
+            days.entry(day_start)
+                .or_default()
+                .add(&self.totals_of(price, size));
+        }
+        self.persist_completed_days(day_start);
+        true
+    }
+
+    /// Takes back a match counted by [`Self::record_match`]. A day already
+    /// in the file is appended again; the later line wins on startup.
+    pub fn undo_match(&self, price: u128, size: u128, timestamp: u64) {
+        let day_start = timestamp - timestamp % DAY_MS;
+        let corrected = {
+            let mut days = self.days.write().unwrap();
+            let Some(totals) = days.get_mut(&day_start) else {
+                return;
+            };
+            totals.subtract(&self.totals_of(price, size));
+            *totals
+        };
+        if day_start < *self.persisted_before.read().unwrap() {
+            if let Err(e) = self.append_to_file(&[corrected.to_bucket(day_start)]) {
+                error!(
+                    "Failed to write fee revenue to {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    fn totals_of(&self, price: u128, size: u128) -> Totals {
+        let notional = price.saturating_mul(size);
+        Totals {
+            trades: 1,
+            volume: size,
+            notional,
+            fees: (notional.saturating_mul(self.maker_bps) / 10_000)
+                .saturating_add(notional.saturating_mul(self.taker_bps) / 10_000),
+        }
+    }
+
+    /// Buckets of `period` overlapping `[from, to]` (ms), oldest first.
+    pub fn series(&self, period: StatsPeriod, from: u64, to: u64) -> Vec<FeeBucket> {
+        let mut buckets: BTreeMap<u64, Totals> = BTreeMap::new();
+        for (&day_start, totals) in self.days.read().unwrap().iter() {
+            if day_start + DAY_MS <= from || day_start > to {
+                continue;
+            }
+            buckets
+                .entry(period.bucket_start(day_start))
+                .or_default()
+                .add(totals);
+        }
+        buckets
+            .into_iter()
+            .map(|(period_start, totals)| totals.to_bucket(period_start))
+            .collect()
+    }
+
+    /// Appends every day before `today` not yet written.
+    fn persist_completed_days(&self, today: u64) {
+        let mut persisted_before = self.persisted_before.write().unwrap();
+        if *persisted_before >= today {
+            return;
+        }
+
+        let completed: Vec<FeeBucket> = self
+            .days
+            .read()
+            .unwrap()
+            .range(*persisted_before..today)
+            .map(|(&day_start, totals)| totals.to_bucket(day_start))
+            .collect();
+        if !completed.is_empty() {
+            if let Err(e) = self.append_to_file(&completed) {
+                error!(
+                    "Failed to write fee revenue to {}: {}",
+                    self.path.display(),
+                    e
+                );
+            }
+        }
+        *persisted_before = today;
+    }
+
+    fn append_to_file(&self, buckets: &[FeeBucket]) -> Result<(), Error> {
+        let mut lines = String::new();
+        for bucket in buckets {
+            lines.push_str(&serde_json::to_string(bucket)?);
+            lines.push('\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .map_err(|e| Error::FileError(self.path.display().to_string(), e))
+    }
+}
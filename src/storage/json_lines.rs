@@ -0,0 +1,39 @@
+use log::warn;
+use serde::de::DeserializeOwned;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use crate::error::Error;
+
+/// Reads a file of JSON lines, empty if it doesn't exist yet.
+///
+/// Lines that do not parse, such as one torn by a crash mid-write, are
+/// skipped with a warning rather than failing the whole file. A torn last
+/// line is ended, so lines appended later start on a line of their own.
+pub fn read_json_lines<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(Error::FileError(path.display().to_string(), e)),
+    };
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file))
+            .map_err(|e| Error::FileError(path.display().to_string(), e))?;
+    }
+
+    let mut entries = vec![];
+    for (number, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("Skipping line {} of {}: {}", number + 1, path.display(), e),
+        }
+    }
+    Ok(entries)
+}
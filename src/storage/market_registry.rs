@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::task::AbortHandle;
+
+use crate::config::env::{ev, ev_opt};
+use crate::error::Error;
+use crate::indexer::resync::ResyncRequests;
+use crate::indexer::status::IndexerStatus;
+use crate::storage::backend::Storage;
+use crate::storage::checkpoint::CheckpointStore;
+use crate::storage::cold_storage::{merge_tape, ColdTradeStore};
+use crate::storage::daily_reports::DailyReports;
+use crate::storage::event_log::EventLog;
+use crate::storage::fee_revenue::FeeRevenue;
+use crate::storage::order_book::OrderBook;
+use crate::web::graphql::TradeOrderEvent;
+
+/// Contract ids from `CONTRACT_ID`, which may list several markets
+/// separated by commas. The first one is the default market.
+pub fn contract_ids() -> Result<Vec<String>, Error> {
+    let value = ev("CONTRACT_ID")?;
+    let ids: Vec<String> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_owned)
+        .collect();
+    if ids.is_empty() {
+        return Err(Error::EnvVarError(
+            "CONTRACT_ID".to_owned(),
+            "no contract ids".to_owned(),
+        ));
+    }
+    Ok(ids)
+}
+
+/// Contract migrations from `MARKET_MIGRATIONS`, comma-separated
+/// `old_id:new_id` pairs, keyed by the old id without `0x` in lowercase.
+pub fn market_migrations() -> Result<HashMap<String, String>, Error> {
+    let mut migrations = HashMap::new();
+    for pair in ev_opt("MARKET_MIGRATIONS")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let Some((old_id, new_id)) = pair.split_once(':') else {
+            return Err(Error::EnvVarError(
+                "MARKET_MIGRATIONS".to_owned(),
+                format!("'{}' is not old_id:new_id", pair),
+            ));
+        };
+        migrations.insert(
+            strip_hex_prefix(old_id.trim()).to_ascii_lowercase(),
+            new_id.trim().to_owned(),
+        );
+    }
+    Ok(migrations)
+}
+
+/// Start blocks from `MARKET_START_BLOCKS`, comma-separated
+/// `market_id:block` pairs, keyed by the id without `0x` in lowercase.
+pub fn market_start_blocks() -> Result<HashMap<String, i64>, Error> {
+    let mut start_blocks = HashMap::new();
+    for pair in ev_opt("MARKET_START_BLOCKS")
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+    {
+        let Some((market_id, block)) = pair.split_once(':') else {
+            return Err(Error::EnvVarError(
+                "MARKET_START_BLOCKS".to_owned(),
+                format!("'{}' is not market_id:block", pair),
+            ));
+        };
+        start_blocks.insert(
+            strip_hex_prefix(market_id.trim()).to_ascii_lowercase(),
+            block.trim().parse()?,
+        );
+    }
+    Ok(start_blocks)
+}
+
+/// Everything kept separately for one indexed market.
+#[derive(Clone)]
+pub struct MarketState {
+    pub order_book: Arc<dyn Storage>,
+    pub status: Arc<IndexerStatus>,
+    pub cold_store: Option<Arc<ColdTradeStore>>,
+    pub fee_revenue: Option<Arc<FeeRevenue>>,
+    pub checkpoints: Option<Arc<CheckpointStore>>,
+    pub daily_reports: Option<Arc<DailyReports>>,
+    pub event_log: Option<Arc<EventLog>>,
+    pub resyncs: Arc<ResyncRequests>,
+    /// Overrides `CONTRACT_START_BLOCK`, from `MARKET_START_BLOCKS` or for
+    /// markets added at runtime.
+    pub start_block: Option<i64>,
+}
+
+impl MarketState {
+    /// A market with an empty book and nothing persisted.
+    pub fn new(market_id: String) -> Self {
+        MarketState {
+            order_book: Arc::new(OrderBook::new()),
+            status: Arc::new(IndexerStatus::new(market_id)),
+            cold_store: None,
+            fee_revenue: None,
+            checkpoints: None,
+            daily_reports: None,
+            event_log: None,
+            resyncs: Arc::new(ResyncRequests::default()),
+            start_block: None,
+        }
+    }
+
+    /// A market with whichever of cold storage (`COLD_STORAGE_DIR`), fee
+    /// revenue (`FEE_MAKER_BPS`/`FEE_TAKER_BPS`), checkpoints (`CHECKPOINT_DIR`), daily
+    /// reports (`DAILY_REPORT_DIR`), an event log (`EVENT_LOG_DIR`) and a
+    /// start block (`MARKET_START_BLOCKS`) are configured.
+    pub fn open(market_id: String) -> Result<Self, Error> {
+        let start_block = market_start_blocks()?
+            .get(&strip_hex_prefix(&market_id).to_ascii_lowercase())
+            .copied();
+        let cold_store = ColdTradeStore::from_env(&market_id)?.map(Arc::new);
+        let fee_revenue = FeeRevenue::from_env(&market_id)?.map(Arc::new);
+        let checkpoints = CheckpointStore::from_env(&market_id)?.map(Arc::new);
+        let daily_reports = DailyReports::from_env(&market_id)?.map(Arc::new);
+        let event_log = EventLog::from_env(&market_id)?.map(Arc::new);
+        Ok(MarketState {
+            cold_store,
+            fee_revenue,
+            checkpoints,
+            daily_reports,
+            event_log,
+            start_block,
+            ..MarketState::new(market_id)
+        })
+    }
+
+    pub fn market_id(&self) -> &str {
+        self.status.market_id()
+    }
+
+    /// The matches with timestamps in `[from, to]`, archived and hot, in
+    /// chain order. Each match appears once, as its first fill, and busted
+    /// trades are left out.
+    pub fn matches_between(&self, from: u64, to: u64) -> Result<Vec<TradeOrderEvent>, Error> {
+        let mut archived = vec![];
+        if let Some(cold_store) = &self.cold_store {
+            if cold_store.newest().is_some_and(|newest| newest >= from) {
+                archived = cold_store.load_range(from, to)?;
+            }
+        }
+        let hot = self
+            .order_book
+            .get_trade_events()
+            .into_iter()
+            .filter(|trade| (from..=to).contains(&trade.timestamp));
+        let mut trades = merge_tape(archived, hot);
+        trades.retain(|trade| !trade.busted && !trade.counterpart_fill);
+        Ok(trades)
+    }
+}
+
+/// Maps market ids to their separate state, so events and queries for one
+/// market never touch another market's book. Markets keep the order they
+/// were configured, discovered or added in.
+///
+/// Markets can be added and removed at runtime, except the default market.
+/// The registry also remembers each market's background tasks so removing
+/// a market stops them.
+///
+/// A market whose contract was migrated is indexed under its new id and
+/// also takes the old contract's events, so its history runs on from the
+/// old id into live data from the new one. The old id finds the same
+/// market.
+pub struct MarketRegistry {
+    markets: RwLock<Vec<MarketState>>,
+    tasks: Mutex<HashMap<String, Vec<AbortHandle>>>,
+    migrations: HashMap<String, String>,
+}
+
+impl MarketRegistry {
+    /// Panics if `markets` is empty.
+    pub fn new(markets: Vec<MarketState>) -> Self {
+        assert!(!markets.is_empty(), "a registry needs at least one market");
+        MarketRegistry {
+            markets: RwLock::new(markets),
+            tasks: Mutex::new(HashMap::new()),
+            migrations: HashMap::new(),
+        }
+    }
+
+    /// Opens the given markets, with the migrations in `MARKET_MIGRATIONS`.
+    /// The first one is the default market.
+    pub fn open(market_ids: Vec<String>) -> Result<Self, Error> {
+        let markets = market_ids
+            .into_iter()
+            .map(MarketState::open)
+            .collect::<Result<_, Error>>()?;
+        Ok(MarketRegistry {
+            migrations: market_migrations()?,
+            ..Self::new(markets)
+        })
+    }
+
+    /// Served when a request does not name a market.
+    pub fn default_market(&self) -> MarketState {
+        self.markets.read().unwrap()[0].clone()
+    }
+
+    /// Looks up a market, ignoring case and a `0x` prefix. A migrated
+    /// contract id finds the market it migrated to.
+    pub fn get(&self, market_id: &str) -> Option<MarketState> {
+        let migrated = self.migrated_to(market_id);
+        let wanted = strip_hex_prefix(migrated.as_deref().unwrap_or(market_id));
+        self.markets
+            .read()
+            .unwrap()
+            .iter()
+            .find(|market| same_market(market.market_id(), wanted))
+            .cloned()
+    }
+
+    /// The id `market_id` was last migrated to, following chained
+    /// migrations, or `None` if it was never migrated.
+    pub fn migrated_to(&self, market_id: &str) -> Option<String> {
+        let mut current: Option<&String> = None;
+        let mut id = market_id;
+        // Bounded so a cyclic mapping cannot loop forever.
+        for _ in 0..=self.migrations.len() {
+            match self
+                .migrations
+                .get(&strip_hex_prefix(id).to_ascii_lowercase())
+            {
+                Some(next) => {
+                    current = Some(next);
+                    id = next;
+                }
+                None => break,
+            }
+        }
+        current.cloned()
+    }
+
+    /// Every contract id that migrated, directly or through others, to
+    /// `market_id`.
+    pub fn predecessors(&self, market_id: &str) -> Vec<String> {
+        self.migrations
+            .keys()
+            .filter(|old_id| {
+                self.migrated_to(old_id)
+                    .is_some_and(|new_id| same_market(&new_id, strip_hex_prefix(market_id)))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The markets currently indexed.
+    pub fn all(&self) -> Vec<MarketState> {
+        self.markets.read().unwrap().clone()
+    }
+
+    /// Adds `market` unless one with the same id is already indexed.
+    pub fn insert(&self, market: MarketState) -> bool {
+        let mut markets = self.markets.write().unwrap();
+        let wanted = strip_hex_prefix(market.market_id());
+        if markets
+            .iter()
+            .any(|existing| same_market(existing.market_id(), wanted))
+        {
+            return false;
+        }
+        markets.push(market);
+        true
+    }
+
+    /// Removes a market and aborts its tasks. The default market cannot be
+    /// removed.
+    pub fn remove(&self, market_id: &str) -> Result<Option<MarketState>, Error> {
+        let wanted = strip_hex_prefix(market_id);
+        let removed = {
+            let mut markets = self.markets.write().unwrap();
+            match markets
+                .iter()
+                .position(|market| same_market(market.market_id(), wanted))
+            {
+                Some(0) => return Err(Error::DefaultMarketRemoval(market_id.to_owned())),
+                Some(position) => markets.remove(position),
+                None => return Ok(None),
+            }
+        };
+        if let Some(tasks) = self.tasks.lock().unwrap().remove(removed.market_id()) {
+            for task in tasks {
+                task.abort();
+            }
+        }
+        Ok(Some(removed))
+    }
+
+    /// Remembers tasks to abort when `market_id` is removed.
+    pub fn track_tasks(&self, market_id: &str, tasks: impl IntoIterator<Item = AbortHandle>) {
+        self.tasks
+            .lock()
+            .unwrap()
+            .entry(market_id.to_owned())
+            .or_default()
+            .extend(tasks);
+    }
+}
+
+/// `market_id` as a map key: without a `0x` prefix and in lower case, so
+/// every spelling of a market finds the same entry.
+pub fn market_key(market_id: &str) -> String {
+    strip_hex_prefix(market_id).to_ascii_lowercase()
+}
+
+fn same_market(market_id: &str, wanted: &str) -> bool {
+    strip_hex_prefix(market_id).eq_ignore_ascii_case(wanted)
+}
+
+fn strip_hex_prefix(id: &str) -> &str {
+    id.strip_prefix("0x").unwrap_or(id)
+}
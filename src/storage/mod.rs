@@ -1 +1,24 @@
+pub mod address_labels;
+pub mod audit_log;
+pub mod backend;
+pub mod candles;
+pub mod checkpoint;
+pub mod cold_storage;
+pub mod daily_reports;
+pub mod event_log;
+pub mod fair_price;
+pub mod fee_revenue;
+pub mod json_lines;
+pub mod market_registry;
 pub mod order_book;
+pub mod order_flow;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod quote_stats;
+pub mod retention;
+pub mod size_distribution;
+pub mod state;
+pub mod subscription_usage;
+pub mod trader_stats;
+pub mod undo;
+pub mod usage;
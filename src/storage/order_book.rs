@@ -1,13 +1,71 @@
-use std::collections::BTreeMap;
-use std::sync::{Arc, RwLock};
+use chrono::Utc;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+use tokio::sync::broadcast;
 
+use crate::indexer::anomaly_detector::Anomaly;
+use crate::indexer::order_event_handler::{AppliedEvents, EventIndex, TradeFill};
 use crate::indexer::spot_order::{OrderType, SpotOrder};
+use crate::indexer::timestamp_normalizer::EventTime;
+use crate::storage::candles::CandleStore;
+use crate::storage::fair_price::PriceLevel;
+use crate::storage::order_flow::OrderFlow;
+use crate::storage::quote_stats::QuoteChangeTracker;
+use crate::storage::retention::Retention;
+use crate::storage::size_distribution::SizeDistribution;
+use crate::storage::size_distribution::SizeKind;
+use crate::storage::state::{sort_entries, StateDigest, StateEntry};
+use crate::storage::trader_stats::TraderStats;
+use crate::storage::undo::Change;
 use crate::web::graphql::TradeOrderEvent;
+use crate::web::order_feed::{feed_buffer, OrderChange, OrderUpdate};
+
+const MAX_FLAGGED_ANOMALIES: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct QueuePosition {
+    pub order_type: OrderType,
+    pub price: u128,
+    pub orders_ahead: usize,
+    pub size_ahead: u128,
+}
+
+/// Where a recorded fill stands in its match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchFill {
+    /// The first fill of its match; statistics count the match here.
+    First,
+    /// The other side of the fill before it, which settles who made and who
+    /// took the match; carries the taker's side if that could be told.
+    Second { aggressor: Option<OrderType> },
+}
 
 pub struct OrderBook {
     buy_orders: Arc<RwLock<BTreeMap<u128, Vec<SpotOrder>>>>,
     sell_orders: Arc<RwLock<BTreeMap<u128, Vec<SpotOrder>>>>,
     trade_events: Arc<RwLock<Vec<TradeOrderEvent>>>,
+    trader_stats: TraderStats,
+    candles: CandleStore,
+    order_flow: OrderFlow,
+    quote_changes: QuoteChangeTracker,
+    size_distribution: SizeDistribution,
+    retention: Retention,
+    applied_events: AppliedEvents,
+    /// Hash of the resting orders, updated with every change to them.
+    state_digest: RwLock<StateDigest>,
+    anomalies: RwLock<VecDeque<Anomaly>>,
+    next_priority: AtomicU64,
+    /// Every change to a resting order, published as it is applied.
+    /// Replaced when the whole book is, which ends its subscriptions.
+    order_feed: RwLock<broadcast::Sender<OrderUpdate>>,
+    /// Numbers the updates of `order_feed`.
+    feed_sequence: AtomicU64,
+    /// Bumped on every change to orders or trades.
+    version: AtomicU64,
+    /// Creation time in ms. Versions restart with every new book, so they
+    /// only compare within one epoch.
+    epoch: u64,
 }
 
 impl Default for OrderBook {
@@ -16,6 +74,20 @@ impl Default for OrderBook {
             buy_orders: Arc::new(RwLock::new(BTreeMap::new())),
             sell_orders: Arc::new(RwLock::new(BTreeMap::new())),
             trade_events: Arc::new(RwLock::new(vec![])),
+            trader_stats: TraderStats::new(),
+            candles: CandleStore::new(),
+            order_flow: OrderFlow::default(),
+            quote_changes: QuoteChangeTracker::new(),
+            size_distribution: SizeDistribution::from_env(),
+            retention: Retention::default(),
+            applied_events: AppliedEvents::default(),
+            state_digest: RwLock::new(StateDigest::default()),
+            anomalies: RwLock::new(VecDeque::new()),
+            next_priority: AtomicU64::new(0),
+            order_feed: RwLock::new(broadcast::channel(feed_buffer()).0),
+            feed_sequence: AtomicU64::new(0),
+            version: AtomicU64::new(0),
+            epoch: Utc::now().timestamp_millis() as u64,
         }
     }
 }
@@ -25,15 +97,73 @@ impl OrderBook {
         Self::default()
     }
 
+    /// Locks both sides of the book for a batch of changes.
+    pub fn batch(&self) -> BookBatch<'_> {
+        BookBatch {
+            book: self,
+            buy_orders: self.buy_orders.write().unwrap(),
+            sell_orders: self.sell_orders.write().unwrap(),
+            state_digest: self.state_digest.write().unwrap(),
+            changed: false,
+            changes: vec![],
+        }
+    }
+
+    /// Appends the order to the back of its price level's queue.
     pub fn add_order(&self, order: SpotOrder) {
-        let mut target_tree = match order.order_type {
-            OrderType::Buy => self.buy_orders.write().unwrap(),
-            OrderType::Sell => self.sell_orders.write().unwrap(),
-        };
-        target_tree
-            .entry(order.price)
-            .or_insert(Vec::new())
-            .push(order);
+        self.batch().add_order(order);
+    }
+
+    /// Changes whenever orders or trades change, so derived views can tell
+    /// whether they are stale.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    fn bump_version(&self) {
+        self.version.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Subscribes to the order feed, along with the resting orders and the
+    /// sequence of the last update they include. Read under the book's
+    /// locks, so the first update received is the next change after them.
+    pub fn subscribe_orders(&self) -> (broadcast::Receiver<OrderUpdate>, Vec<SpotOrder>, u64) {
+        let buy_orders = self.buy_orders.read().unwrap();
+        let sell_orders = self.sell_orders.read().unwrap();
+        let updates = self.order_feed.read().unwrap().subscribe();
+        let orders = buy_orders
+            .values()
+            .chain(sell_orders.values())
+            .flatten()
+            .cloned()
+            .collect();
+        (updates, orders, self.feed_sequence.load(Ordering::SeqCst))
+    }
+
+    /// Publishes a change to `order`. Called while the change's side of the
+    /// book is locked, so updates go out in the order they were applied.
+    fn publish(&self, change: OrderChange, order: &SpotOrder) {
+        let order_feed = self.order_feed.read().unwrap();
+        if order_feed.receiver_count() == 0 {
+            return;
+        }
+        let sequence = self.feed_sequence.fetch_add(1, Ordering::SeqCst) + 1;
+        // Fails only when the last subscriber left meanwhile.
+        let _ = order_feed.send(OrderUpdate {
+            change,
+            sequence,
+            order: order.clone(),
+        });
+    }
+
+    /// Ends the feed's subscriptions, whose view of the book no longer
+    /// applies once it was swapped out.
+    fn restart_order_feed(&self) {
+        *self.order_feed.write().unwrap() = broadcast::channel(feed_buffer()).0;
     }
 
     pub fn get_orders_in_range(
@@ -61,63 +191,559 @@ impl OrderBook {
         self.sell_orders.read().unwrap()
     }
 
+    pub fn order_count(&self, order_type: OrderType) -> usize {
+        let target_tree = match order_type {
+            OrderType::Buy => self.buy_orders.read().unwrap(),
+            OrderType::Sell => self.sell_orders.read().unwrap(),
+        };
+        target_tree
+            .values()
+            .map(|order_list| order_list.len())
+            .sum()
+    }
+
+    pub fn best_bid(&self) -> Option<u128> {
+        self.buy_orders.read().unwrap().keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<u128> {
+        self.sell_orders.read().unwrap().keys().next().copied()
+    }
+
+    /// Aggregated size of the best `levels` price levels, best price first.
+    pub fn top_levels(&self, order_type: OrderType, levels: usize) -> Vec<PriceLevel> {
+        let to_level = |(&price, order_list): (&u128, &Vec<SpotOrder>)| PriceLevel {
+            price,
+            size: order_list.iter().map(|order| order.amount).sum(),
+        };
+
+        match order_type {
+            OrderType::Buy => {
+                let tree = self.buy_orders.read().unwrap();
+                tree.iter().rev().take(levels).map(to_level).collect()
+            }
+            OrderType::Sell => {
+                let tree = self.sell_orders.read().unwrap();
+                tree.iter().take(levels).map(to_level).collect()
+            }
+        }
+    }
+
+    /// Every active order reduced to its state-defining fields, sorted.
+    pub fn state_entries(&self) -> Vec<StateEntry> {
+        let mut entries = vec![];
+        for tree in [
+            self.buy_orders.read().unwrap(),
+            self.sell_orders.read().unwrap(),
+        ] {
+            for order_list in tree.values() {
+                entries.extend(order_list.iter().map(StateEntry::of));
+            }
+        }
+        sort_entries(&mut entries);
+        entries
+    }
+
+    /// Deterministic hash of the active book, `hash_entries` of
+    /// [`Self::state_entries`] kept up to date as orders change instead of
+    /// recomputed from the whole book. Timestamps are left out since they
+    /// depend on when events were processed, not on chain state.
+    pub fn state_hash(&self) -> String {
+        self.state_digest.read().unwrap().hash()
+    }
+
     pub fn get_order(&self, id: &str, order_type: OrderType) -> Option<SpotOrder> {
         let target_tree = match order_type {
             OrderType::Buy => self.buy_orders.read().unwrap(),
             OrderType::Sell => self.sell_orders.read().unwrap(),
         };
 
-        for (_price, order_list) in target_tree.iter() {
-            if let Some(order) = order_list.iter().find(|o| o.id == id) {
-                return Some(order.clone());
+        find_order(&target_tree, id)
+    }
+
+    /// Where an order stands in its price level's queue. Read from the live
+    /// level, so fills and cancels of earlier orders are reflected at once.
+    pub fn queue_position(&self, id: &str) -> Option<QueuePosition> {
+        for (order_type, tree) in [
+            (OrderType::Buy, self.buy_orders.read().unwrap()),
+            (OrderType::Sell, self.sell_orders.read().unwrap()),
+        ] {
+            for (&price, order_list) in tree.iter() {
+                if let Some(index) = order_list.iter().position(|o| o.id == id) {
+                    let ahead = &order_list[..index];
+                    return Some(QueuePosition {
+                        order_type,
+                        price,
+                        orders_ahead: ahead.len(),
+                        size_ahead: ahead.iter().map(|order| order.amount).sum(),
+                    });
+                }
             }
         }
         None
     }
 
+    /// Updates an order in place; see [`BookBatch::update_order`].
     pub fn update_order(&self, order: SpotOrder) {
-        self.remove_order(&order.id, Some(order.order_type));
-        self.add_order(order);
+        self.batch().update_order(order);
     }
 
-    pub fn remove_order(&self, id: &str, order_type: Option<OrderType>) {
-        match order_type {
-            Some(order_type) => {
-                let mut target_tree = match order_type {
-                    OrderType::Buy => self.buy_orders.write().unwrap(),
-                    OrderType::Sell => self.sell_orders.write().unwrap(),
-                };
-                self.remove_order_from_tree(&mut target_tree, id);
-            }
-            None => {
-                {
-                    let mut buy_tree = self.buy_orders.write().unwrap();
-                    self.remove_order_from_tree(&mut buy_tree, id);
-                }
-                {
-                    let mut sell_tree = self.sell_orders.write().unwrap();
-                    self.remove_order_from_tree(&mut sell_tree, id);
+    pub fn remove_order(&self, id: &str, order_type: Option<OrderType>, change: OrderChange) {
+        self.batch().remove_order(id, order_type, change);
+    }
+
+    /// Adds a trade to the tape in chain order. A trade that arrives behind
+    /// a later one rebuilds its candle so open and close follow chain order
+    /// rather than arrival order. Candles count each match once, with its
+    /// first fill.
+    pub fn record_trade(
+        &self,
+        market_id: &str,
+        price: u128,
+        size: u128,
+        time: EventTime,
+        index: EventIndex,
+        fill: TradeFill,
+    ) -> MatchFill {
+        let (in_order, recorded) = {
+            let mut trades = self.trade_events.write().unwrap();
+            let position = trades.partition_point(|trade| trade.index() <= index);
+            let (buyer, seller) = match fill.side {
+                Some(OrderType::Buy) => (fill.user, None),
+                Some(OrderType::Sell) => (None, fill.user),
+                None => (None, None),
+            };
+            let mut trade = TradeOrderEvent {
+                id: fill.order_id,
+                market_id: market_id.to_owned(),
+                trade_price: price.to_string(),
+                trade_size: size.to_string(),
+                timestamp: time.normalized,
+                raw_timestamp: time.raw,
+                block_number: index.block_number,
+                transaction_index: index.transaction_index,
+                log_index: index.log_index,
+                maker_order_id: None,
+                taker_order_id: None,
+                buyer,
+                seller,
+                aggressor_side: None,
+                busted: false,
+                resting_since: fill.resting_since,
+                counterpart_fill: false,
+            };
+            // Spark reports each side of a match as its own fill, one right
+            // after the other; each record gets the parties of both.
+            let mut recorded = MatchFill::First;
+            if let Some(previous) = position.checked_sub(1).map(|i| &mut trades[i]) {
+                if previous.is_counterpart(&trade) {
+                    recorded = MatchFill::Second {
+                        aggressor: previous.pair_with(&mut trade, fill.side),
+                    };
                 }
             }
+            trades.insert(position, trade);
+            (position == trades.len() - 1, recorded)
+        };
+
+        if recorded == MatchFill::First {
+            if in_order {
+                self.candles.record_trade(price, size, time.normalized);
+            } else {
+                self.rebuild_candles(time.normalized, time.normalized);
+            }
+        }
+        self.bump_version();
+        recorded
+    }
+
+    /// Marks the trade recorded at `index` busted, leaving it on the tape.
+    /// Returns its timestamp, for rebuilding its candle, or `None` if no
+    /// live trade is recorded there.
+    pub fn bust_trade(&self, index: EventIndex) -> Option<u64> {
+        let mut trades = self.trade_events.write().unwrap();
+        let end = trades.partition_point(|trade| trade.index() <= index);
+        let trade = trades[..end]
+            .iter_mut()
+            .rev()
+            .take_while(|trade| trade.index() == index)
+            .find(|trade| !trade.busted)?;
+        trade.busted = true;
+        let timestamp = trade.timestamp;
+        drop(trades);
+        self.bump_version();
+        Some(timestamp)
+    }
+
+    /// Rebuilds candles in `[from, to]` from the recorded trade events, for
+    /// when aggregation logic changes and past bars must be recomputed.
+    /// Busted trades and the second fills of matches are left out.
+    pub fn rebuild_candles(&self, from: u64, to: u64) -> usize {
+        let trades = self.trade_events.read().unwrap();
+        self.candles.rebuild_range(
+            from,
+            to,
+            trades
+                .iter()
+                .filter(|trade| !trade.busted && !trade.counterpart_fill)
+                .filter_map(|trade| {
+                    Some((
+                        trade.trade_price.parse().ok()?,
+                        trade.trade_size.parse().ok()?,
+                        trade.timestamp,
+                    ))
+                }),
+        )
+    }
+
+    pub fn last_trade_timestamp(&self) -> Option<u64> {
+        self.trade_events
+            .read()
+            .unwrap()
+            .last()
+            .map(|trade| trade.timestamp)
+    }
+
+    /// Removes and returns the trades older than `cutoff` (ms) from the
+    /// front of the tape.
+    pub fn take_trades_before(&self, cutoff: u64) -> Vec<TradeOrderEvent> {
+        let mut trades = self.trade_events.write().unwrap();
+        let split = trades.partition_point(|trade| trade.timestamp < cutoff);
+        trades.drain(..split).collect()
+    }
+
+    /// Drops every order and trade, e.g. before restoring a checkpoint.
+    pub fn clear(&self) {
+        self.buy_orders.write().unwrap().clear();
+        self.sell_orders.write().unwrap().clear();
+        *self.state_digest.write().unwrap() = StateDigest::default();
+        self.trade_events.write().unwrap().clear();
+        self.applied_events.clear();
+        self.restart_order_feed();
+        self.bump_version();
+    }
+
+    /// Swaps in the orders, trades and candles of `fresh`, a book filled off
+    /// to the side. Every part is locked for the swap, so readers see the old
+    /// book or the new one, never a half-filled one.
+    pub fn replace_with(&self, fresh: OrderBook) {
+        let mut buy_orders = self.buy_orders.write().unwrap();
+        let mut sell_orders = self.sell_orders.write().unwrap();
+        let mut trades = self.trade_events.write().unwrap();
+        let mut state_digest = self.state_digest.write().unwrap();
+        *buy_orders = std::mem::take(&mut *fresh.buy_orders.write().unwrap());
+        *sell_orders = std::mem::take(&mut *fresh.sell_orders.write().unwrap());
+        *trades = std::mem::take(&mut *fresh.trade_events.write().unwrap());
+        *state_digest = *fresh.state_digest.read().unwrap();
+        self.candles.replace_with(fresh.candles);
+        self.applied_events.clear();
+        self.next_priority
+            .store(fresh.next_priority.load(Ordering::SeqCst), Ordering::SeqCst);
+        self.restart_order_feed();
+        self.bump_version();
+    }
+
+    /// Puts trades taken with [`Self::take_trades_before`] back in front.
+    pub fn restore_trades(&self, restored: Vec<TradeOrderEvent>) {
+        let mut trades = self.trade_events.write().unwrap();
+        trades.splice(0..0, restored);
+    }
+
+    /// The latest trade not busted.
+    pub fn last_trade(&self) -> Option<TradeOrderEvent> {
+        let trades = self.trade_events.read().unwrap();
+        trades.iter().rev().find(|trade| !trade.busted).cloned()
+    }
+
+    /// The latest `limit` trades, newest first.
+    pub fn recent_trades(&self, limit: usize) -> Vec<TradeOrderEvent> {
+        let trades = self.trade_events.read().unwrap();
+        trades.iter().rev().take(limit).cloned().collect()
+    }
+
+    pub fn get_trade_events(&self) -> Vec<TradeOrderEvent> {
+        self.trade_events.read().unwrap().clone()
+    }
+
+    pub fn trader_stats(&self) -> &TraderStats {
+        &self.trader_stats
+    }
+
+    pub fn candles(&self) -> &CandleStore {
+        &self.candles
+    }
+
+    pub fn order_flow(&self) -> &OrderFlow {
+        &self.order_flow
+    }
+
+    pub fn quote_changes(&self) -> &QuoteChangeTracker {
+        &self.quote_changes
+    }
+
+    pub fn size_distribution(&self) -> &SizeDistribution {
+        &self.size_distribution
+    }
+
+    pub fn retention(&self) -> &Retention {
+        &self.retention
+    }
+
+    pub fn applied_events(&self) -> &AppliedEvents {
+        &self.applied_events
+    }
+
+    pub fn flag_anomaly(&self, anomaly: Anomaly) {
+        let mut anomalies = self.anomalies.write().unwrap();
+        anomalies.push_back(anomaly);
+        if anomalies.len() > MAX_FLAGGED_ANOMALIES {
+            anomalies.pop_front();
         }
     }
 
-    fn remove_order_from_tree(&self, target_tree: &mut BTreeMap<u128, Vec<SpotOrder>>, id: &str) {
-        let mut empty_keys = Vec::new();
+    pub fn get_anomalies(&self) -> Vec<Anomaly> {
+        self.anomalies.read().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Both sides of a book locked for writing, so a batch of events is applied
+/// under one lock acquisition and readers never see part of it. The version
+/// is bumped once, when the batch is dropped, if anything changed.
+///
+/// Every change made through the batch, to the book or its statistics, is
+/// kept as a [`Change`] until [`Self::take_changes`], so a reorg can undo
+/// it.
+///
+/// Reading the book through `OrderBook` while a batch is held on the same
+/// thread deadlocks; read through the batch instead.
+pub struct BookBatch<'a> {
+    book: &'a OrderBook,
+    buy_orders: RwLockWriteGuard<'a, BTreeMap<u128, Vec<SpotOrder>>>,
+    sell_orders: RwLockWriteGuard<'a, BTreeMap<u128, Vec<SpotOrder>>>,
+    state_digest: RwLockWriteGuard<'a, StateDigest>,
+    changed: bool,
+    changes: Vec<Change>,
+}
+
+impl BookBatch<'_> {
+    fn tree(&mut self, order_type: OrderType) -> &mut BTreeMap<u128, Vec<SpotOrder>> {
+        match order_type {
+            OrderType::Buy => &mut self.buy_orders,
+            OrderType::Sell => &mut self.sell_orders,
+        }
+    }
+
+    /// Appends the order to the back of its price level's queue.
+    pub fn add_order(&mut self, order: SpotOrder) {
+        self.changes.push(Change::OrderAdded {
+            id: order.id.clone(),
+            order_type: order.order_type,
+        });
+        self.insert_order(order);
+    }
+
+    fn insert_order(&mut self, mut order: SpotOrder) {
+        order.priority = self.book.next_priority.fetch_add(1, Ordering::Relaxed);
+        self.book.publish(OrderChange::Add, &order);
+        self.state_digest.add(&StateEntry::of(&order));
+        self.tree(order.order_type)
+            .entry(order.price)
+            .or_default()
+            .push(order);
+        self.changed = true;
+    }
+
+    /// Takes an order off its side of the book.
+    fn take_order(&mut self, order_type: OrderType, id: &str) -> Option<SpotOrder> {
+        let order = take_order_from_tree(self.tree(order_type), id)?;
+        self.state_digest.remove(&StateEntry::of(&order));
+        Some(order)
+    }
+
+    pub fn get_order(&self, id: &str, order_type: OrderType) -> Option<SpotOrder> {
+        match order_type {
+            OrderType::Buy => find_order(&self.buy_orders, id),
+            OrderType::Sell => find_order(&self.sell_orders, id),
+        }
+    }
 
-        for (&price, order_list) in target_tree.iter_mut() {
-            order_list.retain(|order| order.id != id);
-            if order_list.is_empty() {
-                empty_keys.push(price);
+    /// Updates an order in place so a partial fill keeps its queue
+    /// position. An order whose price changed goes to the back of the new
+    /// level.
+    pub fn update_order(&mut self, mut order: SpotOrder) {
+        let change = match self.get_order(&order.id, order.order_type) {
+            Some(previous) => Change::OrderReplaced(previous),
+            None => Change::OrderAdded {
+                id: order.id.clone(),
+                order_type: order.order_type,
+            },
+        };
+        self.changes.push(change);
+        let book = self.book;
+        let tree = match order.order_type {
+            OrderType::Buy => &mut *self.buy_orders,
+            OrderType::Sell => &mut *self.sell_orders,
+        };
+        if let Some(existing) = tree
+            .get_mut(&order.price)
+            .and_then(|order_list| order_list.iter_mut().find(|o| o.id == order.id))
+        {
+            order.priority = existing.priority;
+            book.publish(OrderChange::Modify, &order);
+            self.state_digest.remove(&StateEntry::of(existing));
+            self.state_digest.add(&StateEntry::of(&order));
+            *existing = order;
+            self.changed = true;
+            return;
+        }
+        self.take_order(order.order_type, &order.id);
+        self.insert_order(order);
+    }
+
+    /// Removes the order from its side, or from both when the side is
+    /// unknown; `change` tells the order feed why, `Cancel` or `Fill`.
+    pub fn remove_order(&mut self, id: &str, order_type: Option<OrderType>, change: OrderChange) {
+        for side in [OrderType::Buy, OrderType::Sell] {
+            if order_type.is_some_and(|order_type| order_type != side) {
+                continue;
+            }
+            if let Some(removed) = self.take_order(side, id) {
+                self.book.publish(change, &removed);
+                self.changes.push(Change::OrderReplaced(removed));
             }
         }
+        self.changed = true;
+    }
 
-        for key in empty_keys {
-            target_tree.remove(&key);
+    /// Takes an order off the book without keeping the change, for undoing
+    /// one.
+    pub fn discard_order(&mut self, id: &str, order_type: OrderType) {
+        if let Some(discarded) = self.take_order(order_type, id) {
+            self.book.publish(OrderChange::Revert, &discarded);
         }
+        self.changed = true;
     }
 
-    pub fn get_trade_events(&self) -> Vec<TradeOrderEvent> {
-        self.trade_events.read().unwrap().clone()
+    /// Puts an order back where its priority places it in its level's
+    /// queue, without keeping the change, for undoing one.
+    pub fn restore_order(&mut self, order: SpotOrder) {
+        self.book.publish(OrderChange::Add, &order);
+        self.state_digest.add(&StateEntry::of(&order));
+        let order_list = self.tree(order.order_type).entry(order.price).or_default();
+        let position = order_list.partition_point(|o| o.priority < order.priority);
+        order_list.insert(position, order);
+        self.changed = true;
+    }
+
+    /// Records a fill; see [`OrderBook::record_trade`].
+    pub fn record_trade(
+        &mut self,
+        market_id: &str,
+        price: u128,
+        size: u128,
+        time: EventTime,
+        index: EventIndex,
+        fill: TradeFill,
+    ) -> MatchFill {
+        self.changes.push(Change::TradeRecorded(index));
+        self.book
+            .record_trade(market_id, price, size, time, index, fill)
+    }
+
+    pub fn record_activity(&mut self, user: &str, timestamp: u64) {
+        if let Some(activity) = self.book.trader_stats.record_activity(user, timestamp) {
+            self.changes.push(Change::Activity(activity));
+        }
+    }
+
+    pub fn record_size(&mut self, kind: SizeKind, size: u128, timestamp: u64) {
+        self.book.size_distribution.record(kind, size, timestamp);
+        self.changes.push(Change::Size {
+            kind,
+            size,
+            timestamp,
+        });
+    }
+
+    pub fn record_order_flow(&mut self, order_type: OrderType, size: u128, timestamp: u64) {
+        self.book
+            .order_flow
+            .record_order(order_type, size, timestamp);
+        self.changes.push(Change::NewOrderFlow {
+            order_type,
+            size,
+            timestamp,
+        });
+    }
+
+    pub fn record_trade_flow(&mut self, initiator: OrderType, size: u128, timestamp: u64) {
+        self.book
+            .order_flow
+            .record_trade(initiator, size, timestamp);
+        self.changes.push(Change::TradeFlow {
+            initiator,
+            size,
+            timestamp,
+        });
+    }
+
+    /// Counts a move of the best bid or ask at `timestamp`, if the batch
+    /// moved either.
+    pub fn observe_quote(&mut self, timestamp: u64) {
+        let (best_bid, best_ask) = (self.best_bid(), self.best_ask());
+        if let Some(quote_move) = self
+            .book
+            .quote_changes
+            .observe(best_bid, best_ask, timestamp)
+        {
+            self.changes.push(Change::Quote(quote_move));
+        }
+    }
+
+    /// Keeps a change made outside the book, to market-wide statistics.
+    pub fn record(&mut self, change: Change) {
+        self.changes.push(change);
+    }
+
+    /// The changes made so far, oldest first.
+    pub fn take_changes(&mut self) -> Vec<Change> {
+        std::mem::take(&mut self.changes)
+    }
+
+    pub fn best_bid(&self) -> Option<u128> {
+        self.buy_orders.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<u128> {
+        self.sell_orders.keys().next().copied()
+    }
+}
+
+impl Drop for BookBatch<'_> {
+    fn drop(&mut self) {
+        if self.changed {
+            self.book.bump_version();
+        }
+    }
+}
+
+fn find_order(tree: &BTreeMap<u128, Vec<SpotOrder>>, id: &str) -> Option<SpotOrder> {
+    tree.values()
+        .find_map(|order_list| order_list.iter().find(|o| o.id == id))
+        .cloned()
+}
+
+fn take_order_from_tree(tree: &mut BTreeMap<u128, Vec<SpotOrder>>, id: &str) -> Option<SpotOrder> {
+    let (price, position) = tree.iter().find_map(|(&price, order_list)| {
+        order_list
+            .iter()
+            .position(|o| o.id == id)
+            .map(|position| (price, position))
+    })?;
+    let order_list = tree.get_mut(&price)?;
+    let order = order_list.remove(position);
+    if order_list.is_empty() {
+        tree.remove(&price);
     }
+    Some(order)
 }
@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::indexer::spot_order::OrderType;
+use crate::storage::candles::CANDLE_INTERVAL_MS;
+
+/// Flow for one bucket. Volumes and sizes are in raw base-asset units.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FlowBucket {
+    pub period_start: u64,
+    /// Traded size initiated by buyers and by sellers.
+    pub buy_volume: u128,
+    pub sell_volume: u128,
+    /// Size and number of newly opened orders per side.
+    pub new_buy_size: u128,
+    pub new_sell_size: u128,
+    pub new_buy_orders: u64,
+    pub new_sell_orders: u64,
+}
+
+impl FlowBucket {
+    /// Buy-initiated minus sell-initiated volume.
+    pub fn net_volume(&self) -> i128 {
+        self.buy_volume as i128 - self.sell_volume as i128
+    }
+
+    fn add(&mut self, other: &FlowBucket) {
+        self.buy_volume += other.buy_volume;
+        self.sell_volume += other.sell_volume;
+        self.new_buy_size += other.new_buy_size;
+        self.new_sell_size += other.new_sell_size;
+        self.new_buy_orders += other.new_buy_orders;
+        self.new_sell_orders += other.new_sell_orders;
+    }
+}
+
+/// Order flow per minute: traded volume by the side that initiated it and
+/// new orders per side, summed into any coarser interval on read.
+#[derive(Default)]
+pub struct OrderFlow {
+    minutes: RwLock<BTreeMap<u64, FlowBucket>>,
+}
+
+impl OrderFlow {
+    pub fn record_order(&self, order_type: OrderType, size: u128, timestamp: u64) {
+        self.update(timestamp, |bucket| match order_type {
+            OrderType::Buy => {
+                bucket.new_buy_size += size;
+                bucket.new_buy_orders += 1;
+            }
+            OrderType::Sell => {
+                bucket.new_sell_size += size;
+                bucket.new_sell_orders += 1;
+            }
+        });
+    }
+
+    pub fn record_trade(&self, initiator: OrderType, size: u128, timestamp: u64) {
+        self.update(timestamp, |bucket| match initiator {
+            OrderType::Buy => bucket.buy_volume += size,
+            OrderType::Sell => bucket.sell_volume += size,
+        });
+    }
+
+    /// Takes back an order recorded with [`Self::record_order`].
+    pub fn undo_order(&self, order_type: OrderType, size: u128, timestamp: u64) {
+        self.update(timestamp, |bucket| match order_type {
+            OrderType::Buy => {
+                bucket.new_buy_size = bucket.new_buy_size.saturating_sub(size);
+                bucket.new_buy_orders = bucket.new_buy_orders.saturating_sub(1);
+            }
+            OrderType::Sell => {
+                bucket.new_sell_size = bucket.new_sell_size.saturating_sub(size);
+                bucket.new_sell_orders = bucket.new_sell_orders.saturating_sub(1);
+            }
+        });
+    }
+
+    /// Takes back a trade recorded with [`Self::record_trade`].
+    pub fn undo_trade(&self, initiator: OrderType, size: u128, timestamp: u64) {
+        self.update(timestamp, |bucket| match initiator {
+            OrderType::Buy => bucket.buy_volume = bucket.buy_volume.saturating_sub(size),
+            OrderType::Sell => bucket.sell_volume = bucket.sell_volume.saturating_sub(size),
+        });
+    }
+
+    /// Buckets of `interval_ms` (whole minutes, at least one) overlapping
+    /// `[from, to]`, oldest first. Intervals without flow are left out.
+    pub fn series(&self, from: u64, to: u64, interval_ms: u64) -> Vec<FlowBucket> {
+        if from > to {
+            return vec![];
+        }
+        let interval_ms = (interval_ms - interval_ms % CANDLE_INTERVAL_MS).max(CANDLE_INTERVAL_MS);
+        let mut buckets: BTreeMap<u64, FlowBucket> = BTreeMap::new();
+        for (&minute, flow) in self
+            .minutes
+            .read()
+            .unwrap()
+            .range(from - from % CANDLE_INTERVAL_MS..=to)
+        {
+            let period_start = minute - minute % interval_ms;
+            buckets
+                .entry(period_start)
+                .or_insert_with(|| FlowBucket {
+                    period_start,
+                    ..Default::default()
+                })
+                .add(flow);
+        }
+        buckets.into_values().collect()
+    }
+
+    fn update(&self, timestamp: u64, apply: impl FnOnce(&mut FlowBucket)) {
+        let minute = timestamp - timestamp % CANDLE_INTERVAL_MS;
+        let mut minutes = self.minutes.write().unwrap();
+        apply(minutes.entry(minute).or_insert_with(|| FlowBucket {
+            period_start: minute,
+            ..Default::default()
+        }));
+    }
+}
@@ -0,0 +1,313 @@
+use log::{error, info};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::types::Json;
+use sqlx::{Postgres, QueryBuilder, Row};
+use std::sync::OnceLock;
+use tokio::sync::{mpsc, OnceCell};
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::checkpoint::Checkpoint;
+use crate::web::graphql::TradeOrderEvent;
+
+const DEFAULT_MAX_CONNECTIONS: u32 = 5;
+/// Rows per `INSERT`, well below Postgres' limit of 65535 bound values.
+const INSERT_CHUNK_ROWS: usize = 1000;
+
+const SCHEMA: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS checkpoints (
+        market_id TEXT PRIMARY KEY,
+        block_number BIGINT NOT NULL,
+        hot_block BIGINT,
+        hot_transaction BIGINT,
+        hot_log BIGINT,
+        state JSONB NOT NULL,
+        saved_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    )",
+    "CREATE TABLE IF NOT EXISTS orders (
+        market_id TEXT NOT NULL,
+        id TEXT NOT NULL,
+        priority BIGINT NOT NULL,
+        block_number BIGINT NOT NULL,
+        data JSONB NOT NULL,
+        PRIMARY KEY (market_id, id)
+    )",
+    "CREATE TABLE IF NOT EXISTS trades (
+        market_id TEXT NOT NULL,
+        id TEXT NOT NULL,
+        block_number BIGINT NOT NULL,
+        transaction_index BIGINT NOT NULL,
+        log_index BIGINT NOT NULL,
+        timestamp BIGINT NOT NULL,
+        data JSONB NOT NULL,
+        PRIMARY KEY (market_id, block_number, transaction_index, log_index)
+    )",
+];
+
+static POOL: OnceLock<PgPool> = OnceLock::new();
+static SCHEMA_READY: OnceCell<()> = OnceCell::const_new();
+
+/// The database in `DATABASE_URL`, shared by every market in the process
+/// with at most `DATABASE_MAX_CONNECTIONS` (default 5) connections. The
+/// pool connects on first use.
+pub fn database() -> Result<Option<PgPool>, Error> {
+    let Some(url) = ev_opt("DATABASE_URL") else {
+        return Ok(None);
+    };
+    if let Some(pool) = POOL.get() {
+        return Ok(Some(pool.clone()));
+    }
+    let max_connections = match ev_opt("DATABASE_MAX_CONNECTIONS") {
+        Some(max) => max.parse()?,
+        None => DEFAULT_MAX_CONNECTIONS,
+    };
+    let pool = PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect_lazy(&url)?;
+    Ok(Some(POOL.get_or_init(|| pool).clone()))
+}
+
+async fn ensure_schema(pool: &PgPool) -> Result<(), Error> {
+    SCHEMA_READY
+        .get_or_try_init(|| async {
+            for statement in SCHEMA {
+                sqlx::query(statement).execute(pool).await?;
+            }
+            info!("Postgres schema ready");
+            Ok::<(), Error>(())
+        })
+        .await?;
+    Ok(())
+}
+
+/// One market's checkpoints in Postgres: its open orders, every trade it
+/// indexed, including those since moved to cold storage, and the rest of
+/// the book as JSON.
+///
+/// Saves are queued to a writer task and written in one transaction each,
+/// so the table always holds a whole checkpoint. When saves arrive faster
+/// than they are written, only the newest queued one is.
+pub struct PostgresCheckpoints {
+    market_id: String,
+    pool: PgPool,
+    saves: mpsc::UnboundedSender<Checkpoint>,
+}
+
+impl PostgresCheckpoints {
+    pub fn open(market_id: &str, pool: PgPool) -> Self {
+        let (saves, mut queued) = mpsc::unbounded_channel::<Checkpoint>();
+        let writer_pool = pool.clone();
+        let writer_market = market_id.to_owned();
+        tokio::spawn(async move {
+            while let Some(mut checkpoint) = queued.recv().await {
+                while let Ok(newer) = queued.try_recv() {
+                    checkpoint = newer;
+                }
+                match write(&writer_pool, &writer_market, &checkpoint).await {
+                    Ok(()) => info!(
+                        "Saved checkpoint of {} at block {} to Postgres",
+                        writer_market, checkpoint.block_number
+                    ),
+                    Err(e) => error!(
+                        "Failed to save checkpoint of {} to Postgres: {}",
+                        writer_market, e
+                    ),
+                }
+            }
+        });
+        PostgresCheckpoints {
+            market_id: market_id.to_owned(),
+            pool,
+            saves,
+        }
+    }
+
+    pub fn save(&self, checkpoint: Checkpoint) {
+        // The writer only stops once this store is dropped.
+        let _ = self.saves.send(checkpoint);
+    }
+
+    /// Block of the stored checkpoint, without reading the rest of it.
+    pub async fn saved_block(&self) -> Result<Option<i64>, Error> {
+        ensure_schema(&self.pool).await?;
+        Ok(
+            sqlx::query_scalar("SELECT block_number FROM checkpoints WHERE market_id = $1")
+                .bind(&self.market_id)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    /// The stored checkpoint as JSON text, for the caller to migrate.
+    pub async fn load(&self) -> Result<Option<String>, Error> {
+        ensure_schema(&self.pool).await?;
+        let Some(row) = sqlx::query(
+            "SELECT state::text AS state, hot_block, hot_transaction, hot_log
+             FROM checkpoints WHERE market_id = $1",
+        )
+        .bind(&self.market_id)
+        .fetch_optional(&self.pool)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let state: String = row.try_get("state")?;
+
+        let orders: Vec<String> = sqlx::query_scalar(
+            "SELECT data::text FROM orders WHERE market_id = $1 ORDER BY priority",
+        )
+        .bind(&self.market_id)
+        .fetch_all(&self.pool)
+        .await?;
+        let trades: Vec<String> = match row.try_get::<Option<i64>, _>("hot_block")? {
+            Some(hot_block) => {
+                sqlx::query_scalar(
+                    "SELECT data::text FROM trades WHERE market_id = $1
+                     AND (block_number, transaction_index, log_index) >= ($2, $3, $4)
+                     ORDER BY block_number, transaction_index, log_index",
+                )
+                .bind(&self.market_id)
+                .bind(hot_block)
+                .bind(row.try_get::<Option<i64>, _>("hot_transaction")?)
+                .bind(row.try_get::<Option<i64>, _>("hot_log")?)
+                .fetch_all(&self.pool)
+                .await?
+            }
+            None => vec![],
+        };
+        // Spliced as text: a `Value` would round amounts past `u64::MAX`.
+        let state = state.trim_end().strip_suffix('}').unwrap_or("{").trim_end();
+        let separator = if state.ends_with('{') { "" } else { "," };
+        Ok(Some(format!(
+            r#"{}{}"orders":[{}],"trades":[{}]}}"#,
+            state,
+            separator,
+            orders.join(","),
+            trades.join(",")
+        )))
+    }
+
+    /// Deletes the checkpoint and open orders. Trades are history and stay.
+    pub async fn clear(&self) -> Result<(), Error> {
+        ensure_schema(&self.pool).await?;
+        let mut tx = self.pool.begin().await?;
+        for table in ["checkpoints", "orders"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE market_id = $1", table))
+                .bind(&self.market_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Replaces the market's orders and the trades from the checkpoint's
+/// oldest in-memory trade on, so trades a reorg rolled back don't linger,
+/// and then the checkpoint row.
+async fn write(pool: &PgPool, market_id: &str, checkpoint: &Checkpoint) -> Result<(), Error> {
+    ensure_schema(pool).await?;
+    // Orders and trades are stored in their own tables.
+    let mut state = serde_json::to_value(Checkpoint {
+        version: checkpoint.version,
+        block_number: checkpoint.block_number,
+        orders: vec![],
+        trades: vec![],
+    })?;
+    if let Some(state) = state.as_object_mut() {
+        state.remove("orders");
+        state.remove("trades");
+    }
+    let hot = checkpoint.trades.first().map(|trade| {
+        (
+            trade.block_number,
+            trade.transaction_index as i64,
+            trade.log_index as i64,
+        )
+    });
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM orders WHERE market_id = $1")
+        .bind(market_id)
+        .execute(&mut *tx)
+        .await?;
+    for orders in checkpoint.orders.chunks(INSERT_CHUNK_ROWS) {
+        let mut insert = QueryBuilder::<Postgres>::new(
+            "INSERT INTO orders (market_id, id, priority, block_number, data) ",
+        );
+        insert.push_values(orders, |mut row, order| {
+            row.push_bind(market_id)
+                .push_bind(&order.id)
+                .push_bind(order.priority as i64)
+                .push_bind(order.block_number)
+                .push_bind(Json(order));
+        });
+        insert.build().execute(&mut *tx).await?;
+    }
+
+    if let Some((block, transaction, log)) = hot {
+        sqlx::query(
+            "DELETE FROM trades WHERE market_id = $1
+             AND (block_number, transaction_index, log_index) >= ($2, $3, $4)",
+        )
+        .bind(market_id)
+        .bind(block)
+        .bind(transaction)
+        .bind(log)
+        .execute(&mut *tx)
+        .await?;
+    }
+    // A reorg leaves the trade it busted on the tape, and the canonical
+    // branch may record another one at the same position; the row keeps the
+    // later one, and one `INSERT` must not touch a row twice.
+    let trades: Vec<&TradeOrderEvent> = checkpoint
+        .trades
+        .iter()
+        .zip(checkpoint.trades.iter().skip(1).map(Some).chain([None]))
+        .filter(|(trade, next)| next.is_none_or(|next| next.index() != trade.index()))
+        .map(|(trade, _)| trade)
+        .collect();
+    for trades in trades.chunks(INSERT_CHUNK_ROWS) {
+        let mut insert = QueryBuilder::<Postgres>::new(
+            "INSERT INTO trades \
+             (market_id, id, block_number, transaction_index, log_index, timestamp, data) ",
+        );
+        insert.push_values(trades, |mut row, trade| {
+            row.push_bind(market_id)
+                .push_bind(&trade.id)
+                .push_bind(trade.block_number)
+                .push_bind(trade.transaction_index as i64)
+                .push_bind(trade.log_index as i64)
+                .push_bind(trade.timestamp as i64)
+                .push_bind(Json(trade));
+        });
+        insert.push(
+            " ON CONFLICT (market_id, block_number, transaction_index, log_index) \
+             DO UPDATE SET id = EXCLUDED.id, timestamp = EXCLUDED.timestamp, data = EXCLUDED.data",
+        );
+        insert.build().execute(&mut *tx).await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO checkpoints
+            (market_id, block_number, hot_block, hot_transaction, hot_log, state, saved_at)
+         VALUES ($1, $2, $3, $4, $5, $6, now())
+         ON CONFLICT (market_id) DO UPDATE SET
+            block_number = EXCLUDED.block_number,
+            hot_block = EXCLUDED.hot_block,
+            hot_transaction = EXCLUDED.hot_transaction,
+            hot_log = EXCLUDED.hot_log,
+            state = EXCLUDED.state,
+            saved_at = EXCLUDED.saved_at",
+    )
+    .bind(market_id)
+    .bind(checkpoint.block_number)
+    .bind(hot.map(|(block, _, _)| block))
+    .bind(hot.map(|(_, transaction, _)| transaction))
+    .bind(hot.map(|(_, _, log)| log))
+    .bind(Json(state))
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+const MINUTE_MS: u64 = 60_000;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QuoteChangeBucket {
+    pub minute: u64,
+    pub bid_changes: u64,
+    pub ask_changes: u64,
+}
+
+/// A change of the best bid or ask, and the quote before it.
+#[derive(Debug, Clone, Copy)]
+pub struct QuoteMove {
+    previous: (Option<u128>, Option<u128>),
+    minute: u64,
+    bid_changed: bool,
+    ask_changed: bool,
+}
+
+/// Counts how often the best bid and best ask move, per minute.
+#[derive(Default)]
+pub struct QuoteChangeTracker {
+    last_quote: RwLock<(Option<u128>, Option<u128>)>,
+    per_minute: RwLock<BTreeMap<u64, QuoteChangeBucket>>,
+}
+
+impl QuoteChangeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the move, if the quote moved, for [`Self::undo`].
+    pub fn observe(
+        &self,
+        best_bid: Option<u128>,
+        best_ask: Option<u128>,
+        timestamp: u64,
+    ) -> Option<QuoteMove> {
+        let (previous, bid_changed, ask_changed) = {
+            let mut last_quote = self.last_quote.write().unwrap();
+            let previous = *last_quote;
+            *last_quote = (best_bid, best_ask);
+            (previous, previous.0 != best_bid, previous.1 != best_ask)
+        };
+        if !bid_changed && !ask_changed {
+            return None;
+        }
+
+        let minute = timestamp - timestamp % MINUTE_MS;
+        let mut per_minute = self.per_minute.write().unwrap();
+        let bucket = per_minute.entry(minute).or_insert(QuoteChangeBucket {
+            minute,
+            ..Default::default()
+        });
+        if bid_changed {
+            bucket.bid_changes += 1;
+        }
+        if ask_changed {
+            bucket.ask_changes += 1;
+        }
+        Some(QuoteMove {
+            previous,
+            minute,
+            bid_changed,
+            ask_changed,
+        })
+    }
+
+    /// Takes back a move returned by [`Self::observe`].
+    pub fn undo(&self, quote_move: &QuoteMove) {
+        *self.last_quote.write().unwrap() = quote_move.previous;
+        if let Some(bucket) = self.per_minute.write().unwrap().get_mut(&quote_move.minute) {
+            if quote_move.bid_changed {
+                bucket.bid_changes = bucket.bid_changes.saturating_sub(1);
+            }
+            if quote_move.ask_changed {
+                bucket.ask_changes = bucket.ask_changes.saturating_sub(1);
+            }
+        }
+    }
+
+    pub fn series(&self, from: u64, to: u64) -> Vec<QuoteChangeBucket> {
+        if from > to {
+            return vec![];
+        }
+        self.per_minute
+            .read()
+            .unwrap()
+            .range(from..=to)
+            .map(|(_, bucket)| *bucket)
+            .collect()
+    }
+}
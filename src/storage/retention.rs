@@ -0,0 +1,130 @@
+use log::{error, info};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::time;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::market_registry::MarketState;
+use crate::storage::trader_stats::StatsPeriod;
+use crate::web::graphql::TradeOrderEvent;
+
+const DEFAULT_RETENTION_REFRESH_SECS: u64 = 3600;
+const WEEK_MS: u64 = 7 * 86_400_000;
+
+/// Traders whose first trade fell in the week starting `week_start`.
+/// `active[k]` counts those who traded again `k` weeks later, so
+/// `active[0]` is the cohort size.
+#[derive(Debug, Clone)]
+pub struct RetentionCohort {
+    pub week_start: u64,
+    pub active: Vec<u64>,
+}
+
+/// Weekly trader retention: cohorts by first-trade week and their activity
+/// in each following week.
+///
+/// The matrix is rebuilt from the market's stored trades, archived and
+/// hot, by a background job every `RETENTION_REFRESH_SECS` (default 3600)
+/// and served from the last build. A match makes both its buyer and its
+/// seller active; busted trades count for no one.
+#[derive(Default)]
+pub struct Retention {
+    cohorts: RwLock<Vec<RetentionCohort>>,
+}
+
+impl Retention {
+    /// Cohorts from the last rebuild, oldest first.
+    pub fn cohorts(&self) -> Vec<RetentionCohort> {
+        self.cohorts.read().unwrap().clone()
+    }
+
+    pub fn replace(&self, cohorts: Vec<RetentionCohort>) {
+        *self.cohorts.write().unwrap() = cohorts;
+    }
+
+    /// The cohorts of every stored trade of `market`. Reads them all, so
+    /// call it off the runtime.
+    pub fn build(market: &MarketState) -> Result<Vec<RetentionCohort>, Error> {
+        let hot = market.order_book.get_trade_events();
+        // Archived trades still on the hot tape are read from it.
+        let hot_start = hot.first().map(TradeOrderEvent::index);
+        let mut trade_weeks: HashMap<String, BTreeSet<u64>> = HashMap::new();
+        let mut record = |trade: TradeOrderEvent| {
+            if trade.busted || trade.counterpart_fill {
+                return;
+            }
+            let week_start = StatsPeriod::Week.bucket_start(trade.timestamp);
+            for user in [trade.buyer, trade.seller].into_iter().flatten() {
+                trade_weeks.entry(user).or_default().insert(week_start);
+            }
+        };
+        if let Some(cold_store) = &market.cold_store {
+            cold_store.scan(|trade| {
+                if hot_start.is_none_or(|hot_start| trade.index() < hot_start) {
+                    record(trade);
+                }
+            })?;
+        }
+        hot.into_iter().for_each(&mut record);
+
+        let mut cohorts: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+        for weeks in trade_weeks.values() {
+            let Some(&first) = weeks.first() else {
+                continue;
+            };
+            let active = cohorts.entry(first).or_default();
+            for week in weeks {
+                let offset = ((week - first) / WEEK_MS) as usize;
+                if active.len() <= offset {
+                    active.resize(offset + 1, 0);
+                }
+                active[offset] += 1;
+            }
+        }
+        Ok(cohorts
+            .into_iter()
+            .map(|(week_start, active)| RetentionCohort { week_start, active })
+            .collect())
+    }
+}
+
+/// Rebuilds the market's retention matrix on `RETENTION_REFRESH_SECS`.
+pub fn initialize_retention(
+    tasks: &mut Vec<tokio::task::JoinHandle<()>>,
+    market: MarketState,
+) -> Result<(), Error> {
+    let refresh = Duration::from_secs(match ev_opt("RETENTION_REFRESH_SECS") {
+        Some(secs) => secs.parse()?,
+        None => DEFAULT_RETENTION_REFRESH_SECS,
+    });
+
+    tasks.push(tokio::spawn(async move {
+        let mut ticker = time::interval(refresh);
+        loop {
+            ticker.tick().await;
+            let rebuilding = market.clone();
+            let rebuilt = tokio::task::spawn_blocking(move || Retention::build(&rebuilding))
+                .await
+                .map_err(Error::from)
+                .and_then(|rebuilt| rebuilt);
+            match rebuilt {
+                Ok(cohorts) => {
+                    info!(
+                        "Rebuilt trader retention of {}: {} cohorts",
+                        market.market_id(),
+                        cohorts.len()
+                    );
+                    market.order_book.set_retention_cohorts(cohorts);
+                }
+                Err(e) => error!(
+                    "Failed to rebuild trader retention of {}: {}",
+                    market.market_id(),
+                    e
+                ),
+            }
+        }
+    }));
+    Ok(())
+}
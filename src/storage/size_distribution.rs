@@ -0,0 +1,150 @@
+use log::warn;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::config::env::ev_opt;
+
+const HOUR_MS: u64 = 3_600_000;
+/// Upper bounds of the default buckets, in raw size units.
+const DEFAULT_BOUNDS: [u128; 8] = [
+    1_000,
+    10_000,
+    100_000,
+    1_000_000,
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+    10_000_000_000,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeKind {
+    Order,
+    Trade,
+}
+
+impl SizeKind {
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "Order" | "order" => Some(SizeKind::Order),
+            "Trade" | "trade" => Some(SizeKind::Trade),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SizeBucket {
+    /// Inclusive.
+    pub lower: u128,
+    /// Exclusive; `None` for the last, open-ended bucket.
+    pub upper: Option<u128>,
+    pub count: u64,
+}
+
+#[derive(Default)]
+struct Histograms {
+    orders: BTreeMap<u64, Vec<u64>>,
+    trades: BTreeMap<u64, Vec<u64>>,
+}
+
+/// Histograms of new order sizes and trade sizes, counted per hour so any
+/// period can be summed without rescanning events.
+///
+/// Buckets are set by `SIZE_HISTOGRAM_BOUNDS`, ascending comma-separated
+/// upper bounds; sizes at or above the last bound fall in a final
+/// open-ended bucket. The default is powers of ten from 1e3 to 1e10.
+pub struct SizeDistribution {
+    bounds: Vec<u128>,
+    histograms: RwLock<Histograms>,
+}
+
+impl Default for SizeDistribution {
+    fn default() -> Self {
+        Self::new(DEFAULT_BOUNDS.to_vec())
+    }
+}
+
+impl SizeDistribution {
+    pub fn new(bounds: Vec<u128>) -> Self {
+        SizeDistribution {
+            bounds,
+            histograms: RwLock::new(Histograms::default()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let Some(value) = ev_opt("SIZE_HISTOGRAM_BOUNDS") else {
+            return Self::default();
+        };
+        let bounds: Option<Vec<u128>> = value
+            .split(',')
+            .map(|bound| bound.trim().parse().ok())
+            .collect();
+        match bounds {
+            Some(bounds) if !bounds.is_empty() && bounds.windows(2).all(|w| w[0] < w[1]) => {
+                Self::new(bounds)
+            }
+            _ => {
+                warn!(
+                    "Invalid SIZE_HISTOGRAM_BOUNDS '{}', using the default buckets",
+                    value
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn record(&self, kind: SizeKind, size: u128, timestamp: u64) {
+        self.update(kind, size, timestamp, |count| *count += 1);
+    }
+
+    /// Takes back a size recorded with [`Self::record`].
+    pub fn undo(&self, kind: SizeKind, size: u128, timestamp: u64) {
+        self.update(kind, size, timestamp, |count| {
+            *count = count.saturating_sub(1)
+        });
+    }
+
+    fn update(&self, kind: SizeKind, size: u128, timestamp: u64, apply: impl FnOnce(&mut u64)) {
+        let hour = timestamp - timestamp % HOUR_MS;
+        let bucket = self.bounds.partition_point(|&bound| bound <= size);
+        let mut histograms = self.histograms.write().unwrap();
+        let per_hour = match kind {
+            SizeKind::Order => &mut histograms.orders,
+            SizeKind::Trade => &mut histograms.trades,
+        };
+        apply(
+            &mut per_hour
+                .entry(hour)
+                .or_insert_with(|| vec![0; self.bounds.len() + 1])[bucket],
+        );
+    }
+
+    /// Counts per bucket for the hours overlapping `[from, to]` (ms).
+    pub fn histogram(&self, kind: SizeKind, from: u64, to: u64) -> Vec<SizeBucket> {
+        let mut counts = vec![0; self.bounds.len() + 1];
+        if from <= to {
+            let histograms = self.histograms.read().unwrap();
+            let per_hour = match kind {
+                SizeKind::Order => &histograms.orders,
+                SizeKind::Trade => &histograms.trades,
+            };
+            for (_, hour_counts) in per_hour.range(from - from % HOUR_MS..=to) {
+                for (total, count) in counts.iter_mut().zip(hour_counts) {
+                    *total += count;
+                }
+            }
+        }
+
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(i, count)| SizeBucket {
+                lower: if i == 0 { 0 } else { self.bounds[i - 1] },
+                upper: self.bounds.get(i).copied(),
+                count,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,130 @@
+use fuel_crypto::Hasher;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::indexer::spot_order::{OrderType, SpotOrder};
+
+/// The parts of an active order that define book state: what two
+/// deployments must agree on after processing the same blocks.
+#[derive(Debug, Clone, PartialEq, Eq, JsonSchema, Serialize, Deserialize)]
+pub struct StateEntry {
+    pub id: String,
+    pub order_type: OrderType,
+    pub price: u128,
+    pub amount: u128,
+}
+
+impl StateEntry {
+    pub fn of(order: &SpotOrder) -> Self {
+        StateEntry {
+            id: order.id.clone(),
+            order_type: order.order_type,
+            price: order.price,
+            amount: order.amount,
+        }
+    }
+
+    fn side_byte(&self) -> u8 {
+        match self.order_type {
+            OrderType::Buy => 0,
+            OrderType::Sell => 1,
+        }
+    }
+
+    /// The entry's own hash, as its high and low halves.
+    fn hash(&self) -> (u128, u128) {
+        let mut bytes = vec![self.side_byte()];
+        bytes.extend_from_slice(&self.price.to_be_bytes());
+        bytes.extend_from_slice(&(self.id.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(self.id.as_bytes());
+        bytes.extend_from_slice(&self.amount.to_be_bytes());
+        let hash = Hasher::hash(&bytes);
+        let hash: &[u8] = hash.as_ref();
+        let (high, low) = hash.split_at(16);
+        (
+            u128::from_be_bytes(high.try_into().unwrap()),
+            u128::from_be_bytes(low.try_into().unwrap()),
+        )
+    }
+}
+
+pub fn sort_entries(entries: &mut [StateEntry]) {
+    entries.sort_by(|a, b| {
+        (a.side_byte(), a.price, &a.id, a.amount).cmp(&(b.side_byte(), b.price, &b.id, b.amount))
+    });
+}
+
+/// Hash of a set of entries, in any order.
+pub fn hash_entries(entries: &[StateEntry]) -> String {
+    let mut digest = StateDigest::default();
+    for entry in entries {
+        digest.add(entry);
+    }
+    digest.hash()
+}
+
+/// [`hash_entries`] of a set of entries kept up to date as entries come and
+/// go: the sum of the entries' own hashes, modulo 2^256, so adding or
+/// removing one costs the same however large the set is.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StateDigest {
+    high: u128,
+    low: u128,
+}
+
+impl StateDigest {
+    pub fn add(&mut self, entry: &StateEntry) {
+        let (high, low) = entry.hash();
+        let (sum, carry) = self.low.overflowing_add(low);
+        self.low = sum;
+        self.high = self.high.wrapping_add(high).wrapping_add(carry as u128);
+    }
+
+    pub fn remove(&mut self, entry: &StateEntry) {
+        let (high, low) = entry.hash();
+        let (difference, borrow) = self.low.overflowing_sub(low);
+        self.low = difference;
+        self.high = self.high.wrapping_sub(high).wrapping_sub(borrow as u128);
+    }
+
+    pub fn hash(&self) -> String {
+        format!("{:032x}{:032x}", self.high, self.low)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StateDiff {
+    pub missing_on_peer: Vec<StateEntry>,
+    pub missing_locally: Vec<StateEntry>,
+    /// `(local, peer)` pairs for orders both sides have but disagree on.
+    pub different: Vec<(StateEntry, StateEntry)>,
+}
+
+pub fn diff_entries(local: &[StateEntry], peer: &[StateEntry]) -> StateDiff {
+    let peer_by_id: HashMap<&str, &StateEntry> = peer
+        .iter()
+        .map(|entry| (entry.id.as_str(), entry))
+        .collect();
+    let local_by_id: HashMap<&str, &StateEntry> = local
+        .iter()
+        .map(|entry| (entry.id.as_str(), entry))
+        .collect();
+
+    let mut diff = StateDiff::default();
+    for entry in local {
+        match peer_by_id.get(entry.id.as_str()) {
+            None => diff.missing_on_peer.push(entry.clone()),
+            Some(&peer_entry) if peer_entry != entry => {
+                diff.different.push((entry.clone(), peer_entry.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    diff.missing_locally = peer
+        .iter()
+        .filter(|entry| !local_by_id.contains_key(entry.id.as_str()))
+        .cloned()
+        .collect();
+    diff
+}
@@ -0,0 +1,210 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::json_lines::read_json_lines;
+use crate::storage::usage::UsageTracker;
+
+const DEFAULT_SUBSCRIPTION_USAGE_PATH: &str = "subscription_usage.log";
+const DAY_MS: u64 = 86_400_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DailySubscriptionUsage {
+    pub day_start: u64,
+    /// Hash of the API key, as in the request usage file.
+    pub key_hash: String,
+    /// The subscription field, e.g. `activeOrders`.
+    pub subscription: String,
+    /// Subscriptions opened this day.
+    pub opened: u64,
+    pub messages: u64,
+    pub bytes_sent: u64,
+    /// Summed lifetime of the subscriptions that closed this day.
+    pub lifetime_ms: u64,
+}
+
+/// One API key's subscription usage summed over a period.
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionConsumer {
+    pub key_hash: String,
+    pub opened: u64,
+    pub messages: u64,
+    pub bytes_sent: u64,
+    pub lifetime_ms: u64,
+}
+
+/// Subscription counts, messages and bytes sent per API key, subscription
+/// field and UTC day, persisted the way [`UsageTracker`] persists request
+/// rollups: completed days are appended to `SUBSCRIPTION_USAGE_PATH`
+/// (default `subscription_usage.log`) as JSON lines and reloaded on startup.
+pub struct SubscriptionUsage {
+    path: PathBuf,
+    days: RwLock<BTreeMap<(u64, String, String), DailySubscriptionUsage>>,
+    /// Days before this one are already in the file.
+    persisted_before: RwLock<u64>,
+}
+
+impl SubscriptionUsage {
+    pub fn open(path: PathBuf) -> Result<Self, Error> {
+        let rollups: Vec<DailySubscriptionUsage> = read_json_lines(&path)?;
+
+        let persisted_before = rollups
+            .iter()
+            .map(|usage| usage.day_start + DAY_MS)
+            .max()
+            .unwrap_or(0);
+        let days = rollups
+            .into_iter()
+            .map(|usage| {
+                (
+                    (
+                        usage.day_start,
+                        usage.key_hash.clone(),
+                        usage.subscription.clone(),
+                    ),
+                    usage,
+                )
+            })
+            .collect();
+
+        Ok(SubscriptionUsage {
+            path,
+            days: RwLock::new(days),
+            persisted_before: RwLock::new(persisted_before),
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Error> {
+        let path = ev_opt("SUBSCRIPTION_USAGE_PATH")
+            .unwrap_or_else(|| DEFAULT_SUBSCRIPTION_USAGE_PATH.to_owned());
+        Self::open(PathBuf::from(path))
+    }
+
+    pub fn record_opened(&self, api_key: &str, subscription: &str, timestamp: u64) {
+        self.update(api_key, subscription, timestamp, |usage| usage.opened += 1);
+    }
+
+    pub fn record_message(&self, api_key: &str, subscription: &str, bytes: u64, timestamp: u64) {
+        self.update(api_key, subscription, timestamp, |usage| {
+            usage.messages += 1;
+            usage.bytes_sent += bytes;
+        });
+    }
+
+    pub fn record_closed(
+        &self,
+        api_key: &str,
+        subscription: &str,
+        lifetime_ms: u64,
+        timestamp: u64,
+    ) {
+        self.update(api_key, subscription, timestamp, |usage| {
+            usage.lifetime_ms += lifetime_ms
+        });
+    }
+
+    /// API keys by bytes sent over subscriptions in `[from, to]` (ms),
+    /// heaviest first, at most `limit`.
+    pub fn top_consumers(&self, from: u64, to: u64, limit: usize) -> Vec<SubscriptionConsumer> {
+        let mut consumers: BTreeMap<String, SubscriptionConsumer> = BTreeMap::new();
+        for usage in self
+            .days
+            .read()
+            .unwrap()
+            .values()
+            .filter(|usage| usage.day_start + DAY_MS > from && usage.day_start <= to)
+        {
+            let consumer =
+                consumers
+                    .entry(usage.key_hash.clone())
+                    .or_insert_with(|| SubscriptionConsumer {
+                        key_hash: usage.key_hash.clone(),
+                        ..SubscriptionConsumer::default()
+                    });
+            consumer.opened += usage.opened;
+            consumer.messages += usage.messages;
+            consumer.bytes_sent += usage.bytes_sent;
+            consumer.lifetime_ms += usage.lifetime_ms;
+        }
+
+        let mut consumers: Vec<SubscriptionConsumer> = consumers.into_values().collect();
+        consumers.sort_by(|a, b| b.bytes_sent.cmp(&a.bytes_sent));
+        consumers.truncate(limit);
+        consumers
+    }
+
+    fn update(
+        &self,
+        api_key: &str,
+        subscription: &str,
+        timestamp: u64,
+        apply: impl FnOnce(&mut DailySubscriptionUsage),
+    ) {
+        let day_start = timestamp - timestamp % DAY_MS;
+        let key_hash = UsageTracker::hash_key(api_key);
+        {
+            let mut days = self.days.write().unwrap();
+            apply(
+                days.entry((day_start, key_hash.clone(), subscription.to_owned()))
+                    .or_insert_with(|| DailySubscriptionUsage {
+                        day_start,
+                        key_hash,
+                        subscription: subscription.to_owned(),
+                        ..DailySubscriptionUsage::default()
+                    }),
+            );
+        }
+        self.persist_completed_days(day_start);
+    }
+
+    /// Appends the rollups of every day before `today` not yet written.
+    fn persist_completed_days(&self, today: u64) {
+        let mut persisted_before = self.persisted_before.write().unwrap();
+        if *persisted_before >= today {
+            return;
+        }
+
+        let completed: Vec<DailySubscriptionUsage> = self
+            .days
+            .read()
+            .unwrap()
+            .range(
+                (*persisted_before, String::new(), String::new())
+                    ..(today, String::new(), String::new()),
+            )
+            .map(|(_, usage)| usage.clone())
+            .collect();
+        if completed.is_empty() {
+            *persisted_before = today;
+            return;
+        }
+        if let Err(e) = self.append_to_file(&completed) {
+            error!(
+                "Failed to write subscription usage to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+        *persisted_before = today;
+    }
+
+    fn append_to_file(&self, rollups: &[DailySubscriptionUsage]) -> Result<(), Error> {
+        let mut lines = String::new();
+        for usage in rollups {
+            lines.push_str(&serde_json::to_string(usage)?);
+            lines.push('\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .map_err(|e| Error::FileError(self.path.display().to_string(), e))
+    }
+}
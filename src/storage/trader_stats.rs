@@ -0,0 +1,159 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::RwLock;
+
+const DAY_MS: u64 = 86_400_000;
+const WEEK_MS: u64 = 7 * DAY_MS;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StatsPeriod {
+    Day,
+    Week,
+}
+
+impl StatsPeriod {
+    pub fn parse(period: &str) -> Option<Self> {
+        match period {
+            "Day" | "day" => Some(StatsPeriod::Day),
+            "Week" | "week" => Some(StatsPeriod::Week),
+            _ => None,
+        }
+    }
+
+    fn length_ms(self) -> u64 {
+        match self {
+            StatsPeriod::Day => DAY_MS,
+            StatsPeriod::Week => WEEK_MS,
+        }
+    }
+
+    pub fn bucket_start(self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.length_ms()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraderStatsBucket {
+    pub period_start: u64,
+    pub unique_traders: u64,
+    pub new_traders: u64,
+}
+
+/// What one activity added to the stats: a trader seen for the first time,
+/// or the first time in its day and week.
+#[derive(Debug, Clone)]
+pub struct Activity {
+    user: String,
+    timestamp: u64,
+    is_new: bool,
+    /// Day, then week.
+    first_in_period: [bool; 2],
+}
+
+#[derive(Default)]
+struct PeriodBuckets {
+    active: BTreeMap<u64, HashSet<String>>,
+    new_traders: BTreeMap<u64, u64>,
+}
+
+/// Unique and first-time trader counts per day and week, updated on every
+/// event that carries a user address.
+#[derive(Default)]
+pub struct TraderStats {
+    first_seen: RwLock<HashMap<String, u64>>,
+    daily: RwLock<PeriodBuckets>,
+    weekly: RwLock<PeriodBuckets>,
+}
+
+impl TraderStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns what the activity added, if anything, for [`Self::undo`].
+    pub fn record_activity(&self, user: &str, timestamp: u64) -> Option<Activity> {
+        let is_new = match self.first_seen.write().unwrap().entry(user.to_owned()) {
+            Entry::Vacant(entry) => {
+                entry.insert(timestamp);
+                true
+            }
+            Entry::Occupied(_) => false,
+        };
+
+        let mut first_in_period = [false; 2];
+        for (first, (period, buckets)) in first_in_period.iter_mut().zip([
+            (StatsPeriod::Day, &self.daily),
+            (StatsPeriod::Week, &self.weekly),
+        ]) {
+            let start = period.bucket_start(timestamp);
+            let mut buckets = buckets.write().unwrap();
+            *first = buckets
+                .active
+                .entry(start)
+                .or_default()
+                .insert(user.to_owned());
+            if is_new {
+                *buckets.new_traders.entry(start).or_default() += 1;
+            }
+        }
+
+        (is_new || first_in_period.contains(&true)).then(|| Activity {
+            user: user.to_owned(),
+            timestamp,
+            is_new,
+            first_in_period,
+        })
+    }
+
+    /// Takes back an activity recorded with [`Self::record_activity`].
+    pub fn undo(&self, activity: &Activity) {
+        if activity.is_new {
+            self.first_seen.write().unwrap().remove(&activity.user);
+        }
+        for (&first, (period, buckets)) in activity.first_in_period.iter().zip([
+            (StatsPeriod::Day, &self.daily),
+            (StatsPeriod::Week, &self.weekly),
+        ]) {
+            let start = period.bucket_start(activity.timestamp);
+            let mut buckets = buckets.write().unwrap();
+            if first {
+                if let Some(traders) = buckets.active.get_mut(&start) {
+                    traders.remove(&activity.user);
+                    if traders.is_empty() {
+                        buckets.active.remove(&start);
+                    }
+                }
+            }
+            if activity.is_new {
+                if let Some(count) = buckets.new_traders.get_mut(&start) {
+                    *count = count.saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    pub fn first_seen(&self, user: &str) -> Option<u64> {
+        self.first_seen.read().unwrap().get(user).copied()
+    }
+
+    pub fn buckets(&self, period: StatsPeriod) -> Vec<TraderStatsBucket> {
+        let buckets = match period {
+            StatsPeriod::Day => self.daily.read().unwrap(),
+            StatsPeriod::Week => self.weekly.read().unwrap(),
+        };
+
+        buckets
+            .active
+            .iter()
+            .map(|(&period_start, traders)| TraderStatsBucket {
+                period_start,
+                unique_traders: traders.len() as u64,
+                new_traders: buckets
+                    .new_traders
+                    .get(&period_start)
+                    .copied()
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+}
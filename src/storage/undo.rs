@@ -0,0 +1,102 @@
+use crate::indexer::order_event_handler::EventIndex;
+use crate::indexer::spot_order::{OrderType, SpotOrder};
+use crate::metrics::metrics;
+use crate::storage::market_registry::MarketState;
+use crate::storage::quote_stats::QuoteMove;
+use crate::storage::size_distribution::SizeKind;
+use crate::storage::trader_stats::Activity;
+
+/// One change an event made to a market, with what it takes to undo it.
+/// The indexer keeps the changes of its most recent blocks so a reorg can
+/// roll back over them without a copy of the whole book.
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// An order put on the book.
+    OrderAdded {
+        id: String,
+        order_type: OrderType,
+    },
+    /// An order filled, moved or taken off the book, as it was before.
+    OrderReplaced(SpotOrder),
+    /// A fill put on the tape.
+    TradeRecorded(EventIndex),
+    /// A match counted in the trade metrics.
+    MatchCounted {
+        size: u128,
+    },
+    Activity(Activity),
+    NewOrderFlow {
+        order_type: OrderType,
+        size: u128,
+        timestamp: u64,
+    },
+    TradeFlow {
+        initiator: OrderType,
+        size: u128,
+        timestamp: u64,
+    },
+    Size {
+        kind: SizeKind,
+        size: u128,
+        timestamp: u64,
+    },
+    Quote(QuoteMove),
+    /// A match counted in the fee revenue.
+    FeeRevenue {
+        price: u128,
+        size: u128,
+        timestamp: u64,
+    },
+}
+
+/// Undoes `changes`, newest first, leaving `market` as it was before the
+/// oldest of them. Fills stay on the tape marked busted, and the candles
+/// they were in are rebuilt without them. Returns how many were busted.
+pub fn undo(market: &MarketState, changes: Vec<Change>) -> usize {
+    let order_book = &market.order_book;
+    let mut busted = vec![];
+    let mut batch = order_book.batch();
+    for change in changes.into_iter().rev() {
+        match change {
+            Change::OrderAdded { id, order_type } => batch.discard_order(&id, order_type),
+            Change::OrderReplaced(order) => {
+                batch.discard_order(&order.id, order.order_type);
+                batch.restore_order(order);
+            }
+            Change::TradeRecorded(index) => busted.extend(order_book.bust_trade(index)),
+            Change::MatchCounted { size } => metrics().record_bust(market.market_id(), size),
+            Change::Activity(activity) => order_book.undo_activity(&activity),
+            Change::NewOrderFlow {
+                order_type,
+                size,
+                timestamp,
+            } => order_book.undo_order_flow(order_type, size, timestamp),
+            Change::TradeFlow {
+                initiator,
+                size,
+                timestamp,
+            } => order_book.undo_trade_flow(initiator, size, timestamp),
+            Change::Size {
+                kind,
+                size,
+                timestamp,
+            } => order_book.undo_size(kind, size, timestamp),
+            Change::Quote(quote_move) => order_book.undo_quote(&quote_move),
+            Change::FeeRevenue {
+                price,
+                size,
+                timestamp,
+            } => {
+                if let Some(fee_revenue) = &market.fee_revenue {
+                    fee_revenue.undo_match(price, size, timestamp);
+                }
+            }
+        }
+    }
+    drop(batch);
+
+    if let (Some(&from), Some(&to)) = (busted.iter().min(), busted.iter().max()) {
+        order_book.rebuild_candles(from, to);
+    }
+    busted.len()
+}
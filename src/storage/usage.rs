@@ -0,0 +1,143 @@
+use fuel_crypto::Hasher;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::json_lines::read_json_lines;
+
+const DEFAULT_USAGE_LOG_PATH: &str = "usage.log";
+const DAY_MS: u64 = 86_400_000;
+pub const ANONYMOUS_KEY: &str = "anonymous";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DailyUsage {
+    pub day_start: u64,
+    /// Hash of the API key, so the usage file holds no credentials.
+    pub key_hash: String,
+    pub requests: u64,
+    pub bytes_out: u64,
+}
+
+/// Request counts and response bytes per API key and UTC day.
+///
+/// The current day is kept in memory; once a day is over its rollups are
+/// appended to the usage file as JSON lines and reloaded on startup.
+pub struct UsageTracker {
+    path: PathBuf,
+    days: RwLock<BTreeMap<(u64, String), DailyUsage>>,
+    /// Days before this one are already in the file.
+    persisted_before: RwLock<u64>,
+}
+
+impl UsageTracker {
+    pub fn open(path: PathBuf) -> Result<Self, Error> {
+        let rollups: Vec<DailyUsage> = read_json_lines(&path)?;
+
+        let persisted_before = rollups
+            .iter()
+            .map(|usage| usage.day_start + DAY_MS)
+            .max()
+            .unwrap_or(0);
+        let days = rollups
+            .into_iter()
+            .map(|usage| ((usage.day_start, usage.key_hash.clone()), usage))
+            .collect();
+
+        Ok(UsageTracker {
+            path,
+            days: RwLock::new(days),
+            persisted_before: RwLock::new(persisted_before),
+        })
+    }
+
+    pub fn from_env() -> Result<Self, Error> {
+        let path = ev_opt("USAGE_LOG_PATH").unwrap_or_else(|| DEFAULT_USAGE_LOG_PATH.to_owned());
+        Self::open(PathBuf::from(path))
+    }
+
+    pub fn hash_key(api_key: &str) -> String {
+        hex::encode(Hasher::hash(api_key.as_bytes()))
+    }
+
+    pub fn record(&self, api_key: &str, bytes_out: u64, timestamp: u64) {
+        let day_start = timestamp - timestamp % DAY_MS;
+        let key_hash = Self::hash_key(api_key);
+        {
+            let mut days = self.days.write().unwrap();
+            let usage = days
+                .entry((day_start, key_hash.clone()))
+                .or_insert_with(|| DailyUsage {
+                    day_start,
+                    key_hash,
+                    ..DailyUsage::default()
+                });
+            usage.requests += 1;
+            usage.bytes_out += bytes_out;
+        }
+        self.persist_completed_days(day_start);
+    }
+
+    /// Daily rollups for `api_key` in `[from, to]` (ms).
+    pub fn usage(&self, api_key: &str, from: u64, to: u64) -> Vec<DailyUsage> {
+        let key_hash = Self::hash_key(api_key);
+        self.days
+            .read()
+            .unwrap()
+            .values()
+            .filter(|usage| {
+                usage.key_hash == key_hash
+                    && usage.day_start + DAY_MS > from
+                    && usage.day_start <= to
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Appends the rollups of every day before `today` not yet written.
+    fn persist_completed_days(&self, today: u64) {
+        let mut persisted_before = self.persisted_before.write().unwrap();
+        if *persisted_before >= today {
+            return;
+        }
+
+        let completed: Vec<DailyUsage> = self
+            .days
+            .read()
+            .unwrap()
+            .range((*persisted_before, String::new())..(today, String::new()))
+            .map(|(_, usage)| usage.clone())
+            .collect();
+        if completed.is_empty() {
+            *persisted_before = today;
+            return;
+        }
+        if let Err(e) = self.append_to_file(&completed) {
+            error!(
+                "Failed to write usage rollups to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+        *persisted_before = today;
+    }
+
+    fn append_to_file(&self, rollups: &[DailyUsage]) -> Result<(), Error> {
+        let mut lines = String::new();
+        for usage in rollups {
+            lines.push_str(&serde_json::to_string(usage)?);
+            lines.push('\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .map_err(|e| Error::FileError(self.path.display().to_string(), e))
+    }
+}
@@ -0,0 +1,112 @@
+use async_graphql::{Context, Guard};
+use rocket::request::{FromRequest, Outcome, Request};
+use std::net::IpAddr;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+use crate::config::env::ev_opt;
+
+use super::client_ip::{ranges_from_env, ClientIp, IpRange};
+use super::oidc::OidcVerifier;
+
+pub const ADMIN_KEY_HEADER: &str = "X-Admin-Key";
+const AUTHORIZATION_HEADER: &str = "Authorization";
+const DEFAULT_ADMIN_ACTOR: &str = "admin";
+
+/// Admin credentials presented by the caller.
+pub struct AdminCredentials {
+    pub key: Option<String>,
+    pub oidc_identity: Option<String>,
+}
+
+impl AdminCredentials {
+    /// The actor recorded in the audit log: the verified SSO identity when
+    /// an OIDC token was accepted, otherwise the holder of the admin key.
+    pub fn actor(&self) -> &str {
+        self.oidc_identity.as_deref().unwrap_or(DEFAULT_ADMIN_ACTOR)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminCredentials {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = request.headers();
+        let bearer = headers
+            .get_one(AUTHORIZATION_HEADER)
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let oidc_identity = match (bearer, request.rocket().state::<Arc<OidcVerifier>>()) {
+            (Some(token), Some(verifier)) => {
+                verifier.verify(token).await.map(|token| token.identity)
+            }
+            _ => None,
+        };
+
+        Outcome::Success(AdminCredentials {
+            key: headers.get_one(ADMIN_KEY_HEADER).map(str::to_owned),
+            oidc_identity,
+        })
+    }
+}
+
+/// Admin mutations are disabled unless `ADMIN_API_KEY` is set or OIDC login
+/// is configured. With `ADMIN_IP_ALLOWLIST` set, admin access is further
+/// limited to those addresses and CIDR blocks.
+pub struct AdminConfig {
+    api_key: Option<String>,
+    ip_allowlist: Option<Vec<IpRange>>,
+}
+
+impl AdminConfig {
+    pub fn from_env() -> Self {
+        AdminConfig {
+            api_key: ev_opt("ADMIN_API_KEY"),
+            ip_allowlist: ranges_from_env("ADMIN_IP_ALLOWLIST"),
+        }
+    }
+
+    pub fn is_ip_allowed(&self, ip: Option<IpAddr>) -> bool {
+        match (&self.ip_allowlist, ip) {
+            (None, _) => true,
+            (Some(allowlist), Some(ip)) => allowlist.iter().any(|range| range.contains(ip)),
+            (Some(_), None) => false,
+        }
+    }
+
+    pub fn is_authorized(&self, presented: Option<&str>) -> bool {
+        match (&self.api_key, presented) {
+            (Some(expected), Some(presented)) => {
+                expected.as_bytes().ct_eq(presented.as_bytes()).into()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether the request carries a valid admin key or an accepted OIDC token
+/// and comes from an allowed address.
+pub fn is_admin(ctx: &Context<'_>) -> bool {
+    let (Some(credentials), Some(config)) = (
+        ctx.data_opt::<AdminCredentials>(),
+        ctx.data_opt::<AdminConfig>(),
+    ) else {
+        return false;
+    };
+    let client_ip = ctx.data_opt::<ClientIp>().and_then(|client_ip| client_ip.0);
+
+    config.is_ip_allowed(client_ip)
+        && (credentials.oidc_identity.is_some() || config.is_authorized(credentials.key.as_deref()))
+}
+
+pub struct AdminGuard;
+
+impl Guard for AdminGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        if is_admin(ctx) {
+            Ok(())
+        } else {
+            Err("Admin authorization required".into())
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use log::warn;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::{Method, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{get, routes, Data, Route};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::config::env::ev_opt;
+
+pub const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+const DENIED_PATH: &str = "/__denied";
+
+/// A single address or a CIDR block such as `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                prefix_matches(&network.octets(), &ip.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(network: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = prefix_len as usize / 8;
+    let rest_bits = prefix_len % 8;
+    if network[..full_bytes] != ip[..full_bytes] {
+        return false;
+    }
+    if rest_bits == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - rest_bits);
+    network[full_bytes] & mask == ip[full_bytes] & mask
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (value, None),
+        };
+        let network: IpAddr = address
+            .parse()
+            .map_err(|_| format!("invalid address '{}'", address))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid prefix length in '{}'", value))?,
+            None => max_len,
+        };
+        Ok(IpRange {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+/// Parses a comma-separated list of addresses and CIDR blocks from `key`,
+/// skipping (and logging) invalid entries.
+pub fn ranges_from_env(key: &str) -> Option<Vec<IpRange>> {
+    let value = ev_opt(key)?;
+    Some(
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| match entry.parse() {
+                Ok(range) => Some(range),
+                Err(e) => {
+                    warn!("Ignoring {} entry: {}", key, e);
+                    None
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Proxies in `TRUSTED_PROXIES` whose `X-Forwarded-For` is believed, and
+/// clients in `IP_DENYLIST` that are refused outright.
+pub struct ClientIpConfig {
+    trusted_proxies: Vec<IpRange>,
+    denylist: Vec<IpRange>,
+}
+
+impl ClientIpConfig {
+    pub fn from_env() -> Self {
+        ClientIpConfig {
+            trusted_proxies: ranges_from_env("TRUSTED_PROXIES").unwrap_or_default(),
+            denylist: ranges_from_env("IP_DENYLIST").unwrap_or_default(),
+        }
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|range| range.contains(ip))
+    }
+
+    fn is_denied(&self, ip: IpAddr) -> bool {
+        self.denylist.iter().any(|range| range.contains(ip))
+    }
+
+    /// The peer address, unless it is a trusted proxy: then the right-most
+    /// `X-Forwarded-For` hop that is not itself a trusted proxy.
+    pub fn resolve(&self, request: &Request<'_>) -> Option<IpAddr> {
+        let peer = request.remote()?.ip();
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+
+        let forwarded: Vec<IpAddr> = request
+            .headers()
+            .get(FORWARDED_FOR_HEADER)
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse().ok())
+            .collect();
+        Some(
+            forwarded
+                .into_iter()
+                .rev()
+                .find(|hop| !self.is_trusted(*hop))
+                .unwrap_or(peer),
+        )
+    }
+}
+
+/// Address of the client that made the request, accounting for trusted
+/// proxies.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ip = request
+            .rocket()
+            .state::<ClientIpConfig>()
+            .and_then(|config| config.resolve(request))
+            .or_else(|| request.remote().map(|remote| remote.ip()));
+        Outcome::Success(ClientIp(ip))
+    }
+}
+
+/// Sends requests from denylisted clients to a route that answers 403.
+pub struct IpDenylist;
+
+#[rocket::async_trait]
+impl Fairing for IpDenylist {
+    fn info(&self) -> Info {
+        Info {
+            name: "IP denylist",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let denied_ip = request
+            .rocket()
+            .state::<ClientIpConfig>()
+            .and_then(|config| config.resolve(request).filter(|ip| config.is_denied(*ip)));
+        if let Some(ip) = denied_ip {
+            warn!("Refusing request from denylisted client {}", ip);
+            request.set_method(Method::Get);
+            request.set_uri(Origin::parse(DENIED_PATH).unwrap());
+        }
+    }
+}
+
+#[get("/__denied")]
+fn denied() -> Status {
+    Status::Forbidden
+}
+
+pub fn get_denied_routes() -> Vec<Route> {
+    routes![denied]
+}
@@ -0,0 +1,63 @@
+use async_graphql::{Context, ErrorExtensions};
+use std::any::Any;
+use std::sync::Arc;
+
+use crate::indexer::status::{IndexerStatus, SyncPhase};
+use crate::storage::backend::Storage;
+use crate::web::warmup::WarmupGate;
+
+/// Suggested wait before retrying a query rejected as initializing.
+pub(crate) const RETRY_AFTER_SECS: u64 = 5;
+
+/// Typed access to the shared state registered on the schema. Resolvers use
+/// this instead of unwrapping `ctx.data`, so a query that arrives before the
+/// service is wired up gets a "service initializing" error rather than a
+/// panic.
+pub trait ServiceContext {
+    fn service<T: Any + Send + Sync>(&self) -> async_graphql::Result<&T>;
+
+    /// The order book, once the indexer has started loading it and, with
+    /// `WARMUP_MODE=refuse`, once it has caught up with the chain.
+    fn order_book(&self) -> async_graphql::Result<&Arc<dyn Storage>>;
+}
+
+impl ServiceContext for Context<'_> {
+    fn service<T: Any + Send + Sync>(&self) -> async_graphql::Result<&T> {
+        self.data_opt::<T>().ok_or_else(service_initializing)
+    }
+
+    fn order_book(&self) -> async_graphql::Result<&Arc<dyn Storage>> {
+        let status = self.service::<Arc<IndexerStatus>>()?;
+        if status.phase() == SyncPhase::Starting {
+            return Err(service_initializing());
+        }
+        if let Some(gate) = self.data_opt::<WarmupGate>() {
+            if gate.refuses(status) {
+                return Err(warming_up(status));
+            }
+        }
+        self.service::<Arc<dyn Storage>>()
+    }
+}
+
+/// Error with `code: SERVICE_INITIALIZING` and a `retryAfter` hint in
+/// seconds in its extensions.
+pub fn service_initializing() -> async_graphql::Error {
+    async_graphql::Error::new("Service initializing, retry shortly").extend_with(|_, e| {
+        e.set("code", "SERVICE_INITIALIZING");
+        e.set("retryAfter", RETRY_AFTER_SECS);
+    })
+}
+
+/// Error with `code: WARMING_UP`, the current lag when known, and a
+/// `retryAfter` hint.
+pub fn warming_up(status: &IndexerStatus) -> async_graphql::Error {
+    let blocks_behind = status.blocks_behind();
+    async_graphql::Error::new("Order book is still backfilling").extend_with(|_, e| {
+        e.set("code", "WARMING_UP");
+        if let Some(blocks_behind) = blocks_behind {
+            e.set("blocksBehind", blocks_behind);
+        }
+        e.set("retryAfter", RETRY_AFTER_SECS);
+    })
+}
@@ -0,0 +1,61 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::outcome::Outcome;
+use rocket::{Request, Response};
+use std::sync::Arc;
+
+use crate::indexer::kill_switches::KillSwitches;
+
+use super::market::SelectedMarket;
+use super::warmup::WarmupGate;
+
+pub const FROZEN_DATA_HEADER: &str = "X-Data-Frozen";
+pub const WARMING_UP_HEADER: &str = "X-Warming-Up";
+
+/// Marks every response served during maintenance mode so clients know the
+/// data is a frozen snapshot rather than the live market.
+pub struct FrozenDataHeader;
+
+#[rocket::async_trait]
+impl Fairing for FrozenDataHeader {
+    fn info(&self) -> Info {
+        Info {
+            name: "Frozen data header",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let frozen = request
+            .rocket()
+            .state::<Arc<KillSwitches>>()
+            .is_some_and(|kill_switches| kill_switches.is_maintenance());
+        if frozen {
+            response.set_raw_header(FROZEN_DATA_HEADER, "true");
+        }
+    }
+}
+
+/// Marks responses for a market whose book is still backfilling, when
+/// `WARMUP_MODE` is `flag` or `refuse`.
+pub struct WarmingUpHeader;
+
+#[rocket::async_trait]
+impl Fairing for WarmingUpHeader {
+    fn info(&self) -> Info {
+        Info {
+            name: "Warming up header",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(gate) = request.rocket().state::<WarmupGate>() else {
+            return;
+        };
+        if let Outcome::Success(market) = request.guard::<SelectedMarket>().await {
+            if gate.flags(&market.status) {
+                response.set_raw_header(WARMING_UP_HEADER, "true");
+            }
+        }
+    }
+}
@@ -1,179 +1,2075 @@
-use crate::indexer::spot_order::OrderType;
-use crate::storage::order_book::OrderBook;
-use async_graphql::{Context, Object, SimpleObject, Subscription};
+use crate::indexer::connection_pool::connection_pool;
+use crate::indexer::kill_switches::KillSwitches;
+use crate::indexer::market_tasks::start_market;
+use crate::indexer::order_event_handler::EventIndex;
+use crate::indexer::spark_contracts::MarketDecimals;
+use crate::indexer::spot_order::{OrderType, SpotOrder};
+use crate::indexer::status::{ActivityLevel, ConfirmationDepth, IndexerStatus, SyncPhase};
+use crate::oracle::price_signer::PriceSigner;
+use crate::oracle::reference_rates::{ReferenceRates, REFERENCE_DECIMALS};
+use crate::storage::address_labels::{self, AddressLabels};
+use crate::storage::audit_log::{AuditEntry, AuditLog};
+use crate::storage::backend::Storage;
+use crate::storage::candles::{CANDLE_INTERVAL_MS, MAX_VOLATILITY_STEPS};
+use crate::storage::cold_storage::{merge_tape, ColdTradeStore};
+use crate::storage::daily_reports::DailyReports;
+use crate::storage::fair_price::compute_fair_price;
+use crate::storage::fee_revenue::FeeRevenue;
+use crate::storage::market_registry::{MarketRegistry, MarketState};
+use crate::storage::size_distribution::SizeKind;
+use crate::storage::state::StateEntry;
+use crate::storage::subscription_usage::SubscriptionUsage;
+use crate::storage::trader_stats::StatsPeriod;
+use crate::storage::usage::{UsageTracker, ANONYMOUS_KEY};
+use crate::web::auth::{is_admin, AdminCredentials, AdminGuard};
+use crate::web::client_ip::ClientIp;
+use crate::web::context::ServiceContext;
+use crate::web::load::{DepthLimits, LoadMonitor};
+use crate::web::order_feed::{OrderChange, OrderFeedConfig, OrderUpdate};
+use crate::web::query_traces::{QueryTrace, QueryTracing, ResolverTiming};
+use crate::web::reconcile::reconcile_with_peer;
+use crate::web::request_id::RequestId;
+use crate::web::scopes::{ApiKey, Scope, ScopeGuard};
+use crate::web::timestamps::Timestamp;
+use crate::web::visibility::{hidden_since, MarketVisibleGuard};
+use crate::web::ws_auth::ConnectionAuth;
+use async_graphql::{
+    ComplexObject, Context, GuardExt, Object, Schema, SimpleObject,
+    Subscription,
+};
 use async_stream::stream;
+use chrono::Utc;
+use ethers_core::types::H256;
 use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::time::{self, Duration};
 
-#[derive(SimpleObject, Clone)]
+/// Most trades one `tradeHistory` page returns.
+const MAX_TRADE_HISTORY_PAGE: usize = 1000;
+
+#[derive(SimpleObject, Clone, Serialize)]
+#[graphql(complex)]
 pub struct Order {
     id: String,
+    market_id: String,
     user: String,
     asset: String,
     amount: String,
     price: String,
-    timestamp: u64,
+    timestamp: Timestamp,
+    raw_timestamp: Option<Timestamp>,
     order_type: String,
     status: Option<String>,
+    /// Arrival sequence; lower is matched first within a price level.
+    priority: u64,
+    /// Number of orders ahead of this one at its price level.
+    queue_position: u32,
+    block_number: i64,
+}
+
+#[ComplexObject]
+impl Order {
+    /// False while fewer than `CONFIRMATION_DEPTH` blocks follow the
+    /// order's block; such liquidity may still disappear in a reorg.
+    async fn confirmed(&self, ctx: &Context<'_>) -> async_graphql::Result<bool> {
+        let status = ctx.service::<Arc<IndexerStatus>>()?;
+        let depth = ctx.service::<ConfirmationDepth>()?;
+        Ok(status.is_confirmed(self.block_number, *depth))
+    }
+
+    /// Label of the order's owner, if one is configured. Admin only.
+    #[graphql(guard = "AdminGuard")]
+    async fn user_label(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<AddressLabel>> {
+        let labels = ctx.service::<Arc<AddressLabels>>()?;
+        Ok(labels
+            .get(&self.user)
+            .map(|label| AddressLabel::new(self.user.clone(), label)))
+    }
 }
 
 #[derive(SimpleObject, Clone)]
+pub struct AddressLabel {
+    address: String,
+    name: String,
+    /// E.g. `market-maker`, `team`, `exchange`.
+    tags: Vec<String>,
+}
+
+impl AddressLabel {
+    fn new(address: String, label: address_labels::AddressLabel) -> Self {
+        AddressLabel {
+            address,
+            name: label.name,
+            tags: label.tags,
+        }
+    }
+}
+
+#[derive(SimpleObject, Clone, Serialize, Deserialize)]
+#[graphql(complex)]
 pub struct TradeOrderEvent {
+    pub id: String,
+    /// Empty for trades archived before events carried their market.
+    #[serde(default)]
+    pub market_id: String,
+    pub trade_price: String,
+    pub trade_size: String,
+    #[graphql(skip)]
+    pub timestamp: u64,
+    #[graphql(skip)]
+    pub raw_timestamp: Option<u64>,
+    pub block_number: i64,
+    pub transaction_index: u64,
+    pub log_index: u64,
+    /// The order that rested in the book first and the one that took it.
+    /// Both are null until the fill events of both sides are indexed, and
+    /// for trades recorded before parties were tracked.
+    #[serde(default)]
+    pub maker_order_id: Option<String>,
+    #[serde(default)]
+    pub taker_order_id: Option<String>,
+    #[serde(default)]
+    pub buyer: Option<String>,
+    #[serde(default)]
+    pub seller: Option<String>,
+    /// The side of the taker.
+    #[serde(default)]
+    pub aggressor_side: Option<OrderType>,
+    /// Voided by a reorg. Busted trades stay on the tape, so subscribers
+    /// see the bust, but count toward no candles or statistics.
+    #[serde(default)]
+    pub busted: bool,
+    /// Book priority of the filled order, lower for orders that rested
+    /// earlier; `None` if it never rested in the book.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub resting_since: Option<u64>,
+    /// The second fill of a match, recorded right after the first. The
+    /// match counts once, with its first fill, in candles and statistics.
+    #[graphql(skip)]
+    #[serde(default)]
+    pub counterpart_fill: bool,
+}
+
+#[ComplexObject]
+impl TradeOrderEvent {
+    #[graphql(name = "timestamp")]
+    async fn formatted_timestamp(&self) -> Timestamp {
+        Timestamp(self.timestamp)
+    }
+
+    #[graphql(name = "rawTimestamp")]
+    async fn formatted_raw_timestamp(&self) -> Option<Timestamp> {
+        self.raw_timestamp.map(Timestamp)
+    }
+
+    /// Position of the trade on chain, `<block>-<transaction>-<log>`. Pass it
+    /// as `after` to `tradeEvents` to resume after this trade; cursors stay
+    /// valid across restarts.
+    async fn cursor(&self) -> String {
+        self.index().cursor()
+    }
+}
+
+impl TradeOrderEvent {
+    pub fn index(&self) -> EventIndex {
+        EventIndex {
+            block_number: self.block_number,
+            transaction_index: self.transaction_index,
+            log_index: self.log_index,
+        }
+    }
+
+    /// Whether `other`, recorded right after this fill, reports the other
+    /// side of the same match: the same transaction, price and size, from
+    /// the opposite side. A fill already paired, or busted, has no further
+    /// counterpart.
+    pub fn is_counterpart(&self, other: &TradeOrderEvent) -> bool {
+        let disjoint = |a: &Option<String>, b: &Option<String>| a.is_none() || b.is_none();
+        !self.busted
+            && !other.busted
+            && !self.counterpart_fill
+            && self.block_number == other.block_number
+            && self.transaction_index == other.transaction_index
+            && self.trade_price == other.trade_price
+            && self.trade_size == other.trade_size
+            && disjoint(&self.buyer, &other.buyer)
+            && disjoint(&self.seller, &other.seller)
+    }
+
+    /// Pairs this fill with `second`, its counterpart, so both record the
+    /// parties of the whole match. The maker is the order that rested in the
+    /// book first; an order that never rested is the taker. `second_side` is
+    /// the side of `second`, the first fill's being the opposite one.
+    /// Returns the side of the taker, if the roles could be told apart.
+    pub fn pair_with(
+        &mut self,
+        second: &mut TradeOrderEvent,
+        second_side: Option<OrderType>,
+    ) -> Option<OrderType> {
+        second.counterpart_fill = true;
+        let first_is_maker = match (self.resting_since, second.resting_since) {
+            (Some(first), Some(second)) => Some(first < second),
+            (Some(_), None) => Some(true),
+            (None, Some(_)) => Some(false),
+            (None, None) => None,
+        };
+        let aggressor_side = first_is_maker.and_then(|first_is_maker| {
+            let (maker, taker) = if first_is_maker {
+                (&self.id, &second.id)
+            } else {
+                (&second.id, &self.id)
+            };
+            let (maker, taker) = (maker.clone(), taker.clone());
+            for fill in [&mut *self, &mut *second] {
+                fill.maker_order_id = Some(maker.clone());
+                fill.taker_order_id = Some(taker.clone());
+            }
+            if first_is_maker {
+                second_side
+            } else {
+                second_side.map(OrderType::opposite)
+            }
+        });
+        for fill in [&mut *self, &mut *second] {
+            fill.aggressor_side = aggressor_side;
+        }
+        let (buyer, seller) = (
+            self.buyer.clone().or(second.buyer.clone()),
+            self.seller.clone().or(second.seller.clone()),
+        );
+        for fill in [self, second] {
+            fill.buyer = buyer.clone();
+            fill.seller = seller.clone();
+        }
+        aggressor_side
+    }
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct TraderStatsBucket {
+    period_start: Timestamp,
+    unique_traders: u64,
+    new_traders: u64,
+}
+
+/// Traders whose first trade was in the week starting `week_start`;
+/// `active[k]` is how many of them traded `k` weeks later.
+#[derive(SimpleObject, Clone)]
+pub struct RetentionCohort {
+    week_start: Timestamp,
+    active: Vec<u64>,
+}
+
+/// Amounts are in raw units: `notional` is price × size and `fees` is the
+/// configured maker plus taker rate applied to it. `trades` counts matches.
+#[derive(SimpleObject, Clone)]
+pub struct FeeRevenueBucket {
+    period_start: Timestamp,
+    trades: u64,
+    volume: String,
+    notional: String,
+    fees: String,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct TopTrader {
+    user: String,
+    volume: String,
+    trades: u64,
+}
+
+/// A market's end-of-day summary. Prices and sizes are in raw units; OHLC
+/// is null on a day without trades and `fees` without fee tracking.
+#[derive(SimpleObject, Clone)]
+pub struct DailyReportInfo {
+    market_id: String,
+    date: String,
+    day_start: Timestamp,
+    open: Option<String>,
+    high: Option<String>,
+    low: Option<String>,
+    close: Option<String>,
+    volume: String,
+    trades: u64,
+    fees: Option<String>,
+    top_traders: Vec<TopTrader>,
+}
+
+/// Sizes in `[lower, upper)`; `upper` is null for the last bucket.
+#[derive(SimpleObject, Clone)]
+pub struct SizeBucket {
+    lower: String,
+    upper: Option<String>,
+    count: u64,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct VolumeProfileEntry {
+    day_of_week: u32,
+    hour: u32,
+    average_volume: String,
+}
+
+/// Flow in the interval starting at `period_start`, in raw base-asset units.
+/// `net_volume` is buy-initiated minus sell-initiated volume.
+#[derive(SimpleObject, Clone)]
+pub struct OrderFlowBucket {
+    period_start: Timestamp,
+    buy_volume: String,
+    sell_volume: String,
+    net_volume: String,
+    new_buy_size: String,
+    new_sell_size: String,
+    new_buy_orders: u64,
+    new_sell_orders: u64,
+}
+
+/// Realized volatility over the window ending at `timestamp`, from
+/// `samples` log returns; not annualized.
+#[derive(SimpleObject, Clone)]
+pub struct VolatilityPoint {
+    timestamp: Timestamp,
+    volatility: f64,
+    samples: u32,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct QuoteChangeBucket {
+    minute: Timestamp,
+    bid_changes: u64,
+    ask_changes: u64,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct PriceLevel {
+    price: String,
+    size: String,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct QueuePosition {
+    order_id: String,
+    order_type: String,
+    price: String,
+    orders_ahead: u64,
+    size_ahead: String,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct Depth {
+    /// Book sequence this depth was read at; see `syncState`.
+    sequence: u64,
+    epoch: u64,
+    bids: Vec<PriceLevel>,
+    asks: Vec<PriceLevel>,
+    levels: i32,
+    /// Fewer levels than requested were returned because of high load.
+    truncated: bool,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct TradeHistory {
+    trades: Vec<TradeOrderEvent>,
+    /// Part of the range was read from cold storage.
+    from_cold_storage: bool,
+    /// Pass as `after` for the next page; null on the last one.
+    next_cursor: Option<String>,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct FairPrice {
+    price: String,
+    levels: i32,
+    bid_vwap: String,
+    ask_vwap: String,
+    bid_size: String,
+    ask_size: String,
+    bids: Vec<PriceLevel>,
+    asks: Vec<PriceLevel>,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct SignedPrice {
+    price: String,
+    timestamp: Timestamp,
+    levels: i32,
+    payload: String,
+    signature: String,
+    public_key: String,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct Anomaly {
+    kind: String,
+    order_id: String,
+    event_type: String,
+    block_number: i64,
+    transaction_hash: String,
+    value: String,
+    expected: String,
+    timestamp: Timestamp,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct Market {
+    market_id: String,
+    indexing_halted: bool,
+    api_hidden: bool,
+    /// Whether the public API serves this market at all.
+    listed: bool,
+    /// True until the market's backfill has caught up with the chain.
+    syncing: bool,
+}
+
+impl Market {
+    fn new(market: &MarketState, kill_switches: &KillSwitches) -> Self {
+        let market_id = market.market_id();
+        let switches = kill_switches.effective(market_id);
+        Market {
+            market_id: market_id.to_string(),
+            indexing_halted: switches.indexing_halted,
+            api_hidden: switches.api_hidden,
+            listed: kill_switches.is_listed(market_id),
+            syncing: market.status.phase() != SyncPhase::Live,
+        }
+    }
+}
+
+/// Trading across the listed markets in a time range, with notionals
+/// summed in the reference currency.
+#[derive(SimpleObject, Clone)]
+pub struct ExchangeStats {
+    reference_currency: String,
+    /// Decimals of the normalized notionals.
+    decimals: u32,
+    /// Matches, each counted once.
+    trades: u64,
+    /// Sum of the markets' normalized notionals.
+    notional: String,
+    markets: Vec<MarketStats>,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct MarketStats {
+    market_id: String,
+    trades: u64,
+    /// Raw base-asset units.
+    volume: String,
+    /// Raw price × size, carrying the decimals of both assets.
+    notional: String,
+    /// Value of one quote unit in the reference currency.
+    rate: f64,
+    /// The notional in the reference currency.
+    normalized_notional: String,
+}
+
+#[derive(SimpleObject, Clone)]
+#[graphql(name = "IndexerStatus")]
+pub struct IndexerStatusInfo {
+    market_id: String,
+    phase: String,
+    last_processed_block: i64,
+    /// Latest block the stream is caught up to, with or without events for
+    /// the market; `blocksBehind` counts from it.
+    stream_block: i64,
+    indexing_halted: bool,
+    api_hidden: bool,
+    global_indexing_halted: bool,
+    global_api_hidden: bool,
+    /// Indexing is paused for maintenance and all data is a frozen snapshot.
+    maintenance: bool,
+    /// Hash of the active orders after `state_hash_block`; equal
+    /// across deployments that converged to the same state.
+    state_hash: Option<String>,
+    state_hash_block: Option<i64>,
+    /// Latest block reported by the node, tracked even in quiet markets.
+    chain_head: Option<i64>,
+    chain_head_timestamp: Option<i64>,
+    chain_head_observed_at: Option<Timestamp>,
+    blocks_behind: Option<i64>,
+    /// Live events per second over the last minute.
+    events_per_second: f64,
+    /// `Low`, `Medium` or `High`; see the `activityLevel` subscription.
+    activity_level: String,
+}
+
+/// Progress of the historical sync; see `SyncProgress`.
+#[derive(SimpleObject, Clone)]
+pub struct SyncStatus {
+    phase: String,
+    start_block: i64,
+    current_block: i64,
+    /// Chain head the sync is heading for, once one has been observed.
+    target_block: Option<i64>,
+    blocks_processed: i64,
+    total_blocks: Option<i64>,
+    events: u64,
+    events_per_second: f64,
+    elapsed_seconds: u64,
+    eta_seconds: Option<u64>,
+}
+
+/// Resume conventions shared by GraphQL and REST:
+/// - `sequence` increases with every change to the market's book. Responses
+///   carrying the same `epoch` and `sequence` describe the same book state.
+/// - `epoch` is when the book was created (ms). It changes on restart, when
+///   sequences start over and a client should reload its snapshot.
+/// - cursors are chain positions, `<block>-<transaction>-<log>`, and stay
+///   valid across restarts.
+#[derive(SimpleObject, Clone)]
+pub struct SyncState {
+    market_id: String,
+    sequence: u64,
+    epoch: u64,
+    /// Cursor of the newest trade, if any.
+    cursor: Option<String>,
+    last_processed_block: i64,
+    /// Whether the book has caught up and follows new blocks.
+    live: bool,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct KillSwitchState {
+    market_id: Option<String>,
+    indexing_halted: bool,
+    api_hidden: bool,
+}
+
+#[derive(SimpleObject, Clone)]
+#[graphql(name = "AuditEntry")]
+pub struct AuditEntryInfo {
+    timestamp: Timestamp,
+    actor: String,
+    operation: String,
+    parameters: String,
+    request_id: Option<String>,
+    client_ip: Option<String>,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct StateOrder {
+    id: String,
+    order_type: String,
+    price: String,
+    amount: String,
+}
+
+impl From<StateEntry> for StateOrder {
+    fn from(entry: StateEntry) -> Self {
+        StateOrder {
+            id: entry.id,
+            order_type: format!("{:?}", entry.order_type),
+            price: entry.price.to_string(),
+            amount: entry.amount.to_string(),
+        }
+    }
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct StateOrderMismatch {
     id: String,
-    trade_price: String,
-    trade_size: String,
-    timestamp: u64,
+    local: StateOrder,
+    peer: StateOrder,
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct ReconciliationReport {
+    matches: bool,
+    local_hash: String,
+    peer_hash: String,
+    peer_market_id: String,
+    peer_block_number: i64,
+    missing_on_peer: Vec<StateOrder>,
+    missing_locally: Vec<StateOrder>,
+    different: Vec<StateOrderMismatch>,
+}
+
+/// Pairs each order with the number of orders ahead of it at its price
+/// level. Expects orders grouped by level in queue order, as returned by
+/// `Storage::get_range`.
+fn with_queue_positions(orders: Vec<SpotOrder>) -> impl Iterator<Item = (u32, SpotOrder)> {
+    let mut level = None;
+    let mut position = 0;
+    orders.into_iter().map(move |order| {
+        if level == Some(order.price) {
+            position += 1;
+        } else {
+            level = Some(order.price);
+            position = 0;
+        }
+        (position, order)
+    })
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct UsageBucket {
+    day_start: Timestamp,
+    requests: u64,
+    bytes_out: u64,
+}
+
+/// One change to a resting order: `Add`, `Modify`, `Fill`, `Cancel` or
+/// `Revert`, with the order as it is now or, when taken off the book, as
+/// last seen. `user` is a keyed hash of the owner when addresses are masked.
+#[derive(SimpleObject, Clone, Serialize)]
+pub struct OrderFeedEvent {
+    change: String,
+    /// Position of the change in the book's feed; the snapshot's events
+    /// carry that of the last change they include.
+    sequence: u64,
+    id: String,
+    user: String,
+    order_type: String,
+    price: String,
+    amount: String,
+    status: Option<String>,
+    priority: u64,
+    timestamp: Timestamp,
+}
+
+impl OrderFeedEvent {
+    fn new(config: &OrderFeedConfig, update: OrderUpdate) -> Self {
+        let order = update.order;
+        OrderFeedEvent {
+            change: format!("{:?}", update.change),
+            sequence: update.sequence,
+            user: config.user(&order.user),
+            order_type: format!("{:?}", order.order_type),
+            price: order.price.to_string(),
+            amount: order.amount.to_string(),
+            status: order.status.map(|s| format!("{:?}", s)),
+            priority: order.priority,
+            timestamp: Timestamp(order.timestamp),
+            id: order.id,
+        }
+    }
+}
+
+#[derive(SimpleObject, Clone)]
+pub struct SubscriptionConsumer {
+    key_hash: String,
+    subscriptions: u64,
+    messages: u64,
+    bytes_sent: u64,
+    /// Summed lifetime of the subscriptions closed in the period.
+    lifetime_secs: u64,
+}
+
+/// The Pangea connections this process holds.
+#[derive(SimpleObject, Clone)]
+pub struct PangeaConnections {
+    connections: u64,
+    /// Distinct logins among them.
+    credentials: u64,
+    /// Markets and tools sharing them.
+    holders: u64,
+}
+
+/// Time spent in one resolver of a slow query.
+#[derive(SimpleObject, Clone)]
+pub struct ResolverTimingInfo {
+    path: String,
+    /// Microseconds after the request started.
+    started_us: u64,
+    duration_us: u64,
+}
+
+impl From<ResolverTiming> for ResolverTimingInfo {
+    fn from(timing: ResolverTiming) -> Self {
+        ResolverTimingInfo {
+            path: timing.path,
+            started_us: timing.started_us,
+            duration_us: timing.duration_us,
+        }
+    }
+}
+
+/// A GraphQL request that took longer than `SLOW_QUERY_MS`.
+#[derive(SimpleObject, Clone)]
+pub struct SlowQuery {
+    request_id: Option<String>,
+    operation_name: Option<String>,
+    query: String,
+    timestamp: Timestamp,
+    duration_ms: u64,
+    errors: u64,
+    resolvers: Vec<ResolverTimingInfo>,
+}
+
+impl From<QueryTrace> for SlowQuery {
+    fn from(trace: QueryTrace) -> Self {
+        SlowQuery {
+            request_id: trace.request_id,
+            operation_name: trace.operation_name,
+            query: trace.query,
+            timestamp: Timestamp(trace.timestamp),
+            duration_ms: trace.duration_ms,
+            errors: trace.errors as u64,
+            resolvers: trace.resolvers.into_iter().map(ResolverTimingInfo::from).collect(),
+        }
+    }
 }
 
 pub struct Query;
 
 #[Object]
 impl Query {
-    pub async fn buy_orders(&self, ctx: &Context<'_>) -> Vec<Order> {
-        let order_book = ctx.data::<Arc<OrderBook>>().unwrap();
-        let buy_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Buy);
-        buy_orders
-            .into_iter()
-            .map(|order| Order {
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn buy_orders(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Order>> {
+        let order_book = ctx.order_book()?;
+        let buy_orders = order_book.get_range(0, u128::MAX, OrderType::Buy);
+        Ok(with_queue_positions(buy_orders)
+            .map(|(queue_position, order)| Order {
                 id: order.id,
+                market_id: order.market_id,
                 user: order.user,
                 asset: order.asset,
                 amount: order.amount.to_string(),
                 price: order.price.to_string(),
-                timestamp: order.timestamp,
+                timestamp: Timestamp(order.timestamp),
+                raw_timestamp: order.raw_timestamp.map(Timestamp),
                 order_type: "Buy".to_string(),
                 status: order.status.map(|s| format!("{:?}", s)),
+                priority: order.priority,
+                queue_position,
+                block_number: order.block_number,
             })
-            .collect()
+            .collect())
     }
 
-    pub async fn sell_orders(&self, ctx: &Context<'_>) -> Vec<Order> {
-        let order_book = ctx.data::<Arc<OrderBook>>().unwrap();
-        let sell_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Sell);
-        sell_orders
-            .into_iter()
-            .map(|order| Order {
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn sell_orders(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Order>> {
+        let order_book = ctx.order_book()?;
+        let sell_orders = order_book.get_range(0, u128::MAX, OrderType::Sell);
+        Ok(with_queue_positions(sell_orders)
+            .map(|(queue_position, order)| Order {
                 id: order.id,
+                market_id: order.market_id,
                 user: order.user,
                 asset: order.asset,
                 amount: order.amount.to_string(),
                 price: order.price.to_string(),
-                timestamp: order.timestamp,
+                timestamp: Timestamp(order.timestamp),
+                raw_timestamp: order.raw_timestamp.map(Timestamp),
                 order_type: "Sell".to_string(),
                 status: order.status.map(|s| format!("{:?}", s)),
+                priority: order.priority,
+                queue_position,
+                block_number: order.block_number,
             })
-            .collect()
+            .collect())
     }
 
-    pub async fn spread(&self, ctx: &Context<'_>) -> Option<String> {
-        let order_book = ctx.data::<Arc<OrderBook>>().unwrap();
-        let buy_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Buy);
-        let sell_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Sell);
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn spread(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<String>> {
+        let order_book = ctx.order_book()?;
+        let buy_orders = order_book.get_range(0, u128::MAX, OrderType::Buy);
+        let sell_orders = order_book.get_range(0, u128::MAX, OrderType::Sell);
 
         let max_buy_price = buy_orders.iter().map(|o| o.price).max();
         let min_sell_price = sell_orders.iter().map(|o| o.price).min();
 
         if let (Some(max_buy), Some(min_sell)) = (max_buy_price, min_sell_price) {
-            Some((min_sell as i128 - max_buy as i128).to_string())
+            Ok(Some((min_sell as i128 - max_buy as i128).to_string()))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    pub async fn all_orders(&self, ctx: &Context<'_>, limit: Option<i32>, offset: Option<i32>) -> Vec<Order> {
-        let order_book = ctx.data::<Arc<OrderBook>>().unwrap();
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn all_orders(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> async_graphql::Result<Vec<Order>> {
+        let order_book = ctx.order_book()?;
         let mut all_orders = vec![];
 
-        let buy_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Buy);
-        let sell_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Sell);
+        let buy_orders = order_book.get_range(0, u128::MAX, OrderType::Buy);
+        let sell_orders = order_book.get_range(0, u128::MAX, OrderType::Sell);
 
-        all_orders.extend(buy_orders.into_iter().map(|order| Order {
+        all_orders.extend(with_queue_positions(buy_orders).map(|(queue_position, order)| Order {
             id: order.id.clone(),
+            market_id: order.market_id.clone(),
             user: order.user.clone(),
             asset: order.asset.clone(),
             amount: order.amount.to_string(),
             price: order.price.to_string(),
-            timestamp: order.timestamp,
+            timestamp: Timestamp(order.timestamp),
+            raw_timestamp: order.raw_timestamp.map(Timestamp),
             order_type: "Buy".to_string(),
             status: order.status.map(|s| format!("{:?}", s)),
+            priority: order.priority,
+            queue_position,
+            block_number: order.block_number,
         }));
 
-        all_orders.extend(sell_orders.into_iter().map(|order| Order {
+        all_orders.extend(with_queue_positions(sell_orders).map(|(queue_position, order)| Order {
             id: order.id.clone(),
+            market_id: order.market_id.clone(),
             user: order.user.clone(),
             asset: order.asset.clone(),
             amount: order.amount.to_string(),
             price: order.price.to_string(),
-            timestamp: order.timestamp,
+            timestamp: Timestamp(order.timestamp),
+            raw_timestamp: order.raw_timestamp.map(Timestamp),
             order_type: "Sell".to_string(),
             status: order.status.map(|s| format!("{:?}", s)),
+            priority: order.priority,
+            queue_position,
+            block_number: order.block_number,
         }));
 
         let offset = offset.unwrap_or(0) as usize;
         let limit = limit.unwrap_or(all_orders.len() as i32) as usize;
-        all_orders.into_iter().skip(offset).take(limit).collect()
+        Ok(all_orders.into_iter().skip(offset).take(limit).collect())
     }
 
-    pub async fn trade_events(&self, ctx: &Context<'_>, limit: Option<i32>, offset: Option<i32>) -> Vec<TradeOrderEvent> {
-        let order_book = ctx.data::<Arc<OrderBook>>().unwrap();
+    /// Trades in chain order. With `after`, only trades after that cursor,
+    /// so a client can resume from the last trade it saw.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn trade_events(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<Vec<TradeOrderEvent>> {
+        let order_book = ctx.order_book()?;
 
-        let events = order_book.get_trade_events();
+        let mut events = order_book.get_trade_events();
+        if let Some(after) = after {
+            let after = EventIndex::from_cursor(&after)
+                .ok_or_else(|| async_graphql::Error::new(format!("Invalid cursor '{}'", after)))?;
+            events.retain(|event| event.index() > after);
+        }
         let offset = offset.unwrap_or(0) as usize;
         let limit = limit.unwrap_or(events.len() as i32) as usize;
-        events.into_iter().skip(offset).take(limit).collect()
+        Ok(events.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// Trades with timestamps in `[from, to]` in chain order, a page of at
+    /// most `limit` (default and at most 1000) at a time. With `after`, the
+    /// page starts after that cursor. Ranges reaching into archived history
+    /// are read back from cold storage; a trade both archived and still in
+    /// memory is returned once.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn trade_history(
+        &self,
+        ctx: &Context<'_>,
+        from: u64,
+        to: u64,
+        limit: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<TradeHistory> {
+        let order_book = ctx.order_book()?;
+        let limit = limit.map_or(MAX_TRADE_HISTORY_PAGE, |limit| {
+            (limit.max(1) as usize).min(MAX_TRADE_HISTORY_PAGE)
+        });
+        let after = match after {
+            Some(after) => Some(EventIndex::from_cursor(&after).ok_or_else(|| {
+                async_graphql::Error::new(format!("Invalid cursor '{}'", after))
+            })?),
+            None => None,
+        };
+
+        let mut archived = vec![];
+        if let Some(cold_store) = ctx.data_opt::<Arc<ColdTradeStore>>() {
+            archived = cold_store
+                .load_range(from, to)
+                .map_err(|err| async_graphql::Error::new(err.to_string()))?;
+        }
+        let from_cold_storage = !archived.is_empty();
+        let hot = order_book
+            .get_trade_events()
+            .into_iter()
+            .filter(|trade| (from..=to).contains(&trade.timestamp));
+        let mut trades = merge_tape(archived, hot);
+        if let Some(after) = after {
+            trades.retain(|trade| trade.index() > after);
+        }
+
+        let next_cursor = (trades.len() > limit).then(|| trades[limit - 1].index().cursor());
+        trades.truncate(limit);
+        Ok(TradeHistory { trades, from_cold_storage, next_cursor })
+    }
+
+    #[graphql(guard = "MarketVisibleGuard.and(ScopeGuard::new(Scope::RawEvents))")]
+    pub async fn anomalies(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> async_graphql::Result<Vec<Anomaly>> {
+        let order_book = ctx.order_book()?;
+
+        let anomalies = order_book.get_anomalies();
+        let offset = offset.unwrap_or(0) as usize;
+        let limit = limit.unwrap_or(anomalies.len() as i32) as usize;
+        Ok(anomalies
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|anomaly| Anomaly {
+                kind: format!("{:?}", anomaly.kind),
+                order_id: anomaly.order_id,
+                event_type: anomaly.event_type,
+                block_number: anomaly.block_number,
+                transaction_hash: anomaly.transaction_hash,
+                value: anomaly.value.to_string(),
+                expected: anomaly.expected.to_string(),
+                timestamp: Timestamp(anomaly.timestamp),
+            })
+            .collect())
+    }
+
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn trader_stats(
+        &self,
+        ctx: &Context<'_>,
+        period: String,
+    ) -> async_graphql::Result<Vec<TraderStatsBucket>> {
+        let order_book = ctx.order_book()?;
+        let period = match StatsPeriod::parse(&period) {
+            Some(period) => period,
+            None => return Ok(vec![]),
+        };
+
+        Ok(order_book
+            .trader_buckets(period)
+            .into_iter()
+            .map(|bucket| TraderStatsBucket {
+                period_start: Timestamp(bucket.period_start),
+                unique_traders: bucket.unique_traders,
+                new_traders: bucket.new_traders,
+            })
+            .collect())
+    }
+
+    /// Weekly retention matrix, oldest cohort first. Rebuilt from the stored
+    /// trades in the background every `RETENTION_REFRESH_SECS`.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn retention(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Vec<RetentionCohort>> {
+        let order_book = ctx.order_book()?;
+
+        Ok(order_book
+            .retention_cohorts()
+            .into_iter()
+            .map(|cohort| RetentionCohort {
+                week_start: Timestamp(cohort.week_start),
+                active: cohort.active,
+            })
+            .collect())
+    }
+
+    #[graphql(guard = "MarketVisibleGuard.and(ScopeGuard::new(Scope::UserData))")]
+    pub async fn trader_first_seen(
+        &self,
+        ctx: &Context<'_>,
+        user: String,
+    ) -> async_graphql::Result<Option<u64>> {
+        let order_book = ctx.order_book()?;
+        Ok(order_book.first_seen(&user))
+    }
+
+    /// Fee revenue per `interval` (`day` or `week`) for the buckets
+    /// overlapping `[from, to]` (ms). Only available with a fee schedule
+    /// (`FEE_MAKER_BPS`, `FEE_TAKER_BPS` or `FEE_RATE_BPS`) set.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn fee_revenue(
+        &self,
+        ctx: &Context<'_>,
+        from: u64,
+        to: u64,
+        interval: String,
+    ) -> async_graphql::Result<Vec<FeeRevenueBucket>> {
+        let fee_revenue = ctx
+            .data_opt::<Arc<FeeRevenue>>()
+            .ok_or_else(|| async_graphql::Error::new("Fee revenue tracking is not enabled"))?;
+        let period = match StatsPeriod::parse(&interval) {
+            Some(period) => period,
+            None => return Ok(vec![]),
+        };
+
+        Ok(fee_revenue
+            .series(period, from, to)
+            .into_iter()
+            .map(|bucket| FeeRevenueBucket {
+                period_start: Timestamp(bucket.period_start),
+                trades: bucket.trades,
+                volume: bucket.volume,
+                notional: bucket.notional,
+                fees: bucket.fees,
+            })
+            .collect())
+    }
+
+    /// The end-of-day report for `date` (`YYYY-MM-DD`, UTC), or null until
+    /// it is written shortly after the day ends.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn daily_report(
+        &self,
+        ctx: &Context<'_>,
+        date: String,
+    ) -> async_graphql::Result<Option<DailyReportInfo>> {
+        let reports = ctx
+            .data_opt::<Arc<DailyReports>>()
+            .ok_or_else(|| async_graphql::Error::new("Daily reports are not enabled"))?;
+        let day_start = chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+            .map_err(|_| async_graphql::Error::new("Invalid date, expected YYYY-MM-DD"))?
+            .and_hms_opt(0, 0, 0)
+            .map(|midnight| midnight.and_utc().timestamp_millis() as u64)
+            .unwrap_or_default();
+
+        Ok(reports.report(day_start).map(|report| DailyReportInfo {
+            market_id: report.market_id,
+            date: report.date,
+            day_start: Timestamp(report.day_start),
+            open: report.open,
+            high: report.high,
+            low: report.low,
+            close: report.close,
+            volume: report.volume,
+            trades: report.trades,
+            fees: report.fees,
+            top_traders: report
+                .top_traders
+                .into_iter()
+                .map(|trader| TopTrader {
+                    user: trader.user,
+                    volume: trader.volume,
+                    trades: trader.trades,
+                })
+                .collect(),
+        }))
+    }
+
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn volume_profile(
+        &self,
+        ctx: &Context<'_>,
+        lookback_days: Option<i32>,
+    ) -> async_graphql::Result<Vec<VolumeProfileEntry>> {
+        let order_book = ctx.order_book()?;
+        let lookback_days = lookback_days.unwrap_or(7).max(1) as u64;
+        let now = Utc::now().timestamp_millis() as u64;
+
+        Ok(order_book
+            .volume_profile(lookback_days, now)
+            .into_iter()
+            .map(|entry| VolumeProfileEntry {
+                day_of_week: entry.day_of_week,
+                hour: entry.hour,
+                average_volume: entry.average_volume.to_string(),
+            })
+            .collect())
+    }
+
+    /// Signed traded volume and new-order flow per side in `interval`-second
+    /// buckets (default 300, whole minutes) overlapping `[from, to]` (ms).
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn order_flow(
+        &self,
+        ctx: &Context<'_>,
+        from: u64,
+        to: u64,
+        interval: Option<u64>,
+    ) -> async_graphql::Result<Vec<OrderFlowBucket>> {
+        let order_book = ctx.order_book()?;
+        let interval_ms = interval.unwrap_or(300).saturating_mul(1000);
+
+        Ok(order_book
+            .order_flow_series(from, to, interval_ms)
+            .into_iter()
+            .map(|bucket| OrderFlowBucket {
+                period_start: Timestamp(bucket.period_start),
+                buy_volume: bucket.buy_volume.to_string(),
+                sell_volume: bucket.sell_volume.to_string(),
+                net_volume: bucket.net_volume().to_string(),
+                new_buy_size: bucket.new_buy_size.to_string(),
+                new_sell_size: bucket.new_sell_size.to_string(),
+                new_buy_orders: bucket.new_buy_orders,
+                new_sell_orders: bucket.new_sell_orders,
+            })
+            .collect())
+    }
+
+    /// Rolling realized volatility from the candle store: closes sampled
+    /// every `interval` seconds (default 300, at least 60) and a `window`
+    /// (seconds, default 3600) of log returns per point. Points cover
+    /// `[from, to]` (ms), by default the last 24 hours. Ranges and windows
+    /// of more than 10,000 intervals are refused.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn volatility(
+        &self,
+        ctx: &Context<'_>,
+        window: Option<u64>,
+        interval: Option<u64>,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> async_graphql::Result<Vec<VolatilityPoint>> {
+        let order_book = ctx.order_book()?;
+        let window_ms = window.unwrap_or(3600).saturating_mul(1000);
+        let interval_ms = interval.unwrap_or(300).saturating_mul(1000).max(CANDLE_INTERVAL_MS);
+        let to = to.unwrap_or_else(|| Utc::now().timestamp_millis() as u64);
+        let from = from.unwrap_or_else(|| to.saturating_sub(86_400_000));
+        if to.saturating_sub(from) / interval_ms > MAX_VOLATILITY_STEPS
+            || window_ms / interval_ms > MAX_VOLATILITY_STEPS
+        {
+            return Err(format!(
+                "Range and window may span at most {} intervals each",
+                MAX_VOLATILITY_STEPS
+            )
+            .into());
+        }
+
+        Ok(order_book
+            .realized_volatility(window_ms, interval_ms, from, to)
+            .into_iter()
+            .map(|point| VolatilityPoint {
+                timestamp: Timestamp(point.timestamp),
+                volatility: point.volatility,
+                samples: point.samples,
+            })
+            .collect())
+    }
+
+    /// Histogram of new order sizes (`kind: "order"`) or trade sizes
+    /// (`kind: "trade"`) over the hours overlapping `[from, to]` (ms).
+    /// Buckets come from `SIZE_HISTOGRAM_BOUNDS`.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn size_distribution(
+        &self,
+        ctx: &Context<'_>,
+        kind: String,
+        from: u64,
+        to: u64,
+    ) -> async_graphql::Result<Vec<SizeBucket>> {
+        let order_book = ctx.order_book()?;
+        let kind = match SizeKind::parse(&kind) {
+            Some(kind) => kind,
+            None => return Ok(vec![]),
+        };
+
+        Ok(order_book
+            .size_histogram(kind, from, to)
+            .into_iter()
+            .map(|bucket| SizeBucket {
+                lower: bucket.lower.to_string(),
+                upper: bucket.upper.map(|upper| upper.to_string()),
+                count: bucket.count,
+            })
+            .collect())
+    }
+
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn quote_change_rate(
+        &self,
+        ctx: &Context<'_>,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> async_graphql::Result<Vec<QuoteChangeBucket>> {
+        let order_book = ctx.order_book()?;
+
+        Ok(order_book
+            .quote_change_series(from.unwrap_or(0), to.unwrap_or(u64::MAX))
+            .into_iter()
+            .map(|bucket| QuoteChangeBucket {
+                minute: Timestamp(bucket.minute),
+                bid_changes: bucket.bid_changes,
+                ask_changes: bucket.ask_changes,
+            })
+            .collect())
+    }
+
+    /// Aggregated depth per side, best price first. Under high load the
+    /// level count is reduced and `truncated` is set.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn depth(
+        &self,
+        ctx: &Context<'_>,
+        levels: Option<i32>,
+    ) -> async_graphql::Result<Depth> {
+        let order_book = ctx.order_book()?;
+        let depth_limits = ctx.service::<DepthLimits>()?;
+        let load = ctx.service::<Arc<LoadMonitor>>()?;
+        let requested = levels.map(|levels| levels.max(1) as usize);
+        let (levels, truncated) = depth_limits.levels(requested, load);
+        let sequence = order_book.version();
+        let to_levels = |order_type: OrderType| -> Vec<PriceLevel> {
+            order_book
+                .top_levels(order_type, levels)
+                .into_iter()
+                .map(|level| PriceLevel {
+                    price: level.price.to_string(),
+                    size: level.size.to_string(),
+                })
+                .collect()
+        };
+
+        Ok(Depth {
+            sequence,
+            epoch: order_book.epoch(),
+            bids: to_levels(OrderType::Buy),
+            asks: to_levels(OrderType::Sell),
+            levels: levels as i32,
+            truncated,
+        })
+    }
+
+    /// Cumulative size resting ahead of `order_id` at its price level.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn queue_position(
+        &self,
+        ctx: &Context<'_>,
+        order_id: String,
+    ) -> async_graphql::Result<Option<QueuePosition>> {
+        let order_book = ctx.order_book()?;
+        Ok(order_book.queue_position(&order_id).map(|position| QueuePosition {
+            order_type: format!("{:?}", position.order_type),
+            price: position.price.to_string(),
+            orders_ahead: position.orders_ahead as u64,
+            size_ahead: position.size_ahead.to_string(),
+            order_id,
+        }))
+    }
+
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn fair_price(
+        &self,
+        ctx: &Context<'_>,
+        levels: Option<i32>,
+    ) -> async_graphql::Result<Option<FairPrice>> {
+        let order_book = ctx.order_book()?;
+        let levels = levels.unwrap_or(5).max(1);
+        let to_levels = |side: Vec<crate::storage::fair_price::PriceLevel>| -> Vec<PriceLevel> {
+            side.into_iter()
+                .map(|level| PriceLevel {
+                    price: level.price.to_string(),
+                    size: level.size.to_string(),
+                })
+                .collect()
+        };
+
+        Ok(compute_fair_price(order_book, levels as usize).map(|fair| FairPrice {
+            price: fair.price.to_string(),
+            levels,
+            bid_vwap: fair.bid_vwap.to_string(),
+            ask_vwap: fair.ask_vwap.to_string(),
+            bid_size: fair.bid_size.to_string(),
+            ask_size: fair.ask_size.to_string(),
+            bids: to_levels(fair.bids),
+            asks: to_levels(fair.asks),
+        }))
+    }
+
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn signed_fair_price(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<SignedPrice>> {
+        let order_book = ctx.order_book()?;
+        let Some(price_signer) = ctx.data_opt::<Arc<PriceSigner>>() else {
+            return Ok(None);
+        };
+
+        Ok(price_signer
+            .sign_fair_price(order_book)
+            .map(|signed| SignedPrice {
+                price: signed.price.to_string(),
+                timestamp: Timestamp(signed.timestamp),
+                levels: signed.levels as i32,
+                payload: signed.payload,
+                signature: signed.signature,
+                public_key: signed.public_key,
+            }))
+    }
+
+    /// Labelled addresses, optionally only those tagged `tag`. Admin only.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn address_labels(
+        &self,
+        ctx: &Context<'_>,
+        tag: Option<String>,
+    ) -> async_graphql::Result<Vec<AddressLabel>> {
+        let labels = ctx.service::<Arc<AddressLabels>>()?;
+        Ok(labels
+            .all(tag.as_deref())
+            .into_iter()
+            .map(|(address, label)| AddressLabel::new(address, label))
+            .collect())
+    }
+
+    #[graphql(guard = "AdminGuard")]
+    pub async fn audit_log(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+        offset: Option<i32>,
+    ) -> async_graphql::Result<Vec<AuditEntryInfo>> {
+        let audit_log = ctx.service::<Arc<AuditLog>>()?;
+
+        let entries = audit_log.entries();
+        let offset = offset.unwrap_or(0) as usize;
+        let limit = limit.unwrap_or(entries.len() as i32) as usize;
+        Ok(entries
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|entry| AuditEntryInfo {
+                timestamp: Timestamp(entry.timestamp),
+                actor: entry.actor,
+                operation: entry.operation,
+                parameters: entry.parameters.to_string(),
+                request_id: entry.request_id,
+                client_ip: entry.client_ip,
+            })
+            .collect())
+    }
+
+    /// Daily request and bandwidth rollups for `api_key` in the current
+    /// `period` ("day" or "week").
+    #[graphql(guard = "AdminGuard")]
+    pub async fn usage(
+        &self,
+        ctx: &Context<'_>,
+        api_key: String,
+        period: String,
+    ) -> async_graphql::Result<Vec<UsageBucket>> {
+        let usage = ctx.service::<Arc<UsageTracker>>()?;
+        let period = match StatsPeriod::parse(&period) {
+            Some(period) => period,
+            None => return Ok(vec![]),
+        };
+
+        let now = Utc::now().timestamp_millis() as u64;
+        Ok(usage
+            .usage(&api_key, period.bucket_start(now), now)
+            .into_iter()
+            .map(|usage| UsageBucket {
+                day_start: Timestamp(usage.day_start),
+                requests: usage.requests,
+                bytes_out: usage.bytes_out,
+            })
+            .collect())
+    }
+
+    /// API keys by bytes sent over subscriptions in the current `period`
+    /// ("day" or "week"), heaviest first.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn top_subscription_consumers(
+        &self,
+        ctx: &Context<'_>,
+        period: String,
+        limit: Option<i32>,
+    ) -> async_graphql::Result<Vec<SubscriptionConsumer>> {
+        let usage = ctx.service::<Arc<SubscriptionUsage>>()?;
+        let period = match StatsPeriod::parse(&period) {
+            Some(period) => period,
+            None => return Ok(vec![]),
+        };
+
+        let now = Utc::now().timestamp_millis() as u64;
+        let limit = limit.unwrap_or(10).max(0) as usize;
+        Ok(usage
+            .top_consumers(period.bucket_start(now), now, limit)
+            .into_iter()
+            .map(|consumer| SubscriptionConsumer {
+                key_hash: consumer.key_hash,
+                subscriptions: consumer.opened,
+                messages: consumer.messages,
+                bytes_sent: consumer.bytes_sent,
+                lifetime_secs: consumer.lifetime_ms / 1000,
+            })
+            .collect())
+    }
+
+    /// The most recent slow queries, newest first, up to `SLOW_QUERY_BUFFER`.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn slow_queries(
+        &self,
+        ctx: &Context<'_>,
+        limit: Option<i32>,
+    ) -> async_graphql::Result<Vec<SlowQuery>> {
+        let tracing = ctx.service::<Arc<QueryTracing>>()?;
+        let limit = limit.map_or(usize::MAX, |limit| limit.max(0) as usize);
+        Ok(tracing.slow_queries(limit).into_iter().map(SlowQuery::from).collect())
+    }
+
+    #[graphql(guard = "AdminGuard")]
+    pub async fn pangea_connections(&self) -> async_graphql::Result<PangeaConnections> {
+        let stats = connection_pool().stats();
+        Ok(PangeaConnections {
+            connections: stats.connections as u64,
+            credentials: stats.credentials as u64,
+            holders: stats.holders as u64,
+        })
+    }
+
+    /// Indexed markets. Unlisted markets are only shown to admins. Other
+    /// queries act on the market named by the `X-Market-Id` header, or on the
+    /// first market when it is absent.
+    pub async fn markets(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Market>> {
+        let markets = ctx.service::<Arc<MarketRegistry>>()?;
+        let kill_switches = ctx.service::<Arc<KillSwitches>>()?;
+        let admin = is_admin(ctx);
+
+        Ok(markets
+            .all()
+            .iter()
+            .filter(|market| admin || kill_switches.is_listed(market.market_id()))
+            .map(|market| Market::new(market, kill_switches))
+            .collect())
+    }
+
+    /// Matches and notional across markets in `[from, to]`, each market's
+    /// notional converted from its quote asset into the reference currency
+    /// using the decimals its contract is configured with. Fails when a
+    /// market has no reference rate or its decimals cannot be read. Ranges reaching into archived
+    /// history are read from cold storage.
+    #[graphql(guard = "ScopeGuard::new(Scope::ExchangeData)")]
+    pub async fn exchange_stats(
+        &self,
+        ctx: &Context<'_>,
+        from: u64,
+        to: u64,
+    ) -> async_graphql::Result<ExchangeStats> {
+        let markets = ctx.service::<Arc<MarketRegistry>>()?;
+        let kill_switches = ctx.service::<Arc<KillSwitches>>()?;
+        let market_decimals = ctx.service::<Arc<MarketDecimals>>()?;
+        let rates = ctx.service::<Arc<ReferenceRates>>()?;
+        let admin = is_admin(ctx);
+
+        let mut total = 0u128;
+        let mut stats = vec![];
+        for market in markets.all() {
+            let market_id = market.market_id();
+            if !admin
+                && (!kill_switches.is_listed(market_id)
+                    || kill_switches.effective(market_id).api_hidden)
+            {
+                continue;
+            }
+            let Some(rate) = rates.rate(market_id) else {
+                return Err(format!(
+                    "No reference rate for the quote asset of market {}",
+                    market_id
+                )
+                .into());
+            };
+            let (base_decimals, quote_decimals) = market_decimals
+                .get(market_id)
+                .await
+                .map_err(|err| format!("Decimals of market {} are unknown: {}", market_id, err))?;
+
+            let trades = market
+                .matches_between(from, to)
+                .map_err(|err| async_graphql::Error::new(err.to_string()))?;
+
+            let (mut volume, mut notional) = (0u128, 0u128);
+            for trade in &trades {
+                let price: u128 = trade.trade_price.parse().unwrap_or_default();
+                let size: u128 = trade.trade_size.parse().unwrap_or_default();
+                volume = volume.saturating_add(size);
+                notional = notional.saturating_add(price.saturating_mul(size));
+            }
+            // Price × size carries the decimals of both assets.
+            let normalized = rates
+                .normalize(market_id, notional, base_decimals + quote_decimals)
+                .unwrap_or_default();
+            total = total.saturating_add(normalized);
+            stats.push(MarketStats {
+                market_id: market_id.to_string(),
+                trades: trades.len() as u64,
+                volume: volume.to_string(),
+                notional: notional.to_string(),
+                rate,
+                normalized_notional: normalized.to_string(),
+            });
+        }
+
+        Ok(ExchangeStats {
+            reference_currency: rates.currency().to_owned(),
+            decimals: REFERENCE_DECIMALS,
+            trades: stats.iter().map(|market| market.trades).sum(),
+            notional: total.to_string(),
+            markets: stats,
+        })
+    }
+
+    /// Everything a client needs to resume: the book position and the cursor
+    /// of the newest trade.
+    #[graphql(guard = "MarketVisibleGuard")]
+    pub async fn sync_state(&self, ctx: &Context<'_>) -> async_graphql::Result<SyncState> {
+        let order_book = ctx.order_book()?;
+        let status = ctx.service::<Arc<IndexerStatus>>()?;
+
+        Ok(SyncState {
+            market_id: status.market_id().to_string(),
+            sequence: order_book.version(),
+            epoch: order_book.epoch(),
+            cursor: order_book.recent_trades(1).pop().map(|trade| trade.index().cursor()),
+            last_processed_block: status.last_processed_block(),
+            live: status.phase() == SyncPhase::Live,
+        })
+    }
+
+    /// Historical sync progress with an ETA at the rate so far. Null until
+    /// the sync has started.
+    pub async fn sync_status(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<Option<SyncStatus>> {
+        let status = ctx.service::<Arc<IndexerStatus>>()?;
+        let Some(progress) = status.sync_progress(Utc::now().timestamp_millis() as u64) else {
+            return Ok(None);
+        };
+
+        Ok(Some(SyncStatus {
+            phase: format!("{:?}", status.phase()),
+            start_block: progress.start_block,
+            current_block: progress.current_block,
+            target_block: progress.target_block,
+            blocks_processed: progress.blocks_processed(),
+            total_blocks: progress.total_blocks(),
+            events: progress.events,
+            events_per_second: progress.events_per_second(),
+            elapsed_seconds: progress.elapsed_ms / 1000,
+            eta_seconds: progress.eta_secs(),
+        }))
+    }
+
+    pub async fn indexer_status(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<IndexerStatusInfo> {
+        let status = ctx.service::<Arc<IndexerStatus>>()?;
+        let kill_switches = ctx.service::<Arc<KillSwitches>>()?;
+        let global = kill_switches.global();
+        let switches = kill_switches.effective(status.market_id());
+        let state_hash = status.state_hash();
+        let chain_head = status.chain_head();
+        let events_per_second = status.events_per_second(Utc::now().timestamp_millis() as u64);
+
+        Ok(IndexerStatusInfo {
+            market_id: status.market_id().to_string(),
+            phase: format!("{:?}", status.phase()),
+            last_processed_block: status.last_processed_block(),
+            stream_block: status.stream_block(),
+            indexing_halted: switches.indexing_halted,
+            api_hidden: switches.api_hidden,
+            global_indexing_halted: global.indexing_halted,
+            global_api_hidden: global.api_hidden,
+            maintenance: kill_switches.is_maintenance(),
+            state_hash_block: state_hash.as_ref().map(|state| state.block_number),
+            state_hash: state_hash.map(|state| state.hash),
+            chain_head: chain_head.map(|head| head.block_number),
+            chain_head_timestamp: chain_head.and_then(|head| head.block_timestamp),
+            chain_head_observed_at: chain_head.map(|head| Timestamp(head.observed_at)),
+            blocks_behind: status.blocks_behind(),
+            events_per_second,
+            activity_level: format!("{:?}", ActivityLevel::from_rate(events_per_second)),
+        })
+    }
+}
+
+/// Records an admin mutation in the audit log. Called by every mutation
+/// after its guard has passed.
+fn audit(
+    ctx: &Context<'_>,
+    operation: &str,
+    parameters: serde_json::Value,
+) -> async_graphql::Result<()> {
+    let audit_log = ctx.service::<Arc<AuditLog>>()?;
+    let actor = ctx
+        .data_opt::<AdminCredentials>()
+        .map(|credentials| credentials.actor().to_owned())
+        .unwrap_or_default();
+
+    audit_log.record(AuditEntry {
+        timestamp: Utc::now().timestamp_millis() as u64,
+        actor,
+        operation: operation.to_owned(),
+        parameters,
+        request_id: ctx.data_opt::<RequestId>().map(|id| id.0.clone()),
+        client_ip: ctx
+            .data_opt::<ClientIp>()
+            .and_then(|client_ip| client_ip.0)
+            .map(|ip| ip.to_string()),
+    });
+    Ok(())
+}
+
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    /// Recomputes candles in `[from, to]` (ms) from recorded trades.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn rebuild_candles(
+        &self,
+        ctx: &Context<'_>,
+        from: u64,
+        to: u64,
+    ) -> async_graphql::Result<u64> {
+        let order_book = ctx.order_book()?;
+        audit(ctx, "rebuildCandles", json!({ "from": from, "to": to }))?;
+        Ok(order_book.rebuild_candles(from, to) as u64)
+    }
+
+    /// Halts indexing and/or hides API data for `market_id`, or for every
+    /// market when it is omitted. Unset arguments keep their current value.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn set_kill_switch(
+        &self,
+        ctx: &Context<'_>,
+        market_id: Option<String>,
+        indexing_halted: Option<bool>,
+        api_hidden: Option<bool>,
+    ) -> async_graphql::Result<KillSwitchState> {
+        let kill_switches = ctx.service::<Arc<KillSwitches>>()?;
+        audit(
+            ctx,
+            "setKillSwitch",
+            json!({
+                "marketId": market_id,
+                "indexingHalted": indexing_halted,
+                "apiHidden": api_hidden,
+            }),
+        )?;
+        let state = match &market_id {
+            Some(market_id) => kill_switches.update_market(market_id, indexing_halted, api_hidden),
+            None => kill_switches.update_global(indexing_halted, api_hidden),
+        };
+
+        Ok(KillSwitchState {
+            market_id,
+            indexing_halted: state.indexing_halted,
+            api_hidden: state.api_hidden,
+        })
+    }
+
+    /// Rolls the last `blocks` blocks of `market_id`, or of the selected
+    /// market, back and streams them from Pangea again up to the chain head,
+    /// for when events may have been missed without a full replay. Runs in
+    /// the background once the live stream picks it up. At most the last
+    /// `REORG_DEPTH` blocks can be resynced; the indexer logs and drops a
+    /// deeper request.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn resync(
+        &self,
+        ctx: &Context<'_>,
+        blocks: i64,
+        market_id: Option<String>,
+    ) -> async_graphql::Result<bool> {
+        let markets = ctx.service::<Arc<MarketRegistry>>()?;
+        if blocks < 1 {
+            return Err(async_graphql::Error::new("blocks must be at least 1"));
+        }
+        let market_id = match market_id {
+            Some(market_id) => market_id,
+            None => ctx.service::<Arc<IndexerStatus>>()?.market_id().to_owned(),
+        };
+        let market = markets
+            .get(&market_id)
+            .ok_or_else(|| async_graphql::Error::new(format!("Unknown market {}", market_id)))?;
+        audit(ctx, "resync", json!({ "marketId": market_id, "blocks": blocks }))?;
+        market.resyncs.request(blocks);
+        Ok(true)
+    }
+
+    /// Pauses indexing at the next block boundary while the API keeps serving
+    /// the last state, flagged as frozen.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn set_maintenance_mode(
+        &self,
+        ctx: &Context<'_>,
+        enabled: bool,
+    ) -> async_graphql::Result<bool> {
+        let kill_switches = ctx.service::<Arc<KillSwitches>>()?;
+        audit(ctx, "setMaintenanceMode", json!({ "enabled": enabled }))?;
+        kill_switches.set_maintenance(enabled);
+        Ok(enabled)
+    }
+
+    /// Lists or unlists a market on the public API without affecting its
+    /// indexing.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn set_market_listed(
+        &self,
+        ctx: &Context<'_>,
+        market_id: String,
+        listed: bool,
+    ) -> async_graphql::Result<bool> {
+        let kill_switches = ctx.service::<Arc<KillSwitches>>()?;
+        audit(ctx, "setMarketListed", json!({ "marketId": market_id, "listed": listed }))?;
+        kill_switches.set_listed(&market_id, listed);
+        Ok(listed)
+    }
+
+    /// Starts indexing another market without a restart, from `start_block`,
+    /// its `MARKET_START_BLOCKS` entry or else `CONTRACT_START_BLOCK`. The
+    /// market backfills in the background while the others keep streaming,
+    /// and `markets` shows it as syncing until it has caught up. Markets
+    /// added here are not kept across restarts.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn add_market(
+        &self,
+        ctx: &Context<'_>,
+        contract_id: String,
+        start_block: Option<i64>,
+    ) -> async_graphql::Result<Market> {
+        let markets = ctx.service::<Arc<MarketRegistry>>()?;
+        let kill_switches = ctx.service::<Arc<KillSwitches>>()?;
+        let contract_h256 = H256::from_str(&contract_id)
+            .map_err(|err| async_graphql::Error::new(err.to_string()))?;
+        audit(ctx, "addMarket", json!({ "contractId": contract_id, "startBlock": start_block }))?;
+
+        let market = MarketState::open(format!("{:?}", contract_h256))
+            .map_err(|err| async_graphql::Error::new(err.to_string()))?;
+        let market = MarketState {
+            start_block: start_block.or(market.start_block),
+            ..market
+        };
+        let market_id = market.market_id().to_string();
+        if !markets.insert(market.clone()) {
+            return Err(async_graphql::Error::new(format!(
+                "Market {} is already indexed",
+                market_id
+            )));
+        }
+        if let Err(err) = start_market(&mut vec![], markets, market.clone(), kill_switches).await {
+            markets.remove(&market_id).ok();
+            return Err(async_graphql::Error::new(err.to_string()));
+        }
+
+        Ok(Market::new(&market, kill_switches))
+    }
+
+    /// Stops indexing a market and drops its book. Returns false when the
+    /// market was not indexed. The default market cannot be removed.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn remove_market(
+        &self,
+        ctx: &Context<'_>,
+        contract_id: String,
+    ) -> async_graphql::Result<bool> {
+        let markets = ctx.service::<Arc<MarketRegistry>>()?;
+        audit(ctx, "removeMarket", json!({ "contractId": contract_id }))?;
+        let removed = markets
+            .remove(&contract_id)
+            .map_err(|err| async_graphql::Error::new(err.to_string()))?;
+        Ok(removed.is_some())
+    }
+
+    /// Names `address` and replaces its tags.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn set_address_label(
+        &self,
+        ctx: &Context<'_>,
+        address: String,
+        name: String,
+        tags: Option<Vec<String>>,
+    ) -> async_graphql::Result<AddressLabel> {
+        let labels = ctx.service::<Arc<AddressLabels>>()?;
+        let tags = tags.unwrap_or_default();
+        audit(
+            ctx,
+            "setAddressLabel",
+            json!({ "address": address, "name": name, "tags": tags }),
+        )?;
+        let label = address_labels::AddressLabel { name, tags };
+        labels
+            .set(address.clone(), label.clone())
+            .map_err(|err| async_graphql::Error::new(err.to_string()))?;
+        Ok(AddressLabel::new(address, label))
+    }
+
+    #[graphql(guard = "AdminGuard")]
+    pub async fn remove_address_label(
+        &self,
+        ctx: &Context<'_>,
+        address: String,
+    ) -> async_graphql::Result<bool> {
+        let labels = ctx.service::<Arc<AddressLabels>>()?;
+        audit(ctx, "removeAddressLabel", json!({ "address": address }))?;
+        labels
+            .remove(&address)
+            .map_err(|err| async_graphql::Error::new(err.to_string()))
+    }
+
+    /// Compares the local book with another instance's `GET /state` and
+    /// lists the orders that differ when the state hashes do not match.
+    #[graphql(guard = "AdminGuard")]
+    pub async fn reconcile_with_peer(
+        &self,
+        ctx: &Context<'_>,
+        peer_url: String,
+    ) -> async_graphql::Result<ReconciliationReport> {
+        let order_book = ctx.order_book()?;
+        let status = ctx.service::<Arc<IndexerStatus>>()?;
+        audit(ctx, "reconcileWithPeer", json!({ "peerUrl": peer_url }))?;
+        let reconciliation = reconcile_with_peer(order_book, status.market_id(), &peer_url)
+            .await
+            .map_err(|err| async_graphql::Error::new(err.to_string()))?;
+
+        Ok(ReconciliationReport {
+            matches: reconciliation.matches(),
+            local_hash: reconciliation.local_hash,
+            peer_hash: reconciliation.peer.hash,
+            peer_market_id: reconciliation.peer.market_id,
+            peer_block_number: reconciliation.peer.block_number,
+            missing_on_peer: reconciliation
+                .diff
+                .missing_on_peer
+                .into_iter()
+                .map(StateOrder::from)
+                .collect(),
+            missing_locally: reconciliation
+                .diff
+                .missing_locally
+                .into_iter()
+                .map(StateOrder::from)
+                .collect(),
+            different: reconciliation
+                .diff
+                .different
+                .into_iter()
+                .map(|(local, peer)| StateOrderMismatch {
+                    id: local.id.clone(),
+                    local: local.into(),
+                    peer: peer.into(),
+                })
+                .collect(),
+        })
+    }
+}
+
+pub type AppSchema = Schema<Query, Mutation, Subscription>;
+
+/// Closes a tracked subscription's books when the client goes away.
+struct TrackedSubscription {
+    usage: Arc<SubscriptionUsage>,
+    api_key: String,
+    name: &'static str,
+    opened_at: u64,
+}
+
+impl Drop for TrackedSubscription {
+    fn drop(&mut self) {
+        let now = Utc::now().timestamp_millis() as u64;
+        self.usage.record_closed(
+            &self.api_key,
+            self.name,
+            now.saturating_sub(self.opened_at),
+            now,
+        );
     }
 }
 
+/// Ends `inner` once the connection's token expired without a refresh.
+fn until_expired<T: Send + 'static>(
+    ctx: &Context<'_>,
+    mut inner: BoxStream<'static, T>,
+) -> BoxStream<'static, T> {
+    let Some(auth) = ctx.data_opt::<Arc<ConnectionAuth>>().cloned() else {
+        return inner;
+    };
+    Box::pin(stream! {
+        while let Some(item) = inner.next().await {
+            if auth.is_expired(Utc::now().timestamp_millis() as u64) {
+                break;
+            }
+            yield item;
+        }
+    })
+}
+
+/// Counts `inner`'s messages and their JSON size against the caller's API
+/// key, and its lifetime once dropped. Ends with the connection's session.
+fn tracked<T: Serialize + Send + 'static>(
+    ctx: &Context<'_>,
+    name: &'static str,
+    inner: BoxStream<'static, T>,
+) -> BoxStream<'static, T> {
+    let mut inner = until_expired(ctx, inner);
+    let Some(usage) = ctx.data_opt::<Arc<SubscriptionUsage>>().cloned() else {
+        return inner;
+    };
+    let api_key = ctx
+        .data_opt::<ApiKey>()
+        .and_then(|key| key.0.clone())
+        .unwrap_or_else(|| ANONYMOUS_KEY.to_owned());
+    let opened_at = Utc::now().timestamp_millis() as u64;
+    usage.record_opened(&api_key, name, opened_at);
+    let tracking = TrackedSubscription {
+        usage,
+        api_key,
+        name,
+        opened_at,
+    };
+
+    Box::pin(stream! {
+        let tracking = tracking;
+        while let Some(item) = inner.next().await {
+            let bytes = serde_json::to_vec(&item).map_or(0, |json| json.len() as u64);
+            let now = Utc::now().timestamp_millis() as u64;
+            tracking.usage.record_message(&tracking.api_key, tracking.name, bytes, now);
+            yield item;
+        }
+    })
+}
+
 pub struct Subscription;
 
 #[Subscription]
 impl Subscription {
+    /// Active orders of one side; with `watchlist`, only orders from
+    /// addresses tagged with it, which only admins may ask for. Like every
+    /// market data subscription, it ends once the market is hidden.
+    #[graphql(guard = "MarketVisibleGuard")]
     async fn active_orders(
         &self,
         ctx: &Context<'_>,
         order_type: String,
-    ) -> BoxStream<'static, Vec<Order>> {
-        let order_book = ctx.data::<Arc<OrderBook>>().unwrap().clone();  // Клонируем Arc<OrderBook>, чтобы он был 'static
-
-        Box::pin(stream! {
-            loop {
-                let orders = match order_type.as_str() {
-                    "Buy" => order_book.get_orders_in_range(0, u128::MAX, OrderType::Buy),
-                    "Sell" => order_book.get_orders_in_range(0, u128::MAX, OrderType::Sell),
+        watchlist: Option<String>,
+    ) -> async_graphql::Result<BoxStream<'static, Vec<Order>>> {
+        // Клонируем Arc<dyn Storage>, чтобы он был 'static
+        let order_book = ctx.order_book()?.clone();
+        let labels = ctx.service::<Arc<AddressLabels>>()?.clone();
+        if watchlist.is_some() && !is_admin(ctx) {
+            return Err("Admin authorization required".into());
+        }
+        let hidden = hidden_since(ctx)?;
+
+        Ok(tracked(ctx, "activeOrders", Box::pin(stream! {
+            while !hidden() {
+                let mut orders = match order_type.as_str() {
+                    "Buy" => order_book.get_range(0, u128::MAX, OrderType::Buy),
+                    "Sell" => order_book.get_range(0, u128::MAX, OrderType::Sell),
                     _ => vec![],
                 };
+                if let Some(tag) = &watchlist {
+                    orders.retain(|order| {
+                        labels
+                            .get(&order.user)
+                            .is_some_and(|label| label.has_tag(tag))
+                    });
+                }
 
-                yield orders.into_iter().map(|order| Order {
+                yield with_queue_positions(orders).map(|(queue_position, order)| Order {
                     id: order.id.clone(),
+                    market_id: order.market_id.clone(),
                     user: order.user.clone(),
                     asset: order.asset.clone(),
                     amount: order.amount.to_string(),
                     price: order.price.to_string(),
-                    timestamp: order.timestamp,
+                    timestamp: Timestamp(order.timestamp),
+                    raw_timestamp: order.raw_timestamp.map(Timestamp),
                     order_type: order_type.clone(),
                     status: order.status.map(|s| format!("{:?}", s)),
+                    priority: order.priority,
+                    queue_position,
+                    block_number: order.block_number,
                 }).collect();
 
                 time::sleep(Duration::from_secs(1)).await;
             }
-        })
+        })))
     }
 
+    /// `Low`, `Medium` or `High` by live events per second over the last
+    /// minute, sent on subscribe and on every change, so clients can slow
+    /// their rendering down during volume spikes.
+    #[graphql(guard = "MarketVisibleGuard")]
+    async fn activity_level(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<BoxStream<'static, String>> {
+        let status = ctx.service::<Arc<IndexerStatus>>()?.clone();
+        let hidden = hidden_since(ctx)?;
+
+        Ok(tracked(ctx, "activityLevel", Box::pin(stream! {
+            let mut last = None;
+            while !hidden() {
+                let level = status.activity_level(Utc::now().timestamp_millis() as u64);
+                if last != Some(level) {
+                    last = Some(level);
+                    yield format!("{:?}", level);
+                }
+
+                time::sleep(Duration::from_secs(1)).await;
+            }
+        })))
+    }
+
+    /// Every change to a resting order as it is applied, starting with an
+    /// `Add` for every resting order. Changes applied together arrive
+    /// together. A subscriber falling more than `ORDER_FEED_BUFFER` changes
+    /// behind, or outliving a reload of the book, sees the stream end and
+    /// resubscribes for a fresh snapshot.
+    #[graphql(guard = "MarketVisibleGuard.and(ScopeGuard::new(Scope::OrderFeed))")]
+    async fn order_feed(
+        &self,
+        ctx: &Context<'_>,
+    ) -> async_graphql::Result<BoxStream<'static, Vec<OrderFeedEvent>>> {
+        let config = ctx.service::<OrderFeedConfig>()?.clone();
+        let (mut updates, orders, sequence) = ctx.order_book()?.subscribe_orders();
+        let hidden = hidden_since(ctx)?;
+
+        Ok(tracked(ctx, "orderFeed", Box::pin(stream! {
+            let snapshot: Vec<OrderFeedEvent> = orders
+                .into_iter()
+                .map(|order| {
+                    let update = OrderUpdate { change: OrderChange::Add, sequence, order };
+                    OrderFeedEvent::new(&config, update)
+                })
+                .collect();
+            if !snapshot.is_empty() {
+                yield snapshot;
+            }
+            // Lagging or a replaced book ends the stream.
+            while let Ok(update) = updates.recv().await {
+                if hidden() {
+                    break;
+                }
+                let mut events = vec![OrderFeedEvent::new(&config, update)];
+                while let Ok(update) = updates.try_recv() {
+                    events.push(OrderFeedEvent::new(&config, update));
+                }
+                yield events;
+            }
+        })))
+    }
+
+    #[graphql(guard = "MarketVisibleGuard")]
     async fn trade_events(
         &self,
         ctx: &Context<'_>,
-    ) -> BoxStream<'static, Vec<TradeOrderEvent>> {
-        let order_book = ctx.data::<Arc<OrderBook>>().unwrap().clone();  // Клонируем Arc<OrderBook>
+    ) -> async_graphql::Result<BoxStream<'static, Vec<TradeOrderEvent>>> {
+        let order_book = ctx.order_book()?.clone();  // Клонируем Arc<dyn Storage>
+        let hidden = hidden_since(ctx)?;
 
-        Box::pin(stream! {
-            loop {
+        Ok(tracked(ctx, "tradeEvents", Box::pin(stream! {
+            while !hidden() {
                 let events = order_book.get_trade_events();
 
                 yield events.clone();
 
                 time::sleep(Duration::from_secs(1)).await;
             }
-        })
+        })))
     }
 }
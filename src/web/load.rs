@@ -0,0 +1,100 @@
+use chrono::Utc;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::config::env::ev_opt;
+
+const DEFAULT_DEPTH_LEVELS: usize = 50;
+const DEFAULT_REDUCED_DEPTH_LEVELS: usize = 10;
+const DEFAULT_HIGH_LOAD_RPS: u64 = 200;
+
+/// Requests per second, counted over whole one-second windows.
+#[derive(Default)]
+pub struct LoadMonitor {
+    current_second: AtomicU64,
+    current_count: AtomicU64,
+    last_rate: AtomicU64,
+}
+
+impl LoadMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_request(&self) {
+        let second = Utc::now().timestamp() as u64;
+        let previous = self.current_second.swap(second, Ordering::SeqCst);
+        if previous != second {
+            let count = self.current_count.swap(0, Ordering::SeqCst);
+            // A gap of more than a second means the last window was idle.
+            let rate = if previous + 1 == second { count } else { 0 };
+            self.last_rate.store(rate, Ordering::SeqCst);
+        }
+        self.current_count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Rate over the last complete second.
+    pub fn requests_per_second(&self) -> u64 {
+        let second = Utc::now().timestamp() as u64;
+        if self.current_second.load(Ordering::SeqCst) + 1 < second {
+            return 0;
+        }
+        self.last_rate.load(Ordering::SeqCst)
+    }
+}
+
+/// How many depth levels to return. Above `DEPTH_HIGH_LOAD_RPS` the limit
+/// drops from `DEPTH_DEFAULT_LEVELS` to `DEPTH_REDUCED_LEVELS` to keep
+/// latency bounded, and recovers once load falls back.
+pub struct DepthLimits {
+    default_levels: usize,
+    reduced_levels: usize,
+    high_load_rps: u64,
+}
+
+impl DepthLimits {
+    pub fn from_env() -> Self {
+        let parse = |key: &str, default: u64| {
+            ev_opt(key)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        DepthLimits {
+            default_levels: parse("DEPTH_DEFAULT_LEVELS", DEFAULT_DEPTH_LEVELS as u64) as usize,
+            reduced_levels: parse("DEPTH_REDUCED_LEVELS", DEFAULT_REDUCED_DEPTH_LEVELS as u64)
+                as usize,
+            high_load_rps: parse("DEPTH_HIGH_LOAD_RPS", DEFAULT_HIGH_LOAD_RPS),
+        }
+    }
+
+    /// Levels to serve for a request asking for `requested` (or the default),
+    /// and whether that is fewer than asked for because of load.
+    pub fn levels(&self, requested: Option<usize>, load: &LoadMonitor) -> (usize, bool) {
+        let wanted = requested.unwrap_or(self.default_levels).max(1);
+        if load.requests_per_second() > self.high_load_rps && wanted > self.reduced_levels {
+            (self.reduced_levels.max(1), true)
+        } else {
+            (wanted, false)
+        }
+    }
+}
+
+pub struct LoadTracking;
+
+#[rocket::async_trait]
+impl Fairing for LoadTracking {
+    fn info(&self) -> Info {
+        Info {
+            name: "Load tracking",
+            kind: Kind::Request,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        if let Some(load) = request.rocket().state::<Arc<LoadMonitor>>() {
+            load.record_request();
+        }
+    }
+}
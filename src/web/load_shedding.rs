@@ -0,0 +1,202 @@
+use async_graphql::extensions::{Extension, ExtensionContext, ExtensionFactory, NextParseQuery};
+use async_graphql::parser::types::{ExecutableDocument, OperationType, Selection, SelectionSet};
+use async_graphql::{ErrorExtensionValues, Name, ServerError, ServerResult, Variables};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::config::env::ev_opt;
+
+use super::context::RETRY_AFTER_SECS;
+use super::load::LoadMonitor;
+
+/// Root query fields serving the live book, kept up under any load.
+const LIVE_FIELDS: &[&str] = &[
+    "buyOrders",
+    "sellOrders",
+    "spread",
+    "depth",
+    "fairPrice",
+    "signedFairPrice",
+    "queuePosition",
+    "syncState",
+    "__typename",
+];
+
+/// Root query fields scanning history, shed first.
+const ANALYTICS_FIELDS: &[&str] = &[
+    "tradeEvents",
+    "tradeHistory",
+    "anomalies",
+    "traderStats",
+    "retention",
+    "traderFirstSeen",
+    "feeRevenue",
+    "dailyReport",
+    "volumeProfile",
+    "orderFlow",
+    "volatility",
+    "sizeDistribution",
+    "quoteChangeRate",
+    "exchangeStats",
+    "allOrders",
+    "auditLog",
+    "usage",
+];
+
+/// What a GraphQL request is for, from least to most important.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrafficClass {
+    Analytics,
+    Standard,
+    Live,
+}
+
+impl TrafficClass {
+    fn of_field(name: &str) -> Self {
+        if LIVE_FIELDS.contains(&name) {
+            TrafficClass::Live
+        } else if ANALYTICS_FIELDS.contains(&name) {
+            TrafficClass::Analytics
+        } else {
+            TrafficClass::Standard
+        }
+    }
+
+    /// The class of a whole document: that of its least important root
+    /// field, including root fields selected through inline fragments and
+    /// fragment spreads. Mutations and subscriptions are always live.
+    pub fn of_document(document: &ExecutableDocument) -> Self {
+        let mut class = TrafficClass::Live;
+        for (_, operation) in document.operations.iter() {
+            if operation.node.ty != OperationType::Query {
+                continue;
+            }
+            let mut visited = HashSet::new();
+            class = class.min(TrafficClass::of_selection_set(
+                document,
+                &operation.node.selection_set.node,
+                &mut visited,
+            ));
+        }
+        class
+    }
+
+    /// The document has not been validated yet, so fragment cycles are
+    /// possible; each named fragment is expanded once.
+    fn of_selection_set<'a>(
+        document: &'a ExecutableDocument,
+        selection_set: &'a SelectionSet,
+        visited: &mut HashSet<&'a Name>,
+    ) -> Self {
+        let mut class = TrafficClass::Live;
+        for selection in &selection_set.items {
+            let selected = match &selection.node {
+                Selection::Field(field) => TrafficClass::of_field(&field.node.name.node),
+                Selection::InlineFragment(fragment) => TrafficClass::of_selection_set(
+                    document,
+                    &fragment.node.selection_set.node,
+                    visited,
+                ),
+                Selection::FragmentSpread(spread) => {
+                    let name = &spread.node.fragment_name.node;
+                    match document.fragments.get(name) {
+                        Some(fragment) if visited.insert(name) => TrafficClass::of_selection_set(
+                            document,
+                            &fragment.node.selection_set.node,
+                            visited,
+                        ),
+                        // Already expanded, or unknown and rejected by
+                        // validation later.
+                        _ => TrafficClass::Live,
+                    }
+                }
+            };
+            class = class.min(selected);
+        }
+        class
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrafficClass::Analytics => "analytics",
+            TrafficClass::Standard => "standard",
+            TrafficClass::Live => "live",
+        }
+    }
+}
+
+/// Admission control under overload. Above `LOAD_SHED_ANALYTICS_RPS`
+/// requests per second, queries touching deep-history analytics are
+/// refused; above `LOAD_SHED_STANDARD_RPS` everything but the live book,
+/// mutations and subscriptions is. Either threshold unset disables that
+/// tier. Refused requests fail before execution with `code: LOAD_SHED`,
+/// their `trafficClass` and a `retryAfter` hint.
+pub struct LoadShedding {
+    analytics_rps: Option<u64>,
+    standard_rps: Option<u64>,
+    load: Arc<LoadMonitor>,
+}
+
+impl LoadShedding {
+    pub fn from_env(load: Arc<LoadMonitor>) -> Arc<Self> {
+        let parse = |key: &str| ev_opt(key).and_then(|value| value.parse().ok());
+        Arc::new(LoadShedding {
+            analytics_rps: parse("LOAD_SHED_ANALYTICS_RPS"),
+            standard_rps: parse("LOAD_SHED_STANDARD_RPS"),
+            load,
+        })
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.analytics_rps.is_some() || self.standard_rps.is_some()
+    }
+
+    pub fn admits(&self, class: TrafficClass) -> bool {
+        let threshold = match class {
+            TrafficClass::Analytics => self
+                .analytics_rps
+                .into_iter()
+                .chain(self.standard_rps)
+                .min(),
+            TrafficClass::Standard => self.standard_rps,
+            TrafficClass::Live => None,
+        };
+        threshold.is_none_or(|rps| self.load.requests_per_second() <= rps)
+    }
+}
+
+/// Registers [`LoadShedding`] with the schema.
+pub struct LoadSheddingExtension(pub Arc<LoadShedding>);
+
+impl ExtensionFactory for LoadSheddingExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(LoadSheddingExtension(Arc::clone(&self.0)))
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Extension for LoadSheddingExtension {
+    async fn parse_query(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        query: &str,
+        variables: &Variables,
+        next: NextParseQuery<'_>,
+    ) -> ServerResult<ExecutableDocument> {
+        let document = next.run(ctx, query, variables).await?;
+        if !self.0.is_enabled() {
+            return Ok(document);
+        }
+        let class = TrafficClass::of_document(&document);
+        if self.0.admits(class) {
+            return Ok(document);
+        }
+        let mut error = ServerError::new("Service overloaded, retry later", None);
+        let mut extensions = ErrorExtensionValues::default();
+        extensions.set("code", "LOAD_SHED");
+        extensions.set("trafficClass", class.as_str());
+        extensions.set("retryAfter", RETRY_AFTER_SECS);
+        error.extensions = Some(extensions);
+        Err(error)
+    }
+}
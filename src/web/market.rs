@@ -0,0 +1,51 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::storage::market_registry::{MarketRegistry, MarketState};
+
+pub const MARKET_HEADER: &str = "X-Market-Id";
+
+/// The market a request is about: the one named by `X-Market-Id`, or the
+/// default market when the header is absent. Unknown ids fail with 404.
+pub struct SelectedMarket(pub MarketState);
+
+impl Deref for SelectedMarket {
+    type Target = MarketState;
+
+    fn deref(&self) -> &MarketState {
+        &self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SelectedMarket {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(markets) = request.rocket().state::<Arc<MarketRegistry>>() else {
+            return Outcome::Error((Status::ServiceUnavailable, ()));
+        };
+        let market = match request.headers().get_one(MARKET_HEADER) {
+            Some(market_id) => markets.get(market_id),
+            None => Some(markets.default_market()),
+        };
+        match market {
+            Some(market) => Outcome::Success(SelectedMarket(market)),
+            None => Outcome::Error((Status::NotFound, ())),
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for SelectedMarket {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
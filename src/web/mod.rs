@@ -1,3 +1,24 @@
+pub mod auth;
+pub mod client_ip;
+pub mod context;
+pub mod fairings;
 pub mod graphql;
+pub mod load;
+pub mod load_shedding;
+pub mod market;
+pub mod oidc;
+pub mod order_feed;
+pub mod projection;
+pub mod query_traces;
+pub mod reconcile;
+pub mod request_id;
 pub mod routes;
+pub mod scopes;
 pub mod server;
+pub mod snapshots;
+pub mod subscriptions;
+pub mod timestamps;
+pub mod usage;
+pub mod visibility;
+pub mod warmup;
+pub mod ws_auth;
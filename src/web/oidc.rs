@@ -0,0 +1,184 @@
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, DecodingKey, Validation};
+use log::{info, warn};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+use crate::config::env::ev_opt;
+
+/// How long fetched signing keys are trusted before they are fetched again.
+const JWKS_CACHE_TTL_MS: u64 = 3_600_000;
+/// How often a token signed by an unknown key may trigger a fetch, so
+/// forged key ids can't make every request call the issuer.
+const JWKS_MIN_REFRESH_MS: u64 = 60_000;
+
+#[derive(Deserialize)]
+struct Discovery {
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    email: Option<String>,
+    /// Unix seconds; required, so tokens without one are rejected.
+    exp: u64,
+}
+
+/// The issuer's signing keys as last fetched.
+struct CachedKeys {
+    keys: JwkSet,
+    fetched_at: u64,
+}
+
+/// An accepted admin token.
+pub struct AdminToken {
+    pub identity: String,
+    /// When the token expires, in ms.
+    pub expires_at: u64,
+}
+
+/// Accepts `Authorization: Bearer` tokens issued by `OIDC_ISSUER_URL` for
+/// the admin surface. Tokens are JWTs checked locally against the issuer's
+/// published signing keys, fetched once and then refreshed hourly or when a
+/// token names a key not seen yet. They must carry the issuer, an
+/// unexpired `exp` and, with `OIDC_AUDIENCE` set, that audience. Only
+/// identities (email or subject) listed in `OIDC_ADMIN_IDENTITIES` are
+/// admitted.
+pub struct OidcVerifier {
+    issuer: String,
+    audience: Option<String>,
+    admins: HashSet<String>,
+    http: reqwest::Client,
+    jwks_uri: RwLock<Option<String>>,
+    keys: RwLock<Option<CachedKeys>>,
+}
+
+impl OidcVerifier {
+    pub fn from_env() -> Option<Self> {
+        let issuer = ev_opt("OIDC_ISSUER_URL")?;
+        let admins: HashSet<String> = ev_opt("OIDC_ADMIN_IDENTITIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|identity| !identity.is_empty())
+            .map(str::to_owned)
+            .collect();
+        if admins.is_empty() {
+            warn!("OIDC_ISSUER_URL is set but OIDC_ADMIN_IDENTITIES is empty, OIDC login disabled");
+            return None;
+        }
+        info!("Admin OIDC login enabled for issuer {}", issuer);
+
+        Some(OidcVerifier {
+            issuer: issuer.trim_end_matches('/').to_owned(),
+            audience: ev_opt("OIDC_AUDIENCE"),
+            admins,
+            http: reqwest::Client::new(),
+            jwks_uri: RwLock::new(None),
+            keys: RwLock::new(None),
+        })
+    }
+
+    /// The admin behind `token`, or `None` if the token doesn't verify or
+    /// the identity is not an admin.
+    pub async fn verify(&self, token: &str) -> Option<AdminToken> {
+        match self.decode(token).await {
+            Ok(claims) => [claims.email, Some(claims.sub)]
+                .into_iter()
+                .flatten()
+                .find(|identity| self.admins.contains(identity))
+                .map(|identity| AdminToken {
+                    identity,
+                    expires_at: claims.exp.saturating_mul(1000),
+                }),
+            Err(e) => {
+                warn!("OIDC token rejected: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn decode(&self, token: &str) -> Result<Claims, String> {
+        let header = decode_header(token).map_err(|e| e.to_string())?;
+        let kid = header.kid.ok_or("token names no signing key")?;
+        let key = self.key(&kid).await?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[self.issuer.clone(), format!("{}/", self.issuer)]);
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        decode::<Claims>(token, &key, &validation)
+            .map(|token| token.claims)
+            .map_err(|e| e.to_string())
+    }
+
+    /// The signing key `kid`, fetching the issuer's keys when they are
+    /// stale or don't include it.
+    async fn key(&self, kid: &str) -> Result<DecodingKey, String> {
+        let now = Utc::now().timestamp_millis() as u64;
+        match self.cached_key(kid, now) {
+            Some(Some(jwk)) => return jwk,
+            Some(None) => return Err(format!("unknown signing key {}", kid)),
+            None => {}
+        }
+
+        let keys = self.fetch_keys().await.map_err(|e| e.to_string())?;
+        let key = keys
+            .find(kid)
+            .map(|jwk| DecodingKey::from_jwk(jwk).map_err(|e| e.to_string()));
+        *self.keys.write().unwrap() = Some(CachedKeys {
+            keys,
+            fetched_at: now,
+        });
+        key.unwrap_or_else(|| Err(format!("unknown signing key {}", kid)))
+    }
+
+    /// The cached key `kid`, or `Some(None)` for a key the fresh cache lacks
+    /// that was fetched too recently to fetch again; `None` means fetch.
+    fn cached_key(&self, kid: &str, now: u64) -> Option<Option<Result<DecodingKey, String>>> {
+        let cached = self.keys.read().unwrap();
+        let cached = cached.as_ref()?;
+        let age = now.saturating_sub(cached.fetched_at);
+        if age >= JWKS_CACHE_TTL_MS {
+            return None;
+        }
+        match cached.keys.find(kid) {
+            Some(jwk) => Some(Some(DecodingKey::from_jwk(jwk).map_err(|e| e.to_string()))),
+            None if age < JWKS_MIN_REFRESH_MS => Some(None),
+            None => None,
+        }
+    }
+
+    async fn fetch_keys(&self) -> Result<JwkSet, reqwest::Error> {
+        let jwks_uri = self.jwks_uri().await?;
+        self.http
+            .get(jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+    }
+
+    async fn jwks_uri(&self) -> Result<String, reqwest::Error> {
+        if let Some(jwks_uri) = self.jwks_uri.read().unwrap().clone() {
+            return Ok(jwks_uri);
+        }
+
+        let discovery: Discovery = self
+            .http
+            .get(format!("{}/.well-known/openid-configuration", self.issuer))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        *self.jwks_uri.write().unwrap() = Some(discovery.jwks_uri.clone());
+        Ok(discovery.jwks_uri)
+    }
+}
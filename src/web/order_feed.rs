@@ -0,0 +1,78 @@
+use hmac::{Hmac, Mac};
+use log::warn;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::config::env::ev_opt;
+use crate::indexer::spot_order::SpotOrder;
+
+const DEFAULT_FEED_BUFFER: usize = 1024;
+
+/// Why a resting order changed. `Revert` is a change undone by a reorg
+/// taking the order off the book; the undone removal of an order comes back
+/// as an `Add`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderChange {
+    Add,
+    Modify,
+    Fill,
+    Cancel,
+    Revert,
+}
+
+/// A change to a resting order as the book applied it, with the order as it
+/// is now or, when taken off the book, as last seen. `sequence` numbers the
+/// updates of one book without gaps.
+#[derive(Debug, Clone)]
+pub struct OrderUpdate {
+    pub change: OrderChange,
+    pub sequence: u64,
+    pub order: SpotOrder,
+}
+
+/// How many updates a subscriber may fall behind, `ORDER_FEED_BUFFER`
+/// (default 1024), before it is dropped and has to resubscribe.
+pub fn feed_buffer() -> usize {
+    ev_opt("ORDER_FEED_BUFFER")
+        .and_then(|buffer| buffer.parse().ok())
+        .filter(|&buffer| buffer > 0)
+        .unwrap_or(DEFAULT_FEED_BUFFER)
+}
+
+/// Settings for the per-order feed. With `ORDER_FEED_MASK_ADDRESSES`
+/// (default true) owners are replaced by an HMAC of their address keyed by
+/// `ORDER_FEED_MASK_KEY`, so consumers can still tell one trader's orders
+/// apart without learning who placed them, nor hash known addresses to find
+/// them. Without a key one is generated, and masks change on restart.
+#[derive(Clone)]
+pub struct OrderFeedConfig {
+    mask_key: Option<Vec<u8>>,
+}
+
+impl OrderFeedConfig {
+    pub fn from_env() -> Self {
+        if ev_opt("ORDER_FEED_MASK_ADDRESSES").as_deref() == Some("false") {
+            return OrderFeedConfig { mask_key: None };
+        }
+        let mask_key = match ev_opt("ORDER_FEED_MASK_KEY") {
+            Some(key) => key.into_bytes(),
+            None => {
+                warn!("ORDER_FEED_MASK_KEY is unset, order feed masks will change on restart");
+                Uuid::new_v4().as_bytes().to_vec()
+            }
+        };
+        OrderFeedConfig {
+            mask_key: Some(mask_key),
+        }
+    }
+
+    pub fn user(&self, address: &str) -> String {
+        let Some(mask_key) = &self.mask_key else {
+            return address.to_owned();
+        };
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(mask_key).expect("HMAC takes keys of any length");
+        mac.update(address.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
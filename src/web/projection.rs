@@ -0,0 +1,419 @@
+use log::error;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, content, Responder};
+use rocket::serde::json::Json;
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::okapi::openapi3::Responses;
+use rocket_okapi::response::OpenApiResponderInner;
+use rocket_okapi::JsonSchema;
+use serde::ser::{
+    Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+use std::collections::HashMap;
+
+use super::timestamps::TimestampFormat;
+
+/// Fields picked with a REST `fields=` parameter, e.g.
+/// `fields=bids.price,asks.price`. Dotted paths select nested struct fields;
+/// lists are transparent, so `bids.price` is the price of every bid. Naming
+/// a field keeps everything under it. Maps are not filtered by key.
+#[derive(Debug, Default)]
+pub struct FieldSelection {
+    /// Keep this whole subtree.
+    all: bool,
+    children: HashMap<String, FieldSelection>,
+}
+
+impl FieldSelection {
+    /// `None` when `fields` selects nothing, meaning the full response.
+    pub fn parse(fields: &str) -> Option<Self> {
+        let mut root = FieldSelection::default();
+        for path in fields.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let mut node = &mut root;
+            for name in path.split('.') {
+                node = node.children.entry(name.to_owned()).or_default();
+            }
+            node.all = true;
+        }
+        (!root.children.is_empty()).then_some(root)
+    }
+}
+
+/// JSON response trimmed to a [`FieldSelection`]. Unselected fields are
+/// skipped while serializing, so they are never rendered.
+pub struct ProjectedJson<T> {
+    value: T,
+    fields: Option<FieldSelection>,
+}
+
+impl<T> ProjectedJson<T> {
+    pub fn new(value: T, fields: Option<&str>) -> Self {
+        ProjectedJson {
+            value,
+            fields: fields.and_then(FieldSelection::parse),
+        }
+    }
+}
+
+impl<T: Serialize> ProjectedJson<T> {
+    fn to_json(&self) -> serde_json::Result<String> {
+        match &self.fields {
+            Some(fields) => serde_json::to_string(&Project {
+                value: &self.value,
+                fields,
+            }),
+            None => serde_json::to_string(&self.value),
+        }
+    }
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for ProjectedJson<T> {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let format = TimestampFormat::from_headers_and_query(request);
+        let json = format.serializing(|| self.to_json()).map_err(|e| {
+            error!("Failed to serialize response: {}", e);
+            Status::InternalServerError
+        })?;
+        content::RawJson(json).respond_to(request)
+    }
+}
+
+impl<T: Serialize + JsonSchema> OpenApiResponderInner for ProjectedJson<T> {
+    fn responses(gen: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        Json::<T>::responses(gen)
+    }
+}
+
+/// `value` serialized through [`Projector`].
+struct Project<'a, T: ?Sized> {
+    value: &'a T,
+    fields: &'a FieldSelection,
+}
+
+impl<T: ?Sized + Serialize> Serialize for Project<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(Projector {
+            inner: serializer,
+            fields: self.fields,
+        })
+    }
+}
+
+/// Forwards everything to `inner`, dropping unselected struct fields.
+struct Projector<'a, S> {
+    inner: S,
+    fields: &'a FieldSelection,
+}
+
+macro_rules! forward {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method(self, value: $ty) -> Result<S::Ok, S::Error> {
+                self.inner.$method(value)
+            }
+        )*
+    };
+}
+
+impl<'a, S: Serializer> Serializer for Projector<'a, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Elements<'a, S::SerializeSeq>;
+    type SerializeTuple = Elements<'a, S::SerializeTuple>;
+    type SerializeTupleStruct = Elements<'a, S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Elements<'a, S::SerializeTupleVariant>;
+    type SerializeMap = Elements<'a, S::SerializeMap>;
+    type SerializeStruct = Fields<'a, S::SerializeStruct>;
+    type SerializeStructVariant = Fields<'a, S::SerializeStructVariant>;
+
+    forward!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_unit_struct(&'static str),
+    );
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let value = Project {
+            value,
+            fields: self.fields,
+        };
+        self.inner.serialize_some(&value)
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = Project {
+            value,
+            fields: self.fields,
+        };
+        self.inner.serialize_newtype_struct(name, &value)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = Project {
+            value,
+            fields: self.fields,
+        };
+        self.inner
+            .serialize_newtype_variant(name, index, variant, &value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Ok(Elements {
+            inner: self.inner.serialize_seq(len)?,
+            fields: self.fields,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        Ok(Elements {
+            inner: self.inner.serialize_tuple(len)?,
+            fields: self.fields,
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        Ok(Elements {
+            inner: self.inner.serialize_tuple_struct(name, len)?,
+            fields: self.fields,
+        })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        Ok(Elements {
+            inner: self
+                .inner
+                .serialize_tuple_variant(name, index, variant, len)?,
+            fields: self.fields,
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        Ok(Elements {
+            inner: self.inner.serialize_map(len)?,
+            fields: self.fields,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Ok(Fields {
+            inner: self.inner.serialize_struct(name, len)?,
+            fields: self.fields,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        Ok(Fields {
+            inner: self
+                .inner
+                .serialize_struct_variant(name, index, variant, len)?,
+            fields: self.fields,
+        })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+/// Sequence, tuple and map contents, projected with the same selection as
+/// their container.
+struct Elements<'a, S> {
+    inner: S,
+    fields: &'a FieldSelection,
+}
+
+impl<S: SerializeSeq> SerializeSeq for Elements<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner.serialize_element(&Project {
+            value,
+            fields: self.fields,
+        })
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeTuple> SerializeTuple for Elements<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner.serialize_element(&Project {
+            value,
+            fields: self.fields,
+        })
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeTupleStruct> SerializeTupleStruct for Elements<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner.serialize_field(&Project {
+            value,
+            fields: self.fields,
+        })
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeTupleVariant> SerializeTupleVariant for Elements<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner.serialize_field(&Project {
+            value,
+            fields: self.fields,
+        })
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeMap> SerializeMap for Elements<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), S::Error> {
+        self.inner.serialize_key(key)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner.serialize_value(&Project {
+            value,
+            fields: self.fields,
+        })
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+/// Struct fields: unselected ones are skipped, fields inside a selected
+/// subtree are written as they are.
+struct Fields<'a, S> {
+    inner: S,
+    fields: &'a FieldSelection,
+}
+
+impl<S: SerializeStruct> SerializeStruct for Fields<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        match self.fields.children.get(key) {
+            None => self.inner.skip_field(key),
+            Some(fields) if fields.all => self.inner.serialize_field(key, value),
+            Some(fields) => self.inner.serialize_field(key, &Project { value, fields }),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+impl<S: SerializeStructVariant> SerializeStructVariant for Fields<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        match self.fields.children.get(key) {
+            None => self.inner.skip_field(key),
+            Some(fields) if fields.all => self.inner.serialize_field(key, value),
+            Some(fields) => self.inner.serialize_field(key, &Project { value, fields }),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
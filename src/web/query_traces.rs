@@ -0,0 +1,255 @@
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextExecute, NextPrepareRequest, NextRequest,
+    NextResolve, ResolveInfo,
+};
+use async_graphql::{Request, Response, ServerResult, Value};
+use chrono::Utc;
+use log::{info, warn};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::env::ev_opt;
+use crate::web::request_id::RequestId;
+
+const DEFAULT_SLOW_QUERY_BUFFER: usize = 100;
+/// Longer queries are cut, so a huge request can't bloat the buffer.
+const MAX_QUERY_CHARS: usize = 4096;
+/// Resolvers timed per request; a query fanning out over a long list would
+/// otherwise grow its trace without bound. Later resolvers are only counted.
+const MAX_RESOLVERS_PER_TRACE: usize = 1000;
+
+/// Time spent in one field resolver, relative to the start of the request.
+#[derive(Debug, Clone)]
+pub struct ResolverTiming {
+    pub path: String,
+    pub started_us: u64,
+    pub duration_us: u64,
+}
+
+/// One GraphQL request with the timing of every resolver it ran.
+#[derive(Debug, Clone)]
+pub struct QueryTrace {
+    pub request_id: Option<String>,
+    pub operation_name: Option<String>,
+    pub query: String,
+    /// When the request finished (ms).
+    pub timestamp: u64,
+    pub duration_ms: u64,
+    pub errors: usize,
+    pub resolvers: Vec<ResolverTiming>,
+    /// Resolvers that ran past `MAX_RESOLVERS_PER_TRACE` and were not timed.
+    pub resolvers_dropped: usize,
+}
+
+/// Per-request tracing of GraphQL resolvers.
+///
+/// `TRACE_SAMPLE_RATE` (0 to 1, default 0) is the share of requests whose
+/// resolver timings are logged. Independently, every request that takes
+/// longer than `SLOW_QUERY_MS` is traced in full and kept in a ring buffer
+/// of the last `SLOW_QUERY_BUFFER` (default 100) slow queries for the admin
+/// API. Resolvers are only timed while either is enabled, since whether a
+/// request is slow is known only once it has finished.
+pub struct QueryTracing {
+    /// Every how many requests one is sampled; 0 when sampling is off.
+    sample_every: u64,
+    slow_threshold: Option<Duration>,
+    capacity: usize,
+    requests: AtomicU64,
+    slow: Mutex<VecDeque<QueryTrace>>,
+}
+
+impl QueryTracing {
+    pub fn from_env() -> Self {
+        let sample_rate = ev_opt("TRACE_SAMPLE_RATE")
+            .and_then(|value| value.parse::<f64>().ok())
+            .unwrap_or(0.0)
+            .clamp(0.0, 1.0);
+        let sample_every = if sample_rate > 0.0 {
+            (1.0 / sample_rate).round() as u64
+        } else {
+            0
+        };
+        let slow_threshold = ev_opt("SLOW_QUERY_MS")
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_millis);
+        let capacity = ev_opt("SLOW_QUERY_BUFFER")
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_SLOW_QUERY_BUFFER)
+            .max(1);
+        QueryTracing {
+            sample_every,
+            slow_threshold,
+            capacity,
+            requests: AtomicU64::new(0),
+            slow: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Samples every n-th request rather than at random, so the rate holds
+    /// over any window of requests.
+    fn sample(&self) -> bool {
+        self.sample_every > 0
+            && self.requests.fetch_add(1, Ordering::Relaxed) % self.sample_every == 0
+    }
+
+    fn is_slow(&self, elapsed: Duration) -> bool {
+        self.slow_threshold
+            .is_some_and(|threshold| elapsed >= threshold)
+    }
+
+    fn record_slow(&self, trace: QueryTrace) {
+        let mut slow = self.slow.lock().unwrap();
+        if slow.len() == self.capacity {
+            slow.pop_front();
+        }
+        slow.push_back(trace);
+    }
+
+    /// Captured slow queries, newest first.
+    pub fn slow_queries(&self, limit: usize) -> Vec<QueryTrace> {
+        self.slow
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Registers [`QueryTracing`] with the schema.
+pub struct QueryTracingExtension(pub Arc<QueryTracing>);
+
+impl ExtensionFactory for QueryTracingExtension {
+    fn create(&self) -> Arc<dyn Extension> {
+        let sampled = self.0.sample();
+        Arc::new(RequestTrace {
+            active: sampled || self.0.slow_threshold.is_some(),
+            sampled,
+            tracing: Arc::clone(&self.0),
+            started: Instant::now(),
+            query: Mutex::new(String::new()),
+            operation_name: Mutex::new(None),
+            resolvers: Mutex::new(vec![]),
+            resolvers_dropped: AtomicUsize::new(0),
+        })
+    }
+}
+
+struct RequestTrace {
+    /// Whether resolvers are timed at all.
+    active: bool,
+    sampled: bool,
+    tracing: Arc<QueryTracing>,
+    started: Instant,
+    query: Mutex<String>,
+    operation_name: Mutex<Option<String>>,
+    resolvers: Mutex<Vec<ResolverTiming>>,
+    resolvers_dropped: AtomicUsize,
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Extension for RequestTrace {
+    async fn request(&self, ctx: &ExtensionContext<'_>, next: NextRequest<'_>) -> Response {
+        let response = next.run(ctx).await;
+        if !self.active {
+            return response;
+        }
+        let elapsed = self.started.elapsed();
+        let slow = self.tracing.is_slow(elapsed);
+        if !slow && !self.sampled {
+            return response;
+        }
+
+        let trace = QueryTrace {
+            request_id: ctx.data_opt::<RequestId>().map(|id| id.0.clone()),
+            operation_name: self.operation_name.lock().unwrap().clone(),
+            query: std::mem::take(&mut *self.query.lock().unwrap()),
+            timestamp: Utc::now().timestamp_millis() as u64,
+            duration_ms: elapsed.as_millis() as u64,
+            errors: response.errors.len(),
+            resolvers: std::mem::take(&mut *self.resolvers.lock().unwrap()),
+            resolvers_dropped: self.resolvers_dropped.load(Ordering::Relaxed),
+        };
+        if self.sampled {
+            info!(
+                "Traced GraphQL request {} ({}): {} ms, {} resolvers, slowest {}",
+                trace.request_id.as_deref().unwrap_or("-"),
+                trace.operation_name.as_deref().unwrap_or("anonymous"),
+                trace.duration_ms,
+                trace.resolvers.len() + trace.resolvers_dropped,
+                slowest_resolver(&trace.resolvers)
+            );
+        }
+        if slow {
+            warn!(
+                "Slow GraphQL request {} ({}): {} ms, slowest {}",
+                trace.request_id.as_deref().unwrap_or("-"),
+                trace.operation_name.as_deref().unwrap_or("anonymous"),
+                trace.duration_ms,
+                slowest_resolver(&trace.resolvers)
+            );
+            self.tracing.record_slow(trace);
+        }
+        response
+    }
+
+    async fn prepare_request(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        request: Request,
+        next: NextPrepareRequest<'_>,
+    ) -> ServerResult<Request> {
+        if self.active {
+            *self.query.lock().unwrap() = request.query.chars().take(MAX_QUERY_CHARS).collect();
+        }
+        next.run(ctx, request).await
+    }
+
+    async fn execute(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        operation_name: Option<&str>,
+        next: NextExecute<'_>,
+    ) -> Response {
+        if self.active {
+            *self.operation_name.lock().unwrap() = operation_name.map(str::to_owned);
+        }
+        next.run(ctx, operation_name).await
+    }
+
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        if !self.active {
+            return next.run(ctx, info).await;
+        }
+        let path = info.path_node.to_string();
+        let started = Instant::now();
+        let result = next.run(ctx, info).await;
+        let mut resolvers = self.resolvers.lock().unwrap();
+        if resolvers.len() < MAX_RESOLVERS_PER_TRACE {
+            resolvers.push(ResolverTiming {
+                path,
+                started_us: started.duration_since(self.started).as_micros() as u64,
+                duration_us: started.elapsed().as_micros() as u64,
+            });
+        } else {
+            self.resolvers_dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+fn slowest_resolver(resolvers: &[ResolverTiming]) -> String {
+    match resolvers.iter().max_by_key(|timing| timing.duration_us) {
+        Some(timing) => format!("{} at {} us", timing.path, timing.duration_us),
+        None => "none".to_owned(),
+    }
+}
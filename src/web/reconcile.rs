@@ -0,0 +1,51 @@
+use crate::error::Error;
+use crate::storage::backend::Storage;
+use crate::storage::state::{diff_entries, hash_entries, StateDiff};
+
+use super::market::MARKET_HEADER;
+use super::routes::StateResponse;
+
+pub struct Reconciliation {
+    pub local_hash: String,
+    pub peer: StateResponse,
+    pub diff: StateDiff,
+}
+
+impl Reconciliation {
+    pub fn matches(&self) -> bool {
+        self.local_hash == self.peer.hash
+    }
+}
+
+/// Fetches `GET {peer_url}/state` for the same market from another instance
+/// and compares it with the local book. The diff is only computed when the
+/// hashes disagree.
+pub async fn reconcile_with_peer(
+    order_book: &dyn Storage,
+    market_id: &str,
+    peer_url: &str,
+) -> Result<Reconciliation, Error> {
+    let url = format!("{}/state", peer_url.trim_end_matches('/'));
+    let peer: StateResponse = reqwest::Client::new()
+        .get(&url)
+        .header(MARKET_HEADER, market_id)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let local = order_book.state_entries();
+    let local_hash = hash_entries(&local);
+    let diff = if local_hash == peer.hash {
+        StateDiff::default()
+    } else {
+        diff_entries(&local, &peer.orders)
+    };
+
+    Ok(Reconciliation {
+        local_hash,
+        peer,
+        diff,
+    })
+}
@@ -0,0 +1,89 @@
+use async_graphql::{BatchResponse, Response as GraphQLResult};
+use log::{info, warn};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Correlation id of the current request: the caller's `X-Request-Id` if it
+/// sent a usable one, otherwise a fresh UUID.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    fn from_header(value: Option<&str>) -> Self {
+        match value {
+            Some(id)
+                if !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LEN
+                    && id.chars().all(|c| c.is_ascii_graphic()) =>
+            {
+                RequestId(id.to_owned())
+            }
+            _ => RequestId(Uuid::new_v4().to_string()),
+        }
+    }
+
+    /// Adds `requestId` to the extensions of every error in `response` and
+    /// logs them, so a failure a user reports can be found in the logs.
+    pub fn tag_graphql_errors(&self, response: &mut BatchResponse) {
+        let responses: Vec<&mut GraphQLResult> = match response {
+            BatchResponse::Single(response) => vec![response],
+            BatchResponse::Batch(responses) => responses.iter_mut().collect(),
+        };
+
+        for error in responses.into_iter().flat_map(|r| r.errors.iter_mut()) {
+            warn!("[{}] GraphQL error: {}", self.0, error.message);
+            error
+                .extensions
+                .get_or_insert_with(Default::default)
+                .set("requestId", self.0.clone());
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let request_id = request
+            .local_cache(|| RequestId::from_header(request.headers().get_one(REQUEST_ID_HEADER)));
+        Outcome::Success(request_id.clone())
+    }
+}
+
+/// Assigns a [`RequestId`] to every request, echoes it in the response
+/// headers and logs the request outcome under it.
+pub struct RequestIdFairing;
+
+#[rocket::async_trait]
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request id",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let request_id = RequestId::from_header(request.headers().get_one(REQUEST_ID_HEADER));
+        request.local_cache(|| request_id);
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let request_id = request
+            .local_cache(|| RequestId::from_header(request.headers().get_one(REQUEST_ID_HEADER)));
+        info!(
+            "[{}] {} {} -> {}",
+            request_id.0,
+            request.method(),
+            request.uri(),
+            response.status()
+        );
+        response.set_raw_header(REQUEST_ID_HEADER, request_id.0.clone());
+    }
+}
@@ -2,7 +2,6 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
-use async_graphql::{EmptyMutation, EmptySubscription, Schema};
 use async_graphql_rocket::{GraphQLRequest, GraphQLResponse};
 use log::warn;
 use rocket::response::content;
@@ -10,12 +9,26 @@ use rocket::serde::json::Json;
 use rocket::{get, routes, Route, State};
 use rocket_okapi::swagger_ui::SwaggerUIConfig;
 use rocket_okapi::{openapi, openapi_get_routes, JsonSchema};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::indexer::spot_order::{OrderType, SpotOrder};
-use crate::storage::order_book::OrderBook;
+use crate::metrics::metrics;
+use crate::oracle::price_signer::PriceSigner;
+use crate::storage::market_registry::MarketRegistry;
+use crate::storage::state::{hash_entries, StateEntry};
 
-use super::graphql::Query;
+use super::auth::AdminCredentials;
+use super::client_ip::ClientIp;
+use super::graphql::AppSchema;
+use super::load::{DepthLimits, LoadMonitor};
+use super::market::SelectedMarket;
+use super::projection::ProjectedJson;
+use super::request_id::RequestId;
+use super::scopes::ApiKey;
+use super::snapshots::ResponseSnapshots;
+use super::timestamps::{serialize_ms, TimestampFormat};
+use super::visibility::MarketVisible;
+use super::warmup::WarmupGate;
 
 #[derive(Serialize, JsonSchema)]
 pub struct OrdersResponse {
@@ -29,27 +42,84 @@ pub struct SpreadResponse {
     pub spread: Option<i128>,
 }
 
+#[derive(Serialize, JsonSchema)]
+pub struct SignedPriceResponse {
+    pub price: u128,
+    #[serde(serialize_with = "serialize_ms")]
+    pub timestamp: u64,
+    pub levels: usize,
+    pub payload: String,
+    pub signature: String,
+    pub public_key: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct StateResponse {
+    pub market_id: String,
+    pub block_number: i64,
+    pub hash: String,
+    pub orders: Vec<StateEntry>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DepthLevel {
+    pub price: u128,
+    pub size: u128,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct DepthResponse {
+    /// Book sequence this depth was read at. Equal `epoch` and `sequence`
+    /// mean the same book state.
+    pub sequence: u64,
+    /// Book creation time in ms; changes on restart, resetting `sequence`.
+    pub epoch: u64,
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+    pub levels: usize,
+    /// Fewer levels than requested were returned because of high load.
+    pub truncated: bool,
+}
+
 #[openapi]
-#[get("/orders/buy")]
-pub fn get_buy_orders(order_book: &State<Arc<OrderBook>>) -> Json<OrdersResponse> {
-    let buy_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Buy);
-    Json(OrdersResponse { orders: buy_orders })
+#[get("/orders/buy?<fields>")]
+pub fn get_buy_orders(
+    market: SelectedMarket,
+    fields: Option<&str>,
+    _visible: MarketVisible,
+) -> ProjectedJson<OrdersResponse> {
+    let order_book = &market.order_book;
+    let buy_orders = order_book.get_range(0, u128::MAX, OrderType::Buy);
+    ProjectedJson::new(OrdersResponse { orders: buy_orders }, fields)
 }
 
 #[openapi]
-#[get("/orders/sell")]
-pub fn get_sell_orders(order_book: &State<Arc<OrderBook>>) -> Json<OrdersResponse> {
-    let sell_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Sell);
-    Json(OrdersResponse {
-        orders: sell_orders,
-    })
+#[get("/orders/sell?<fields>")]
+pub fn get_sell_orders(
+    market: SelectedMarket,
+    fields: Option<&str>,
+    _visible: MarketVisible,
+) -> ProjectedJson<OrdersResponse> {
+    let order_book = &market.order_book;
+    let sell_orders = order_book.get_range(0, u128::MAX, OrderType::Sell);
+    ProjectedJson::new(
+        OrdersResponse {
+            orders: sell_orders,
+        },
+        fields,
+    )
 }
 
 #[openapi]
-#[get("/spread")]
-pub fn get_indexer_spread(order_book: &State<Arc<OrderBook>>) -> Json<SpreadResponse> {
-    let buy_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Buy);
-    let sell_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Sell);
+#[get("/spread?<fields>")]
+pub fn get_indexer_spread(
+    market: SelectedMarket,
+    fields: Option<&str>,
+    _visible: MarketVisible,
+) -> ProjectedJson<SpreadResponse> {
+    let order_book = &market.order_book;
+    let buy_orders = order_book.get_range(0, u128::MAX, OrderType::Buy);
+    let sell_orders = order_book.get_range(0, u128::MAX, OrderType::Sell);
 
     let max_buy_price = buy_orders.iter().map(|o| o.price).max();
     let min_sell_price = sell_orders.iter().map(|o| o.price).min();
@@ -60,18 +130,25 @@ pub fn get_indexer_spread(order_book: &State<Arc<OrderBook>>) -> Json<SpreadResp
         None
     };
 
-    Json(SpreadResponse {
-        buy: max_buy_price,
-        sell: min_sell_price,
-        spread,
-    })
+    ProjectedJson::new(
+        SpreadResponse {
+            buy: max_buy_price,
+            sell: min_sell_price,
+            spread,
+        },
+        fields,
+    )
 }
 
 #[openapi]
 #[get("/orders/count")]
-pub fn get_orders_count(order_book: &State<Arc<OrderBook>>) -> Json<HashMap<String, usize>> {
-    let buy_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Buy);
-    let sell_orders = order_book.get_orders_in_range(0, u128::MAX, OrderType::Sell);
+pub fn get_orders_count(
+    market: SelectedMarket,
+    _visible: MarketVisible,
+) -> Json<HashMap<String, usize>> {
+    let order_book = &market.order_book;
+    let buy_orders = order_book.get_range(0, u128::MAX, OrderType::Buy);
+    let sell_orders = order_book.get_range(0, u128::MAX, OrderType::Sell);
 
     let mut counts = HashMap::new();
     counts.insert("buy_orders".to_string(), buy_orders.len());
@@ -80,12 +157,173 @@ pub fn get_orders_count(order_book: &State<Arc<OrderBook>>) -> Json<HashMap<Stri
     Json(counts)
 }
 
+/// Fair price signed with the configured `PRICE_SIGNER_KEY`. Returns 404 if
+/// signing is disabled or either side of the book is empty.
+#[openapi]
+#[get("/price/signed?<fields>")]
+pub fn get_signed_price(
+    market: SelectedMarket,
+    price_signer: &State<Option<Arc<PriceSigner>>>,
+    fields: Option<&str>,
+    _visible: MarketVisible,
+) -> Option<ProjectedJson<SignedPriceResponse>> {
+    let signed = price_signer
+        .inner()
+        .as_ref()?
+        .sign_fair_price(&market.order_book)?;
+    Some(ProjectedJson::new(
+        SignedPriceResponse {
+            price: signed.price,
+            timestamp: signed.timestamp,
+            levels: signed.levels,
+            payload: signed.payload,
+            signature: signed.signature,
+            public_key: signed.public_key,
+        },
+        fields,
+    ))
+}
+
+/// Aggregated depth per side, best price first. Under high load the level
+/// count is reduced and `truncated` is set. `fields` trims the response,
+/// e.g. `fields=bids.price,asks.price`.
+#[openapi]
+#[get("/depth?<levels>&<fields>")]
+pub fn get_depth(
+    market: SelectedMarket,
+    depth_limits: &State<DepthLimits>,
+    load: &State<Arc<LoadMonitor>>,
+    levels: Option<usize>,
+    fields: Option<&str>,
+    _visible: MarketVisible,
+) -> ProjectedJson<DepthResponse> {
+    let (levels, truncated) = depth_limits.levels(levels, load);
+    let sequence = market.order_book.version();
+    let to_levels = |order_type: OrderType| -> Vec<DepthLevel> {
+        market
+            .order_book
+            .top_levels(order_type, levels)
+            .into_iter()
+            .map(|level| DepthLevel {
+                price: level.price,
+                size: level.size,
+            })
+            .collect()
+    };
+
+    ProjectedJson::new(
+        DepthResponse {
+            sequence,
+            epoch: market.order_book.epoch(),
+            bids: to_levels(OrderType::Buy),
+            asks: to_levels(OrderType::Sell),
+            levels,
+            truncated,
+        },
+        fields,
+    )
+}
+
+/// Full active book with its state hash, used by other instances to
+/// reconcile against this one.
+#[openapi]
+#[get("/state")]
+pub fn get_state(market: SelectedMarket, _visible: MarketVisible) -> Json<StateResponse> {
+    let orders = market.order_book.state_entries();
+    Json(StateResponse {
+        market_id: market.market_id().to_owned(),
+        block_number: market.status.last_processed_block(),
+        hash: hash_entries(&orders),
+        orders,
+    })
+}
+
 #[rocket::post("/graphql", data = "<request>")]
 pub async fn graphql_handler(
-    schema: &State<Schema<Query, EmptyMutation, EmptySubscription>>,
+    schema: &State<AppSchema>,
+    admin_credentials: AdminCredentials,
+    request_id: RequestId,
+    api_key: ApiKey,
+    client_ip: ClientIp,
+    market: SelectedMarket,
+    timestamp_format: TimestampFormat,
+    warmup: &State<WarmupGate>,
     request: GraphQLRequest,
 ) -> GraphQLResponse {
-    request.execute(&**schema).await // Разыменовываем State
+    let mut request = request
+        .data(Arc::clone(&market.order_book))
+        .data(Arc::clone(&market.status));
+    if let Some(cold_store) = &market.cold_store {
+        request = request.data(Arc::clone(cold_store));
+    }
+    if let Some(fee_revenue) = &market.fee_revenue {
+        request = request.data(Arc::clone(fee_revenue));
+    }
+    if let Some(daily_reports) = &market.daily_reports {
+        request = request.data(Arc::clone(daily_reports));
+    }
+    let mut response = request
+        .data(admin_credentials)
+        .data(api_key)
+        .data(client_ip)
+        .data(request_id.clone())
+        .data(timestamp_format)
+        .execute(&**schema) // Разыменовываем State
+        .await;
+    if warmup.flags(&market.status) {
+        response
+            .0
+            .extensions
+            .insert("warmingUp".to_owned(), true.into());
+    }
+    request_id.tag_graphql_errors(&mut response.0);
+    response
+}
+
+/// Top of book from the snapshot cache: no GraphQL and, while the book is
+/// unchanged, no access to the book at all.
+#[rocket::get("/bbo")]
+pub fn get_bbo(
+    market: SelectedMarket,
+    snapshots: &State<ResponseSnapshots>,
+    _visible: MarketVisible,
+) -> content::RawJson<String> {
+    content::RawJson(snapshots.bbo(&market))
+}
+
+#[rocket::get("/snapshot/depth")]
+pub fn get_depth_snapshot(
+    market: SelectedMarket,
+    snapshots: &State<ResponseSnapshots>,
+    _visible: MarketVisible,
+) -> content::RawJson<String> {
+    content::RawJson(snapshots.depth(&market))
+}
+
+#[rocket::get("/snapshot/ticker")]
+pub fn get_ticker_snapshot(
+    market: SelectedMarket,
+    snapshots: &State<ResponseSnapshots>,
+    timestamp_format: TimestampFormat,
+    _visible: MarketVisible,
+) -> content::RawJson<String> {
+    content::RawJson(snapshots.ticker(&market, timestamp_format))
+}
+
+#[rocket::get("/snapshot/trades")]
+pub fn get_trades_snapshot(
+    market: SelectedMarket,
+    snapshots: &State<ResponseSnapshots>,
+    timestamp_format: TimestampFormat,
+    _visible: MarketVisible,
+) -> content::RawJson<String> {
+    content::RawJson(snapshots.trades(&market, timestamp_format))
+}
+
+/// Prometheus text exposition, labelled by market id.
+#[rocket::get("/metrics")]
+pub fn get_metrics(markets: &State<Arc<MarketRegistry>>) -> String {
+    metrics().encode(markets)
 }
 
 #[rocket::get("/graphql/playground")]
@@ -102,9 +340,25 @@ pub fn get_routes() -> Vec<Route> {
         get_sell_orders,
         get_indexer_spread,
         get_orders_count,
+        get_signed_price,
+        get_state,
+        get_depth,
     ]
 }
 
+pub fn get_snapshot_routes() -> Vec<Route> {
+    routes![
+        get_bbo,
+        get_depth_snapshot,
+        get_ticker_snapshot,
+        get_trades_snapshot
+    ]
+}
+
+pub fn get_metrics_routes() -> Vec<Route> {
+    routes![get_metrics]
+}
+
 pub fn get_graphql_routes() -> Vec<Route> {
     routes![graphql_handler, graphql_playground]
 }
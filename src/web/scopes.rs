@@ -0,0 +1,121 @@
+use async_graphql::{Context, Guard};
+use log::warn;
+use rocket::request::{FromRequest, Outcome, Request};
+use std::collections::{HashMap, HashSet};
+use subtle::{Choice, ConstantTimeEq};
+
+use crate::config::env::ev_opt;
+
+use super::auth::is_admin;
+use super::context::ServiceContext;
+use super::usage::API_KEY_HEADER;
+
+/// Access classes for fields beyond public market data.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Scope {
+    /// Per-user activity, e.g. when an address first traded.
+    UserData,
+    /// Internal diagnostics derived from raw events, e.g. anomaly flags.
+    RawEvents,
+    /// The per-order book feed.
+    OrderFeed,
+    /// Data across every market: aggregates scanning their archived
+    /// history, and the markets registered on Spark.
+    ExchangeData,
+}
+
+impl Scope {
+    fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "user_data" => Some(Scope::UserData),
+            "raw_events" => Some(Scope::RawEvents),
+            "order_feed" => Some(Scope::OrderFeed),
+            "exchange_data" => Some(Scope::ExchangeData),
+            _ => None,
+        }
+    }
+}
+
+/// The caller's `X-Api-Key`, if any.
+#[derive(Debug, Clone)]
+pub struct ApiKey(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(ApiKey(
+            request.headers().get_one(API_KEY_HEADER).map(str::to_owned),
+        ))
+    }
+}
+
+/// Scopes granted per API key, from `API_KEY_SCOPES` in the form
+/// `key1=user_data|raw_events,key2=raw_events`.
+pub struct ScopeConfig {
+    grants: HashMap<String, HashSet<Scope>>,
+}
+
+impl ScopeConfig {
+    pub fn from_env() -> Self {
+        let mut grants = HashMap::new();
+        for grant in ev_opt("API_KEY_SCOPES").unwrap_or_default().split(',') {
+            let Some((key, scopes)) = grant.trim().split_once('=') else {
+                continue;
+            };
+            let scopes = scopes
+                .split('|')
+                .filter_map(|scope| {
+                    let parsed = Scope::parse(scope.trim());
+                    if parsed.is_none() {
+                        warn!("Unknown scope '{}' in API_KEY_SCOPES", scope);
+                    }
+                    parsed
+                })
+                .collect();
+            grants.insert(key.trim().to_owned(), scopes);
+        }
+        ScopeConfig { grants }
+    }
+
+    /// Compares `api_key` with every configured key in constant time
+    /// instead of looking it up, so response times don't reveal keys.
+    pub fn has_scope(&self, api_key: Option<&str>, scope: Scope) -> bool {
+        let Some(api_key) = api_key else {
+            return false;
+        };
+        let mut granted = Choice::from(0);
+        for (key, scopes) in &self.grants {
+            granted |= key.as_bytes().ct_eq(api_key.as_bytes())
+                & Choice::from(scopes.contains(&scope) as u8);
+        }
+        granted.into()
+    }
+}
+
+/// Restricts a field to API keys granted `scope`. Admins pass every scope
+/// check.
+pub struct ScopeGuard {
+    scope: Scope,
+}
+
+impl ScopeGuard {
+    pub fn new(scope: Scope) -> Self {
+        ScopeGuard { scope }
+    }
+}
+
+impl Guard for ScopeGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let config = ctx.service::<ScopeConfig>()?;
+        let api_key = ctx
+            .data_opt::<ApiKey>()
+            .and_then(|api_key| api_key.0.as_deref());
+        if config.has_scope(api_key, self.scope) || is_admin(ctx) {
+            Ok(())
+        } else {
+            Err(format!("Scope {:?} required", self.scope).into())
+        }
+    }
+}
@@ -1,32 +1,132 @@
 use std::sync::Arc;
 
-use crate::storage::order_book::OrderBook;
+use crate::indexer::kill_switches::KillSwitches;
+use crate::indexer::spark_contracts::MarketDecimals;
+use crate::indexer::status::ConfirmationDepth;
+use crate::oracle::price_signer::PriceSigner;
+use crate::oracle::reference_rates::ReferenceRates;
+use crate::storage::address_labels::AddressLabels;
+use crate::storage::audit_log::AuditLog;
+use crate::storage::market_registry::MarketRegistry;
+use crate::storage::subscription_usage::SubscriptionUsage;
+use crate::storage::usage::UsageTracker;
 use crate::web::routes::{get_docs, get_routes};
 use async_graphql::Schema;
+use log::error;
 use rocket::{Build, Config, Rocket};
 use rocket_okapi::swagger_ui::make_swagger_ui;
 
-use super::graphql::Query;
-use super::routes::get_graphql_routes;
+use super::auth::AdminConfig;
+use super::client_ip::{get_denied_routes, ClientIpConfig, IpDenylist};
+use super::fairings::{FrozenDataHeader, WarmingUpHeader};
+use super::graphql::{Mutation, Query, Subscription};
+use super::load::{DepthLimits, LoadMonitor, LoadTracking};
+use super::load_shedding::{LoadShedding, LoadSheddingExtension};
+use super::oidc::OidcVerifier;
+use super::order_feed::OrderFeedConfig;
+use super::query_traces::{QueryTracing, QueryTracingExtension};
+use super::request_id::RequestIdFairing;
+use super::routes::{get_graphql_routes, get_metrics_routes, get_snapshot_routes};
+use super::scopes::ScopeConfig;
+use super::snapshots::ResponseSnapshots;
+use super::subscriptions::SubscriptionServer;
+use super::timestamps::TimestampFormatting;
+use super::usage::UsageAccounting;
+use super::warmup::WarmupGate;
 
-pub fn rocket(port: u16, order_book: Arc<OrderBook>) -> Rocket<Build> {
+/// Shared state handed to the web server by `main`.
+pub struct ServerState {
+    pub markets: Arc<MarketRegistry>,
+    pub price_signer: Option<Arc<PriceSigner>>,
+    pub kill_switches: Arc<KillSwitches>,
+    pub audit_log: Arc<AuditLog>,
+    pub usage: Arc<UsageTracker>,
+    pub subscription_usage: Arc<SubscriptionUsage>,
+    pub market_decimals: Arc<MarketDecimals>,
+    pub labels: Arc<AddressLabels>,
+    pub reference_rates: Arc<ReferenceRates>,
+    pub warmup: WarmupGate,
+}
+
+pub fn rocket(port: u16, state: ServerState) -> Rocket<Build> {
+    let ServerState {
+        markets,
+        price_signer,
+        kill_switches,
+        audit_log,
+        usage,
+        subscription_usage,
+        market_decimals,
+        labels,
+        reference_rates,
+        warmup,
+    } = state;
+    let default_market = markets.default_market();
     let config = Config {
         port,
         ..Config::default()
     };
 
-    let schema = Schema::build(
-        Query,
-        async_graphql::EmptyMutation,
-        async_graphql::EmptySubscription,
-    )
-    .data(Arc::clone(&order_book))
-    .finish();
+    let load = Arc::new(LoadMonitor::new());
+    let tracing = Arc::new(QueryTracing::from_env());
+    let shedding = LoadShedding::from_env(Arc::clone(&load));
+    let mut schema = Schema::build(Query, Mutation, Subscription)
+        .data(Arc::clone(&markets))
+        .data(default_market.order_book)
+        .data(default_market.status)
+        .data(Arc::clone(&kill_switches))
+        .data(audit_log)
+        .data(Arc::clone(&usage))
+        .data(subscription_usage)
+        .data(market_decimals)
+        .data(labels)
+        .data(reference_rates)
+        .data(AdminConfig::from_env())
+        .data(ScopeConfig::from_env())
+        .data(OrderFeedConfig::from_env())
+        .data(DepthLimits::from_env())
+        .data(warmup.clone())
+        .data(ConfirmationDepth::from_env())
+        .data(Arc::clone(&load))
+        .data(Arc::clone(&tracing))
+        .extension(QueryTracingExtension(tracing))
+        .extension(LoadSheddingExtension(shedding))
+        .extension(TimestampFormatting);
+    if let Some(price_signer) = &price_signer {
+        schema = schema.data(Arc::clone(price_signer));
+    }
+    let schema = schema.finish();
+
+    let mut rocket = rocket::custom(config);
+    if let Some(verifier) = OidcVerifier::from_env() {
+        rocket = rocket.manage(Arc::new(verifier));
+    }
+    match SubscriptionServer::from_env(port) {
+        Ok(subscriptions) => rocket = rocket.attach(subscriptions),
+        Err(e) => error!("GraphQL subscriptions disabled: {}", e),
+    }
 
-    rocket::custom(config)
-        .manage(order_book)
+    rocket
+        .manage(markets)
+        .manage(price_signer)
+        .manage(kill_switches)
+        .manage(usage)
+        .manage(ResponseSnapshots::new())
+        .manage(DepthLimits::from_env())
+        .manage(load)
+        .attach(LoadTracking)
+        .manage(ClientIpConfig::from_env())
+        .attach(IpDenylist)
+        .attach(RequestIdFairing)
+        .attach(FrozenDataHeader)
+        .manage(warmup)
+        .attach(WarmingUpHeader)
+        .attach(UsageAccounting)
         .manage(schema)
         .mount("/", get_routes())
+        .mount("/", get_snapshot_routes())
+        .mount("/", get_metrics_routes())
+        .mount("/", get_denied_routes())
         .mount("/api", get_graphql_routes())
         .mount("/swagger", make_swagger_ui(&get_docs()))
 }
@@ -0,0 +1,230 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::indexer::spot_order::OrderType;
+use crate::storage::backend::Storage;
+use crate::storage::fair_price::PriceLevel;
+use crate::storage::market_registry::MarketState;
+
+use super::timestamps::{serialize_ms, serialize_opt_ms, TimestampFormat};
+
+pub const DEPTH_LEVELS: usize = 20;
+pub const RECENT_TRADES: usize = 50;
+
+#[derive(Serialize)]
+struct Level {
+    price: u128,
+    size: u128,
+}
+
+impl From<PriceLevel> for Level {
+    fn from(level: PriceLevel) -> Self {
+        Level {
+            price: level.price,
+            size: level.size,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Depth {
+    sequence: u64,
+    epoch: u64,
+    bids: Vec<Level>,
+    asks: Vec<Level>,
+}
+
+#[derive(Serialize)]
+struct Ticker {
+    sequence: u64,
+    epoch: u64,
+    best_bid: Option<u128>,
+    best_ask: Option<u128>,
+    last_price: Option<String>,
+    last_size: Option<String>,
+    #[serde(serialize_with = "serialize_opt_ms")]
+    last_trade_timestamp: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Bbo {
+    sequence: u64,
+    epoch: u64,
+    best_bid: Option<u128>,
+    best_ask: Option<u128>,
+    last_price: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Trade {
+    id: String,
+    cursor: String,
+    price: String,
+    size: String,
+    #[serde(serialize_with = "serialize_ms")]
+    timestamp: u64,
+    block_number: i64,
+}
+
+#[derive(Serialize)]
+struct RecentTrades {
+    sequence: u64,
+    epoch: u64,
+    trades: Vec<Trade>,
+}
+
+/// A serialized response tagged with the book epoch and version it was
+/// built from.
+#[derive(Default)]
+struct Slot(RwLock<Option<((u64, u64), String)>>);
+
+impl Slot {
+    fn get_or_refresh(&self, version: (u64, u64), build: impl FnOnce() -> String) -> String {
+        if let Some((cached_version, json)) = self.0.read().unwrap().as_ref() {
+            if *cached_version == version {
+                return json.clone();
+            }
+        }
+        let json = build();
+        *self.0.write().unwrap() = Some((version, json.clone()));
+        json
+    }
+
+    /// Only responses in the default timestamp format are cached; others
+    /// are built for the request.
+    fn get_or_build(
+        &self,
+        version: (u64, u64),
+        format: TimestampFormat,
+        build: impl FnOnce() -> String,
+    ) -> String {
+        if format.is_default() {
+            self.get_or_refresh(version, build)
+        } else {
+            format.serializing(build)
+        }
+    }
+}
+
+#[derive(Default)]
+struct MarketSnapshots {
+    bbo: Slot,
+    depth: Slot,
+    ticker: Slot,
+    trades: Slot,
+}
+
+/// Pre-serialized JSON for the hottest endpoints, per market. Each blob is
+/// rebuilt only when the book version has moved since it was last built, so
+/// repeated requests skip the book entirely.
+#[derive(Default)]
+pub struct ResponseSnapshots {
+    markets: RwLock<HashMap<String, Arc<MarketSnapshots>>>,
+}
+
+impl ResponseSnapshots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn market(&self, market_id: &str) -> Arc<MarketSnapshots> {
+        if let Some(snapshots) = self.markets.read().unwrap().get(market_id) {
+            return Arc::clone(snapshots);
+        }
+        Arc::clone(
+            self.markets
+                .write()
+                .unwrap()
+                .entry(market_id.to_owned())
+                .or_default(),
+        )
+    }
+
+    /// Best bid, best ask and last price only, for latency-sensitive
+    /// clients polling the top of the book.
+    pub fn bbo(&self, market: &MarketState) -> String {
+        let order_book = &market.order_book;
+        let (epoch, sequence) = (order_book.epoch(), order_book.version());
+        let slots = self.market(market.market_id());
+        slots.bbo.get_or_refresh((epoch, sequence), || {
+            to_json(&Bbo {
+                sequence,
+                epoch,
+                best_bid: order_book.best_bid(),
+                best_ask: order_book.best_ask(),
+                last_price: order_book.last_trade().map(|trade| trade.trade_price),
+            })
+        })
+    }
+
+    pub fn depth(&self, market: &MarketState) -> String {
+        let order_book = &market.order_book;
+        let (epoch, sequence) = (order_book.epoch(), order_book.version());
+        let slots = self.market(market.market_id());
+        slots.depth.get_or_refresh((epoch, sequence), || {
+            to_json(&Depth {
+                sequence,
+                epoch,
+                bids: levels(order_book, OrderType::Buy),
+                asks: levels(order_book, OrderType::Sell),
+            })
+        })
+    }
+
+    pub fn ticker(&self, market: &MarketState, format: TimestampFormat) -> String {
+        let order_book = &market.order_book;
+        let (epoch, sequence) = (order_book.epoch(), order_book.version());
+        let slots = self.market(market.market_id());
+        slots.ticker.get_or_build((epoch, sequence), format, || {
+            let last = order_book.last_trade();
+            to_json(&Ticker {
+                sequence,
+                epoch,
+                best_bid: order_book.best_bid(),
+                best_ask: order_book.best_ask(),
+                last_trade_timestamp: last.as_ref().map(|trade| trade.timestamp),
+                last_price: last.as_ref().map(|trade| trade.trade_price.clone()),
+                last_size: last.map(|trade| trade.trade_size),
+            })
+        })
+    }
+
+    pub fn trades(&self, market: &MarketState, format: TimestampFormat) -> String {
+        let order_book = &market.order_book;
+        let (epoch, sequence) = (order_book.epoch(), order_book.version());
+        let slots = self.market(market.market_id());
+        slots.trades.get_or_build((epoch, sequence), format, || {
+            let trades = order_book
+                .recent_trades(RECENT_TRADES)
+                .into_iter()
+                .map(|trade| Trade {
+                    cursor: trade.index().cursor(),
+                    id: trade.id,
+                    price: trade.trade_price,
+                    size: trade.trade_size,
+                    timestamp: trade.timestamp,
+                    block_number: trade.block_number,
+                })
+                .collect();
+            to_json(&RecentTrades {
+                sequence,
+                epoch,
+                trades,
+            })
+        })
+    }
+}
+
+fn levels(order_book: &dyn Storage, order_type: OrderType) -> Vec<Level> {
+    order_book
+        .top_levels(order_type, DEPTH_LEVELS)
+        .into_iter()
+        .map(Level::from)
+        .collect()
+}
+
+fn to_json(value: &impl Serialize) -> String {
+    // These types contain only strings and numbers, which always serialize.
+    serde_json::to_string(value).unwrap()
+}
@@ -0,0 +1,197 @@
+use async_graphql::http::{WebSocket, WebSocketProtocols, WsMessage};
+use async_graphql::Data;
+use futures_util::{future, SinkExt, StreamExt};
+use log::{error, info, warn};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Orbit, Rocket};
+use serde_json::Value;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::storage::market_registry::MarketRegistry;
+
+use super::graphql::AppSchema;
+use super::oidc::OidcVerifier;
+use super::ws_auth::ConnectionAuth;
+
+const PROTOCOL_HEADER: &str = "Sec-WebSocket-Protocol";
+/// The `connection_init` payload field standing in for `X-Market-Id`.
+const MARKET_ID_FIELD: &str = "marketId";
+
+/// Serves the GraphQL subscriptions over WebSocket on `GRAPHQL_WS_PORT`
+/// (default the HTTP port + 1), speaking both `graphql-transport-ws` and
+/// the older `graphql-ws`. Rocket has no WebSocket support, so the
+/// listener is its own, started once Rocket is up and running the schema
+/// Rocket serves.
+pub struct SubscriptionServer {
+    port: u16,
+}
+
+impl SubscriptionServer {
+    pub fn from_env(http_port: u16) -> Result<Self, Error> {
+        let port = match ev_opt("GRAPHQL_WS_PORT") {
+            Some(port) => port.parse()?,
+            None => http_port + 1,
+        };
+        Ok(SubscriptionServer { port })
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for SubscriptionServer {
+    fn info(&self) -> Info {
+        Info {
+            name: "GraphQL subscriptions",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let (Some(schema), Some(markets)) = (
+            rocket.state::<AppSchema>().cloned(),
+            rocket.state::<Arc<MarketRegistry>>().cloned(),
+        ) else {
+            return;
+        };
+        let verifier = rocket.state::<Arc<OidcVerifier>>().cloned();
+
+        let address = rocket.config().address;
+        let listener = match TcpListener::bind((address, self.port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!(
+                    "Failed to listen for GraphQL subscriptions on {}:{}: {}",
+                    address, self.port, e
+                );
+                return;
+            }
+        };
+        info!(
+            "Serving GraphQL subscriptions on ws://{}:{}",
+            address, self.port
+        );
+        tokio::spawn(accept_connections(listener, schema, markets, verifier));
+    }
+}
+
+async fn accept_connections(
+    listener: TcpListener,
+    schema: AppSchema,
+    markets: Arc<MarketRegistry>,
+    verifier: Option<Arc<OidcVerifier>>,
+) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                warn!("Failed to accept a subscription connection: {}", e);
+                continue;
+            }
+        };
+        let (schema, markets, verifier) = (schema.clone(), Arc::clone(&markets), verifier.clone());
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(stream, schema, markets, verifier).await {
+                warn!("Subscription connection failed: {}", e);
+            }
+        });
+    }
+}
+
+async fn serve_connection(
+    stream: TcpStream,
+    schema: AppSchema,
+    markets: Arc<MarketRegistry>,
+    verifier: Option<Arc<OidcVerifier>>,
+) -> Result<(), Error> {
+    let mut protocol = None;
+    let socket =
+        tokio_tungstenite::accept_hdr_async(stream, |request: &Request, mut response: Response| {
+            protocol = request
+                .headers()
+                .get(PROTOCOL_HEADER)
+                .and_then(|protocols| protocols.to_str().ok())
+                .and_then(|protocols| {
+                    protocols
+                        .split(',')
+                        .find_map(|protocol| WebSocketProtocols::from_str(protocol.trim()).ok())
+                });
+            if let Some(protocol) = protocol {
+                response.headers_mut().insert(
+                    PROTOCOL_HEADER,
+                    HeaderValue::from_static(protocol.sec_websocket_protocol()),
+                );
+            }
+            Ok(response)
+        })
+        .await?;
+    // Clients that name no protocol get the older one, which came first.
+    let protocol = protocol.unwrap_or(WebSocketProtocols::SubscriptionsTransportWS);
+
+    let (mut sink, stream) = socket.split();
+    let input = stream
+        .take_while(|message| future::ready(message.is_ok()))
+        .filter_map(|message| {
+            future::ready(match message {
+                Ok(Message::Text(text)) => Some(text.into_bytes()),
+                Ok(Message::Binary(bytes)) => Some(bytes),
+                _ => None,
+            })
+        });
+
+    let auth = ConnectionAuth::new(verifier);
+    let refreshing = Arc::clone(&auth);
+    let mut output = WebSocket::new(schema, input, protocol)
+        .on_connection_init(move |payload| connection_init(auth, markets, payload))
+        .on_ping(move |_, payload| Arc::clone(&refreshing).refresh(payload));
+
+    while let Some(message) = output.next().await {
+        let message = match message {
+            WsMessage::Text(text) => Message::Text(text),
+            WsMessage::Close(code, reason) => Message::Close(Some(CloseFrame {
+                code: code.into(),
+                reason: reason.into(),
+            })),
+        };
+        sink.send(message).await?;
+    }
+    Ok(())
+}
+
+/// Authenticates the connection and, with `marketId` in the payload, points
+/// its operations at that market instead of the default one. Unknown
+/// markets close the connection.
+async fn connection_init(
+    auth: Arc<ConnectionAuth>,
+    markets: Arc<MarketRegistry>,
+    payload: Value,
+) -> async_graphql::Result<Data> {
+    let market = match payload.get(MARKET_ID_FIELD).and_then(Value::as_str) {
+        Some(market_id) => markets
+            .get(market_id)
+            .ok_or_else(|| format!("Unknown market {}", market_id))?,
+        None => markets.default_market(),
+    };
+
+    let mut data = auth.init(payload).await?;
+    data.insert(market.order_book);
+    data.insert(market.status);
+    // The market's own stores only: the schema holds none, so a market
+    // without one reports it disabled instead of reading another's.
+    if let Some(cold_store) = market.cold_store {
+        data.insert(cold_store);
+    }
+    if let Some(fee_revenue) = market.fee_revenue {
+        data.insert(fee_revenue);
+    }
+    if let Some(daily_reports) = market.daily_reports {
+        data.insert(daily_reports);
+    }
+    Ok(data)
+}
@@ -0,0 +1,206 @@
+use async_graphql::extensions::{
+    Extension, ExtensionContext, ExtensionFactory, NextResolve, ResolveInfo,
+};
+use async_graphql::{InputValueError, InputValueResult, Scalar, ScalarType, ServerResult, Value};
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use rocket::request::{FromRequest, Outcome, Request};
+use serde::{Serialize, Serializer};
+use std::cell::Cell;
+use std::sync::Arc;
+
+pub const TIMESTAMP_FORMAT_HEADER: &str = "X-Timestamp-Format";
+pub const TIMEZONE_HEADER: &str = "X-Timezone";
+const TIMESTAMP_FORMAT_PARAM: &str = "timestamp_format";
+const TIMEZONE_PARAM: &str = "timezone";
+const TIMESTAMP_TYPE: &str = "Timestamp";
+
+thread_local! {
+    /// The format of the REST response being serialized on this thread.
+    static SERIALIZING: Cell<TimestampFormat> = const { Cell::new(TimestampFormat::Milliseconds) };
+}
+
+/// How times are rendered in responses: Unix seconds, Unix milliseconds
+/// (the default, and what every argument takes) or RFC 3339 strings in UTC
+/// or a fixed offset.
+///
+/// Chosen per request with `X-Timestamp-Format: s|ms|rfc3339` or a
+/// `timestamp_format` query parameter, and for RFC 3339 an optional
+/// `X-Timezone` or `timezone` offset such as `+02:00`. Subscriptions take
+/// `timestampFormat` and `timezone` in their `connection_init` payload.
+///
+/// GraphQL renders fields of the [`Timestamp`] scalar; REST renders fields
+/// serialized with [`serialize_ms`], snapshots included.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    Seconds,
+    #[default]
+    Milliseconds,
+    Rfc3339(FixedOffset),
+}
+
+impl TimestampFormat {
+    /// Unknown formats and offsets read as the default.
+    pub fn parse(format: Option<&str>, timezone: Option<&str>) -> Self {
+        match format.map(str::trim) {
+            Some("s") | Some("seconds") => TimestampFormat::Seconds,
+            Some("rfc3339") => TimestampFormat::Rfc3339(
+                timezone
+                    .and_then(|timezone| timezone.trim().parse().ok())
+                    .unwrap_or_else(|| FixedOffset::east_opt(0).expect("zero offset")),
+            ),
+            _ => TimestampFormat::Milliseconds,
+        }
+    }
+
+    /// The format asked for by a query parameter, or else a header.
+    pub fn from_headers_and_query(request: &Request<'_>) -> Self {
+        let param = |name: &str| request.query_value::<&str>(name).and_then(Result::ok);
+        let headers = request.headers();
+        TimestampFormat::parse(
+            param(TIMESTAMP_FORMAT_PARAM).or(headers.get_one(TIMESTAMP_FORMAT_HEADER)),
+            param(TIMEZONE_PARAM).or(headers.get_one(TIMEZONE_HEADER)),
+        )
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == TimestampFormat::Milliseconds
+    }
+
+    fn render(&self, ms: u64) -> Option<Rendered> {
+        match self {
+            TimestampFormat::Seconds => Some(Rendered::Number(ms / 1000)),
+            TimestampFormat::Milliseconds => Some(Rendered::Number(ms)),
+            TimestampFormat::Rfc3339(offset) => {
+                DateTime::from_timestamp_millis(ms as i64).map(|time| {
+                    Rendered::Text(
+                        time.with_timezone(offset)
+                            .to_rfc3339_opts(SecondsFormat::Millis, true),
+                    )
+                })
+            }
+        }
+    }
+
+    /// `value`, a `Timestamp` or a list of them, in this format.
+    fn render_value(&self, value: Value) -> Value {
+        match value {
+            Value::Number(number) => match number.as_u64().and_then(|ms| self.render(ms)) {
+                Some(Rendered::Number(n)) => Value::from(n),
+                Some(Rendered::Text(text)) => Value::from(text),
+                None => Value::Number(number),
+            },
+            Value::List(items) => Value::List(
+                items
+                    .into_iter()
+                    .map(|item| self.render_value(item))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
+    /// Runs `serialize` with the times it writes through [`serialize_ms`]
+    /// rendered in this format.
+    pub fn serializing<R>(self, serialize: impl FnOnce() -> R) -> R {
+        let previous = SERIALIZING.with(|format| format.replace(self));
+        let result = serialize();
+        SERIALIZING.with(|format| format.set(previous));
+        result
+    }
+}
+
+/// Serializes a time in ms in the format of the REST response being
+/// written, ms outside of one.
+pub fn serialize_ms<S: Serializer>(ms: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    match SERIALIZING.with(Cell::get).render(*ms) {
+        Some(Rendered::Text(text)) => serializer.serialize_str(&text),
+        Some(Rendered::Number(n)) => serializer.serialize_u64(n),
+        None => serializer.serialize_u64(*ms),
+    }
+}
+
+pub fn serialize_opt_ms<S: Serializer>(ms: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+    match ms {
+        Some(ms) => serialize_ms(ms, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// A time in ms since the Unix epoch, rendered in the request's
+/// [`TimestampFormat`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp(pub u64);
+
+/// A time: Unix milliseconds by default, Unix seconds or an RFC 3339
+/// string when the request asks for them with `X-Timestamp-Format`.
+#[Scalar]
+impl ScalarType for Timestamp {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        if let Value::Number(number) = &value {
+            if let Some(ms) = number.as_u64() {
+                return Ok(Timestamp(ms));
+            }
+        }
+        Err(InputValueError::expected_type(value))
+    }
+
+    fn to_value(&self) -> Value {
+        Value::from(self.0)
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_ms(&self.0, serializer)
+    }
+}
+
+enum Rendered {
+    Number(u64),
+    Text(String),
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TimestampFormat {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(TimestampFormat::from_headers_and_query(request))
+    }
+}
+
+/// Renders fields of the [`Timestamp`] scalar, in queries and subscriptions
+/// alike, in the request's [`TimestampFormat`].
+pub struct TimestampFormatting;
+
+impl ExtensionFactory for TimestampFormatting {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(TimestampFormatting)
+    }
+}
+
+#[async_graphql::async_trait::async_trait]
+impl Extension for TimestampFormatting {
+    async fn resolve(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        info: ResolveInfo<'_>,
+        next: NextResolve<'_>,
+    ) -> ServerResult<Option<Value>> {
+        let format = ctx
+            .data_opt::<TimestampFormat>()
+            .copied()
+            .unwrap_or_default();
+        if format.is_default() || !is_timestamp_type(info.return_type) {
+            return next.run(ctx, info).await;
+        }
+        let value = next.run(ctx, info).await?;
+        Ok(value.map(|value| format.render_value(value)))
+    }
+}
+
+/// Whether a field's type is `Timestamp`, or a list of them, nullable or
+/// not.
+fn is_timestamp_type(return_type: &str) -> bool {
+    return_type.trim_matches(|c| matches!(c, '[' | ']' | '!')) == TIMESTAMP_TYPE
+}
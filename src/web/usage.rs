@@ -0,0 +1,34 @@
+use chrono::Utc;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Request, Response};
+use std::sync::Arc;
+
+use crate::storage::usage::{UsageTracker, ANONYMOUS_KEY};
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Counts every response and its body size against the caller's
+/// `X-Api-Key`, or against an anonymous bucket.
+pub struct UsageAccounting;
+
+#[rocket::async_trait]
+impl Fairing for UsageAccounting {
+    fn info(&self) -> Info {
+        Info {
+            name: "Usage accounting",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(usage) = request.rocket().state::<Arc<UsageTracker>>() else {
+            return;
+        };
+        let api_key = request
+            .headers()
+            .get_one(API_KEY_HEADER)
+            .unwrap_or(ANONYMOUS_KEY);
+        let bytes_out = response.body().preset_size().unwrap_or(0) as u64;
+        usage.record(api_key, bytes_out, Utc::now().timestamp_millis() as u64);
+    }
+}
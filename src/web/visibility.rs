@@ -0,0 +1,81 @@
+use async_graphql::{Context, Guard};
+use rocket::http::Status;
+use rocket::outcome::try_outcome;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_okapi::gen::OpenApiGenerator;
+use rocket_okapi::request::{OpenApiFromRequest, RequestHeaderInput};
+use std::sync::Arc;
+
+use crate::indexer::kill_switches::KillSwitches;
+use crate::indexer::status::IndexerStatus;
+use crate::web::context::ServiceContext;
+use crate::web::market::SelectedMarket;
+use crate::web::warmup::WarmupGate;
+
+fn is_api_hidden(kill_switches: &KillSwitches, status: &IndexerStatus) -> bool {
+    kill_switches.effective(status.market_id()).api_hidden
+}
+
+/// Rejects GraphQL market data fields while the market is hidden by a kill
+/// switch.
+pub struct MarketVisibleGuard;
+
+impl Guard for MarketVisibleGuard {
+    async fn check(&self, ctx: &Context<'_>) -> async_graphql::Result<()> {
+        let kill_switches = ctx.service::<Arc<KillSwitches>>()?;
+        let status = ctx.service::<Arc<IndexerStatus>>()?;
+
+        if is_api_hidden(kill_switches, status) {
+            Err("Market data is temporarily unavailable".into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Whether the market of a subscription opened past [`MarketVisibleGuard`]
+/// has been hidden since, so the stream can end rather than keep serving it.
+pub fn hidden_since(
+    ctx: &Context<'_>,
+) -> async_graphql::Result<impl Fn() -> bool + Send + 'static> {
+    let kill_switches = Arc::clone(ctx.service::<Arc<KillSwitches>>()?);
+    let status = Arc::clone(ctx.service::<Arc<IndexerStatus>>()?);
+    Ok(move || is_api_hidden(&kill_switches, &status))
+}
+
+/// REST counterpart of [`MarketVisibleGuard`] for the selected market;
+/// fails with 503 while it is hidden or, with `WARMUP_MODE=refuse`, still
+/// warming up.
+pub struct MarketVisible;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MarketVisible {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let market = try_outcome!(request.guard::<SelectedMarket>().await);
+        let rocket = request.rocket();
+        if rocket
+            .state::<WarmupGate>()
+            .is_some_and(|gate| gate.refuses(&market.status))
+        {
+            return Outcome::Error((Status::ServiceUnavailable, ()));
+        }
+        match rocket.state::<Arc<KillSwitches>>() {
+            Some(kill_switches) if !is_api_hidden(kill_switches, &market.status) => {
+                Outcome::Success(MarketVisible)
+            }
+            _ => Outcome::Error((Status::ServiceUnavailable, ())),
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for MarketVisible {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
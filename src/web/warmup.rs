@@ -0,0 +1,73 @@
+use crate::config::env::ev_opt;
+use crate::error::Error;
+use crate::indexer::status::{IndexerStatus, SyncPhase};
+
+const DEFAULT_MAX_BLOCKS_BEHIND: i64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarmupMode {
+    /// Serve whatever has been loaded so far.
+    Off,
+    /// Serve, but mark responses as coming from a book still backfilling.
+    Flag,
+    /// Reject market data queries until the book has caught up.
+    Refuse,
+}
+
+/// Decides whether a market's book has caught up enough to be served as
+/// the real market state. A market is warm once it is live, or once the
+/// backfill is within `WARMUP_MAX_BLOCKS_BEHIND` blocks (default 10) of the
+/// chain head.
+#[derive(Clone)]
+pub struct WarmupGate {
+    pub mode: WarmupMode,
+    max_blocks_behind: i64,
+}
+
+impl WarmupGate {
+    /// Reads `WARMUP_MODE` (`off`, `flag` or `refuse`, default `off`). Any
+    /// other value, or a `WARMUP_MAX_BLOCKS_BEHIND` that isn't a number, is
+    /// an error.
+    pub fn from_env() -> Result<Self, Error> {
+        let mode = match ev_opt("WARMUP_MODE").as_deref() {
+            None | Some("off") => WarmupMode::Off,
+            Some("flag") => WarmupMode::Flag,
+            Some("refuse") => WarmupMode::Refuse,
+            Some(other) => {
+                return Err(Error::InvalidEnvValue(
+                    "WARMUP_MODE".to_owned(),
+                    other.to_owned(),
+                ))
+            }
+        };
+        let max_blocks_behind = match ev_opt("WARMUP_MAX_BLOCKS_BEHIND") {
+            Some(value) => value.parse().map_err(|_| {
+                Error::InvalidEnvValue("WARMUP_MAX_BLOCKS_BEHIND".to_owned(), value)
+            })?,
+            None => DEFAULT_MAX_BLOCKS_BEHIND,
+        };
+
+        Ok(WarmupGate {
+            mode,
+            max_blocks_behind,
+        })
+    }
+
+    pub fn is_warm(&self, status: &IndexerStatus) -> bool {
+        match status.phase() {
+            SyncPhase::Live => true,
+            SyncPhase::Starting => false,
+            SyncPhase::Backfilling => status
+                .blocks_behind()
+                .is_some_and(|behind| behind <= self.max_blocks_behind),
+        }
+    }
+
+    pub fn refuses(&self, status: &IndexerStatus) -> bool {
+        self.mode == WarmupMode::Refuse && !self.is_warm(status)
+    }
+
+    pub fn flags(&self, status: &IndexerStatus) -> bool {
+        self.mode != WarmupMode::Off && !self.is_warm(status)
+    }
+}
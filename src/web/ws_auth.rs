@@ -0,0 +1,142 @@
+use async_graphql::Data;
+use chrono::Utc;
+use log::info;
+use serde_json::{json, Value};
+use std::sync::{Arc, RwLock};
+
+use crate::config::env::ev_opt;
+
+use super::auth::AdminCredentials;
+use super::oidc::OidcVerifier;
+use super::scopes::ApiKey;
+use super::timestamps::TimestampFormat;
+
+const DEFAULT_GRACE_SECS: u64 = 30;
+/// The `connection_init` and `ping` payload fields, standing in for the
+/// `X-Api-Key` and `Authorization: Bearer` headers browsers can't set on a
+/// WebSocket.
+const API_KEY_FIELD: &str = "apiKey";
+const AUTH_TOKEN_FIELD: &str = "authToken";
+const TIMESTAMP_FORMAT_FIELD: &str = "timestampFormat";
+const TIMEZONE_FIELD: &str = "timezone";
+
+struct Session {
+    oidc_identity: Option<String>,
+    /// When the current token expires (ms), if the connection has one.
+    expires_at: Option<u64>,
+}
+
+/// Authentication of one subscription connection.
+///
+/// The client authenticates in its `connection_init` payload with `apiKey`
+/// and, for admin subscriptions, an `authToken` accepted by the OIDC
+/// issuer. Before the token expires it sends a `ping` whose payload carries
+/// a fresh `authToken` for the same identity; the `pong` answers with the
+/// new `expiresAt`. Subscriptions of a connection whose token expired more
+/// than `WS_AUTH_GRACE_SECS` (default 30) ago without a refresh are ended.
+/// Admin subscriptions are audited under the token's verified identity.
+pub struct ConnectionAuth {
+    verifier: Option<Arc<OidcVerifier>>,
+    grace_ms: u64,
+    session: RwLock<Session>,
+}
+
+impl ConnectionAuth {
+    pub fn new(verifier: Option<Arc<OidcVerifier>>) -> Arc<Self> {
+        let grace_secs = ev_opt("WS_AUTH_GRACE_SECS")
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(DEFAULT_GRACE_SECS);
+        Arc::new(ConnectionAuth {
+            verifier,
+            grace_ms: grace_secs * 1000,
+            session: RwLock::new(Session {
+                oidc_identity: None,
+                expires_at: None,
+            }),
+        })
+    }
+
+    /// Handles `connection_init`: returns the request data the
+    /// connection's subscriptions run with, or an error that closes it.
+    pub async fn init(self: Arc<Self>, payload: Value) -> async_graphql::Result<Data> {
+        let (oidc_identity, expires_at) = match token(&payload) {
+            Some(token) => {
+                let (identity, expires_at) = self.verify(token).await?;
+                (Some(identity), Some(expires_at))
+            }
+            None => (None, None),
+        };
+        *self.session.write().unwrap() = Session {
+            oidc_identity: oidc_identity.clone(),
+            expires_at,
+        };
+
+        let mut data = Data::default();
+        data.insert(ApiKey(
+            payload
+                .get(API_KEY_FIELD)
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+        ));
+        data.insert(AdminCredentials {
+            key: None,
+            oidc_identity,
+        });
+        data.insert(TimestampFormat::parse(
+            payload.get(TIMESTAMP_FORMAT_FIELD).and_then(Value::as_str),
+            payload.get(TIMEZONE_FIELD).and_then(Value::as_str),
+        ));
+        data.insert(Arc::clone(&self));
+        Ok(data)
+    }
+
+    /// Handles `ping`. A payload with a fresh `authToken` for the
+    /// connection's identity extends its session; other pings are answered
+    /// as usual.
+    pub async fn refresh(
+        self: Arc<Self>,
+        payload: Option<Value>,
+    ) -> async_graphql::Result<Option<Value>> {
+        let Some(token) = payload.as_ref().and_then(token) else {
+            return Ok(None);
+        };
+        let (identity, expires_at) = self.verify(token).await?;
+
+        let mut session = self.session.write().unwrap();
+        if session.oidc_identity.as_deref() != Some(identity.as_str()) {
+            return Err("Refreshed token belongs to another identity".into());
+        }
+        session.expires_at = Some(expires_at);
+        info!(
+            "Subscription connection of {} refreshed its token",
+            identity
+        );
+        Ok(Some(json!({ "expiresAt": expires_at })))
+    }
+
+    /// Whether the session's token expired, past the grace period, without
+    /// being refreshed.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.session
+            .read()
+            .unwrap()
+            .expires_at
+            .is_some_and(|expires_at| now > expires_at + self.grace_ms)
+    }
+
+    async fn verify(&self, token: &str) -> async_graphql::Result<(String, u64)> {
+        let Some(verifier) = &self.verifier else {
+            return Err("Token authentication is not configured".into());
+        };
+        match verifier.verify(token).await {
+            Some(token) if token.expires_at > Utc::now().timestamp_millis() as u64 => {
+                Ok((token.identity, token.expires_at))
+            }
+            _ => Err("Token rejected".into()),
+        }
+    }
+}
+
+fn token(payload: &Value) -> Option<&str> {
+    payload.get(AUTH_TOKEN_FIELD).and_then(Value::as_str)
+}